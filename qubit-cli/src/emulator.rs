@@ -0,0 +1,124 @@
+//! 実機の代わりに、ファームウェアと同じ qubit_core の SysEx の処理を動かす
+//!
+//! 設定値は qubit_core のグローバル変数なので、EmulatedDevice は同時に1つだけ使う
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embassy_futures::block_on;
+
+use qubit_core::constants::MIDI_SYSEX_BUFFER_SIZE;
+use qubit_core::hal::{MidiSink, Reboot, SettingsStore};
+use qubit_core::midi::config::{self, handle_sysex};
+use qubit_core::midi::parser::{MidiMessage, UsbMidiParser};
+use qubit_core::settings;
+
+use crate::transport::Transport;
+
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// デバイスが送った USB-MIDI パケット
+#[derive(Default)]
+struct Packets(Vec<[u8; 4]>);
+
+impl MidiSink for Packets {
+    type Error = Infallible;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        self.0.push(packet);
+        Ok(())
+    }
+}
+
+/// フラッシュと再起動の代わり
+#[derive(Default)]
+pub struct EmulatedFlash {
+    pub saved: Option<Vec<u8>>, // フラッシュに保存した設定
+    pub rebooted: bool,         // ブートローダーで再起動した（以後は応答しない）
+}
+
+impl SettingsStore for EmulatedFlash {
+    type Error = Infallible;
+
+    async fn save(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.saved = Some(data.to_vec());
+        Ok(())
+    }
+}
+
+impl Reboot for EmulatedFlash {
+    fn reboot_to_bootloader(&mut self) {
+        self.rebooted = true;
+    }
+}
+
+pub struct EmulatedDevice {
+    pub flash: EmulatedFlash,
+    parser: UsbMidiParser<MIDI_SYSEX_BUFFER_SIZE>,
+    replies: VecDeque<Vec<u8>>,
+    last_telemetry: Instant,
+}
+
+impl EmulatedDevice {
+    pub fn new() -> Self {
+        Self {
+            flash: EmulatedFlash::default(),
+            parser: UsbMidiParser::new(),
+            replies: VecDeque::new(),
+            last_telemetry: Instant::now(),
+        }
+    }
+}
+
+impl Default for EmulatedDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for EmulatedDevice {
+    fn send(&mut self, sysex: &[u8]) -> io::Result<()> {
+        if self.flash.rebooted {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        let body = sysex
+            .strip_prefix(&[0xF0])
+            .and_then(|s| s.strip_suffix(&[0xF7]))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a SysEx"))?;
+        let mut out = Packets::default();
+        block_on(handle_sysex(body, &mut out, &mut self.flash))
+            .map_err(|_| io::Error::other("reply does not fit in a SysEx"))?;
+        // 実機と同じく USB-MIDI パケットから組み立て直す
+        for packet in out.0 {
+            if let Ok(Some(event)) = self.parser.parse(packet)
+                && let MidiMessage::SysEx(data) = event.message
+            {
+                self.replies.push_back(data.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        if let Some(reply) = self.replies.pop_front() {
+            return Ok(Some(reply));
+        }
+        if self.flash.rebooted || settings::TELEMETRY.load(Ordering::Relaxed) == 0 {
+            thread::sleep(timeout);
+            return Ok(None);
+        }
+        // テレメトリは実機と同じ間隔で送る
+        let wait = TELEMETRY_INTERVAL.saturating_sub(self.last_telemetry.elapsed());
+        if wait > timeout {
+            thread::sleep(timeout);
+            return Ok(None);
+        }
+        thread::sleep(wait);
+        self.last_telemetry = Instant::now();
+        let sysex = config::telemetry_sysex();
+        Ok(Some(sysex[1..sysex.len() - 1].to_vec()))
+    }
+}
@@ -0,0 +1,11 @@
+//  Created by Hasebe Masahiko on 2026/02/11.
+//  Copyright (c) 2026 Hasebe Masahiko.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+//! Loopian::QUBIT 設定ツール
+//! qubit_core::midi::config の SysEx で、設定の読み書き・プリセット・テレメトリを扱う
+pub mod emulator;
+pub mod preset;
+pub mod protocol;
+pub mod transport;
@@ -0,0 +1,188 @@
+//  Created by Hasebe Masahiko on 2026/02/11.
+//  Copyright (c) 2026 Hasebe Masahiko.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use qubit_cli::emulator::EmulatedDevice;
+use qubit_cli::preset::{Preset, check_param_count};
+use qubit_cli::protocol::{Client, Telemetry, masked_keys};
+use qubit_cli::transport::{self, RawMidi, Transport};
+use qubit_core::midi::clock::Transport as ClockTransport;
+use qubit_core::settings::{self, PARAMS, Param};
+
+#[derive(Parser)]
+#[command(about = "Loopian::QUBIT configuration tool")]
+struct Args {
+    /// rawmidi デバイス（省略時は /proc/asound/cards から QUBIT を探す）
+    #[arg(long, conflicts_with = "emulate")]
+    port: Option<PathBuf>,
+    /// 実機の代わりにエミュレータにつなぐ（動作確認用）
+    #[arg(long)]
+    emulate: bool,
+    #[command(subcommand)]
+    command: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// ファームウェアのバージョンを表示する
+    Identify,
+    /// すべてのパラメータを表示する
+    List,
+    /// パラメータを読む（名前か ID）
+    Get { param: String },
+    /// パラメータを書く（値は数値か表示名）
+    Set {
+        param: String,
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+    },
+    /// 設定を JSON で書き出す（省略時は標準出力）
+    Dump { file: Option<PathBuf> },
+    /// JSON の設定を書き込む（書いていない項目は今の値のまま）
+    Restore { file: PathBuf },
+    /// 今の設定をフラッシュに保存する
+    Save,
+    /// タッチ位置と圧力を表示し続ける
+    Monitor {
+        /// 表示する回数（省略時は止めるまで）
+        #[arg(long)]
+        count: Option<usize>,
+    },
+    /// 内部クロックをスタート・ストップする（start / stop / continue）
+    Transport { action: String },
+    /// ブートローダー（UF2 の書き込み）で再起動する
+    Bootloader,
+}
+
+/// 名前（大文字小文字は区別しない）か ID でパラメータを探す
+fn find_param(name: &str) -> Result<(u8, &'static Param), String> {
+    settings::find_param(name).ok_or_else(|| format!("unknown parameter \"{}\"", name))
+}
+
+/// 表示名（"Violin" や Transpose の "-2"）を先に探し、なければ数値
+fn parse_value(param: &Param, value: &str) -> Result<u8, String> {
+    param
+        .parse(value)
+        .ok_or_else(|| format!("invalid value \"{}\" for {}", value, param.name))
+}
+
+/// 表示名のある値は、名前も表示する（添字が値）
+fn show_value(param: &Param, value: u8) -> String {
+    param.labels.get(value as usize).map_or_else(
+        || value.to_string(),
+        |label| format!("{} ({})", value, label),
+    )
+}
+
+fn show_telemetry(t: &Telemetry) -> String {
+    let mut line = String::from("touch");
+    for touch in &t.touches {
+        match touch {
+            Some(loc) => line += &format!(" {:6.2}", loc),
+            None => line += "      -",
+        }
+    }
+    line += &format!("  pressure {:5}  adc", t.pressure);
+    for adc in &t.adc {
+        line += &format!(" {:5}", adc);
+    }
+    line
+}
+
+fn run_command<T: Transport>(client: &mut Client<T>, command: Cmd) -> Result<(), String> {
+    let err = |e: qubit_cli::protocol::Error| e.to_string();
+    match command {
+        Cmd::Identify => {
+            let v = client.identify().map_err(err)?.version;
+            println!("Loopian::QUBIT {}.{}.{}", v[0], v[1], v[2]);
+        }
+        Cmd::List => {
+            let snapshot = client.dump().map_err(err)?;
+            check_param_count(&snapshot)?;
+            for (id, (param, value)) in PARAMS.iter().zip(&snapshot.values).enumerate() {
+                if param.is_reserved() {
+                    continue;
+                }
+                println!("{:2} {:12} {}", id, param.name, show_value(param, *value));
+            }
+            println!("masked keys: {:?}", masked_keys(&snapshot.key_mask));
+        }
+        Cmd::Get { param } => {
+            let (id, param) = find_param(&param)?;
+            let value = client.get_param(id).map_err(err)?;
+            println!("{}", show_value(param, value));
+        }
+        Cmd::Set { param, value } => {
+            let (id, param) = find_param(&param)?;
+            client
+                .set_param(id, parse_value(param, &value)?)
+                .map_err(err)?;
+        }
+        Cmd::Dump { file } => {
+            let json = Preset::from_snapshot(&client.dump().map_err(err)?)?.to_json();
+            match file {
+                Some(path) => std::fs::write(&path, json + "\n")
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+                None => println!("{}", json),
+            }
+        }
+        Cmd::Restore { file } => {
+            let json =
+                std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            let preset =
+                Preset::from_json(&json).map_err(|e| format!("{}: {}", file.display(), e))?;
+            let mut snapshot = client.dump().map_err(err)?;
+            preset.apply(&mut snapshot)?;
+            client.restore(&snapshot).map_err(err)?;
+        }
+        Cmd::Save => client.save().map_err(err)?,
+        Cmd::Monitor { count } => {
+            client.set_telemetry(true).map_err(err)?;
+            let mut shown = 0;
+            while count.is_none_or(|count| shown < count) {
+                println!("{}", show_telemetry(&client.next_telemetry().map_err(err)?));
+                shown += 1;
+            }
+            client.set_telemetry(false).map_err(err)?;
+        }
+        Cmd::Transport { action } => {
+            let transport = match action.to_ascii_lowercase().as_str() {
+                "start" => ClockTransport::Start,
+                "stop" => ClockTransport::Stop,
+                "continue" => ClockTransport::Continue,
+                _ => return Err(format!("unknown transport \"{}\"", action)),
+            };
+            client.send_transport(transport).map_err(err)?;
+        }
+        Cmd::Bootloader => client.reboot_to_bootloader().map_err(err)?,
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    if args.emulate {
+        return run_command(&mut Client::new(EmulatedDevice::new()), args.command);
+    }
+    let port = match args.port {
+        Some(port) => port,
+        None => transport::find_port().map_err(|e| e.to_string())?,
+    };
+    let midi = RawMidi::open(&port).map_err(|e| format!("{}: {}", port.display(), e))?;
+    run_command(&mut Client::new(midi), args.command)
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("qubit-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
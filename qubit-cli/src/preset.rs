@@ -0,0 +1,95 @@
+//! プリセット（JSON）
+//!
+//!   {
+//!     "params": { "Mode": 0, "Contrast": 255, ... },
+//!     "manual_key_mask": [3, 40]
+//!   }
+//!
+//! パラメータは settings::PARAMS の名前で持つ。名前と ID の対応はこのツールの PARAMS で決めるので、
+//! 機器のパラメータの数が NUM_PARAMS と違うとき（ファームウェアのバージョン違い）はダンプもリストアもしない
+//! 書いていないパラメータとキーマスクは、リストアの前の値のまま
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use qubit_core::constants::TOTAL_QT_KEYS;
+use qubit_core::settings::{NUM_PARAMS, PARAMS};
+use qubit_core::touch::key_mask::KeyMask;
+
+use crate::protocol::{Snapshot, masked_keys};
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Preset {
+    #[serde(default)]
+    pub params: BTreeMap<String, u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manual_key_mask: Option<Vec<usize>>, // マスクするキー番号
+}
+
+/// ダンプのパラメータがこのツールの PARAMS と同じ数か確かめる
+pub fn check_param_count(snapshot: &Snapshot) -> Result<(), String> {
+    if snapshot.values.len() != NUM_PARAMS {
+        return Err(format!(
+            "the device has {} parameters but this tool knows {}; use the tool for its firmware",
+            snapshot.values.len(),
+            NUM_PARAMS
+        ));
+    }
+    Ok(())
+}
+
+impl Preset {
+    /// ダンプから（予約の ID は入らない）
+    pub fn from_snapshot(snapshot: &Snapshot) -> Result<Self, String> {
+        check_param_count(snapshot)?;
+        Ok(Self {
+            params: PARAMS
+                .iter()
+                .zip(&snapshot.values)
+                .filter(|(param, _)| !param.is_reserved())
+                .map(|(param, value)| (param.name.to_string(), *value))
+                .collect(),
+            manual_key_mask: Some(masked_keys(&snapshot.key_mask)),
+        })
+    }
+
+    /// ダンプした値にプリセットを重ねる。名前か値が正しくなければ何も変えない
+    pub fn apply(&self, snapshot: &mut Snapshot) -> Result<(), String> {
+        check_param_count(snapshot)?;
+        let mut values = snapshot.values.clone();
+        for (name, value) in &self.params {
+            let id = PARAMS
+                .iter()
+                .position(|p| p.name == name && !p.is_reserved())
+                .ok_or_else(|| format!("unknown parameter \"{}\"", name))?;
+            let param = &PARAMS[id];
+            if *value < param.min || *value > param.max {
+                return Err(format!(
+                    "{} must be {}-{}, not {}",
+                    name, param.min, param.max, value
+                ));
+            }
+            values[id] = *value;
+        }
+        if let Some(keys) = &self.manual_key_mask {
+            let mut mask = KeyMask::new();
+            for key in keys {
+                if *key >= TOTAL_QT_KEYS {
+                    return Err(format!("key {} does not exist", key));
+                }
+                mask.set(*key, true);
+            }
+            snapshot.key_mask = mask;
+        }
+        snapshot.values = values;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+}
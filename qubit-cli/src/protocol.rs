@@ -0,0 +1,285 @@
+//! 設定用 SysEx の送受信（コマンドの一覧は qubit_core::midi::config）
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use qubit_core::constants::{KEY_MASK_BYTES, TOTAL_QT_KEYS};
+use qubit_core::midi::clock::Transport as ClockTransport;
+use qubit_core::midi::config::*;
+use qubit_core::touch::key_mask::KeyMask;
+use qubit_core::touch::recording::{pack7, packed_len, unpack7};
+
+use crate::transport::Transport;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+const PACKED_MASK_SIZE: usize = packed_len(KEY_MASK_BYTES);
+const NO_TOUCH: u16 = 10000;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Timeout,
+    Nak { cmd: u8, code: u8 }, // デバイスがエラーを返した（code は ConfigError）
+    BadReply,                  // 返信の形が違う
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Timeout => write!(f, "no reply from the device"),
+            Error::Nak { cmd, code } => {
+                let reason = match *code {
+                    c if c == ConfigError::UnknownCommand as u8 => "unknown command",
+                    c if c == ConfigError::BadLength as u8 => "bad length",
+                    c if c == ConfigError::UnknownParam as u8 => "unknown parameter",
+                    c if c == ConfigError::OutOfRange as u8 => "value out of range",
+                    c if c == ConfigError::SaveFailed as u8 => "saving to flash failed",
+                    c if c == ConfigError::NoCommand as u8 => "no command",
+                    _ => "unknown error",
+                };
+                write!(f, "command 0x{:02X} rejected: {}", cmd, reason)
+            }
+            Error::BadReply => write!(f, "unexpected reply from the device"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Identity Reply の中身
+#[derive(Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub version: [u8; 4],
+}
+
+/// ダンプの中身（リストアにそのまま使える）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub values: Vec<u8>, // パラメータIDの順
+    pub key_mask: KeyMask,
+}
+
+/// テレメトリ 1回分
+#[derive(Clone, Debug, PartialEq)]
+pub struct Telemetry {
+    pub touches: [Option<f32>; 4], // タッチ位置（キー番号、小数あり）
+    pub pressure: u16,
+    pub adc: [u16; 4],
+}
+
+impl Telemetry {
+    /// 0x18 の後ろのデータから
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != TELEMETRY_VALUES * 2 {
+            return None;
+        }
+        let mut values = data
+            .chunks_exact(2)
+            .map(|v| v[0] as u16 | (v[1] as u16) << 7);
+        let mut next = || values.next().unwrap_or(0);
+        let touches = [(); 4].map(|_| match next() {
+            NO_TOUCH => None,
+            loc => Some(loc as f32 / 100.0),
+        });
+        let pressure = next();
+        let adc = [(); 4].map(|_| next());
+        Some(Self {
+            touches,
+            pressure,
+            adc,
+        })
+    }
+}
+
+fn push_value(data: &mut Vec<u8>, value: u8) {
+    data.push(value & 0x7F);
+    data.push(value >> 7);
+}
+
+fn value_at(data: &[u8]) -> u8 {
+    data[0] | (data[1] & 1) << 7
+}
+
+fn pack_key_mask(mask: &KeyMask) -> [u8; PACKED_MASK_SIZE] {
+    let mut packed = [0u8; PACKED_MASK_SIZE];
+    pack7(&mask.to_bytes(), &mut packed);
+    packed
+}
+
+fn unpack_key_mask(packed: &[u8]) -> Result<KeyMask, Error> {
+    if packed.len() != PACKED_MASK_SIZE {
+        return Err(Error::BadReply);
+    }
+    let mut bytes = [0u8; KEY_MASK_BYTES];
+    unpack7(packed, &mut bytes);
+    Ok(KeyMask::from_bytes(&bytes))
+}
+
+/// マスクしているキー番号の一覧
+pub fn masked_keys(mask: &KeyMask) -> Vec<usize> {
+    (0..TOTAL_QT_KEYS).filter(|k| mask.is_masked(*k)).collect()
+}
+
+// =========================================================
+//      Client
+// =========================================================
+pub struct Client<T: Transport> {
+    transport: T,
+    pub timeout: Duration,
+}
+
+impl<T: Transport> Client<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// match_reply が Some を返す SysEx が来るまで待つ（テレメトリなど他の SysEx は読み捨てる）
+    fn wait_for<R>(&mut self, mut match_reply: impl FnMut(&[u8]) -> Option<R>) -> Result<R, Error> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(Error::Timeout);
+            }
+            if let Some(sysex) = self.transport.receive(left)?
+                && let Some(reply) = match_reply(&sysex)
+            {
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// コマンドを送って、返信のデータ（REPLY_FLAG 付きのコマンドの後ろ）を受け取る
+    /// ACK の返信は空のデータ、NAK はエラーにする
+    fn request(&mut self, cmd: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut sysex = vec![0xF0];
+        sysex.extend_from_slice(&CONFIG_HEADER);
+        sysex.push(cmd);
+        sysex.extend_from_slice(data);
+        sysex.push(0xF7);
+        self.transport.send(&sysex)?;
+        self.wait_for(|sysex| {
+            let body = sysex.strip_prefix(&CONFIG_HEADER)?;
+            match body {
+                [ACK, c] if *c == cmd => Some(Ok(Vec::new())),
+                [NAK, c, code] if *c == cmd => Some(Err(Error::Nak { cmd, code: *code })),
+                [c, rest @ ..] if *c == cmd | REPLY_FLAG => Some(Ok(rest.to_vec())),
+                _ => None,
+            }
+        })?
+    }
+
+    fn command(&mut self, cmd: u8, data: &[u8]) -> Result<(), Error> {
+        if self.request(cmd, data)?.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::BadReply)
+        }
+    }
+
+    /// Universal Identity Request で QUBIT かどうかとバージョンを調べる
+    pub fn identify(&mut self) -> Result<Identity, Error> {
+        self.transport.send(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7])?;
+        self.wait_for(|sysex| match sysex {
+            [0x7E, _, 0x06, 0x02, rest @ ..] => {
+                let version = rest.strip_prefix(&CONFIG_HEADER)?;
+                Some(Identity {
+                    version: version.try_into().ok()?,
+                })
+            }
+            _ => None,
+        })
+    }
+
+    pub fn get_param(&mut self, id: u8) -> Result<u8, Error> {
+        match self.request(CMD_GET_PARAM, &[id])?[..] {
+            [reply_id, lo, hi] if reply_id == id => Ok(value_at(&[lo, hi])),
+            _ => Err(Error::BadReply),
+        }
+    }
+
+    pub fn set_param(&mut self, id: u8, value: u8) -> Result<(), Error> {
+        let mut data = vec![id];
+        push_value(&mut data, value);
+        self.command(CMD_SET_PARAM, &data)
+    }
+
+    pub fn dump(&mut self) -> Result<Snapshot, Error> {
+        let reply = self.request(CMD_DUMP, &[])?;
+        let (&count, rest) = reply.split_first().ok_or(Error::BadReply)?;
+        let (values, mask) = rest
+            .split_at_checked(count as usize * 2)
+            .ok_or(Error::BadReply)?;
+        Ok(Snapshot {
+            values: values.chunks_exact(2).map(value_at).collect(),
+            key_mask: unpack_key_mask(mask)?,
+        })
+    }
+
+    /// すべての値を確かめてから反映するので、1つでも範囲外なら何も変わらない
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        let mut data = vec![snapshot.values.len() as u8];
+        for value in &snapshot.values {
+            push_value(&mut data, *value);
+        }
+        data.extend_from_slice(&pack_key_mask(&snapshot.key_mask));
+        self.command(CMD_RESTORE, &data)
+    }
+
+    pub fn save(&mut self) -> Result<(), Error> {
+        self.command(CMD_SAVE, &[])
+    }
+
+    pub fn get_key_mask(&mut self) -> Result<KeyMask, Error> {
+        unpack_key_mask(&self.request(CMD_GET_KEY_MASK, &[])?)
+    }
+
+    pub fn set_key_mask(&mut self, mask: &KeyMask) -> Result<(), Error> {
+        self.command(CMD_SET_KEY_MASK, &pack_key_mask(mask))
+    }
+
+    pub fn set_telemetry(&mut self, on: bool) -> Result<(), Error> {
+        self.command(CMD_TELEMETRY, &[on as u8])
+    }
+
+    /// テレメトリを1回分待つ（set_telemetry(true) の後）
+    pub fn next_telemetry(&mut self) -> Result<Telemetry, Error> {
+        self.wait_for(|sysex| {
+            let body = sysex.strip_prefix(&CONFIG_HEADER)?;
+            Telemetry::parse(body.strip_prefix(&[TELEMETRY_REPORT])?)
+        })
+    }
+
+    /// ACK の後、デバイスは USB から消えて UF2 の書き込みを待つ
+    pub fn reboot_to_bootloader(&mut self) -> Result<(), Error> {
+        self.command(CMD_REBOOT_BOOTLOADER, &[])
+    }
+
+    /// 内部クロックのスタート・ストップ（Clock が Int のときに送る）
+    pub fn send_transport(&mut self, transport: ClockTransport) -> Result<(), Error> {
+        let data = match transport {
+            ClockTransport::Stop => 0,
+            ClockTransport::Start => 1,
+            ClockTransport::Continue => 2,
+        };
+        self.command(CMD_TRANSPORT, &[data])
+    }
+}
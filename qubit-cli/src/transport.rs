@@ -0,0 +1,124 @@
+//! MIDI ポートとのやりとり（SysEx 単位）
+//!
+//! Linux の ALSA rawmidi デバイス（/dev/snd/midiC*D*）を直接読み書きするので、
+//! ALSA のライブラリは要らない
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// SysEx を送って受け取る口（実機と EmulatedDevice）
+pub trait Transport {
+    /// SysEx（F0 から F7 まで）を送る
+    fn send(&mut self, sysex: &[u8]) -> io::Result<()>;
+    /// 受信した SysEx を1つ取り出す（F0 と F7 を除く）。timeout までに来なければ None
+    fn receive(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
+
+// =========================================================
+//      SysEx Splitter
+// =========================================================
+/// MIDI のバイト列から SysEx を切り出す
+/// リアルタイムメッセージ（F8-FF）は SysEx の途中でも無視し、それ以外のステータスで SysEx は終わる
+#[derive(Default)]
+pub struct SysExSplitter {
+    buf: Vec<u8>,
+    in_sysex: bool,
+}
+
+impl SysExSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// SysEx が揃ったら、その中身（F0 と F7 を除く）を返す
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match byte {
+            0xF0 => {
+                self.buf.clear();
+                self.in_sysex = true;
+            }
+            0xF7 if self.in_sysex => {
+                self.in_sysex = false;
+                return Some(std::mem::take(&mut self.buf));
+            }
+            0xF8..=0xFF => {}
+            0x80..=0xF7 => self.in_sysex = false,
+            _ if self.in_sysex => self.buf.push(byte),
+            _ => {}
+        }
+        None
+    }
+}
+
+// =========================================================
+//      Raw MIDI
+// =========================================================
+/// ALSA rawmidi デバイス
+/// 読み込みはスレッドで行い、揃った SysEx をチャンネルで受け取る（タイムアウトのため）
+pub struct RawMidi {
+    output: File,
+    input: Receiver<io::Result<Vec<u8>>>,
+}
+
+impl RawMidi {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let output = OpenOptions::new().write(true).open(path)?;
+        let mut input = File::open(path)?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut splitter = SysExSplitter::new();
+            let mut buf = [0u8; 256];
+            loop {
+                match input.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        for sysex in buf[..n].iter().filter_map(|b| splitter.push(*b)) {
+                            if tx.send(Ok(sysex)).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { output, input: rx })
+    }
+}
+
+impl Transport for RawMidi {
+    fn send(&mut self, sysex: &[u8]) -> io::Result<()> {
+        self.output.write_all(sysex)?;
+        self.output.flush()
+    }
+
+    fn receive(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        match self.input.recv_timeout(timeout) {
+            Ok(received) => received.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "MIDI port closed",
+            )),
+        }
+    }
+}
+
+/// 接続している QUBIT の rawmidi デバイスを探す（/proc/asound/cards のカード名で判断する）
+pub fn find_port() -> io::Result<PathBuf> {
+    let cards = fs::read_to_string("/proc/asound/cards").unwrap_or_default();
+    cards
+        .lines()
+        .filter(|line| line.contains("QUBIT"))
+        .filter_map(|line| line.split_whitespace().next()?.parse::<u32>().ok())
+        .map(|card| PathBuf::from(format!("/dev/snd/midiC{}D0", card)))
+        .find(|path| path.exists())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "QUBIT is not connected"))
+}
@@ -0,0 +1,208 @@
+//! エミュレータ（ファームウェアと同じ SysEx の処理）を相手にした設定ツールのテスト
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use qubit_cli::emulator::EmulatedDevice;
+use qubit_cli::preset::Preset;
+use qubit_cli::protocol::{Client, Error, masked_keys};
+use qubit_cli::transport::SysExSplitter;
+use qubit_core::midi::clock::{Transport, take_transport_request};
+use qubit_core::midi::config::{CMD_SET_PARAM, ConfigError};
+use qubit_core::settings::{self, NUM_PARAMS, PARAMS};
+use qubit_core::touch::key_mask::KeyMask;
+
+// 設定値は qubit_core のグローバル変数なので、テストは1つずつ行う
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+fn client() -> Client<EmulatedDevice> {
+    for param in PARAMS.iter() {
+        param.set(param.min);
+    }
+    KeyMask::new().store_manual();
+    settings::TELEMETRY.store(0, Ordering::Relaxed);
+    let mut client = Client::new(EmulatedDevice::new());
+    client.timeout = Duration::from_millis(200);
+    client
+}
+
+#[test]
+fn identify() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let version = client().identify().unwrap().version;
+    assert_eq!(version[..3], [0, 1, 0]); // qubit-core のバージョン
+}
+
+#[test]
+fn get_and_set_param() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    client.set_param(1, 0xC0).unwrap();
+    assert_eq!(client.get_param(1).unwrap(), 0xC0);
+    assert_eq!(PARAMS[1].get(), 0xC0);
+
+    match client.set_param(0, 2) {
+        Err(Error::Nak { cmd, code }) => {
+            assert_eq!(cmd, CMD_SET_PARAM);
+            assert_eq!(code, ConfigError::OutOfRange as u8);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        client.get_param(NUM_PARAMS as u8),
+        Err(Error::Nak { .. })
+    ));
+}
+
+#[test]
+fn key_mask() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    let mut mask = KeyMask::new();
+    mask.set(0, true);
+    mask.set(95, true);
+    client.set_key_mask(&mask).unwrap();
+    assert_eq!(client.get_key_mask().unwrap(), mask);
+    assert_eq!(masked_keys(&mask), [0, 95]);
+}
+
+#[test]
+fn preset_round_trip() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    client.set_param(0, 1).unwrap();
+    client.set_param(6, 15).unwrap();
+    let mut mask = KeyMask::new();
+    mask.set(40, true);
+    client.set_key_mask(&mask).unwrap();
+    let json = Preset::from_snapshot(&client.dump().unwrap())
+        .unwrap()
+        .to_json();
+
+    let mut client = self::client();
+    let mut snapshot = client.dump().unwrap();
+    Preset::from_json(&json)
+        .unwrap()
+        .apply(&mut snapshot)
+        .unwrap();
+    client.restore(&snapshot).unwrap();
+    assert_eq!(PARAMS[0].get(), 1);
+    assert_eq!(PARAMS[6].get(), 15);
+    assert_eq!(KeyMask::load_manual(), mask);
+}
+
+/// 書いていない項目は今の値のまま、間違った項目があれば何も変えない
+#[test]
+fn partial_and_bad_presets() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    let mut mask = KeyMask::new();
+    mask.set(3, true);
+    client.set_key_mask(&mask).unwrap();
+
+    let mut snapshot = client.dump().unwrap();
+    let preset = Preset::from_json(r#"{ "params": { "Contrast": 128 } }"#).unwrap();
+    preset.apply(&mut snapshot).unwrap();
+    client.restore(&snapshot).unwrap();
+    assert_eq!(PARAMS[1].get(), 128);
+    assert_eq!(KeyMask::load_manual(), mask);
+
+    let snapshot = client.dump().unwrap();
+    for json in [
+        r#"{ "params": { "Mode": 1, "Volume": 3 } }"#,
+        r#"{ "params": { "Mode": 1, "Contrast": 0 } }"#,
+        r#"{ "manual_key_mask": [96] }"#,
+    ] {
+        let mut changed = snapshot.clone();
+        assert!(
+            Preset::from_json(json)
+                .unwrap()
+                .apply(&mut changed)
+                .is_err()
+        );
+        assert_eq!(changed, snapshot, "{}", json);
+    }
+    assert!(Preset::from_json("{ \"params\": ").is_err());
+}
+
+/// パラメータの数が違うファームウェアとは、名前と ID の対応が合わないのでダンプもリストアもしない
+#[test]
+fn preset_refuses_other_param_count() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    let snapshot = client.dump().unwrap();
+    assert_eq!(snapshot.values.len(), NUM_PARAMS);
+    let preset = Preset::from_json(r#"{ "params": { "Contrast": 128 } }"#).unwrap();
+    for len in [NUM_PARAMS - 1, NUM_PARAMS + 1] {
+        let mut other = snapshot.clone();
+        other.values.resize(len, 0);
+        assert!(Preset::from_snapshot(&other).is_err());
+        let before = other.clone();
+        assert!(preset.apply(&mut other).is_err());
+        assert_eq!(other, before);
+    }
+}
+
+#[test]
+fn save_to_flash() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    client.set_param(2, 1).unwrap();
+    client.save().unwrap();
+    let saved = client.transport().flash.saved.clone().unwrap();
+    PARAMS[2].set(0);
+    assert!(settings::load_saved(&saved));
+    assert_eq!(PARAMS[2].get(), 1);
+}
+
+#[test]
+fn telemetry() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    qubit_core::TOUCH0.store(1234, Ordering::Relaxed);
+    qubit_core::TOUCH1.store(10000, Ordering::Relaxed);
+    qubit_core::PRESSURE.store(512, Ordering::Relaxed);
+    qubit_core::AD_VALUE2.store(4000, Ordering::Relaxed);
+    client.set_telemetry(true).unwrap();
+    let t = client.next_telemetry().unwrap();
+    assert_eq!(t.touches[0], Some(12.34));
+    assert_eq!(t.touches[1], None);
+    assert_eq!(t.pressure, 512);
+    assert_eq!(t.adc[2], 4000);
+    // テレメトリの間もコマンドの返信は受け取れる
+    assert_eq!(client.get_param(1).unwrap(), PARAMS[1].min);
+    client.set_telemetry(false).unwrap();
+    assert!(matches!(client.next_telemetry(), Err(Error::Timeout)));
+}
+
+#[test]
+fn clock_transport() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    client.send_transport(Transport::Start).unwrap();
+    assert_eq!(take_transport_request(), Some(Transport::Start));
+    client.send_transport(Transport::Stop).unwrap();
+    assert_eq!(take_transport_request(), Some(Transport::Stop));
+}
+
+#[test]
+fn reboot_to_bootloader() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut client = client();
+    client.reboot_to_bootloader().unwrap();
+    assert!(client.transport().flash.rebooted);
+    assert!(matches!(client.identify(), Err(Error::Io(_))));
+}
+
+#[test]
+fn sysex_splitter() {
+    let mut splitter = SysExSplitter::new();
+    let bytes = [
+        0x90, 60, 100, // SysEx の外は無視
+        0xF0, 1, 2, 0xF8, 3, 0xF7, // リアルタイムは途中でも無視
+        0xF0, 4, 0x80, 5, 0xF7, // 他のステータスで終わる
+        0xF0, 0xF7,
+    ];
+    let sysex: Vec<Vec<u8>> = bytes.iter().filter_map(|b| splitter.push(*b)).collect();
+    assert_eq!(sysex, [vec![1, 2, 3], vec![]]);
+}
@@ -0,0 +1,224 @@
+//! デバッグコンソール（USB の CDC-ACM シリアル。MIDI とは別のインターフェースなので同時に使える）
+//! ターミナルソフトで1行に1コマンド。CR か LF で実行し、BS / DEL で1文字消す。入力はエコーする
+//!
+//!   help              コマンドの一覧
+//!   keys              タッチの生データ（チャンネルごとにリファレンスとの差分 6キー、最後に読んでからの ms）
+//!   tasks [reset]     タスクごとの処理時間（回数、最新・平均・最大 us）
+//!   params            設定の一覧
+//!   get <名前>        設定の値（名前は大文字小文字を区別しない。ID でもよい）
+//!   set <名前> <値>   設定を変える（表示名か数値。保存はしない）
+//!   calibrate         タッチセンサをキャリブレーションし、リファレンスを取り直す
+//!   errors [clear]    エラーコード、I2C のエラー記録とバスリカバリの回数
+//!   log on|off        ファームウェアの log のレコードを流す
+use core::fmt::Write;
+
+use heapless::String;
+use portable_atomic::{AtomicU8, Ordering};
+
+use crate::constants::{AT42QT_KEYS_PER_DEVICE, I2C_DEVICE_ADDRS, I2C_FAULT_LABELS, TOTAL_CH};
+use crate::settings::{self, PARAMS, Param};
+use crate::timing::{TASK_NAMES, TASK_TIMING};
+use crate::touch::frame::TouchFrame;
+use crate::touch::key_mask::KeyMask;
+use crate::{CALIBRATE_REQUEST, ERROR_CODE, I2C_ERROR_LOG, I2C_RECOVERY_COUNT};
+
+pub const LINE_SIZE: usize = 64; // 1行の最大文字数
+pub const REPLY_SIZE: usize = 1024; // 1コマンドの返事の最大バイト数
+pub const ECHO_SIZE: usize = 3 * 64; // 64byte の入力に対するエコー（BS は3文字）
+pub const PROMPT: &str = "> ";
+
+pub type Line = String<LINE_SIZE>;
+pub type Reply = String<REPLY_SIZE>;
+
+// log のレコードをコンソールに流すか（ファームウェアのロガーが見る）
+pub static LOG_STREAM: AtomicU8 = AtomicU8::new(0);
+
+const HELP: &str = "help              this list\r\n\
+keys              raw touch values per channel\r\n\
+tasks [reset]     task timing (us)\r\n\
+params            all parameters\r\n\
+get <name>        parameter value\r\n\
+set <name> <val>  change a parameter (not saved)\r\n\
+calibrate         recalibrate touch sensors\r\n\
+errors [clear]    error code and I2C errors\r\n\
+log on|off        stream firmware log\r\n";
+
+// =========================================================
+//      LineEditor Class
+// =========================================================
+/// 1行の入力。エコーと BS / DEL を扱う
+pub struct LineEditor {
+    line: Line,
+    after_cr: bool, // CR LF の LF を無視する
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+            after_cr: false,
+        }
+    }
+
+    /// 1byte 入力する。エコーする文字は echo に足し、行が終われば返す
+    pub fn feed<const N: usize>(&mut self, byte: u8, echo: &mut String<N>) -> Option<Line> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => None,
+            b'\r' | b'\n' => {
+                let _ = echo.push_str("\r\n");
+                Some(core::mem::take(&mut self.line))
+            }
+            0x08 | 0x7F => {
+                if self.line.pop().is_some() {
+                    let _ = echo.push_str("\x08 \x08");
+                }
+                None
+            }
+            0x20..=0x7E => {
+                if self.line.push(byte as char).is_ok() {
+                    let _ = echo.push(byte as char);
+                }
+                None
+            }
+            _ => None, // 制御文字とエスケープシーケンスは無視する
+        }
+    }
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// =========================================================
+//      Commands
+// =========================================================
+/// 1行のコマンドを実行し、返事を reply に書く（溢れた分は捨てる）
+pub fn execute(line: &str, frame: &TouchFrame, now_us: u64, reply: &mut Reply) {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return;
+    };
+    let (arg1, arg2) = (words.next(), words.next());
+    let _ = match (command, arg1, arg2) {
+        ("help", None, None) => reply.write_str(HELP),
+        ("keys", None, None) => keys(frame, now_us, reply),
+        ("tasks", None, None) => tasks(reply),
+        ("tasks", Some("reset"), None) => {
+            TASK_TIMING.iter().for_each(|t| t.reset());
+            reply.write_str("ok\r\n")
+        }
+        ("params", None, None) => params(reply),
+        ("get", Some(name), None) => match settings::find_param(name) {
+            Some((_, param)) => show_param(param, reply),
+            None => write!(reply, "error: unknown parameter \"{}\"\r\n", name),
+        },
+        ("set", Some(name), Some(value)) => set_param(name, value, reply),
+        ("calibrate", None, None) => {
+            CALIBRATE_REQUEST.store(1, Ordering::Relaxed);
+            reply.write_str("calibrating, keep hands off the ring\r\n")
+        }
+        ("errors", None, None) => errors(reply),
+        ("errors", Some("clear"), None) => {
+            ERROR_CODE.store(0, Ordering::Relaxed);
+            I2C_ERROR_LOG
+                .iter()
+                .flatten()
+                .for_each(|count| count.store(0, Ordering::Relaxed));
+            I2C_RECOVERY_COUNT.store(0, Ordering::Relaxed);
+            reply.write_str("ok\r\n")
+        }
+        ("log", Some(state @ ("on" | "off")), None) => {
+            LOG_STREAM.store((state == "on") as u8, Ordering::Relaxed);
+            write!(reply, "log {}\r\n", state)
+        }
+        _ => write!(reply, "error: \"{}\" (type help)\r\n", line.trim()),
+    };
+}
+
+fn keys(frame: &TouchFrame, now_us: u64, reply: &mut Reply) -> core::fmt::Result {
+    let mask = KeyMask::load();
+    reply.write_str("ch  values                          age ms\r\n")?;
+    for ch in 0..TOTAL_CH {
+        let first = ch * AT42QT_KEYS_PER_DEVICE;
+        write!(reply, "{:2} ", ch)?;
+        for value in &frame.values[first..first + AT42QT_KEYS_PER_DEVICE] {
+            write!(reply, "{:5}", value)?;
+        }
+        let age_ms = now_us.saturating_sub(frame.timestamps[ch]) / 1000;
+        let masked = if mask.is_masked(first) { " masked" } else { "" };
+        write!(reply, "  {:6}{}\r\n", age_ms, masked)?;
+    }
+    Ok(())
+}
+
+fn tasks(reply: &mut Reply) -> core::fmt::Result {
+    reply.write_str("task        count   last    avg    max\r\n")?;
+    for (name, timing) in TASK_NAMES.iter().zip(TASK_TIMING.iter()) {
+        let s = timing.stats();
+        write!(
+            reply,
+            "{:<8}{:>9}{:>7}{:>7}{:>7}\r\n",
+            name, s.count, s.last_us, s.average_us, s.max_us
+        )?;
+    }
+    Ok(())
+}
+
+fn params(reply: &mut Reply) -> core::fmt::Result {
+    for (id, param) in PARAMS.iter().enumerate().filter(|(_, p)| !p.is_reserved()) {
+        write!(reply, "{:2} ", id)?;
+        show_param(param, reply)?;
+    }
+    Ok(())
+}
+
+/// 表示名のある値は、名前も表示する
+fn show_param(param: &Param, reply: &mut Reply) -> core::fmt::Result {
+    match param.label() {
+        Some(label) => write!(reply, "{} = {} ({})\r\n", param.name, param.get(), label),
+        None => write!(reply, "{} = {}\r\n", param.name, param.get()),
+    }
+}
+
+fn set_param(name: &str, value: &str, reply: &mut Reply) -> core::fmt::Result {
+    let Some((_, param)) = settings::find_param(name) else {
+        return write!(reply, "error: unknown parameter \"{}\"\r\n", name);
+    };
+    match param.parse(value) {
+        Some(v) if param.set(v) => show_param(param, reply),
+        Some(_) => write!(
+            reply,
+            "error: {} is {}-{}\r\n",
+            param.name, param.min, param.max
+        ),
+        None => write!(
+            reply,
+            "error: invalid value \"{}\" for {}\r\n",
+            value, param.name
+        ),
+    }
+}
+
+fn errors(reply: &mut Reply) -> core::fmt::Result {
+    write!(
+        reply,
+        "error code {}\r\ni2c recovery {}\r\naddr",
+        ERROR_CODE.load(Ordering::Relaxed),
+        I2C_RECOVERY_COUNT.load(Ordering::Relaxed)
+    )?;
+    for label in I2C_FAULT_LABELS {
+        write!(reply, "{:>7}", label)?;
+    }
+    reply.write_str("\r\n")?;
+    for (addr, counts) in I2C_DEVICE_ADDRS.iter().zip(I2C_ERROR_LOG.iter()) {
+        write!(reply, "0x{:02X}", addr)?;
+        for count in counts {
+            write!(reply, "{:>7}", count.load(Ordering::Relaxed))?;
+        }
+        reply.write_str("\r\n")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//  Created by Hasebe Masahiko on 2026/02/15.
+//  Copyright (c) 2026 Hasebe Masahiko.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+
+pub const CORE1_STACK_SIZE: usize = 8192; // コア1のスタックサイズ
+
+// Message for Ringled
+pub const RINGLED_MESSAGE_SIZE: usize = 64; // バグ対策: 送受信の瞬間的バーストで詰まりにくくする
+pub const RINGLED_CMD_NONE: u8 = 0x00; // コマンドなし
+pub const RINGLED_CMD_TX_ON: u8 = 0x90; // 送信用Note Onコマンド
+pub const RINGLED_CMD_TX_OFF: u8 = 0x80; // 送信用Note Offコマンド
+pub const RINGLED_CMD_TX_MOVED: u8 = 0xa0; // 送信用Note Moveコマンド(NoteOff)
+pub const RINGLED_CMD_RX_ON: u8 = 0x9f; // 受信用Note Onコマンド
+pub const RINGLED_CMD_RX_OFF: u8 = 0x8f; // 受信用Note Offコマンド
+
+pub const PCA9544_NUM_CHANNELS: u8 = 4; // PCA9544のチャネル数
+pub const PCA9544_NUM_DEVICES: u8 = 4; // PCA9544の台数
+pub const AT42QT_KEYS_PER_DEVICE: usize = 6; // AT42QT1070
+
+pub const TOTAL_CH: usize = (PCA9544_NUM_CHANNELS * PCA9544_NUM_DEVICES) as usize;
+pub const TOTAL_QT_KEYS: usize = TOTAL_CH * AT42QT_KEYS_PER_DEVICE;
+pub const NUM_LEDS: usize = TOTAL_QT_KEYS;
+
+// Key Mask (故障したチップのキーを無効化する)
+pub const KEY_MASK_WORDS: usize = TOTAL_QT_KEYS.div_ceil(32); // 1bit/key
+pub const KEY_MASK_BYTES: usize = KEY_MASK_WORDS * 4;
+pub const CHIP_FAIL_LIMIT: u8 = 3; // 連続してこの回数読み込みに失敗したらチップの6キーをマスク
+pub const CHIP_RECOVER_LIMIT: u8 = 50; // 連続してこの回数読み込みに成功したらマスクを解除
+pub const FAILED_CHIP_POLL_INTERVAL: u32 = 8; // マスク中のチップはこの回数に一度だけ読む
+pub const MANUAL_MASKED_KEYS: &[usize] = &[]; // 手動でマスクするキー番号（個体ごとの不調キー）
+
+// Touch Scan Scheduler
+pub const SCAN_ACTIVE_THRESHOLD: u16 = 10; // この値を超えるキーがあるチャンネルはタッチ中とみなす
+pub const SCAN_HOT_PASSES: u8 = 20; // タッチ中のチャンネルと両隣を毎回読み続けるスキャン回数
+pub const SCAN_IDLE_PER_PASS: usize = 4; // タッチのないチャンネルを1回に何チャンネル読むか
+
+// I2C Bus
+pub const I2C_FREQUENCY: u32 = 400_000;
+pub const I2C_TIMEOUT_MS: u64 = 5; // 1トランザクションのタイムアウト
+pub const I2C_RETRIES: u8 = 1; // エラー時のリトライ回数
+pub const I2C_BACKOFF_START: u8 = 3; // 連続してこの回数エラーになったデバイスは休止させる
+pub const I2C_BACKOFF_BASE_MS: u64 = 20; // 休止時間(連続エラーごとに倍)
+pub const I2C_BACKOFF_MAX_SHIFT: u8 = 5; // 休止時間は最大 20ms << 5 = 640ms
+pub const I2C_BUS_FAULT_LIMIT: u16 = 4; // バスレベルのエラーがこの回数続いたらバスリカバリ
+pub const I2C_FAIL_STREAK_LIMIT: u16 = 48; // 全デバイスでエラーがこの回数続いたらバスリカバリ
+pub const I2C_DEVICE_COUNT: usize = 6; // エラーを記録するデバイス数
+pub const I2C_FAULT_KINDS: usize = 5; // エラーの種類数
+pub const I2C_DEVICE_ADDRS: [u8; I2C_DEVICE_COUNT] = [0x70, 0x71, 0x72, 0x73, 0x1B, 0x3C]; // PCA9544 x4, AT42QT1070, OLED
+pub const I2C_FAULT_LABELS: [&str; I2C_FAULT_KINDS] = ["N", "A", "B", "T", "O"]; // NACK, Arbitration, Bus, Timeout, Other
+
+// OLED
+pub const OLED_FLUSH_BUDGET_US: u64 = 1500; // I2C Task の1周で OLED 転送に使う時間の目安
+
+// MIDI Note Number
+pub const KEYBD_LO: u8 = 21; // A0
+
+// MIDI RX
+pub const MIDI_SYSEX_BUFFER_SIZE: usize = 256; // 受信する SysEx の最大長（F0, F7 を除く）
+pub const MIDI_SYSEX_TX_MAX: usize = 384; // 送信する SysEx の最大長（F0, F7 を含む）
+
+// MIDI TX
+pub const MIDI_TX_QUEUE_SIZE: usize = 32; // 送信キューに入るメッセージ数
+pub const MIDI_TX_BATCH: usize = 16; // 1回の USB 転送（64byte）に入るパケット数
+pub const REPORT_QUEUE_SIZE: usize = 8; // 記録とテレメトリの送信を待てるフレーム数（80ms）
+
+// Console (USB CDC-ACM)
+pub const LOG_PIPE_SIZE: usize = 1024; // コンソールに送る前の log のレコードをためるバイト数
+pub const LOG_LINE_SIZE: usize = 128; // log のレコード1行の最大長
+pub const CALIBRATION_WAIT_MS: u64 = 300; // タッチセンサのキャリブレーションが終わるのを待つ時間
+
+pub const MAX_TOUCH_POINTS: usize = 4; // Maximum number of touch points to track
+pub const MAX_TOUCH_POINTS_U8: u8 = MAX_TOUCH_POINTS as u8;
+
+pub const MAX_ADC_CHANNELS: usize = 4; // ADCのチャンネル数
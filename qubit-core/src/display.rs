@@ -0,0 +1,107 @@
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+pub const DISPLAY_WIDTH: usize = 128;
+pub const DISPLAY_HEIGHT: usize = 64;
+pub const DISPLAY_PAGES: usize = DISPLAY_HEIGHT / 8;
+pub const BUFFER_SIZE: usize = (DISPLAY_WIDTH * DISPLAY_HEIGHT) / 8;
+
+/// フレームバッファ（描画用、1024バイト）
+/// SSD1306 と同じ並び: 1バイトが縦8ピクセル、ページ（8行）ごとに横128バイト
+#[derive(Debug)]
+pub struct OledBuffer {
+    pub data: [u8; BUFFER_SIZE],
+}
+
+impl OledBuffer {
+    /// 新規バッファを作成（ゼロクリア）
+    pub fn new() -> Self {
+        Self {
+            data: [0; BUFFER_SIZE],
+        }
+    }
+
+    /// バッファをクリア
+    pub fn clear(&mut self) {
+        self.data.fill(0);
+    }
+}
+
+impl Default for OledBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// embedded-graphics の DrawTarget トレイト実装（OledBuffer用）
+impl DrawTarget for OledBuffer {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(coord, color) in pixels.into_iter() {
+            let x = coord.x;
+            let y = coord.y;
+
+            if x >= 0 && x < DISPLAY_WIDTH as i32 && y >= 0 && y < DISPLAY_HEIGHT as i32 {
+                let x = x as usize;
+                let y = y as usize;
+                let byte_idx = x + (y / 8) * DISPLAY_WIDTH;
+                let bit_idx = y % 8;
+
+                if byte_idx < BUFFER_SIZE {
+                    match color {
+                        BinaryColor::On => self.data[byte_idx] |= 1 << bit_idx,
+                        BinaryColor::Off => self.data[byte_idx] &= !(1 << bit_idx),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for OledBuffer {
+    fn size(&self) -> Size {
+        Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32)
+    }
+}
+
+/// OLEDパネルの種類
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Panel {
+    Ssd1306,
+    Sh1106, // 132カラムのRAMの中央128カラムを表示する互換品
+}
+
+/// 実行中に変更できる表示設定
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OledConfig {
+    pub panel: Panel,
+    pub contrast: u8,
+    pub dim: bool,    // 暗いステージ用に、さらに暗くする
+    pub invert: bool, // 白黒反転
+    pub flip: bool,   // 180度回転
+    pub sleep: bool,  // 表示オフ
+}
+
+impl OledConfig {
+    pub const fn new() -> Self {
+        Self {
+            panel: Panel::Ssd1306,
+            contrast: 0xFF,
+            dim: false,
+            invert: false,
+            flip: false,
+            sleep: false,
+        }
+    }
+}
+
+impl Default for OledConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,150 @@
+//! ハードウェア抽象化: tasks のタスクはこれらのトレイトだけに依存する
+//! RP2350 向けの実装はファームウェアの board.rs、ホスト向けの実装はシミュレータやテストが持つ
+#![allow(async_fn_in_trait)]
+
+use crate::constants::NUM_LEDS;
+use crate::display::OledBuffer;
+use crate::midi::tx_queue::{TxBatch, TxMessage};
+use crate::touch::frame::TouchFrame;
+use crate::touch::recording::FrameRecord;
+
+/// RGBW LED の1ピクセル
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rgbw {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub w: u8,
+}
+
+/// 時刻と待ち時間
+pub trait Clock {
+    /// 起動からの経過時間(us)。TouchFrame の timestamps と同じ時間軸
+    fn now_us(&self) -> u64;
+    /// 指定時刻(us)まで待つ
+    async fn delay_until(&self, deadline_us: u64);
+
+    async fn delay_ms(&self, ms: u64) {
+        self.delay_until(self.now_us() + ms * 1000).await;
+    }
+}
+
+/// タッチセンサの生データ（1フレーム分）の取得元
+pub trait TouchFrameSource {
+    /// 最新のフレームを返す（同じフレームを続けて返すこともある）
+    async fn read_frame(&mut self) -> TouchFrame;
+}
+
+/// RingLED の出力先
+pub trait PixelSink {
+    type Error;
+    async fn write(&mut self, pixels: &[Rgbw; NUM_LEDS]) -> Result<(), Self::Error>;
+}
+
+/// OLED のフレームバッファの出力先（ダブルバッファリング）
+pub trait FrameBufferSink {
+    /// 描画に使う空きバッファを受け取る
+    async fn acquire(&mut self) -> OledBuffer;
+    /// 描画済みのバッファを表示に回す
+    async fn present(&mut self, buffer: OledBuffer);
+}
+
+/// MIDI の送信先（USB-MIDI の4バイトパケット単位）
+pub trait MidiSink {
+    type Error;
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error>;
+
+    /// 続けて送る。SysEx のように、途中に他のタスクのメッセージを挟めないもの用
+    async fn send_all(&mut self, packets: &[[u8; 4]]) -> Result<(), Self::Error> {
+        for packet in packets {
+            self.send(*packet).await?;
+        }
+        Ok(())
+    }
+}
+
+/// MIDI 送信キューの入口（midi::tx_queue。待たない）
+pub trait MidiQueueSink {
+    /// キューが満杯で、方針に従って何かを捨てたら false
+    fn post(&mut self, message: TxMessage) -> bool;
+}
+
+/// MIDI 送信キューの出口（midi_tx_task）
+pub trait MidiQueueSource {
+    /// キューにメッセージが入るまで待ち、1回の USB 転送に入るだけ取り出す
+    async fn take_batch(&mut self, batch: &mut TxBatch);
+}
+
+/// タッチタスクが1フレームごとに送る SysEx（送るのは report_task）
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub header: bool,               // 記録の始まり（フレームの前に .qtr のヘッダを送る）
+    pub frame: Option<FrameRecord>, // 記録中のフレーム
+    pub telemetry: bool,            // テレメトリ（値は送るときに読む）
+}
+
+/// 記録とテレメトリの送信キューの入口（待たない）
+pub trait ReportSink {
+    /// キューが満杯で捨てたら false
+    fn post(&mut self, report: Report) -> bool;
+}
+
+/// 記録とテレメトリの送信キューの出口（report_task）
+pub trait ReportSource {
+    async fn receive(&mut self) -> Report;
+}
+
+/// MIDI の受信元（USB-MIDI の4バイトパケットが並んだもの）
+pub trait MidiSource {
+    type Error;
+    /// 受信したバイト数を返す
+    async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// シリアル（DIN MIDI の UART）の送信先
+pub trait SerialSink {
+    type Error;
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// シリアルの受信元
+pub trait SerialSource {
+    type Error;
+    /// 受信したバイト数を返す（1byte 以上届くまで待つ）
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// 設定の保存先（フラッシュなど）。起動時の読み込みは各ボードで行う
+pub trait SettingsStore {
+    type Error;
+    async fn save(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// 再起動（ファームウェアの書き込み用）
+pub trait Reboot {
+    /// USB のブートローダーで再起動する（実機では戻らない）
+    fn reboot_to_bootloader(&mut self);
+}
+
+/// 2つのスイッチの状態（押されていれば true）
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Switches {
+    pub right: bool,
+    pub left: bool,
+}
+
+/// スイッチ入力
+pub trait SwitchInput {
+    fn read(&mut self) -> Switches;
+}
+
+/// RingLED へのイベント（cmd, location）の送り先
+pub trait LedEventSink {
+    /// キューが満杯なら false（待たない）
+    fn post(&mut self, cmd: u8, location: f32) -> bool;
+}
+
+/// RingLED へのイベントの受け取り元
+pub trait LedEventSource {
+    fn poll(&mut self) -> Option<(u8, f32)>;
+}
@@ -0,0 +1,65 @@
+//  Created by Hasebe Masahiko on 2026/02/11.
+//  Copyright (c) 2026 Hasebe Masahiko.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+//! Loopian::QUBIT のハードウェアに依存しない部分
+//! ファームウェアのタスクと、ホスト上のシミュレータ・テストの両方から使う
+#![no_std]
+
+pub mod console;
+pub mod constants;
+pub mod display;
+pub mod hal;
+pub mod midi;
+pub mod settings;
+pub mod tasks;
+pub mod timing;
+pub mod touch;
+pub mod ui;
+
+use portable_atomic::{AtomicI32, AtomicU8, AtomicU16, AtomicU32, AtomicU64};
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Global static variables
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+// エラーコード（一覧はファームウェアの main.rs を参照）
+pub static ERROR_CODE: AtomicU8 = AtomicU8::new(0);
+
+// 表示用変数
+pub static POINT0: AtomicU16 = AtomicU16::new(0);
+pub static POINT1: AtomicU16 = AtomicU16::new(0);
+pub static POINT2: AtomicU16 = AtomicU16::new(0);
+pub static POINT3: AtomicU16 = AtomicU16::new(0);
+pub static TOUCH0: AtomicI32 = AtomicI32::new(10000);
+pub static TOUCH1: AtomicI32 = AtomicI32::new(10000);
+pub static TOUCH2: AtomicI32 = AtomicI32::new(10000);
+pub static TOUCH3: AtomicI32 = AtomicI32::new(10000);
+pub static ELAPSED_TIME: AtomicU64 = AtomicU64::new(0); // タッチスキャンの経過時間（us）
+pub static AD_VALUE0: AtomicU32 = AtomicU32::new(0); // ADCの値(A0)
+pub static AD_VALUE1: AtomicU32 = AtomicU32::new(0); // ADCの値(A1)
+pub static AD_VALUE2: AtomicU32 = AtomicU32::new(0); // ADCの値(B0)
+pub static AD_VALUE3: AtomicU32 = AtomicU32::new(0); // ADCの値(B1)
+pub static PRESSURE: AtomicU32 = AtomicU32::new(0); // 圧力計算結果
+pub static WORK_MODE: AtomicU8 = AtomicU8::new(0); // 動作モード（Piano/Violin）
+
+// スキャンレート（チャンネルごとの読み込み回数/秒、フレーム数/秒）と、フレーム内の最も古いデータの経過時間
+pub static SCAN_RATE: [AtomicU16; constants::TOTAL_CH] =
+    [const { AtomicU16::new(0) }; constants::TOTAL_CH];
+pub static SCAN_FRAME_RATE: AtomicU16 = AtomicU16::new(0);
+pub static SCAN_MAX_AGE: AtomicU32 = AtomicU32::new(0); // us
+
+// キーマスク（1bit/key）: 読み込み失敗による自動マスクと、手動マスク
+pub static KEY_MASK_AUTO: [AtomicU32; constants::KEY_MASK_WORDS] =
+    [const { AtomicU32::new(0) }; constants::KEY_MASK_WORDS];
+pub static KEY_MASK_MANUAL: [AtomicU32; constants::KEY_MASK_WORDS] =
+    [const { AtomicU32::new(0) }; constants::KEY_MASK_WORDS];
+
+// I2Cエラー記録（デバイスごと・エラー種別ごとの回数）とバスリカバリ回数
+pub static I2C_ERROR_LOG: [[AtomicU32; constants::I2C_FAULT_KINDS]; constants::I2C_DEVICE_COUNT] =
+    [const { [const { AtomicU32::new(0) }; constants::I2C_FAULT_KINDS] };
+        constants::I2C_DEVICE_COUNT];
+pub static I2C_RECOVERY_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// タッチセンサのキャリブレーションの要求（コンソールから。Core1 の I2C タスクが行って 0 に戻す）
+pub static CALIBRATE_REQUEST: AtomicU8 = AtomicU8::new(0);
@@ -0,0 +1,327 @@
+//! MIDI クロック（外部クロックへの同期と、内部クロックの生成）
+//!
+//! 受信した Clock（24/四分音符）・Start・Stop・Continue・Song Position から、
+//! テンポと曲の位置（拍・小節の中の位相）を求める
+//! midi_rx_task（外部クロック）か midi_clock_task（内部クロック）が ClockState をグローバル変数に書き、
+//! RingLED などは ClockState::load() で読む
+use portable_atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+
+use crate::midi::parser::MidiMessage;
+
+pub const CLOCKS_PER_BEAT: u32 = 24;
+pub const BEATS_PER_BAR: u32 = 4;
+const CLOCKS_PER_SIXTEENTH: u32 = CLOCKS_PER_BEAT / 4; // Song Position の単位
+const CLOCK_TIMEOUT_US: u64 = 500_000; // これより Clock が来なければ同期を外す（約 5BPM 以下）
+const WINDOW: usize = CLOCKS_PER_BEAT as usize + 1; // 1拍分の Clock の間隔を平均してテンポにする
+
+// 受信したクロックの状態（midi_rx_task が書く）
+static CLOCK_FLAGS: AtomicU8 = AtomicU8::new(0); // bit0: 再生中, bit1: Start の後に Clock を受信した
+static CLOCK_POSITION: AtomicU32 = AtomicU32::new(0);
+static CLOCK_LAST_US: AtomicU64 = AtomicU64::new(0);
+static CLOCK_INTERVAL_US: AtomicU32 = AtomicU32::new(0);
+
+/// 曲の中の位置（拍の単位）
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BeatPhase {
+    pub beat: u32,  // 曲の頭からの拍
+    pub phase: f32, // 拍の中の位相（0.0-1.0）
+}
+
+impl BeatPhase {
+    pub fn bar(&self) -> u32 {
+        self.beat / BEATS_PER_BAR
+    }
+
+    /// 小節の中の拍（0 が1拍目）
+    pub fn beat_in_bar(&self) -> u32 {
+        self.beat % BEATS_PER_BAR
+    }
+
+    /// 小節の中の位相（0.0-1.0）
+    pub fn bar_phase(&self) -> f32 {
+        (self.beat_in_bar() as f32 + self.phase) / BEATS_PER_BAR as f32
+    }
+}
+
+/// 他のタスクに渡すクロックの状態
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ClockState {
+    pub running: bool,
+    pub ticked: bool,     // Start/Continue/Song Position の後に Clock を受信した
+    pub position: u32,    // 最後の Clock のティック（ticked でなければ次の Clock のティック）
+    pub last_us: u64,     // 最後の Clock の受信時刻
+    pub interval_us: u32, // 平滑化した Clock の間隔（0: まだわからない）
+}
+
+impl ClockState {
+    pub fn load() -> Self {
+        let flags = CLOCK_FLAGS.load(Ordering::Relaxed);
+        Self {
+            running: flags & 1 != 0,
+            ticked: flags & 2 != 0,
+            position: CLOCK_POSITION.load(Ordering::Relaxed),
+            last_us: CLOCK_LAST_US.load(Ordering::Relaxed),
+            interval_us: CLOCK_INTERVAL_US.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn store(&self) {
+        CLOCK_POSITION.store(self.position, Ordering::Relaxed);
+        CLOCK_LAST_US.store(self.last_us, Ordering::Relaxed);
+        CLOCK_INTERVAL_US.store(self.interval_us, Ordering::Relaxed);
+        CLOCK_FLAGS.store(
+            self.running as u8 | (self.ticked as u8) << 1,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Clock を受信し続けているか
+    pub fn is_synced(&self, now_us: u64) -> bool {
+        self.interval_us != 0 && now_us.saturating_sub(self.last_us) < CLOCK_TIMEOUT_US
+    }
+
+    /// テンポ（BPM）。Clock が止まっていれば None
+    pub fn tempo_bpm(&self, now_us: u64) -> Option<f32> {
+        if !self.is_synced(now_us) {
+            return None;
+        }
+        Some(60_000_000.0 / (self.interval_us * CLOCKS_PER_BEAT) as f32)
+    }
+
+    /// Song Position（16分音符の単位）
+    pub fn song_position(&self) -> u32 {
+        (self.position + self.ticked as u32) / CLOCKS_PER_SIXTEENTH
+    }
+
+    /// 今の拍と位相。再生中でなければ None
+    /// Clock の間は平滑化した間隔で補間する（次の Clock の位置は越えない）
+    pub fn beat(&self, now_us: u64) -> Option<BeatPhase> {
+        if !self.running || (self.ticked && !self.is_synced(now_us)) {
+            return None;
+        }
+        let mut frac = 0.0;
+        if self.ticked && self.interval_us != 0 {
+            frac =
+                (now_us.saturating_sub(self.last_us) as f32 / self.interval_us as f32).min(0.999);
+        }
+        let tick = self.position % CLOCKS_PER_BEAT;
+        Some(BeatPhase {
+            beat: self.position / CLOCKS_PER_BEAT,
+            phase: (tick as f32 + frac) / CLOCKS_PER_BEAT as f32,
+        })
+    }
+}
+
+// =========================================================
+//      Clock Receiver
+// =========================================================
+/// 受信したリアルタイムメッセージからクロックの状態を作る
+pub struct MidiClock {
+    state: ClockState,
+    history: [u64; WINDOW], // 最近の Clock の受信時刻（リングバッファ）
+    count: usize,           // history の有効な数
+    head: usize,            // 次に書く位置
+}
+
+impl MidiClock {
+    pub fn new() -> Self {
+        Self {
+            state: ClockState::default(),
+            history: [0; WINDOW],
+            count: 0,
+            head: 0,
+        }
+    }
+
+    pub fn state(&self) -> ClockState {
+        self.state
+    }
+
+    /// クロックに関係するメッセージなら処理して true
+    pub fn receive(&mut self, message: &MidiMessage, now_us: u64) -> bool {
+        match message {
+            MidiMessage::Clock => self.tick(now_us),
+            MidiMessage::Start => {
+                self.state.running = true;
+                self.state.ticked = false;
+                self.state.position = 0;
+            }
+            MidiMessage::Continue => {
+                self.rewind_to_next_tick();
+                self.state.running = true;
+            }
+            MidiMessage::Stop => self.state.running = false,
+            MidiMessage::SongPosition(sixteenths) => {
+                self.state.ticked = false;
+                self.state.position = *sixteenths as u32 * CLOCKS_PER_SIXTEENTH;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// 次の Clock の位置を position にする（Continue はそこから続ける）
+    fn rewind_to_next_tick(&mut self) {
+        if self.state.ticked {
+            self.state.ticked = false;
+            self.state.position = self.state.position.wrapping_add(1);
+        }
+    }
+
+    fn tick(&mut self, now_us: u64) {
+        if now_us.saturating_sub(self.state.last_us) >= CLOCK_TIMEOUT_US {
+            self.count = 0; // 途切れた後は測り直す
+        }
+        self.measure_interval(now_us);
+        self.state.last_us = now_us;
+        if self.state.running {
+            if self.state.ticked {
+                self.state.position = self.state.position.wrapping_add(1);
+            }
+            self.state.ticked = true;
+        }
+    }
+
+    /// USB では Clock がまとめて届くこともあるので、1拍分の平均の間隔を使う
+    fn measure_interval(&mut self, now_us: u64) {
+        self.history[self.head] = now_us;
+        self.head = (self.head + 1) % WINDOW;
+        self.count = (self.count + 1).min(WINDOW);
+        if self.count < 2 {
+            return;
+        }
+        let oldest = self.history[(self.head + WINDOW - self.count) % WINDOW];
+        let interval = (now_us - oldest) / (self.count as u64 - 1);
+        // 間隔が 0 だと「まだわからない」になるので 1us にする
+        self.state.interval_us = interval.clamp(1, u32::MAX as u64) as u32;
+    }
+}
+
+impl Default for MidiClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// =========================================================
+//      Clock Master
+// =========================================================
+/// 内部クロックのスタート・ストップの要求（UI と SysEx から。midi_clock_task が次の Clock で送る）
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Transport {
+    Stop = 1,
+    Start = 2,
+    Continue = 3,
+}
+
+static TRANSPORT_REQUEST: AtomicU8 = AtomicU8::new(0);
+
+pub fn request_transport(transport: Transport) {
+    TRANSPORT_REQUEST.store(transport as u8, Ordering::Relaxed);
+}
+
+/// 再生中なら止め、止まっていれば続きから再生する
+pub fn toggle_transport() {
+    if ClockState::load().running {
+        request_transport(Transport::Stop);
+    } else {
+        request_transport(Transport::Continue);
+    }
+}
+
+pub fn take_transport_request() -> Option<Transport> {
+    match TRANSPORT_REQUEST.swap(0, Ordering::Relaxed) {
+        1 => Some(Transport::Stop),
+        2 => Some(Transport::Start),
+        3 => Some(Transport::Continue),
+        _ => None,
+    }
+}
+
+/// 内部クロックの Clock の時刻
+/// テンポを変えた時点を基準にして n 番目の時刻を毎回計算するので、誤差がたまらない
+pub struct ClockMaster {
+    anchor_us: u64, // テンポを変えた後の最初の Clock の時刻
+    ticks: u64,     // anchor_us から送った Clock の数
+    bpm: u8,
+}
+
+impl ClockMaster {
+    pub fn new(start_us: u64, bpm: u8) -> Self {
+        Self {
+            anchor_us: start_us,
+            ticks: 0,
+            bpm: bpm.max(1),
+        }
+    }
+
+    pub fn bpm(&self) -> u8 {
+        self.bpm
+    }
+
+    /// 次の Clock を送る時刻
+    pub fn next_tick_us(&self) -> u64 {
+        self.anchor_us + self.ticks * 60_000_000 / (self.bpm as u64 * CLOCKS_PER_BEAT as u64)
+    }
+
+    /// Clock を1つ送った
+    pub fn advance(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// テンポの変更は次の Clock から
+    pub fn set_tempo(&mut self, bpm: u8) {
+        let bpm = bpm.max(1);
+        if bpm != self.bpm {
+            self.anchor_us = self.next_tick_us();
+            self.ticks = 0;
+            self.bpm = bpm;
+        }
+    }
+}
+
+// =========================================================
+//      Tap Tempo
+// =========================================================
+const TAP_TIMEOUT_US: u64 = 2_000_000; // これより間が空いたら新しいタップとして数え直す
+const MAX_TAPS: usize = 8; // 最近のタップだけで平均する
+
+/// タップの間隔の平均からテンポを求める
+pub struct TapTempo {
+    taps: [u64; MAX_TAPS], // タップの時刻（リングバッファ）
+    count: usize,
+    head: usize,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        Self {
+            taps: [0; MAX_TAPS],
+            count: 0,
+            head: 0,
+        }
+    }
+
+    /// タップした。2回目からテンポ（BPM）を返す
+    pub fn tap(&mut self, now_us: u64) -> Option<f32> {
+        let last = self.taps[(self.head + MAX_TAPS - 1) % MAX_TAPS];
+        if self.count > 0 && now_us.saturating_sub(last) > TAP_TIMEOUT_US {
+            self.count = 0;
+        }
+        self.taps[self.head] = now_us;
+        self.head = (self.head + 1) % MAX_TAPS;
+        self.count = (self.count + 1).min(MAX_TAPS);
+        let first = self.taps[(self.head + MAX_TAPS - self.count) % MAX_TAPS];
+        if self.count < 2 || now_us <= first {
+            return None;
+        }
+        Some(60_000_000.0 * (self.count - 1) as f32 / (now_us - first) as f32)
+    }
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
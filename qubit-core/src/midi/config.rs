@@ -0,0 +1,316 @@
+//! SysEx による設定の読み書き（ドライバ不要で、USB-MIDI だけで設定できる）
+//!
+//! 機種の識別には USB の VID/PID（0x1209/0x3690）を使う。それぞれ 14bit を 7bit × 2（下位から）にする
+//!
+//!   F0 7D 09 24 10 6D <コマンド> <データ...> F7
+//!
+//! 値（0-255）は 7bit × 2（下位7bit, 上位1bit）、パラメータIDは settings::PARAMS の添字
+//! 使わなくなった ID（予約）は UnknownParam。ダンプでは 0 を入れ、リストアでは値を無視する
+//! 手動キーマスク（12byte）は recording::pack7 で 7bit に詰め直して 14byte にする
+//!
+//!   コマンド                  データ                   返信
+//!   0x01 パラメータ取得        id                       0x11 id 値
+//!   0x02 パラメータ設定        id 値                    ACK
+//!   0x03 ダンプ                -                        0x13 個数 値×個数 キーマスク
+//!   0x04 リストア              個数 値×個数 キーマスク   ACK（ダンプの返信のデータをそのまま送る）
+//!   0x05 フラッシュに保存      -                        ACK
+//!   0x06 キーマスク取得        -                        0x16 キーマスク
+//!   0x07 キーマスク設定        キーマスク               ACK
+//!   0x08 テレメトリ            0: 停止 / 1: 開始          ACK（開始すると 0x18 を 50ms ごとに送る）
+//!   0x09 ブートローダーで再起動 -                        ACK（返信の後に再起動する）
+//!   0x0A 内部クロックの操作     0: ストップ / 1: スタート / 2: コンティニュー   ACK（Clock が Int のときに送る）
+//!
+//!   内部クロックのテンポと、内部/外部の切り替えはパラメータ（Tempo, Clock）
+//!   0x30-0x32 は Loopian の状態の表示（midi::loopian）で、返信しない
+//!   0x40-0x44 はホストからの RingLED のフレーム（ui::host_frame）で、返信しない
+//!   0x48-0x4B はホストからの OLED のページ（ui::host_page）で、返信しない
+//!
+//!   0x18 テレメトリ（デバイスから）: タッチ位置 ×4 | 圧力 | ADC ×4（すべて 14bit を 7bit × 2）
+//!        タッチ位置はキー番号 × 100、10000 はタッチなし
+//!
+//!   ACK: 0x7E <コマンド>    NAK: 0x7F <コマンド> <エラー（ConfigError）>
+//!   コマンドのないもの（ヘッダだけ）には NAK: 0x7F 0x00 0x06
+//!
+//! Universal Identity Request（F0 7E <id> 06 01 F7）には Identity Reply を返す
+//!   id は 0x7F（全機器）か、この機器の id（出力チャンネル - 1、0-15）のときだけ
+//!   F0 7E <この機器の id> 06 02 7D 09 24 10 6D <バージョン 4byte> F7
+use heapless::Vec;
+
+use crate::constants::KEY_MASK_BYTES;
+use portable_atomic::Ordering;
+
+use crate::hal::{MidiSink, Reboot, SettingsStore};
+use crate::midi::clock::{Transport, request_transport};
+use crate::midi::parser::{SysexTxError, send_sysex};
+use crate::settings::{self, NUM_PARAMS, PARAMS};
+use crate::touch::key_mask::KeyMask;
+use crate::touch::recording::{pack7, packed_len, unpack7};
+use crate::{AD_VALUE0, AD_VALUE1, AD_VALUE2, AD_VALUE3, PRESSURE, TOUCH0, TOUCH1, TOUCH2, TOUCH3};
+
+pub const USB_VID: u16 = 0x1209;
+pub const USB_PID: u16 = 0x3690;
+pub const MANUFACTURER_ID: u8 = 0x7D; // 非営利・開発用
+
+/// F0 の後に続くヘッダ
+pub const CONFIG_HEADER: [u8; 5] = [
+    MANUFACTURER_ID,
+    (USB_VID & 0x7F) as u8,
+    (USB_VID >> 7) as u8,
+    (USB_PID & 0x7F) as u8,
+    (USB_PID >> 7) as u8,
+];
+
+pub const CMD_GET_PARAM: u8 = 0x01;
+pub const CMD_SET_PARAM: u8 = 0x02;
+pub const CMD_DUMP: u8 = 0x03;
+pub const CMD_RESTORE: u8 = 0x04;
+pub const CMD_SAVE: u8 = 0x05;
+pub const CMD_GET_KEY_MASK: u8 = 0x06;
+pub const CMD_SET_KEY_MASK: u8 = 0x07;
+pub const CMD_TELEMETRY: u8 = 0x08;
+pub const CMD_REBOOT_BOOTLOADER: u8 = 0x09;
+pub const CMD_TRANSPORT: u8 = 0x0A;
+pub const TELEMETRY_REPORT: u8 = 0x18;
+pub const REPLY_FLAG: u8 = 0x10; // データを返すコマンドの返信は コマンド | 0x10
+pub const ACK: u8 = 0x7E;
+pub const NAK: u8 = 0x7F;
+
+const PACKED_MASK_SIZE: usize = packed_len(KEY_MASK_BYTES);
+const DUMP_SIZE: usize = 1 + NUM_PARAMS * 2 + PACKED_MASK_SIZE;
+const REPLY_SIZE: usize = 1 + CONFIG_HEADER.len() + 1 + DUMP_SIZE + 1;
+pub const TELEMETRY_VALUES: usize = 9;
+pub const TELEMETRY_SYSEX_SIZE: usize = 1 + CONFIG_HEADER.len() + 1 + TELEMETRY_VALUES * 2 + 1;
+
+/// NAK で返すエラー
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ConfigError {
+    UnknownCommand = 0x01,
+    BadLength = 0x02, // データの長さが違う
+    UnknownParam = 0x03,
+    OutOfRange = 0x04, // 値が範囲外（リストアでは1つでも範囲外なら何も変えない）
+    SaveFailed = 0x05,
+    NoCommand = 0x06, // ヘッダだけでコマンドがない
+}
+
+/// Identity Request の宛先として受け付ける id（0x7F は全機器）
+pub const ALL_CALL: u8 = 0x7F;
+
+/// この機器の id（出力チャンネル - 1）
+pub fn device_id() -> u8 {
+    settings::MIDI_CHANNEL
+        .load(Ordering::Relaxed)
+        .saturating_sub(1)
+}
+
+/// ファームウェアのバージョン（Identity Reply 用）
+fn version() -> [u8; 4] {
+    let mut version = [0u8; 4];
+    for (v, part) in version.iter_mut().zip(env!("CARGO_PKG_VERSION").split('.')) {
+        *v = part.parse::<u8>().unwrap_or(0) & 0x7F;
+    }
+    version
+}
+
+fn push_value(reply: &mut Vec<u8, REPLY_SIZE>, value: u8) {
+    let _ = reply.push(value & 0x7F);
+    let _ = reply.push(value >> 7);
+}
+
+fn value_at(data: &[u8]) -> u8 {
+    data[0] | (data[1] & 1) << 7
+}
+
+fn push_key_mask(reply: &mut Vec<u8, REPLY_SIZE>) {
+    let mut packed = [0u8; PACKED_MASK_SIZE];
+    pack7(&KeyMask::load_manual().to_bytes(), &mut packed);
+    let _ = reply.extend_from_slice(&packed);
+}
+
+fn key_mask_of(packed: &[u8]) -> KeyMask {
+    let mut bytes = [0u8; KEY_MASK_BYTES];
+    unpack7(packed, &mut bytes);
+    KeyMask::from_bytes(&bytes)
+}
+
+/// リストア: すべての値を確認してから反映する
+fn restore(data: &[u8]) -> Result<(), ConfigError> {
+    let count = *data.first().ok_or(ConfigError::BadLength)? as usize;
+    if count > NUM_PARAMS || data.len() != 1 + count * 2 + PACKED_MASK_SIZE {
+        return Err(ConfigError::BadLength);
+    }
+    let values = &data[1..1 + count * 2];
+    for (param, v) in PARAMS.iter().zip(values.chunks_exact(2)) {
+        let value = value_at(v);
+        if !param.is_reserved() && (value < param.min || value > param.max) {
+            return Err(ConfigError::OutOfRange);
+        }
+    }
+    for (param, v) in PARAMS.iter().zip(values.chunks_exact(2)) {
+        param.set(value_at(v));
+    }
+    key_mask_of(&data[1 + count * 2..]).store_manual();
+    Ok(())
+}
+
+/// コマンドを実行して、返信のコマンドとデータを reply に入れる
+async fn execute<S: SettingsStore>(
+    cmd: u8,
+    data: &[u8],
+    reply: &mut Vec<u8, REPLY_SIZE>,
+    store: &mut S,
+) -> Result<(), ConfigError> {
+    let expect_len = |len: usize| {
+        if data.len() == len {
+            Ok(())
+        } else {
+            Err(ConfigError::BadLength)
+        }
+    };
+    let param_at = |id: u8| {
+        PARAMS
+            .get(id as usize)
+            .filter(|p| !p.is_reserved())
+            .ok_or(ConfigError::UnknownParam)
+    };
+    match cmd {
+        CMD_GET_PARAM => {
+            expect_len(1)?;
+            let param = param_at(data[0])?;
+            let _ = reply.extend_from_slice(&[cmd | REPLY_FLAG, data[0]]);
+            push_value(reply, param.get());
+        }
+        CMD_SET_PARAM => {
+            expect_len(3)?;
+            if !param_at(data[0])?.set(value_at(&data[1..])) {
+                return Err(ConfigError::OutOfRange);
+            }
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        CMD_DUMP => {
+            expect_len(0)?;
+            let _ = reply.extend_from_slice(&[cmd | REPLY_FLAG, NUM_PARAMS as u8]);
+            for param in PARAMS.iter() {
+                push_value(reply, param.get());
+            }
+            push_key_mask(reply);
+        }
+        CMD_RESTORE => {
+            restore(data)?;
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        CMD_SAVE => {
+            expect_len(0)?;
+            store
+                .save(&settings::encode_saved())
+                .await
+                .map_err(|_| ConfigError::SaveFailed)?;
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        CMD_GET_KEY_MASK => {
+            expect_len(0)?;
+            let _ = reply.push(cmd | REPLY_FLAG);
+            push_key_mask(reply);
+        }
+        CMD_SET_KEY_MASK => {
+            expect_len(PACKED_MASK_SIZE)?;
+            key_mask_of(data).store_manual();
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        CMD_TELEMETRY => {
+            expect_len(1)?;
+            if data[0] > 1 {
+                return Err(ConfigError::OutOfRange);
+            }
+            settings::TELEMETRY.store(data[0], Ordering::Relaxed);
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        CMD_REBOOT_BOOTLOADER => {
+            expect_len(0)?;
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        CMD_TRANSPORT => {
+            expect_len(1)?;
+            let transport = match data[0] {
+                0 => Transport::Stop,
+                1 => Transport::Start,
+                2 => Transport::Continue,
+                _ => return Err(ConfigError::OutOfRange),
+            };
+            request_transport(transport);
+            let _ = reply.extend_from_slice(&[ACK, cmd]);
+        }
+        _ => return Err(ConfigError::UnknownCommand),
+    }
+    Ok(())
+}
+
+/// 受信した SysEx（F0 と F7 を除く）が設定用か Identity Request なら、処理して返信する
+/// どちらでもなければ何もせずに false
+pub async fn handle_sysex<M, D>(
+    sysex: &[u8],
+    midi: &mut M,
+    device: &mut D,
+) -> Result<bool, SysexTxError<M::Error>>
+where
+    M: MidiSink,
+    D: SettingsStore + Reboot,
+{
+    let mut reply: Vec<u8, REPLY_SIZE> = Vec::new();
+    let mut reboot = false;
+    if let [0x7E, id, 0x06, 0x01] = *sysex {
+        if id != ALL_CALL && id != device_id() {
+            return Ok(false);
+        }
+        // Identity Reply: ファミリー = VID、モデル = PID
+        let _ = reply.extend_from_slice(&[0xF0, 0x7E, device_id(), 0x06, 0x02]);
+        let _ = reply.extend_from_slice(&CONFIG_HEADER);
+        let _ = reply.extend_from_slice(&version());
+    } else if let Some(body) = sysex.strip_prefix(&CONFIG_HEADER) {
+        let _ = reply.push(0xF0);
+        let _ = reply.extend_from_slice(&CONFIG_HEADER);
+        let head = reply.len();
+        let (cmd, result) = match body.split_first() {
+            Some((&cmd, data)) => (cmd, execute(cmd, data, &mut reply, device).await),
+            None => (0x00, Err(ConfigError::NoCommand)),
+        };
+        match result {
+            Ok(()) => reboot = cmd == CMD_REBOOT_BOOTLOADER,
+            Err(e) => {
+                reply.truncate(head);
+                let _ = reply.extend_from_slice(&[NAK, cmd, e as u8]);
+            }
+        }
+    } else {
+        return Ok(false);
+    }
+    let _ = reply.push(0xF7);
+    send_sysex(midi, &reply).await?;
+    if reboot {
+        device.reboot_to_bootloader();
+    }
+    Ok(true)
+}
+
+fn push_u14<const N: usize>(buf: &mut Vec<u8, N>, value: u32) {
+    let value = value.min(0x3FFF);
+    let _ = buf.push((value & 0x7F) as u8);
+    let _ = buf.push((value >> 7) as u8);
+}
+
+/// テレメトリの SysEx（F0 から F7 まで）
+pub fn telemetry_sysex() -> Vec<u8, TELEMETRY_SYSEX_SIZE> {
+    let mut sysex = Vec::new();
+    let _ = sysex.push(0xF0);
+    let _ = sysex.extend_from_slice(&CONFIG_HEADER);
+    let _ = sysex.push(TELEMETRY_REPORT);
+    for touch in [&TOUCH0, &TOUCH1, &TOUCH2, &TOUCH3] {
+        push_u14(&mut sysex, touch.load(Ordering::Relaxed).max(0) as u32);
+    }
+    push_u14(&mut sysex, PRESSURE.load(Ordering::Relaxed));
+    for adc in [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3] {
+        push_u14(&mut sysex, adc.load(Ordering::Relaxed));
+    }
+    let _ = sysex.push(0xF7);
+    sysex
+}
@@ -0,0 +1,232 @@
+//! DIN MIDI（UART 31250bps）のバイト列と USB-MIDI のパケット（4byte）の変換
+//!
+//! 送信（DinEncoder）: 同じステータスが続けばステータスを省く（ランニングステータス）
+//!   リアルタイム（F8-FF）はランニングステータスを変えない。システムコモンと SysEx は取り消す
+//! 受信（DinParser）: ランニングステータスを補って、1byte ずつパケットにする
+//!   リアルタイムはメッセージや SysEx の途中でもすぐにパケットにする
+//!   SysEx は3byte ずつ（CIN 0x4）、F7 で終わりのパケット（CIN 0x5-0x7）にする
+//!   ステータスのないデータは捨てる
+use heapless::Vec;
+
+use crate::hal::{MidiSink, MidiSource, SerialSink, SerialSource};
+
+pub const DIN_BAUDRATE: u32 = 31250;
+
+/// リアルタイム以外のメッセージのデータの長さ
+fn data_len(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        0x80..=0xEF | 0xF2 => 2,
+        _ => 0,
+    }
+}
+
+// =========================================================
+//      DinEncoder
+// =========================================================
+#[derive(Default)]
+pub struct DinEncoder {
+    running: Option<u8>, // 最後に送ったチャンネルメッセージのステータス
+}
+
+impl DinEncoder {
+    pub const fn new() -> Self {
+        Self { running: None }
+    }
+
+    /// USB-MIDI のパケットを、DIN に送るバイト列にする
+    pub fn encode(&mut self, packet: [u8; 4]) -> Vec<u8, 3> {
+        let cin = packet[0] & 0x0F;
+        let status = packet[1];
+        let len = match cin {
+            0x2 | 0x6 | 0xC | 0xD => 2,
+            0x3 | 0x4 | 0x7 | 0x8..=0xB | 0xE => 3,
+            0x5 | 0xF => 1,
+            _ => return Vec::new(),
+        };
+        let mut data = [packet[1], packet[2], packet[3]];
+        if matches!(cin, 0x2 | 0x3 | 0x8..=0xE) {
+            // データバイトが 0x80 以上だと、受け手はステータスとして読んでしまう
+            data[1..].iter_mut().for_each(|b| *b &= 0x7F);
+        }
+        let bytes = &data[..len];
+        match cin {
+            0x8..=0xE => {
+                if self.running == Some(status) {
+                    return Vec::from_slice(&bytes[1..]).unwrap_or_default();
+                }
+                self.running = Some(status);
+            }
+            0xF if status >= 0xF8 => {}
+            _ => self.running = None,
+        }
+        Vec::from_slice(bytes).unwrap_or_default()
+    }
+
+    /// 次のメッセージは必ずステータスから送る
+    pub fn reset(&mut self) {
+        self.running = None;
+    }
+}
+
+// =========================================================
+//      DinParser
+// =========================================================
+#[derive(Default)]
+pub struct DinParser {
+    running: Option<u8>, // ランニングステータス（システムコモンは1つのメッセージだけ）
+    data: [u8; 2],
+    count: usize,
+    sysex: Option<Vec<u8, 3>>, // 組み立て中の SysEx（まだパケットにしていない分）
+}
+
+impl DinParser {
+    pub const fn new() -> Self {
+        Self {
+            running: None,
+            data: [0; 2],
+            count: 0,
+            sysex: None,
+        }
+    }
+
+    /// 1byte を受け取る。パケットが揃ったら Some（ケーブル番号は 0）
+    pub fn parse(&mut self, byte: u8) -> Option<[u8; 4]> {
+        match byte {
+            0xF8..=0xFF => Some([0x0F, byte, 0, 0]),
+            0xF0 => {
+                self.running = None;
+                self.sysex = Some(Vec::from_slice(&[0xF0]).unwrap_or_default());
+                None
+            }
+            0xF7 => {
+                let mut part = self.sysex.take()?;
+                let _ = part.push(0xF7);
+                let cin = 0x4 + part.len() as u8;
+                let mut packet = [cin, 0, 0, 0];
+                packet[1..=part.len()].copy_from_slice(&part);
+                Some(packet)
+            }
+            0x80..=0xF6 => {
+                // リアルタイム以外のステータスで SysEx は終わる（途中までの分は捨てる）
+                self.sysex = None;
+                self.count = 0;
+                if byte == 0xF6 {
+                    self.running = None;
+                    return Some([0x05, byte, 0, 0]);
+                }
+                self.running = (data_len(byte) > 0).then_some(byte);
+                None
+            }
+            _ => {
+                if let Some(part) = &mut self.sysex {
+                    let _ = part.push(byte);
+                    if part.is_full() {
+                        let mut packet = [0x04, 0, 0, 0];
+                        packet[1..].copy_from_slice(part);
+                        part.clear();
+                        return Some(packet);
+                    }
+                    return None;
+                }
+                let status = self.running?;
+                let len = data_len(status);
+                self.data[self.count] = byte;
+                self.count += 1;
+                if self.count < len {
+                    return None;
+                }
+                self.count = 0;
+                let cin = match status {
+                    0xF1 | 0xF3 => 0x2,
+                    0xF2 => 0x3,
+                    _ => status >> 4,
+                };
+                if status >= 0xF0 {
+                    self.running = None; // システムコモンにランニングステータスはない
+                }
+                let mut packet = [cin, status, 0, 0];
+                packet[2..2 + len].copy_from_slice(&self.data[..len]);
+                Some(packet)
+            }
+        }
+    }
+}
+
+// =========================================================
+//      DIN MIDI In/Out
+// =========================================================
+/// DIN MIDI の送信。MidiSink として USB-MIDI のパケットを受け取る
+pub struct DinMidiOut<W> {
+    serial: W,
+    encoder: DinEncoder,
+}
+
+impl<W: SerialSink> DinMidiOut<W> {
+    pub const fn new(serial: W) -> Self {
+        Self {
+            serial,
+            encoder: DinEncoder::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.serial
+    }
+}
+
+impl<W: SerialSink> MidiSink for DinMidiOut<W> {
+    type Error = W::Error;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        let bytes = self.encoder.encode(packet);
+        self.serial.write(&bytes).await
+    }
+
+    /// まとめて UART に書く
+    async fn send_all(&mut self, packets: &[[u8; 4]]) -> Result<(), Self::Error> {
+        let mut bytes: Vec<u8, 48> = Vec::new();
+        for packet in packets {
+            if bytes.len() + 3 > bytes.capacity() {
+                self.serial.write(&bytes).await?;
+                bytes.clear();
+            }
+            let _ = bytes.extend_from_slice(&self.encoder.encode(*packet));
+        }
+        self.serial.write(&bytes).await
+    }
+}
+
+/// DIN MIDI の受信。MidiSource として USB-MIDI のパケットを返す
+pub struct DinMidiIn<R> {
+    serial: R,
+    parser: DinParser,
+}
+
+impl<R: SerialSource> DinMidiIn<R> {
+    pub const fn new(serial: R) -> Self {
+        Self {
+            serial,
+            parser: DinParser::new(),
+        }
+    }
+}
+
+impl<R: SerialSource> MidiSource for DinMidiIn<R> {
+    type Error = R::Error;
+
+    /// 1byte から作るパケットは1つまでなので、buf に入る数だけ読む
+    async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut bytes = [0u8; 16];
+        let max = bytes.len().min(buf.len() / 4);
+        let n = self.serial.read(&mut bytes[..max]).await?;
+        let mut len = 0;
+        for byte in &bytes[..n] {
+            if let Some(packet) = self.parser.parse(*byte) {
+                buf[len..len + 4].copy_from_slice(&packet);
+                len += 4;
+            }
+        }
+        Ok(len)
+    }
+}
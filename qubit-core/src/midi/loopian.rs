@@ -0,0 +1,172 @@
+//! Loopian の状態の表示（Loopian から SysEx で受け取る）
+//!
+//! 設定と同じヘッダで、コマンドは 0x30 から。返信はしない（Loopian は変わるたびに送る）
+//!
+//!   F0 7D 09 24 10 6D <コマンド> <データ...> F7
+//!
+//!   コマンド          データ
+//!   0x30 ループ        長さ | 位置 | 拍（小節の中、0 が1拍目）
+//!                     長さと位置はティック（MIDI クロックと同じ 24/四分音符）、14bit を 7bit × 2（下位から）
+//!                     長さ 0 はループなし（プレイヘッドを消す）
+//!   0x31 パート        演奏中のパート（0-3）
+//!   0x32 フレーズ名    パート | ASCII 最大 8文字（0x20-0x7E 以外は '?'。短ければ残りは消す）
+//!
+//! 位置は受信した時刻から MIDI クロックのテンポで進める（同期していなければ止める）
+//! RingLED はループの位置をプレイヘッドとして1周で表示し、OLED の Loopian ページに名前を表示する
+use heapless::{String, Vec};
+use portable_atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+
+use crate::midi::clock::ClockState;
+use crate::midi::config::CONFIG_HEADER;
+
+pub const CMD_LOOP: u8 = 0x30;
+pub const CMD_PART: u8 = 0x31;
+pub const CMD_PHRASE: u8 = 0x32;
+pub const MAX_PARTS: usize = 4;
+pub const NAME_LEN: usize = 8;
+pub const LOOPIAN_SYSEX_SIZE: usize = 1 + CONFIG_HEADER.len() + 2 + NAME_LEN + 1;
+
+static LOOP_LENGTH: AtomicU32 = AtomicU32::new(0);
+static LOOP_POSITION: AtomicU32 = AtomicU32::new(0);
+static LOOP_RECEIVED_US: AtomicU64 = AtomicU64::new(0);
+static LOOP_BEAT: AtomicU8 = AtomicU8::new(0);
+static ACTIVE_PART: AtomicU8 = AtomicU8::new(0);
+static PHRASE_NAMES: [[AtomicU8; NAME_LEN]; MAX_PARTS] =
+    [const { [const { AtomicU8::new(0) }; NAME_LEN] }; MAX_PARTS];
+
+/// 受信した Loopian の状態
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoopianState {
+    pub length: u32,      // ループの長さ（ティック、0: ループなし）
+    pub position: u32,    // 受信したときの位置（ティック）
+    pub received_us: u64, // 位置を受信した時刻
+    pub beat: u8,         // 小節の中の拍
+    pub part: u8,         // 演奏中のパート
+}
+
+impl LoopianState {
+    pub fn load() -> Self {
+        Self {
+            length: LOOP_LENGTH.load(Ordering::Relaxed),
+            position: LOOP_POSITION.load(Ordering::Relaxed),
+            received_us: LOOP_RECEIVED_US.load(Ordering::Relaxed),
+            beat: LOOP_BEAT.load(Ordering::Relaxed),
+            part: ACTIVE_PART.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn store(&self) {
+        LOOP_LENGTH.store(self.length, Ordering::Relaxed);
+        LOOP_POSITION.store(self.position, Ordering::Relaxed);
+        LOOP_RECEIVED_US.store(self.received_us, Ordering::Relaxed);
+        LOOP_BEAT.store(self.beat, Ordering::Relaxed);
+        ACTIVE_PART.store(self.part.min(MAX_PARTS as u8 - 1), Ordering::Relaxed);
+    }
+
+    /// ループの中の位置（0.0-1.0）。ループがなければ None
+    pub fn playhead(&self, clock: &ClockState, now_us: u64) -> Option<f32> {
+        if self.length == 0 {
+            return None;
+        }
+        let mut ticks = self.position as f32;
+        if clock.running && clock.is_synced(now_us) {
+            ticks += now_us.saturating_sub(self.received_us) as f32 / clock.interval_us as f32;
+        }
+        Some((ticks % self.length as f32) / self.length as f32)
+    }
+}
+
+/// パートのフレーズ名（受信していなければ空）
+pub fn phrase_name(part: usize) -> String<NAME_LEN> {
+    let mut name = String::new();
+    if let Some(chars) = PHRASE_NAMES.get(part) {
+        for c in chars.iter().map(|c| c.load(Ordering::Relaxed)) {
+            if c == 0 {
+                break;
+            }
+            let _ = name.push(c as char);
+        }
+    }
+    name
+}
+
+fn u14_at(data: &[u8]) -> u32 {
+    (data[0] & 0x7F) as u32 | ((data[1] & 0x7F) as u32) << 7
+}
+
+/// 受信した SysEx（F0 と F7 を除く）が Loopian の表示なら反映して true
+/// 長さが違うものは無視する（返信しないので、設定のコマンドとして NAK も返さない）
+pub fn receive(sysex: &[u8], now_us: u64) -> bool {
+    let Some([cmd, data @ ..]) = sysex.strip_prefix(&CONFIG_HEADER) else {
+        return false;
+    };
+    match (*cmd, data) {
+        (CMD_LOOP, [l0, l1, p0, p1, beat]) => LoopianState {
+            length: u14_at(&[*l0, *l1]),
+            position: u14_at(&[*p0, *p1]),
+            received_us: now_us,
+            beat: *beat,
+            part: ACTIVE_PART.load(Ordering::Relaxed),
+        }
+        .store(),
+        (CMD_PART, [part]) => {
+            ACTIVE_PART.store((*part).min(MAX_PARTS as u8 - 1), Ordering::Relaxed)
+        }
+        (CMD_PHRASE, [part, name @ ..]) if (*part as usize) < MAX_PARTS => {
+            for (i, c) in PHRASE_NAMES[*part as usize].iter().enumerate() {
+                let value = match name.get(i) {
+                    Some(&b) if (0x20..=0x7E).contains(&b) => b,
+                    Some(_) => b'?',
+                    None => 0,
+                };
+                c.store(value, Ordering::Relaxed);
+            }
+        }
+        (CMD_LOOP | CMD_PART | CMD_PHRASE, _) => {}
+        _ => return false,
+    }
+    true
+}
+
+// =========================================================
+//      Encoder（Loopian 側とテスト用）
+// =========================================================
+fn sysex(cmd: u8, data: &[u8]) -> Vec<u8, LOOPIAN_SYSEX_SIZE> {
+    let mut sysex = Vec::new();
+    let _ = sysex.push(0xF0);
+    let _ = sysex.extend_from_slice(&CONFIG_HEADER);
+    let _ = sysex.push(cmd);
+    let _ = sysex.extend_from_slice(data);
+    let _ = sysex.push(0xF7);
+    sysex
+}
+
+/// ループの SysEx（F0 から F7 まで）
+pub fn loop_sysex(length: u32, position: u32, beat: u8) -> Vec<u8, LOOPIAN_SYSEX_SIZE> {
+    let (length, position) = (length.min(0x3FFF), position.min(0x3FFF));
+    sysex(
+        CMD_LOOP,
+        &[
+            (length & 0x7F) as u8,
+            (length >> 7) as u8,
+            (position & 0x7F) as u8,
+            (position >> 7) as u8,
+            beat & 0x7F,
+        ],
+    )
+}
+
+/// パートの SysEx
+pub fn part_sysex(part: u8) -> Vec<u8, LOOPIAN_SYSEX_SIZE> {
+    sysex(CMD_PART, &[part & 0x7F])
+}
+
+/// フレーズ名の SysEx（8文字を越える分は送らない）
+pub fn phrase_sysex(part: u8, name: &str) -> Vec<u8, LOOPIAN_SYSEX_SIZE> {
+    let mut data: Vec<u8, { NAME_LEN + 1 }> = Vec::new();
+    let _ = data.push(part & 0x7F);
+    for b in name.bytes().take(NAME_LEN) {
+        let _ = data.push(b & 0x7F);
+    }
+    sysex(CMD_PHRASE, &data)
+}
@@ -0,0 +1,9 @@
+pub mod clock;
+pub mod config;
+pub mod din;
+pub mod loopian;
+pub mod note_map;
+pub mod output;
+pub mod parser;
+pub mod router;
+pub mod tx_queue;
@@ -0,0 +1,91 @@
+//! ノート・パッド（キー）・LED の対応。タッチで送るノートと、受信したノートの表示で同じ変換を使う
+//!
+//! パッド p の元のノートは OFFSET_NOTE + p で、LED p の位置にある
+//! 送るノートは NoteOutput（トランスポーズ・オクターブ・スケール・音域）で決まる
+//! 受信したノートは、そのノートを鳴らすパッドの LED に表示する
+//!   鳴らすパッドが複数あれば（スケールや音域の端に寄せたとき）、元のノートが一番近いもの
+//!   どのパッドでも鳴らないノートは表示しない。RxFold が On なら、同じ音名を鳴らす一番近いパッドに表示する
+//! 表示している間に設定が変わっても、Note Off は Note On で点けた LED を消す（RxLeds）
+use portable_atomic::Ordering;
+
+use crate::midi::output::NoteOutput;
+use crate::settings;
+use crate::touch::qtouch::{MAX_PADS, OFFSET_NOTE};
+
+pub const NUM_PADS: u8 = MAX_PADS as u8;
+
+/// パッドの LED の位置
+pub fn pad_led(pad: u8) -> f32 {
+    pad as f32
+}
+
+/// 設定から作るノートとパッドの対応
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NoteMap {
+    pub output: NoteOutput,
+    pub fold: bool, // 鳴らせないノートを同じ音名のところに表示する
+}
+
+impl NoteMap {
+    pub fn load() -> Self {
+        Self {
+            output: NoteOutput::load(),
+            fold: settings::RX_FOLD.load(Ordering::Relaxed) != 0,
+        }
+    }
+
+    /// パッドが鳴らすノート
+    pub fn pad_note(&self, pad: u8) -> u8 {
+        self.output.note(pad.min(NUM_PADS - 1) + OFFSET_NOTE)
+    }
+
+    /// ノートを表示するパッド（表示しなければ None）
+    pub fn note_pad(&self, note: u8) -> Option<u8> {
+        let note = note as i16;
+        // 設定を反映して、スケールや音域に寄せる前のノート
+        let shifted = |pad: u8| (pad + OFFSET_NOTE) as i16 + self.output.shift;
+        let exact = (0..NUM_PADS)
+            .filter(|pad| self.pad_note(*pad) as i16 == note)
+            .min_by_key(|pad| (shifted(*pad) - note).abs());
+        if exact.is_some() || !self.fold {
+            return exact;
+        }
+        (0..NUM_PADS)
+            .filter(|pad| (self.pad_note(*pad) as i16 - note).rem_euclid(12) == 0)
+            .min_by_key(|pad| (self.pad_note(*pad) as i16 - note).abs())
+    }
+
+    /// ノートを表示する LED の位置
+    pub fn note_led(&self, note: u8) -> Option<f32> {
+        self.note_pad(note).map(pad_led)
+    }
+}
+
+/// 受信して表示しているノート → パッド
+pub struct RxLeds {
+    pads: [Option<u8>; 128],
+}
+
+impl RxLeds {
+    pub fn new() -> Self {
+        Self { pads: [None; 128] }
+    }
+
+    /// Note On で点ける LED の位置
+    pub fn note_on(&mut self, note: u8, map: &NoteMap) -> Option<f32> {
+        let pad = map.note_pad(note);
+        self.pads[(note & 0x7f) as usize] = pad;
+        pad.map(pad_led)
+    }
+
+    /// Note Off で消す LED の位置（点けていなければ None）
+    pub fn note_off(&mut self, note: u8) -> Option<f32> {
+        self.pads[(note & 0x7f) as usize].take().map(pad_led)
+    }
+}
+
+impl Default for RxLeds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,140 @@
+//! タッチで送るノートの変換（チャンネル・トランスポーズ・オクターブ・音域）
+//!
+//! QubitTouch はキーの位置から、設定によらないノート番号（KEYBD_LO - 4 から）を作る
+//! ここで設定を反映し、0-127 と設定の音域に収める（範囲外は端に寄せるか、オクターブを変える）
+//! スケールが Chromatic 以外なら、スケールにない音は下のスケールの音にする（C のスケールにしてから
+//! トランスポーズするので、調はトランスポーズで決まる）
+//! 受信したノートの表示も同じ変換を使う（midi::note_map）
+//! 押している間に設定が変わっても、Note Off は Note On と同じチャンネル・ノートで送る
+use heapless::Vec;
+use portable_atomic::Ordering;
+
+use crate::settings;
+
+const MAX_SOUNDING: usize = 8; // タッチ4つ × 移動中の2音
+
+/// 設定から作るノートの変換
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NoteOutput {
+    pub channel: u8, // 0-15
+    pub shift: i16,  // 半音
+    pub low: u8,
+    pub high: u8,
+    pub fold: bool,
+    pub scale: Scale,
+}
+
+/// 送るノートのスケール
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    Pentatonic,
+}
+
+impl Scale {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Major,
+            2 => Self::Minor,
+            3 => Self::Pentatonic,
+            _ => Self::Chromatic,
+        }
+    }
+
+    /// スケールの音（C を bit 0 とする音名のビット）
+    fn pitch_classes(&self) -> u16 {
+        match self {
+            Self::Chromatic => 0xFFF,
+            Self::Major => 0xAB5,      // C D E F G A B
+            Self::Minor => 0x5AD,      // C D Eb F G Ab Bb
+            Self::Pentatonic => 0x295, // C D E G A
+        }
+    }
+
+    /// スケールにない音は、下のスケールの音にする（C はどのスケールにもある）
+    pub fn snap(&self, note: u8) -> u8 {
+        let classes = self.pitch_classes();
+        let mut note = note;
+        while classes & (1 << (note % 12)) == 0 {
+            note -= 1;
+        }
+        note
+    }
+}
+
+impl NoteOutput {
+    pub fn load() -> Self {
+        let transpose = settings::TRANSPOSE.load(Ordering::Relaxed) as i16;
+        let octave = settings::OCTAVE.load(Ordering::Relaxed) as i16;
+        Self {
+            channel: settings::MIDI_CHANNEL.load(Ordering::Relaxed).clamp(1, 16) - 1,
+            shift: transpose - settings::TRANSPOSE_ZERO as i16
+                + (octave - settings::OCTAVE_ZERO as i16) * 12,
+            low: settings::NOTE_LOW.load(Ordering::Relaxed).min(127),
+            high: settings::NOTE_HIGH.load(Ordering::Relaxed).min(127),
+            fold: settings::NOTE_FOLD.load(Ordering::Relaxed) != 0,
+            scale: Scale::from_u8(settings::SCALE.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// 送るノート番号（low > high のときは low だけになる）
+    pub fn note(&self, note: u8) -> u8 {
+        let low = self.low as i16;
+        let high = (self.high as i16).max(low);
+        let mut note = self.scale.snap(note) as i16 + self.shift;
+        // 1オクターブ以上の幅がなければ、同じ音名が範囲にないこともあるので寄せる
+        if self.fold && high - low >= 11 {
+            if note < low {
+                note += (low - note + 11) / 12 * 12;
+            } else if note > high {
+                note -= (note - high + 11) / 12 * 12;
+            }
+        }
+        note.clamp(low, high) as u8
+    }
+}
+
+/// 鳴っているノート: (タッチID, 元のノート) → (ステータス, 送ったノート)
+pub struct SoundingNotes {
+    notes: Vec<(u8, u8, u8, u8), MAX_SOUNDING>,
+}
+
+impl SoundingNotes {
+    pub fn new() -> Self {
+        Self { notes: Vec::new() }
+    }
+
+    /// Note On の (ステータス, ノート)
+    pub fn note_on(&mut self, id: u8, note: u8, output: &NoteOutput) -> (u8, u8) {
+        let status = 0x90 | output.channel;
+        let sent = output.note(note);
+        if self.notes.is_full() {
+            self.notes.remove(0); // Note Off を取りこぼしたもの
+        }
+        let _ = self.notes.push((id, note, status, sent));
+        (status, sent)
+    }
+
+    /// Note Off の (ステータス, ノート)。Note On を送っていなければ今の設定で変換する
+    pub fn note_off(&mut self, id: u8, note: u8, output: &NoteOutput) -> (u8, u8) {
+        match self
+            .notes
+            .iter()
+            .position(|(i, n, _, _)| *i == id && *n == note)
+        {
+            Some(index) => {
+                let (_, _, status, sent) = self.notes.remove(index);
+                (0x80 | (status & 0x0f), sent)
+            }
+            None => (0x80 | output.channel, output.note(note)),
+        }
+    }
+}
+
+impl Default for SoundingNotes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,312 @@
+//! USB-MIDI 1.0 のイベントパケット（4byte）の解析
+//!
+//!   byte0:   ケーブル番号（上位4bit） | CIN: Code Index Number（下位4bit）
+//!   byte1-3: MIDI メッセージ（長さは CIN で決まり、残りは 0）
+//!
+//! SysEx は複数のパケットに分かれて届くので、バッファで組み立ててから1つのメッセージにする
+//! 組み立てるのは1度に1本のケーブルだけで、バッファに入らない SysEx は捨ててエラーにする
+use heapless::Vec;
+
+use crate::constants::MIDI_SYSEX_TX_MAX;
+use crate::hal::MidiSink;
+
+/// 受信した MIDI メッセージ
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiMessage<'a> {
+    // Channel Voice（channel は 0-15）
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 }, // velocity 0 もそのまま渡す
+    PolyPressure { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, control: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    PitchBend { channel: u8, value: i16 }, // -8192..=8191（0 が中央）
+    // System Common
+    TimeCode(u8),
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    // System Real Time
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+    // System Exclusive（F0 と F7 を除いたデータ）
+    SysEx(&'a [u8]),
+}
+
+/// ケーブル番号付きのメッセージ
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UsbMidiEvent<'a> {
+    pub cable: u8,
+    pub message: MidiMessage<'a>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiParseError {
+    SysExOverflow,    // バッファに入らないので捨てた
+    SysExInterrupted, // 組み立て中に別の SysEx が始まった
+    SysExNotStarted,  // F0 のない SysEx の続き
+    Malformed,        // CIN とステータスが合わない、データに最上位ビットが立っている など
+}
+
+// =========================================================
+//      UsbMidiParser
+// =========================================================
+/// USB-MIDI のパケットを1つずつ受け取って、メッセージにする
+/// N は組み立てられる SysEx の最大長
+pub struct UsbMidiParser<const N: usize> {
+    sysex: Vec<u8, N>,
+    sysex_cable: Option<u8>, // 組み立て中の SysEx のケーブル
+    overflow: bool,
+}
+
+impl<const N: usize> UsbMidiParser<N> {
+    pub const fn new() -> Self {
+        Self {
+            sysex: Vec::new(),
+            sysex_cable: None,
+            overflow: false,
+        }
+    }
+
+    /// 1パケットを解析する。メッセージが揃ったときだけ Some を返す
+    /// CIN 0x0/0x1（予約）と、空のパケットは無視する
+    pub fn parse(&mut self, packet: [u8; 4]) -> Result<Option<UsbMidiEvent<'_>>, MidiParseError> {
+        let cable = packet[0] >> 4;
+        let cin = packet[0] & 0x0F;
+        let [_, status, d1, d2] = packet;
+        let message = match cin {
+            0x0 | 0x1 => return Ok(None),
+            0x2 => match status {
+                0xF1 => MidiMessage::TimeCode(data(d1)?),
+                0xF3 => MidiMessage::SongSelect(data(d1)?),
+                _ => return Err(MidiParseError::Malformed),
+            },
+            0x3 => match status {
+                0xF2 => MidiMessage::SongPosition(data(d1)? as u16 | (data(d2)? as u16) << 7),
+                _ => return Err(MidiParseError::Malformed),
+            },
+            0x4..=0x7 => {
+                // 1byte のシステムコモンは CIN 0x5 で届く
+                if cin == 0x5 && status == 0xF6 {
+                    MidiMessage::TuneRequest
+                } else {
+                    let len = if cin == 0x4 { 3 } else { cin as usize - 4 };
+                    // エラーがあってもパケットの残りは読む（F0 から新しい SysEx が始まることがある）
+                    let (mut complete, mut error) = (false, None);
+                    for byte in &packet[1..=len] {
+                        match self.sysex_byte(cable, *byte) {
+                            Ok(done) => complete |= done,
+                            Err(e) => error = error.or(Some(e)),
+                        }
+                    }
+                    match (complete, error) {
+                        (true, _) => MidiMessage::SysEx(&self.sysex),
+                        (false, Some(e)) => return Err(e),
+                        (false, None) => return Ok(None),
+                    }
+                }
+            }
+            0x8..=0xE => {
+                if status >> 4 != cin {
+                    return Err(MidiParseError::Malformed);
+                }
+                // MIDI 1.0 では、リアルタイム以外のステータスで SysEx は終わる
+                if self.sysex_cable == Some(cable) {
+                    self.abort_sysex();
+                }
+                channel_message(status, d1, d2)?
+            }
+            _ => match status {
+                0xF8 => MidiMessage::Clock,
+                0xFA => MidiMessage::Start,
+                0xFB => MidiMessage::Continue,
+                0xFC => MidiMessage::Stop,
+                0xFE => MidiMessage::ActiveSensing,
+                0xFF => MidiMessage::Reset,
+                0xF6 => MidiMessage::TuneRequest,
+                _ => return Err(MidiParseError::Malformed),
+            },
+        };
+        Ok(Some(UsbMidiEvent { cable, message }))
+    }
+
+    /// SysEx の1byte。F7 で SysEx が揃ったら true
+    fn sysex_byte(&mut self, cable: u8, byte: u8) -> Result<bool, MidiParseError> {
+        match byte {
+            0xF0 => {
+                let interrupted = self.sysex_cable.is_some();
+                self.abort_sysex();
+                self.sysex_cable = Some(cable);
+                if interrupted {
+                    return Err(MidiParseError::SysExInterrupted);
+                }
+                Ok(false)
+            }
+            0xF7 => {
+                if self.sysex_cable != Some(cable) {
+                    self.abort_sysex();
+                    return Err(MidiParseError::SysExNotStarted);
+                }
+                if self.overflow {
+                    self.abort_sysex();
+                    return Err(MidiParseError::SysExOverflow);
+                }
+                self.sysex_cable = None; // データは次の F0 まで残す
+                Ok(true)
+            }
+            0x80.. => {
+                self.abort_sysex();
+                Err(MidiParseError::Malformed)
+            }
+            _ => {
+                match self.sysex_cable {
+                    Some(c) if c == cable => {}
+                    Some(_) => {
+                        // 1度に組み立てるのは1本のケーブルだけ
+                        self.abort_sysex();
+                        return Err(MidiParseError::SysExInterrupted);
+                    }
+                    None => return Err(MidiParseError::SysExNotStarted),
+                }
+                if self.sysex.push(byte).is_err() {
+                    self.overflow = true;
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    fn abort_sysex(&mut self) {
+        self.sysex.clear();
+        self.sysex_cable = None;
+        self.overflow = false;
+    }
+}
+
+impl<const N: usize> Default for UsbMidiParser<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn data(byte: u8) -> Result<u8, MidiParseError> {
+    if byte < 0x80 {
+        Ok(byte)
+    } else {
+        Err(MidiParseError::Malformed)
+    }
+}
+
+fn channel_message(status: u8, d1: u8, d2: u8) -> Result<MidiMessage<'static>, MidiParseError> {
+    let channel = status & 0x0F;
+    Ok(match status >> 4 {
+        0x8 => MidiMessage::NoteOff {
+            channel,
+            note: data(d1)?,
+            velocity: data(d2)?,
+        },
+        0x9 => MidiMessage::NoteOn {
+            channel,
+            note: data(d1)?,
+            velocity: data(d2)?,
+        },
+        0xA => MidiMessage::PolyPressure {
+            channel,
+            note: data(d1)?,
+            pressure: data(d2)?,
+        },
+        0xB => MidiMessage::ControlChange {
+            channel,
+            control: data(d1)?,
+            value: data(d2)?,
+        },
+        0xC => MidiMessage::ProgramChange {
+            channel,
+            program: data(d1)?,
+        },
+        0xD => MidiMessage::ChannelPressure {
+            channel,
+            pressure: data(d1)?,
+        },
+        _ => MidiMessage::PitchBend {
+            channel,
+            value: (data(d1)? as i16 | (data(d2)? as i16) << 7) - 8192,
+        },
+    })
+}
+
+// =========================================================
+//      Packetize
+// =========================================================
+/// MIDI のバイト列を USB-MIDI のパケットにする（テストやシミュレータ用）
+/// ランニングステータスは使えない。ステータスのないデータは読み飛ばす
+pub fn packetize<F: FnMut([u8; 4])>(cable: u8, bytes: &[u8], mut out: F) {
+    let head = cable << 4;
+    let mut i = 0;
+    while i < bytes.len() {
+        let status = bytes[i];
+        if status == 0xF0 {
+            // F7 まで（なければ最後まで）を3byteずつ
+            let end = bytes[i..]
+                .iter()
+                .position(|b| *b == 0xF7)
+                .map_or(bytes.len(), |p| i + p + 1);
+            for part in bytes[i..end].chunks(3) {
+                let cin = match (part.last() == Some(&0xF7), part.len()) {
+                    (false, _) => 0x4,
+                    (true, 1) => 0x5,
+                    (true, 2) => 0x6,
+                    (true, _) => 0x7,
+                };
+                let mut packet = [head | cin, 0, 0, 0];
+                packet[1..=part.len()].copy_from_slice(part);
+                out(packet);
+            }
+            i = end;
+            continue;
+        }
+        let (cin, len) = match status {
+            0x80..=0xBF | 0xE0..=0xEF => (status >> 4, 3),
+            0xC0..=0xDF => (status >> 4, 2),
+            0xF1 | 0xF3 => (0x2, 2),
+            0xF2 => (0x3, 3),
+            0xF6 => (0x5, 1),
+            0xF8..=0xFF => (0xF, 1),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let part = &bytes[i..(i + len).min(bytes.len())];
+        let mut packet = [head | cin, 0, 0, 0];
+        packet[1..=part.len()].copy_from_slice(part);
+        out(packet);
+        i += len;
+    }
+}
+
+/// SysEx の送信エラー
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SysexTxError<E> {
+    TooLong, // MIDI_SYSEX_TX_MAX を超える（途中で切ると F7 のない SysEx になるので、何も送らない）
+    Midi(E),
+}
+
+/// SysEx（F0 から F7 まで）を、途中に他のメッセージを挟まずに送る
+pub async fn send_sysex<M: MidiSink>(
+    midi: &mut M,
+    sysex: &[u8],
+) -> Result<(), SysexTxError<M::Error>> {
+    if sysex.len() > MIDI_SYSEX_TX_MAX {
+        return Err(SysexTxError::TooLong);
+    }
+    let mut packets: Vec<[u8; 4], { MIDI_SYSEX_TX_MAX.div_ceil(3) }> = Vec::new();
+    packetize(0, sysex, |packet| {
+        let _ = packets.push(packet);
+    });
+    midi.send_all(&packets).await.map_err(SysexTxError::Midi)
+}
@@ -0,0 +1,43 @@
+//! USB と DIN の間の MIDI のルーティング（設定の Out / UsbThru / DinThru）
+//!
+//!   タッチ（送信キューのメッセージ）→ USB / DIN / 両方
+//!   USB → DIN: SysEx 以外（SysEx は設定の読み書きに使う）
+//!   DIN → USB: 全て
+use portable_atomic::Ordering;
+
+use crate::settings;
+
+/// 設定の Out の値
+pub const OUT_USB: u8 = 0;
+pub const OUT_DIN: u8 = 1;
+pub const OUT_BOTH: u8 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub touch_usb: bool,
+    pub touch_din: bool,
+    pub usb_to_din: bool,
+    pub din_to_usb: bool,
+}
+
+impl Route {
+    /// 今の設定値から作る
+    pub fn load() -> Self {
+        let out = settings::MIDI_OUT.load(Ordering::Relaxed);
+        Self {
+            touch_usb: out != OUT_DIN,
+            touch_din: out != OUT_USB,
+            usb_to_din: settings::USB_THRU.load(Ordering::Relaxed) != 0,
+            din_to_usb: settings::DIN_THRU.load(Ordering::Relaxed) != 0,
+        }
+    }
+}
+
+/// USB で受信したパケットを DIN に流すか（SysEx と予約の CIN は流さない）
+pub fn usb_thru(packet: [u8; 4]) -> bool {
+    match packet[0] & 0x0F {
+        0x0 | 0x1 | 0x4 | 0x6 | 0x7 => false,
+        0x5 => packet[1] == 0xF6, // Tune Request だけ
+        _ => true,
+    }
+}
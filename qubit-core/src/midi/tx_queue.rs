@@ -0,0 +1,206 @@
+//! MIDI の送信キュー（優先度つき）
+//!
+//! qubit_touch_task はノートなどをキューに入れるだけで待たない。midi_tx_task がまとめて USB に送る
+//!
+//!   優先度: Note Off → Note On（Program Change も）→ 連続的な値（CC・Pitch Bend・Pressure）
+//!   同じ優先度の中では入れた順。同じノートの Note On が残っていれば、Note Off はその後に送る
+//!   連続的な値は、同じ相手（チャンネル・CC 番号・ノートなど）の古い値をキューの中で置き換える
+//!
+//! 満杯のときに捨てるもの（Note Off は Note Off 以外を捨ててでも入れる）
+//!   連続的な値: 新しいメッセージを捨てる（次のフレームで新しい値が来る）
+//!   Note On:   最も新しい連続的な値を捨てて入れる。なければ新しい Note On を捨てる
+//!   Note Off:  最も新しい連続的な値、なければ最も新しい Note On を捨てて入れる
+//!   Note Off は（同じノートの Note On の後に送るものも）捨てない
+use core::cmp::Reverse;
+use heapless::Vec;
+
+use crate::constants::MIDI_TX_BATCH;
+
+/// 1回の USB 転送でまとめて送るパケット
+pub type TxBatch = Vec<[u8; 4], MIDI_TX_BATCH>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    NoteOff,
+    NoteOn,
+    Control,
+}
+
+/// キューに入れるメッセージ（USB-MIDI 1.0 のパケット1つ）
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TxMessage {
+    packet: [u8; 4],
+}
+
+impl TxMessage {
+    pub fn midi1(packet: [u8; 4]) -> Self {
+        Self { packet }
+    }
+
+    pub fn packet(&self) -> [u8; 4] {
+        self.packet
+    }
+
+    /// (ステータスの上位4bit, チャンネル, データ1)
+    fn status(&self) -> (u8, u8, u8) {
+        let [_, status, data1, _] = self.packet;
+        (status & 0xf0, status & 0x0f, data1)
+    }
+
+    pub fn priority(&self) -> Priority {
+        match self.status().0 {
+            0x80 => Priority::NoteOff,
+            0x90 if self.packet[3] == 0 => Priority::NoteOff,
+            0x90 | 0xC0 => Priority::NoteOn,
+            _ => Priority::Control,
+        }
+    }
+
+    /// ノートのメッセージなら (チャンネル, ノート)
+    fn note(&self) -> Option<(u8, u8)> {
+        match self.status() {
+            (0x80 | 0x90, channel, note) => Some((channel, note)),
+            _ => None,
+        }
+    }
+
+    /// 連続的な値の相手（同じ相手の値は置き換える）
+    fn target(&self) -> u32 {
+        let (status, channel, data1) = self.status();
+        let data1 = match status {
+            0xD0 | 0xE0 => 0, // Channel Pressure, Pitch Bend はチャンネルに1つ
+            _ => data1,
+        };
+        (status as u32 | channel as u32) << 8 | data1 as u32
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Entry {
+    message: TxMessage,
+    priority: Priority, // メッセージの優先度（捨てるものを選ぶ）
+    order: Priority,    // 送る順（同じノートの Note On の後の Note Off は NoteOn）
+    seq: u32,           // 入れた順
+}
+
+/// N はキューに入るメッセージ数
+pub struct TxQueue<const N: usize> {
+    entries: Vec<Entry, N>,
+    seq: u32,
+}
+
+impl<const N: usize> TxQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// キューに入れる。満杯で、方針に従って何かを捨てたら false
+    pub fn push(&mut self, message: TxMessage) -> bool {
+        let priority = message.priority();
+        if priority == Priority::Control {
+            let target = message.target();
+            if let Some(entry) = self
+                .entries
+                .iter_mut()
+                .find(|e| e.priority == Priority::Control && e.message.target() == target)
+            {
+                entry.message = message;
+                return true;
+            }
+        }
+        let mut order = priority;
+        if priority == Priority::NoteOff {
+            let note = message.note();
+            if self
+                .entries
+                .iter()
+                .any(|e| e.priority == Priority::NoteOn && e.message.note() == note)
+            {
+                order = Priority::NoteOn; // 同じノートの Note On の後にする
+            }
+        }
+
+        let mut lost = false;
+        if self.entries.is_full() {
+            let victim = match priority {
+                Priority::Control => None,
+                Priority::NoteOn => self.newest(Priority::Control),
+                Priority::NoteOff => self
+                    .newest(Priority::Control)
+                    .or_else(|| self.newest(Priority::NoteOn)),
+            };
+            match victim {
+                Some(index) => {
+                    self.entries.remove(index);
+                    lost = true;
+                }
+                None => return false,
+            }
+        }
+        self.seq = self.seq.wrapping_add(1);
+        let _ = self.entries.push(Entry {
+            message,
+            priority,
+            order,
+            seq: self.seq,
+        });
+        !lost
+    }
+
+    /// 優先度の最も高いメッセージを取り出す
+    pub fn pop(&mut self) -> Option<TxMessage> {
+        let index = self.next()?;
+        Some(self.entries.remove(index).message)
+    }
+
+    /// 1回の USB 転送に入るだけ、優先度の順に取り出す
+    pub fn pop_batch(&mut self, batch: &mut TxBatch) {
+        batch.clear();
+        while !batch.is_full()
+            && let Some(index) = self.next()
+        {
+            let _ = batch.push(self.entries.remove(index).message.packet());
+        }
+    }
+
+    /// 入れてからいくつ後に入れたものがあるか（seq が一周しても比べられる）
+    fn age(&self, entry: &Entry) -> u32 {
+        self.seq.wrapping_sub(entry.seq)
+    }
+
+    /// 次に送るもの: 優先度の高いものの中で最も古いもの
+    fn next(&self) -> Option<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, e)| (e.order, Reverse(self.age(e))))
+            .map(|(i, _)| i)
+    }
+
+    /// 指定した優先度の中で最も新しいもの
+    fn newest(&self, priority: Priority) -> Option<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.priority == priority)
+            .min_by_key(|(_, e)| self.age(e))
+            .map(|(i, _)| i)
+    }
+}
+
+impl<const N: usize> Default for TxQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,380 @@
+use portable_atomic::{AtomicU8, Ordering};
+
+use crate::WORK_MODE;
+use crate::constants::KEY_MASK_BYTES;
+use crate::display::{OledConfig, Panel};
+use crate::touch::key_mask::KeyMask;
+
+// =========================================================
+//      Setting Values
+// =========================================================
+pub static OLED_CONTRAST: AtomicU8 = AtomicU8::new(0xFF);
+pub static OLED_DIM: AtomicU8 = AtomicU8::new(0);
+pub static OLED_INVERT: AtomicU8 = AtomicU8::new(0);
+pub static OLED_FLIP: AtomicU8 = AtomicU8::new(0);
+pub static OLED_PANEL: AtomicU8 = AtomicU8::new(0); // 0: SSD1306, 1: SH1106
+pub static OLED_AUTO_SLEEP: AtomicU8 = AtomicU8::new(0); // 操作がなければ表示オフするまでの分数（0: しない）
+
+pub static TOUCH_RECORD: AtomicU8 = AtomicU8::new(0); // タッチの生データを SysEx で送る
+pub static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(0); // 0: 外部（MIDI クロックに従う）, 1: 内部（MIDI クロックを送る）
+pub static TEMPO: AtomicU8 = AtomicU8::new(120); // 内部クロックのテンポ（BPM）
+
+// タッチで送る MIDI（qubit_core::midi::output）
+pub static MIDI_CHANNEL: AtomicU8 = AtomicU8::new(13); // 1-16
+pub static TRANSPOSE: AtomicU8 = AtomicU8::new(TRANSPOSE_ZERO); // 半音（TRANSPOSE_ZERO が 0）
+pub static OCTAVE: AtomicU8 = AtomicU8::new(OCTAVE_ZERO); // オクターブ（OCTAVE_ZERO が 0）
+pub static NOTE_LOW: AtomicU8 = AtomicU8::new(0); // 送るノートの範囲
+pub static NOTE_HIGH: AtomicU8 = AtomicU8::new(127);
+pub static NOTE_FOLD: AtomicU8 = AtomicU8::new(0); // 範囲外のノート 0: 端に寄せる, 1: オクターブを変えて範囲に入れる
+pub static SCALE: AtomicU8 = AtomicU8::new(0); // 0: Chromatic, 1: Major, 2: Minor, 3: Pentatonic
+pub static RX_FOLD: AtomicU8 = AtomicU8::new(0); // 鳴らせないノートを受信したら、同じ音名のところに表示する
+
+// 使わなくなったパラメータの ID に置く値（常に 0）
+static RESERVED: AtomicU8 = AtomicU8::new(0);
+
+// USB と DIN のルーティング（qubit_core::midi::router）
+pub static MIDI_OUT: AtomicU8 = AtomicU8::new(0); // タッチの送信先 0: USB, 1: DIN, 2: 両方
+pub static USB_THRU: AtomicU8 = AtomicU8::new(0); // USB で受信したものを DIN に送る
+pub static DIN_THRU: AtomicU8 = AtomicU8::new(0); // DIN で受信したものを USB に送る
+
+// 設定ではなく実行時の状態: 表示オフ中か
+pub static OLED_SLEEPING: AtomicU8 = AtomicU8::new(0);
+// 設定ではなく実行時の状態: テレメトリを SysEx で送るか（SysEx で切り替える）
+pub static TELEMETRY: AtomicU8 = AtomicU8::new(0);
+
+const OFF_ON: &[&str] = &["Off", "On"];
+
+pub const TRANSPOSE_ZERO: u8 = 12;
+pub const OCTAVE_ZERO: u8 = 3;
+const TRANSPOSE_LABELS: &[&str] = &[
+    "-12", "-11", "-10", "-9", "-8", "-7", "-6", "-5", "-4", "-3", "-2", "-1", "0", "+1", "+2",
+    "+3", "+4", "+5", "+6", "+7", "+8", "+9", "+10", "+11", "+12",
+];
+const OCTAVE_LABELS: &[&str] = &["-3", "-2", "-1", "0", "+1", "+2", "+3"];
+
+// =========================================================
+//      Parameter Table
+// =========================================================
+/// 設定項目（配列の添字がパラメータID）
+pub struct Param {
+    pub name: &'static str,
+    pub min: u8,
+    pub max: u8,
+    pub step: u8,
+    pub labels: &'static [&'static str], // 空でなければ値の表示名
+    pub value: &'static AtomicU8,
+}
+
+impl Param {
+    pub fn get(&self) -> u8 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// 範囲外の値は受け付けない
+    pub fn set(&self, value: u8) -> bool {
+        if value < self.min || value > self.max {
+            return false;
+        }
+        self.value.store(value, Ordering::Relaxed);
+        true
+    }
+
+    /// step ずつ増やし、最大値を超えたら最小値に戻る（OLEDメニュー用）
+    pub fn cycle(&self) {
+        let value = self.get();
+        let next = match value.checked_add(self.step) {
+            Some(next) if next <= self.max => next,
+            _ => self.min,
+        };
+        self.set(next);
+    }
+
+    /// 使わなくなった ID（メニューや一覧に出さず、SysEx でも読み書きできない）
+    pub fn is_reserved(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// 値の表示名（labels がなければ None）
+    pub fn label(&self) -> Option<&'static str> {
+        self.labels.get(self.get() as usize).copied()
+    }
+
+    /// 表示名（"Violin" や Transpose の "-2"）を先に探し、なければ数値（CLI とコンソール用）
+    pub fn parse(&self, value: &str) -> Option<u8> {
+        match self
+            .labels
+            .iter()
+            .position(|label| label.eq_ignore_ascii_case(value))
+        {
+            Some(i) => Some(i as u8),
+            None => value.parse().ok(),
+        }
+    }
+}
+
+/// 名前（大文字小文字は区別しない）か ID でパラメータを探す（予約の ID は見つからない）
+pub fn find_param(name: &str) -> Option<(u8, &'static Param)> {
+    let id = name.parse::<usize>().ok();
+    PARAMS
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| !p.is_reserved())
+        .find(|(i, p)| p.name.eq_ignore_ascii_case(name) || id == Some(*i))
+        .map(|(i, p)| (i as u8, p))
+}
+
+pub const NUM_PARAMS: usize = 22;
+
+pub static PARAMS: [Param; NUM_PARAMS] = [
+    Param {
+        name: "Mode",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: &["Piano", "Violin"],
+        value: &WORK_MODE,
+    },
+    Param {
+        name: "Contrast",
+        min: 0x0F,
+        max: 0xFF,
+        step: 0x10,
+        labels: &[],
+        value: &OLED_CONTRAST,
+    },
+    Param {
+        name: "Dim",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &OLED_DIM,
+    },
+    Param {
+        name: "Invert",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &OLED_INVERT,
+    },
+    Param {
+        name: "Flip",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &OLED_FLIP,
+    },
+    Param {
+        name: "Panel",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: &["SSD1306", "SH1106"],
+        value: &OLED_PANEL,
+    },
+    Param {
+        name: "Sleep(min)",
+        min: 0,
+        max: 30,
+        step: 5,
+        labels: &[],
+        value: &OLED_AUTO_SLEEP,
+    },
+    Param {
+        name: "Record",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &TOUCH_RECORD,
+    },
+    Param {
+        name: "Clock",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: &["Ext", "Int"],
+        value: &CLOCK_SOURCE,
+    },
+    Param {
+        name: "Tempo",
+        min: TEMPO_MIN,
+        max: TEMPO_MAX,
+        step: 10,
+        labels: &[],
+        value: &TEMPO,
+    },
+    Param {
+        name: "Channel",
+        min: 1,
+        max: 16,
+        step: 1,
+        labels: &[],
+        value: &MIDI_CHANNEL,
+    },
+    Param {
+        name: "Transpose",
+        min: 0,
+        max: 2 * TRANSPOSE_ZERO,
+        step: 1,
+        labels: TRANSPOSE_LABELS,
+        value: &TRANSPOSE,
+    },
+    Param {
+        name: "Octave",
+        min: 0,
+        max: 2 * OCTAVE_ZERO,
+        step: 1,
+        labels: OCTAVE_LABELS,
+        value: &OCTAVE,
+    },
+    // メニューでは C ごと（Low）、G ごと（High）に変わる
+    Param {
+        name: "NoteLow",
+        min: 0,
+        max: 120,
+        step: 12,
+        labels: &[],
+        value: &NOTE_LOW,
+    },
+    Param {
+        name: "NoteHigh",
+        min: 7,
+        max: 127,
+        step: 12,
+        labels: &[],
+        value: &NOTE_HIGH,
+    },
+    Param {
+        name: "Range",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: &["Clamp", "Fold"],
+        value: &NOTE_FOLD,
+    },
+    // 16 は予約（以前の MIDI2）。保存したデータと SysEx の ID をずらさないために残す
+    Param {
+        name: "",
+        min: 0,
+        max: 0,
+        step: 0,
+        labels: &[],
+        value: &RESERVED,
+    },
+    Param {
+        name: "Out",
+        min: 0,
+        max: 2,
+        step: 1,
+        labels: &["USB", "DIN", "Both"],
+        value: &MIDI_OUT,
+    },
+    Param {
+        name: "UsbThru",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &USB_THRU,
+    },
+    Param {
+        name: "DinThru",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &DIN_THRU,
+    },
+    // 保存したデータの並びを変えないように、後から足したものは最後に置く
+    Param {
+        name: "Scale",
+        min: 0,
+        max: 3,
+        step: 1,
+        labels: &["Chrom", "Major", "Minor", "Penta"],
+        value: &SCALE,
+    },
+    Param {
+        name: "RxFold",
+        min: 0,
+        max: 1,
+        step: 1,
+        labels: OFF_ON,
+        value: &RX_FOLD,
+    },
+];
+
+pub const TEMPO_MIN: u8 = 40;
+pub const TEMPO_MAX: u8 = 240;
+
+/// 内部クロックで MIDI クロックを送るか
+pub fn is_clock_master() -> bool {
+    CLOCK_SOURCE.load(Ordering::Relaxed) != 0
+}
+
+/// 設定値から OLED の表示設定を作る
+pub fn oled_config() -> OledConfig {
+    OledConfig {
+        panel: if OLED_PANEL.load(Ordering::Relaxed) == 0 {
+            Panel::Ssd1306
+        } else {
+            Panel::Sh1106
+        },
+        contrast: OLED_CONTRAST.load(Ordering::Relaxed),
+        dim: OLED_DIM.load(Ordering::Relaxed) != 0,
+        invert: OLED_INVERT.load(Ordering::Relaxed) != 0,
+        flip: OLED_FLIP.load(Ordering::Relaxed) != 0,
+        sleep: OLED_SLEEPING.load(Ordering::Relaxed) != 0,
+    }
+}
+
+// =========================================================
+//      Saved Settings
+// =========================================================
+// フラッシュに保存する形式
+//   "QSET" | version | パラメータ数 n | 値 × n | 手動キーマスク(12byte) | チェックサム
+// パラメータが増えても、古いデータの先頭 n 個はそのまま読める
+pub const SAVED_MAGIC: [u8; 4] = *b"QSET";
+pub const SAVED_VERSION: u8 = 1;
+pub const SAVED_SIZE: usize = 6 + NUM_PARAMS + KEY_MASK_BYTES + 1;
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// 今の設定値と手動キーマスクを、保存する形式にする
+pub fn encode_saved() -> [u8; SAVED_SIZE] {
+    let mut data = [0u8; SAVED_SIZE];
+    data[0..4].copy_from_slice(&SAVED_MAGIC);
+    data[4] = SAVED_VERSION;
+    data[5] = NUM_PARAMS as u8;
+    for (d, param) in data[6..6 + NUM_PARAMS].iter_mut().zip(PARAMS.iter()) {
+        *d = param.get();
+    }
+    data[6 + NUM_PARAMS..SAVED_SIZE - 1].copy_from_slice(&KeyMask::load_manual().to_bytes());
+    data[SAVED_SIZE - 1] = checksum(&data[..SAVED_SIZE - 1]);
+    data
+}
+
+/// 保存したデータを読み込んで反映する。壊れていれば何もせずに false
+/// 範囲外の値は反映しない
+pub fn load_saved(data: &[u8]) -> bool {
+    if data.len() < 6 || data[0..4] != SAVED_MAGIC || data[4] != SAVED_VERSION {
+        return false;
+    }
+    let count = data[5] as usize;
+    let size = 6 + count + KEY_MASK_BYTES + 1;
+    if data.len() < size || checksum(&data[..size - 1]) != data[size - 1] {
+        return false;
+    }
+    for (param, value) in PARAMS.iter().zip(data[6..6 + count].iter()) {
+        param.set(*value);
+    }
+    let mut mask = [0u8; KEY_MASK_BYTES];
+    mask.copy_from_slice(&data[6 + count..size - 1]);
+    KeyMask::from_bytes(&mask).store_manual();
+    true
+}
@@ -0,0 +1,643 @@
+//! タスクの本体: hal のトレイトだけに依存するので、ファームウェアでもホストでも動く
+//! ファームウェアでは main.rs の embassy タスクから RP2350 向けの実装を渡して呼ぶ
+use core::cell::RefCell;
+use heapless::{String, Vec};
+use portable_atomic::Ordering;
+
+use crate::console::{self, ECHO_SIZE, LineEditor, PROMPT, Reply};
+use crate::constants::*;
+use crate::hal::{
+    Clock, FrameBufferSink, LedEventSink, LedEventSource, MidiQueueSink, MidiQueueSource, MidiSink,
+    MidiSource, PixelSink, Reboot, Report, ReportSink, ReportSource, SerialSink, SerialSource,
+    SettingsStore, SwitchInput, Switches, TouchFrameSource,
+};
+use crate::midi::clock::{
+    ClockMaster, ClockState, MidiClock, TapTempo, Transport, take_transport_request,
+    toggle_transport,
+};
+use crate::midi::config;
+use crate::midi::loopian::{self, LoopianState};
+use crate::midi::note_map::{NoteMap, RxLeds};
+use crate::midi::output::SoundingNotes;
+use crate::midi::parser::{MidiMessage, UsbMidiEvent, UsbMidiParser, send_sysex};
+use crate::midi::router::{self, Route};
+use crate::midi::tx_queue::{TxBatch, TxMessage};
+use crate::timing::{
+    self, TASK_CLOCK, TASK_DIN_RX, TASK_MIDI_RX, TASK_MIDI_TX, TASK_OLED_UI, TASK_RINGLED,
+    TASK_TOUCH,
+};
+use crate::touch::key_mask::KeyMask;
+use crate::touch::qtouch::QubitTouch;
+use crate::touch::recording::{self, FrameRecord};
+use crate::ui::led_look::{self, LedLook};
+use crate::ui::oled_display::{
+    GraphicsDisplay, PAGE_CLOCK, PAGE_HOST, PAGE_SETTINGS, next_page, prev_page,
+};
+use crate::ui::ringled::RingLed;
+use crate::ui::{host_frame, host_page};
+use crate::{AD_VALUE0, AD_VALUE1, AD_VALUE2, AD_VALUE3, ERROR_CODE, SCAN_MAX_AGE, settings};
+
+// タッチイベントのデータ構造
+#[derive(Copy, Clone, Default)]
+struct TouchEvent(u8, u8, u8, f32); // (status, note, velocity, location)
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      QubitTouch Task: タッチセンサの解析とMIDIイベントの送信
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+pub async fn qubit_touch_task<S, R, Q, L, C>(
+    source: &mut S,
+    reports: &mut R,
+    queue: &mut Q,
+    leds: &mut L,
+    clock: &C,
+) where
+    S: TouchFrameSource,
+    R: ReportSink,
+    Q: MidiQueueSink,
+    L: LedEventSink,
+    C: Clock,
+{
+    const MAX_EVENT: usize = 16; // タッチ4つの移動（On と Off）と離したときの Off
+    let send_buffer = RefCell::new([TouchEvent::default(); MAX_EVENT]);
+    let send_index = RefCell::new(0);
+    let mut qt = QubitTouch::new(|status, note, velocity, location| {
+        // MIDIコールバック: タッチイベントをMIDIパケットに変換して送信
+        let packet = TouchEvent(status, note, velocity, location);
+        let mut buf = send_buffer.borrow_mut();
+        let mut idx = send_index.borrow_mut();
+        if *idx < buf.len() {
+            buf[*idx] = packet;
+            *idx += 1;
+        } else {
+            // バッファオーバーフローの場合はエラーカウントをインクリメント
+            ERROR_CODE.store(41, Ordering::Relaxed);
+        }
+    });
+    let mut recording = false;
+    let mut telemetry_counter = 0u32;
+    let mut sounding = SoundingNotes::new();
+
+    loop {
+        // タッチスキャンは10msごとに実行
+        clock.delay_ms(10).await;
+        let start_us = clock.now_us();
+
+        // タッチセンサの生データを取得してQubitTouchにセット
+        let frame = source.read_frame().await;
+        let mut touch_values = frame.values;
+        // 巡回スキャンで最も長く読まれていないチャンネルの経過時間
+        let now = clock.now_us();
+
+        // 記録中は QubitTouch に渡すフレームを、そのまま SysEx で送る（送るのは report_task）
+        let record = settings::TOUCH_RECORD.load(Ordering::Relaxed) != 0;
+        let mut report = Report::default();
+        if record {
+            let adc = [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3]
+                .map(|v| v.load(Ordering::Relaxed).min(u16::MAX as u32) as u16);
+            report.header = !recording;
+            report.frame = Some(FrameRecord {
+                time_us: now,
+                frame,
+                adc,
+            });
+        }
+        recording = record;
+
+        let max_age = frame
+            .timestamps
+            .iter()
+            .map(|t| now.saturating_sub(*t))
+            .max()
+            .unwrap_or(0);
+        SCAN_MAX_AGE.store(max_age.min(u32::MAX as u64) as u32, Ordering::Relaxed);
+        // マスクされたキー（故障チップなど）は両隣から補間して、偽のピークを作らない
+        KeyMask::load().interpolate(&mut touch_values);
+        for (ch, tv) in touch_values.iter().enumerate() {
+            qt.set_value(ch, *tv);
+        }
+        qt.seek_and_update_touch_point();
+        // ノートは送信キューに入れるだけで待たない（USB に送るのは midi_tx_task）
+        let idx = core::mem::take(&mut *send_index.borrow_mut());
+        // 受信したノートの表示（receive_message）と同じ対応で送る
+        let output = NoteMap::load().output;
+        let mut queued = true;
+        for packet in send_buffer.borrow().iter().take(idx) {
+            let id = packet.0 & 0x0f; // タッチID
+            // 移動イベントはNote Offとして扱う
+            let (status, note) = if packet.0 & 0xf0 == RINGLED_CMD_TX_ON {
+                sounding.note_on(id, packet.1, &output)
+            } else {
+                sounding.note_off(id, packet.1, &output)
+            };
+            queued &= queue.post(TxMessage::midi1([status >> 4, status, note, packet.2]));
+            // バグ対策: RingLEDキュー満杯でタスク全体が停止しないよう非ブロッキング送信にする
+            if !leds.post(packet.0, packet.3) {
+                ERROR_CODE.store(44, Ordering::Relaxed);
+            }
+        }
+
+        if !queued {
+            // 送信キューが満杯で、方針に従って捨てた
+            ERROR_CODE.store(43, Ordering::Relaxed);
+        }
+
+        // テレメトリ（タッチ位置と圧力）は 50ms ごと
+        telemetry_counter = telemetry_counter.wrapping_add(1);
+        report.telemetry =
+            settings::TELEMETRY.load(Ordering::Relaxed) != 0 && telemetry_counter.is_multiple_of(5);
+        if (report.frame.is_some() || report.telemetry) && !reports.post(report) {
+            // 送信が追いつかず、キューが満杯で捨てた
+            let code = if report.frame.is_some() { 46 } else { 47 };
+            ERROR_CODE.store(code, Ordering::Relaxed);
+        }
+        qt.lighten_leds(|_location, _intensity| {
+            // LEDの明るさをタッチの強さに応じて変化させる
+            //WHITE_LEVEL.store(intensity as u8, Ordering::Relaxed);
+        });
+        timing::record(TASK_TOUCH, start_us, clock.now_us());
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      MIDI TX Task: 送信キューのメッセージをまとめて送る
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+/// 送信先（USB / DIN / 両方）は設定の Out に従う
+pub async fn midi_tx_task<Q, M, D, C>(queue: &mut Q, usb: &mut M, din: &mut D, clock: &C)
+where
+    Q: MidiQueueSource,
+    M: MidiSink,
+    D: MidiSink,
+    C: Clock,
+{
+    let mut batch = TxBatch::new();
+    loop {
+        queue.take_batch(&mut batch).await;
+        let start_us = clock.now_us();
+        let route = Route::load();
+        if route.touch_usb && usb.send_all(&batch).await.is_err() {
+            // タイムアウトまたは送信エラー（USB未接続時など）
+            ERROR_CODE.store(42, Ordering::Relaxed);
+        }
+        if route.touch_din && din.send_all(&batch).await.is_err() {
+            ERROR_CODE.store(49, Ordering::Relaxed);
+        }
+        timing::record(TASK_MIDI_TX, start_us, clock.now_us());
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Report Task: タッチの記録とテレメトリの SysEx を USB に送る
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+/// SysEx は長いので、USB の送信を待つのはタッチのループではなくこのタスク
+pub async fn report_task<R, M>(reports: &mut R, usb: &mut M)
+where
+    R: ReportSource,
+    M: MidiSink,
+{
+    loop {
+        let report = reports.receive().await;
+        if let Some(rec) = report.frame {
+            let mut sent = true;
+            if report.header {
+                sent &= recording::send_chunk(usb, &recording::encode_header())
+                    .await
+                    .is_ok();
+            }
+            sent &= recording::send_chunk(usb, &rec.encode()).await.is_ok();
+            if !sent {
+                ERROR_CODE.store(46, Ordering::Relaxed);
+            }
+        }
+        if report.telemetry && send_sysex(usb, &config::telemetry_sysex()).await.is_err() {
+            ERROR_CODE.store(47, Ordering::Relaxed);
+        }
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      DIN RX Task: DIN で受信したMIDIを USB に流す
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+/// DinThru が Off のときも読み続けて捨てる
+pub async fn din_rx_task<R, M, C>(receiver: &mut R, usb: &mut M, clock: &C)
+where
+    R: MidiSource,
+    M: MidiSink,
+    C: Clock,
+{
+    let mut buf = [0; 64];
+    loop {
+        match receiver.receive(&mut buf).await {
+            Ok(n) => {
+                if n == 0 || !Route::load().din_to_usb {
+                    continue;
+                }
+                let start_us = clock.now_us();
+                let packets: Vec<[u8; 4], 16> = buf[0..n]
+                    .chunks_exact(4)
+                    .map(|p| [p[0], p[1], p[2], p[3]])
+                    .collect();
+                if usb.send_all(&packets).await.is_err() {
+                    ERROR_CODE.store(42, Ordering::Relaxed);
+                }
+                timing::record(TASK_DIN_RX, start_us, clock.now_us());
+            }
+            Err(_e) => ERROR_CODE.store(57, Ordering::Relaxed),
+        }
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      MIDI RX Task: 受信したMIDIイベントの処理
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+/// UsbThru が On なら、SysEx 以外を DIN にも流す
+pub async fn midi_rx_task<R, L, M, T, D, C>(
+    receiver: &mut R,
+    leds: &mut L,
+    replies: &mut M,
+    thru: &mut T,
+    device: &mut D,
+    clock: &C,
+) where
+    R: MidiSource,
+    L: LedEventSink,
+    M: MidiSink,
+    T: MidiSink,
+    D: SettingsStore + Reboot,
+    C: Clock,
+{
+    let mut buf = [0; 64];
+    let mut parser = UsbMidiParser::<MIDI_SYSEX_BUFFER_SIZE>::new();
+    let mut midi_clock = MidiClock::new();
+    let mut rx_leds = RxLeds::new();
+
+    loop {
+        match receiver.receive(&mut buf).await {
+            Ok(n) => {
+                let start_us = clock.now_us();
+                let route = Route::load();
+                for packet in buf[0..n].chunks_exact(4) {
+                    let packet = [packet[0], packet[1], packet[2], packet[3]];
+                    if route.usb_to_din
+                        && router::usb_thru(packet)
+                        && thru.send(packet).await.is_err()
+                    {
+                        ERROR_CODE.store(49, Ordering::Relaxed);
+                    }
+                    match parser.parse(packet) {
+                        Ok(Some(UsbMidiEvent {
+                            message: MidiMessage::SysEx(data),
+                            ..
+                        })) => {
+                            // Loopian の状態の表示・ホストの LED のフレームと OLED のページ（返信しない）か、設定の読み書き
+                            let now = clock.now_us();
+                            if !loopian::receive(data, now)
+                                && !host_frame::receive(data, now)
+                                && !host_page::receive(data, now)
+                                && config::handle_sysex(data, replies, device).await.is_err()
+                            {
+                                ERROR_CODE.store(56, Ordering::Relaxed);
+                            }
+                        }
+                        Ok(Some(event)) => {
+                            // クロックへの同期（状態は RingLED などが読む）。内部クロックのときは使わない
+                            if midi_clock.receive(&event.message, clock.now_us()) {
+                                if !settings::is_clock_master() {
+                                    midi_clock.state().store();
+                                }
+                            } else {
+                                receive_message(event.message, leds, &mut rx_leds);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(_) => ERROR_CODE.store(55, Ordering::Relaxed),
+                    }
+                }
+                timing::record(TASK_MIDI_RX, start_us, clock.now_us());
+            }
+            Err(_e) => {
+                // エラーカウント
+                ERROR_CODE.store(54, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// 受信したメッセージの処理
+/// Note On/Off は RingLED に表示し、CC と Program Change は RingLED の見た目を変える（ui::led_look）
+/// ノートはタッチで送るときと同じ対応で、そのノートを鳴らすパッドの LED に表示する（midi::note_map）
+fn receive_message<L: LedEventSink>(message: MidiMessage, leds: &mut L, rx_leds: &mut RxLeds) {
+    // バグ対策: RingLEDキュー満杯でもmidi_rx_taskを止めない
+    match message {
+        MidiMessage::NoteOn { note, velocity, .. } => {
+            if velocity > 0 {
+                if let Some(led) = rx_leds.note_on(note, &NoteMap::load())
+                    && !leds.post(RINGLED_CMD_RX_ON, led)
+                {
+                    ERROR_CODE.store(51, Ordering::Relaxed);
+                }
+            } else if let Some(led) = rx_leds.note_off(note)
+                && !leds.post(RINGLED_CMD_RX_OFF, led)
+            {
+                ERROR_CODE.store(52, Ordering::Relaxed);
+            }
+        }
+        MidiMessage::NoteOff { note, .. } => {
+            if let Some(led) = rx_leds.note_off(note)
+                && !leds.post(RINGLED_CMD_RX_OFF, led)
+            {
+                ERROR_CODE.store(53, Ordering::Relaxed);
+            }
+        }
+        MidiMessage::ControlChange { control, value, .. } => {
+            led_look::control_change(control, value);
+        }
+        MidiMessage::ProgramChange { program, .. } => {
+            led_look::program_change(program);
+        }
+        _ => {}
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      MIDI Clock Task: 内部クロックのとき MIDI クロックを送る
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+/// 送信先（USB / DIN / 両方）はタッチと同じく設定の Out に従う
+pub async fn midi_clock_task<M, D, C>(usb: &mut M, din: &mut D, clock: &C)
+where
+    M: MidiSink,
+    D: MidiSink,
+    C: Clock,
+{
+    let mut master = ClockMaster::new(clock.now_us(), settings::TEMPO.load(Ordering::Relaxed));
+    let mut state = MidiClock::new();
+    let mut was_master = false;
+    loop {
+        if !settings::is_clock_master() {
+            // 外部クロックのとき: 内部クロックに戻ったら、今から数え直す
+            if was_master {
+                ClockState::default().store();
+                was_master = false;
+            }
+            clock.delay_ms(100).await;
+            master = ClockMaster::new(clock.now_us(), master.bpm());
+            continue;
+        }
+        was_master = true;
+        // 次の Clock の時刻まで待つ（ループの回数ではなく、時刻で決める）
+        master.set_tempo(settings::TEMPO.load(Ordering::Relaxed));
+        let tick_us = master.next_tick_us();
+        clock.delay_until(tick_us).await;
+        let start_us = clock.now_us();
+        master.advance();
+
+        // スタート・ストップは Clock の直前に送る（Start の次の Clock が曲の頭）
+        let transport = match take_transport_request() {
+            Some(Transport::Start) => Some((0xFA, MidiMessage::Start)),
+            Some(Transport::Continue) => Some((0xFB, MidiMessage::Continue)),
+            Some(Transport::Stop) => Some((0xFC, MidiMessage::Stop)),
+            None => None,
+        };
+        let mut packets: Vec<[u8; 4], 2> = Vec::new();
+        if let Some((status, message)) = transport {
+            let _ = packets.push([0x0F, status, 0, 0]);
+            state.receive(&message, tick_us);
+        }
+        let _ = packets.push([0x0F, 0xF8, 0, 0]);
+        state.receive(&MidiMessage::Clock, tick_us);
+        state.state().store();
+        let route = Route::load();
+        if route.touch_usb && usb.send_all(&packets).await.is_err() {
+            ERROR_CODE.store(48, Ordering::Relaxed);
+        }
+        if route.touch_din && din.send_all(&packets).await.is_err() {
+            ERROR_CODE.store(49, Ordering::Relaxed);
+        }
+        timing::record(TASK_CLOCK, start_us, clock.now_us());
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      RingLED Task: MIDIイベントに応じてLEDを制御
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+pub async fn ringled_task<P, E, C>(pixels: &mut P, events: &mut E, clock: &C)
+where
+    P: PixelSink,
+    E: LedEventSource,
+    C: Clock,
+{
+    const PERIOD_US: u64 = 20_000;
+    let mut ring_led = RingLed::new();
+    let mut data = [crate::hal::Rgbw::default(); NUM_LEDS];
+    let mut next_tick = clock.now_us();
+    loop {
+        let start_us = clock.now_us();
+        // マスクされたキーはLEDで知らせる
+        ring_led.set_key_mask(KeyMask::load());
+        // MIDI クロックに同期しているときは拍に合わせて光らせる
+        ring_led.set_beat(ClockState::load().beat(clock.now_us()));
+        // 明るさと色は MIDI の CC / Program Change で変わる（midi_rx_task が書く）
+        ring_led.set_look(LedLook::load());
+        // Loopian のループの位置（midi_rx_task が書く）
+        ring_led.set_playhead(LoopianState::load().playhead(&ClockState::load(), clock.now_us()));
+        // ホストが送った LED のフレーム（タイムアウトしたらローカルの表示に戻る）
+        ring_led.set_host_frame(host_frame::active_frame(clock.now_us()));
+        // バグ対策: 1周期でキューを可能な限りドレインして、送信側の詰まりを防ぐ
+        let mut drained = false;
+        while let Some((cmd, location)) = events.poll() {
+            drained = true;
+            ring_led.set_color(&mut data, location, cmd);
+        }
+        if !drained {
+            ring_led.set_color(&mut data, RINGLED_CMD_NONE as f32, RINGLED_CMD_NONE);
+        }
+        if pixels.write(&data).await.is_err() {
+            ERROR_CODE.store(45, Ordering::Relaxed);
+        }
+        timing::record(TASK_RINGLED, start_us, clock.now_us());
+        // 20msごとに更新する（処理が遅れたら、次の周期から数え直す）
+        next_tick += PERIOD_US;
+        let now = clock.now_us();
+        if next_tick < now {
+            next_tick = now;
+        }
+        clock.delay_until(next_tick).await;
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      OLED UI Task: OLEDディスプレイの更新
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+pub async fn oled_ui_task<D, W, C>(
+    gui: &mut GraphicsDisplay,
+    display: &mut D,
+    switches: &mut W,
+    clock: &C,
+) where
+    D: FrameBufferSink,
+    W: SwitchInput,
+    C: Clock,
+{
+    let mut counter = 0u32;
+    let mut ui_page = 0u8;
+
+    let mut switch1_prev = false;
+    let mut switch2_prev = false;
+    let mut last_input = clock.now_us(); // 自動スリープ用
+    let mut tap_tempo = TapTempo::new();
+    let mut left_hold = 0u32; // 左スイッチを押し続けている周期数（クロック画面の長押し用）
+    let mut host_closed_us = None; // スイッチで閉じたホストのページの書き込みの時刻
+
+    // 初期画面表示
+    let mut buffer = display.acquire().await;
+    gui.draw_bringup_screen(&mut buffer);
+    display.present(buffer).await;
+
+    loop {
+        // 次のステップまで待機(10fps想定)
+        // スイッチは 10ms ごとに読み、左を押した時刻を覚えておく（タップテンポ用）
+        let mut switch_r_state = false;
+        let mut switch_l_state = false;
+        let mut pressed_r = false;
+        let mut pressed_l_at = None;
+        let mut state = Switches::default();
+        for _ in 0..10 {
+            clock.delay_ms(10).await;
+            state = switches.read();
+            pressed_r |= state.right && !switch1_prev;
+            if state.left && !switch2_prev && pressed_l_at.is_none() {
+                pressed_l_at = Some(clock.now_us());
+            }
+            switch_r_state |= state.right;
+            switch_l_state |= state.left;
+            switch1_prev = state.right;
+            switch2_prev = state.left;
+        }
+        let mut pressed_l = pressed_l_at.is_some();
+
+        // 空バッファを受信
+        buffer = display.acquire().await;
+        let start_us = clock.now_us();
+
+        // ホストのページ: 書き込みがあれば表示し、スイッチを押すと次の書き込みまで閉じる（設定画面では出さない）
+        let mut host_shown = ui_page != PAGE_SETTINGS
+            && host_page::is_active(clock.now_us())
+            && host_closed_us != Some(host_page::received_us());
+        if host_shown && (pressed_r || pressed_l) {
+            host_closed_us = Some(host_page::received_us());
+            host_shown = false;
+            pressed_r = false;
+            pressed_l = false;
+        }
+        if host_shown {
+            // ホストが書いている間はスリープしない
+            last_input = clock.now_us();
+            settings::OLED_SLEEPING.store(0, Ordering::Relaxed);
+        }
+
+        // 自動スリープ: 操作がなければ表示をオフにし、次の操作は表示を戻すだけにする
+        if pressed_r || pressed_l {
+            last_input = clock.now_us();
+            if settings::OLED_SLEEPING.load(Ordering::Relaxed) != 0 {
+                settings::OLED_SLEEPING.store(0, Ordering::Relaxed);
+                pressed_r = false;
+                pressed_l = false;
+            }
+        }
+        let auto_sleep = settings::OLED_AUTO_SLEEP.load(Ordering::Relaxed) as u64;
+        if auto_sleep > 0 && clock.now_us().saturating_sub(last_input) > auto_sleep * 60_000_000 {
+            settings::OLED_SLEEPING.store(1, Ordering::Relaxed);
+        }
+
+        if pressed_r {
+            if switch_l_state {
+                // 両方のスイッチが同時に押された場合は、設定画面に直接遷移
+                ui_page = PAGE_SETTINGS;
+            } else if ui_page == PAGE_SETTINGS {
+                // 設定画面では次の項目へ。最後の項目の次で設定画面を抜ける
+                if !gui.menu_next() {
+                    ui_page = 0;
+                }
+            } else {
+                ui_page = next_page(ui_page);
+            }
+            gui.change_page(ui_page); // ページ切替をGUIに通知
+        }
+        if pressed_l {
+            if switch_r_state {
+                // 両方のスイッチが同時に押された場合は、設定画面に直接遷移
+                ui_page = PAGE_SETTINGS;
+            } else if ui_page == PAGE_SETTINGS {
+                gui.menu_change(); // 選択中の項目の値を変更
+                // 設定変更時にエラーコードをリセットする
+                ERROR_CODE.store(0, Ordering::Relaxed);
+            } else if ui_page == PAGE_CLOCK {
+                // クロック画面ではタップテンポ
+                let tapped = pressed_l_at.unwrap_or_else(|| clock.now_us());
+                if let Some(bpm) = tap_tempo.tap(tapped) {
+                    let bpm =
+                        (bpm + 0.5).clamp(settings::TEMPO_MIN as f32, settings::TEMPO_MAX as f32);
+                    settings::TEMPO.store(bpm as u8, Ordering::Relaxed);
+                }
+            } else {
+                ui_page = prev_page(ui_page);
+            }
+            gui.change_page(ui_page); // ページ切替をGUIに通知
+        }
+
+        // クロック画面で左スイッチを1秒押し続けたら、内部クロックのスタート/ストップ
+        if ui_page == PAGE_CLOCK && state.left && !state.right {
+            left_hold += 1;
+            if left_hold == 10 {
+                toggle_transport();
+            }
+        } else {
+            left_hold = 0;
+        }
+
+        // 描画
+        gui.change_page(if host_shown { PAGE_HOST } else { ui_page });
+        gui.set_time(clock.now_us());
+        gui.tick(&mut buffer, counter);
+        counter = counter.wrapping_add(1);
+        timing::record(TASK_OLED_UI, start_us, clock.now_us());
+
+        // 描画済みバッファを送信
+        display.present(buffer).await;
+    }
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Console Task: USB シリアルのデバッグコンソール
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+/// 1行ずつコマンドを実行して返事を書く。コンソールを開いていないときの送信エラーは無視する
+pub async fn console_task<I, O, S, C>(input: &mut I, output: &mut O, source: &mut S, clock: &C)
+where
+    I: SerialSource,
+    O: SerialSink,
+    S: TouchFrameSource,
+    C: Clock,
+{
+    let mut editor = LineEditor::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let Ok(n) = input.read(&mut buf).await else {
+            continue; // 切断: 次の接続を待つ
+        };
+        let mut echo: String<ECHO_SIZE> = String::new();
+        for &byte in &buf[..n] {
+            let Some(line) = editor.feed(byte, &mut echo) else {
+                continue;
+            };
+            let _ = output.write(echo.as_bytes()).await;
+            echo.clear();
+            let frame = source.read_frame().await;
+            let mut reply = Reply::new();
+            console::execute(&line, &frame, clock.now_us(), &mut reply);
+            let _ = reply.push_str(PROMPT);
+            let _ = output.write(reply.as_bytes()).await;
+        }
+        if !echo.is_empty() {
+            let _ = output.write(echo.as_bytes()).await;
+        }
+    }
+}
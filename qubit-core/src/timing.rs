@@ -0,0 +1,86 @@
+//! タスクごとの処理時間（コンソールの tasks コマンドで表示する）
+//! 1周のうち、周期や入力を待つ時間を除いた部分を測る（送信の待ちは含む）
+use portable_atomic::{AtomicU32, AtomicU64, Ordering};
+
+pub const TASK_TOUCH: usize = 0;
+pub const TASK_MIDI_TX: usize = 1;
+pub const TASK_MIDI_RX: usize = 2;
+pub const TASK_DIN_RX: usize = 3;
+pub const TASK_CLOCK: usize = 4;
+pub const TASK_RINGLED: usize = 5;
+pub const TASK_OLED_UI: usize = 6;
+pub const TASK_I2C: usize = 7; // ファームウェアの core1_i2c_task
+pub const NUM_TIMED_TASKS: usize = 8;
+pub const TASK_NAMES: [&str; NUM_TIMED_TASKS] = [
+    "touch", "midi_tx", "midi_rx", "din_rx", "clock", "ringled", "oled_ui", "i2c",
+];
+
+/// 1つのタスクの処理時間
+pub struct TaskTiming {
+    count: AtomicU32,
+    last_us: AtomicU32,
+    max_us: AtomicU32,
+    total_us: AtomicU64,
+}
+
+/// 表示用のコピー
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimingStats {
+    pub count: u32,
+    pub last_us: u32,
+    pub average_us: u32,
+    pub max_us: u32,
+}
+
+impl TaskTiming {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            last_us: AtomicU32::new(0),
+            max_us: AtomicU32::new(0),
+            total_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, start_us: u64, end_us: u64) {
+        let us = end_us.saturating_sub(start_us).min(u32::MAX as u64) as u32;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.last_us.store(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+        self.total_us.fetch_add(us as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> TimingStats {
+        let count = self.count.load(Ordering::Relaxed);
+        let total = self.total_us.load(Ordering::Relaxed);
+        TimingStats {
+            count,
+            last_us: self.last_us.load(Ordering::Relaxed),
+            average_us: total.checked_div(count as u64).unwrap_or(0) as u32,
+            max_us: self.max_us.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.last_us.store(0, Ordering::Relaxed);
+        self.max_us.store(0, Ordering::Relaxed);
+        self.total_us.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for TaskTiming {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static TASK_TIMING: [TaskTiming; NUM_TIMED_TASKS] =
+    [const { TaskTiming::new() }; NUM_TIMED_TASKS];
+
+/// タスクの1周の処理時間を記録する
+pub fn record(task: usize, start_us: u64, end_us: u64) {
+    if let Some(timing) = TASK_TIMING.get(task) {
+        timing.record(start_us, end_us);
+    }
+}
@@ -0,0 +1,23 @@
+use crate::constants;
+
+/// Core1 から Core0 に渡すタッチの1フレーム
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TouchFrame {
+    pub values: [u16; constants::TOTAL_QT_KEYS], // リファレンスとの差分
+    pub timestamps: [u64; constants::TOTAL_CH],  // チャンネルごとの最終読み込み時刻(us)
+}
+
+impl TouchFrame {
+    pub const fn new() -> Self {
+        Self {
+            values: [0; constants::TOTAL_QT_KEYS],
+            timestamps: [0; constants::TOTAL_CH],
+        }
+    }
+}
+
+impl Default for TouchFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+use portable_atomic::Ordering;
+
+use crate::constants::*;
+use crate::{KEY_MASK_AUTO, KEY_MASK_MANUAL};
+
+// =========================================================
+//      KeyMask Class
+// =========================================================
+/// 無効化したキー（パッド）を 1bit/key で保持する
+/// 読み込みに失敗したチップのキーは自動で、不調なキーは手動でマスクする
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyMask([u32; KEY_MASK_WORDS]);
+
+impl KeyMask {
+    pub const fn new() -> Self {
+        Self([0; KEY_MASK_WORDS])
+    }
+
+    /// 自動マスク（読み込み失敗）と手動マスクを合わせたものを取得する
+    pub fn load() -> Self {
+        let mut mask = Self::new();
+        for (i, word) in mask.0.iter_mut().enumerate() {
+            *word = KEY_MASK_AUTO[i].load(Ordering::Relaxed)
+                | KEY_MASK_MANUAL[i].load(Ordering::Relaxed);
+        }
+        mask
+    }
+
+    /// 自動マスクとして保存する（Core1 のタッチ読み込みから）
+    pub fn store_auto(&self) {
+        for (i, word) in self.0.iter().enumerate() {
+            KEY_MASK_AUTO[i].store(*word, Ordering::Relaxed);
+        }
+    }
+
+    /// 手動マスクだけを取得する
+    pub fn load_manual() -> Self {
+        let mut mask = Self::new();
+        for (i, word) in mask.0.iter_mut().enumerate() {
+            *word = KEY_MASK_MANUAL[i].load(Ordering::Relaxed);
+        }
+        mask
+    }
+
+    /// バイト列にする（キー0 が先頭のバイトの最下位ビット）。SysEx や設定の保存用
+    pub fn to_bytes(&self) -> [u8; KEY_MASK_BYTES] {
+        let mut bytes = [0u8; KEY_MASK_BYTES];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(self.0.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// to_bytes の逆。キーのないビットは捨てる
+    pub fn from_bytes(bytes: &[u8; KEY_MASK_BYTES]) -> Self {
+        let mut mask = Self::new();
+        for key in 0..TOTAL_QT_KEYS {
+            mask.set(key, bytes[key / 8] & (1 << (key % 8)) != 0);
+        }
+        mask
+    }
+
+    /// 手動マスクとして保存する
+    pub fn store_manual(&self) {
+        for (i, word) in self.0.iter().enumerate() {
+            KEY_MASK_MANUAL[i].store(*word, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_masked(&self, key: usize) -> bool {
+        key < TOTAL_QT_KEYS && (self.0[key / 32] & (1 << (key % 32))) != 0
+    }
+
+    pub fn set(&mut self, key: usize, masked: bool) {
+        if key >= TOTAL_QT_KEYS {
+            return;
+        }
+        if masked {
+            self.0[key / 32] |= 1 << (key % 32);
+        } else {
+            self.0[key / 32] &= !(1 << (key % 32));
+        }
+    }
+
+    /// 1チップ分（6キー）をまとめてマスクする
+    pub fn set_chip(&mut self, ch: usize, masked: bool) {
+        let start = ch * AT42QT_KEYS_PER_DEVICE;
+        for key in start..(start + AT42QT_KEYS_PER_DEVICE) {
+            self.set(key, masked);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|w| *w == 0)
+    }
+
+    /// マスクされたキーの数
+    pub fn count(&self) -> u32 {
+        self.0.iter().map(|w| w.count_ones()).sum()
+    }
+
+    /// マスクされたキーの値を、両隣の有効なキーの値から直線補間する（リングなので端は回り込む）
+    /// 穴の部分が 0 のままだと scan_pads の微分の符号変化が誤検出されるため、その対策
+    pub fn interpolate(&self, values: &mut [u16; TOTAL_QT_KEYS]) {
+        if self.is_empty() {
+            return;
+        }
+        if self.count() as usize >= TOTAL_QT_KEYS {
+            values.fill(0);
+            return;
+        }
+        let src = *values;
+        for key in 0..TOTAL_QT_KEYS {
+            if !self.is_masked(key) {
+                continue;
+            }
+            // 前後の有効なキーを探す
+            let mut dist_prev = 1;
+            while self.is_masked((key + TOTAL_QT_KEYS - dist_prev) % TOTAL_QT_KEYS) {
+                dist_prev += 1;
+            }
+            let mut dist_next = 1;
+            while self.is_masked((key + dist_next) % TOTAL_QT_KEYS) {
+                dist_next += 1;
+            }
+            let prev = src[(key + TOTAL_QT_KEYS - dist_prev) % TOTAL_QT_KEYS] as u32;
+            let next = src[(key + dist_next) % TOTAL_QT_KEYS] as u32;
+            let total = (dist_prev + dist_next) as u32;
+            values[key] = ((prev * dist_next as u32 + next * dist_prev as u32) / total) as u16;
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod frame;
+pub mod key_mask;
+pub mod pressure;
+pub mod qtouch;
+pub mod recording;
+pub mod scan_scheduler;
+pub mod synth;
@@ -15,6 +15,8 @@ pub const CLOSE_RANGE: f32 = 3.0; // 同じタッチと見做される 10msec 
 pub const FINGER_RANGE: usize = 3; // Maximum serial numbers of one touch point
 pub const HISTERESIS: f32 = 0.7; // Hysteresis value for touch point detection
 
+pub const OFFSET_NOTE: u8 = constants::KEYBD_LO - 4; // キー番号 0 のノート番号（設定で変える前）
+
 const INIT_VAL: f32 = 100.0; // Invalid location initially
 const RELEASE_WAITING_TIME: u32 = 5; // Number of cycles to wait before considering a touch point released
 
@@ -95,7 +97,6 @@ where
 {
     const NEW_NOTE: u8 = 0xff;
     const TOUCH_POINT_ERROR: u8 = 0xfe;
-    const OFFSET_NOTE: u8 = constants::KEYBD_LO - 4;
 
     /// Constructor は起動時に最大数分呼ばれる
     fn new(id: usize) -> Self {
@@ -126,7 +127,7 @@ where
             if let Some(ref midi_callback) = self.midi_callback {
                 midi_callback(
                     constants::RINGLED_CMD_TX_ON | self.id as u8,
-                    self.real_crnt_note + Self::OFFSET_NOTE,
+                    self.real_crnt_note + OFFSET_NOTE,
                     self.intensity_to_velocity(self.intensity),
                     self.center_location,
                 );
@@ -154,13 +155,13 @@ where
             {
                 midi_callback(
                     constants::RINGLED_CMD_TX_ON | self.id as u8,
-                    updated_note + Self::OFFSET_NOTE,
+                    updated_note + OFFSET_NOTE,
                     self.intensity_to_velocity(self.intensity),
                     self.center_location,
                 );
                 midi_callback(
                     constants::RINGLED_CMD_TX_MOVED | self.id as u8, // Note Off と同じ
-                    self.real_crnt_note + Self::OFFSET_NOTE,
+                    self.real_crnt_note + OFFSET_NOTE,
                     0x40,
                     self.center_location,
                 );
@@ -179,7 +180,7 @@ where
         if let Some(ref midi_callback) = self.midi_callback {
             midi_callback(
                 constants::RINGLED_CMD_TX_OFF | self.id as u8,
-                self.real_crnt_note + Self::OFFSET_NOTE,
+                self.real_crnt_note + OFFSET_NOTE,
                 0x40,
                 self.center_location,
             );
@@ -246,7 +247,7 @@ where
         if intensity < 0 {
             return 0; // No touch
         } else if intensity > 255 {
-            return 255; // Max MIDI velocity
+            return 127; // Max MIDI velocity（DIN では 0x80 以上はステータスになる）
         }
         (100 + (intensity >> 4)) as u8
     }
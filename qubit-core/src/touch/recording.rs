@@ -0,0 +1,259 @@
+//! タッチの生データの記録フォーマット（.qtr）
+//!
+//! ユーザーから報告された不具合の再現や、実際の指のデータでの回帰テストに使う
+//! 数値はすべてリトルエンディアン
+//!
+//!   ヘッダ (8byte): "QTRC" | version | キー数 | チャンネル数 | ADC チャンネル数
+//!   レコード:       tag | payload の長さ(u16) | payload
+//!
+//!   tag 0x01 フレーム: 時刻(us, u64) | values(u16 × キー数)
+//!                      | チャンネルごとの経過時間(100us 単位, u16 × チャンネル数)
+//!                      | ADC(u16 × ADC チャンネル数)
+//!
+//! 知らない tag は長さを見て読み飛ばすので、レコードの種類は後から増やせる
+//!
+//! デバイスからは、ファイルのバイト列をそのまま小分けにして SysEx で送る
+//!   F0 7D 'Q' 'R' <7bit に詰め直したデータ> F7
+use crate::constants::{MAX_ADC_CHANNELS, TOTAL_CH, TOTAL_QT_KEYS};
+use crate::hal::MidiSink;
+use crate::midi::parser::{SysexTxError, send_sysex};
+use crate::touch::frame::TouchFrame;
+
+pub const MAGIC: [u8; 4] = *b"QTRC";
+pub const VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 8;
+
+pub const TAG_FRAME: u8 = 0x01;
+const RECORD_HEAD_SIZE: usize = 3; // tag + 長さ
+pub const FRAME_PAYLOAD_SIZE: usize = 8 + TOTAL_QT_KEYS * 2 + TOTAL_CH * 2 + MAX_ADC_CHANNELS * 2;
+pub const FRAME_RECORD_SIZE: usize = RECORD_HEAD_SIZE + FRAME_PAYLOAD_SIZE;
+
+const AGE_UNIT_US: u64 = 100;
+
+/// SysEx の先頭（0x7D: 非営利・開発用のメーカーID）
+pub const SYSEX_PREFIX: [u8; 4] = [0xF0, 0x7D, b'Q', b'R'];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecordingError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    LayoutMismatch, // キー数などがこのファームウェアと違う
+    Truncated,
+}
+
+/// ヘッダ
+pub fn encode_header() -> [u8; HEADER_SIZE] {
+    [
+        MAGIC[0],
+        MAGIC[1],
+        MAGIC[2],
+        MAGIC[3],
+        VERSION,
+        TOTAL_QT_KEYS as u8,
+        TOTAL_CH as u8,
+        MAX_ADC_CHANNELS as u8,
+    ]
+}
+
+// =========================================================
+//      FrameRecord
+// =========================================================
+/// タッチの1フレームと、その時の ADC の値
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameRecord {
+    pub time_us: u64, // フレームを読み込んだ時刻
+    pub frame: TouchFrame,
+    pub adc: [u16; MAX_ADC_CHANNELS],
+}
+
+impl FrameRecord {
+    pub fn encode(&self) -> [u8; FRAME_RECORD_SIZE] {
+        let mut buf = [0u8; FRAME_RECORD_SIZE];
+        buf[0] = TAG_FRAME;
+        buf[1..3].copy_from_slice(&(FRAME_PAYLOAD_SIZE as u16).to_le_bytes());
+        let mut pos = RECORD_HEAD_SIZE;
+        let mut put = |bytes: &[u8]| {
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(&self.time_us.to_le_bytes());
+        for value in self.frame.values.iter() {
+            put(&value.to_le_bytes());
+        }
+        // 時刻そのものではなく、フレームの時刻からの経過時間にして小さくする
+        for timestamp in self.frame.timestamps.iter() {
+            let age = self.time_us.saturating_sub(*timestamp) / AGE_UNIT_US;
+            put(&(age.min(u16::MAX as u64) as u16).to_le_bytes());
+        }
+        for value in self.adc.iter() {
+            put(&value.to_le_bytes());
+        }
+        buf
+    }
+
+    fn decode(payload: &[u8]) -> Self {
+        let mut pos = 0;
+        let mut take = |n: usize| {
+            let bytes = &payload[pos..pos + n];
+            pos += n;
+            bytes
+        };
+        let u16_at = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]);
+
+        let mut time = [0u8; 8];
+        time.copy_from_slice(take(8));
+        let time_us = u64::from_le_bytes(time);
+        let mut frame = TouchFrame::new();
+        for value in frame.values.iter_mut() {
+            *value = u16_at(take(2));
+        }
+        for timestamp in frame.timestamps.iter_mut() {
+            let age = u16_at(take(2)) as u64 * AGE_UNIT_US;
+            *timestamp = time_us.saturating_sub(age);
+        }
+        let mut adc = [0u16; MAX_ADC_CHANNELS];
+        for value in adc.iter_mut() {
+            *value = u16_at(take(2));
+        }
+        Self {
+            time_us,
+            frame,
+            adc,
+        }
+    }
+}
+
+// =========================================================
+//      Reader
+// =========================================================
+/// 記録ファイルのバイト列からフレームを順に取り出す
+pub struct RecordingReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RecordingReader<'a> {
+    /// ヘッダを確認する
+    pub fn new(data: &'a [u8]) -> Result<Self, RecordingError> {
+        if data.len() < HEADER_SIZE {
+            return Err(RecordingError::Truncated);
+        }
+        if data[0..4] != MAGIC {
+            return Err(RecordingError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(RecordingError::UnsupportedVersion(data[4]));
+        }
+        if data[5..8] != encode_header()[5..8] {
+            return Err(RecordingError::LayoutMismatch);
+        }
+        Ok(Self {
+            data,
+            pos: HEADER_SIZE,
+        })
+    }
+}
+
+impl Iterator for RecordingReader<'_> {
+    type Item = Result<FrameRecord, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let rest = &self.data[self.pos..];
+            if rest.is_empty() {
+                return None;
+            }
+            if rest.len() < RECORD_HEAD_SIZE {
+                self.pos = self.data.len();
+                return Some(Err(RecordingError::Truncated));
+            }
+            let len = u16::from_le_bytes([rest[1], rest[2]]) as usize;
+            let Some(payload) = rest.get(RECORD_HEAD_SIZE..RECORD_HEAD_SIZE + len) else {
+                self.pos = self.data.len();
+                return Some(Err(RecordingError::Truncated));
+            };
+            self.pos += RECORD_HEAD_SIZE + len;
+            match rest[0] {
+                TAG_FRAME if len == FRAME_PAYLOAD_SIZE => {
+                    return Some(Ok(FrameRecord::decode(payload)));
+                }
+                TAG_FRAME => return Some(Err(RecordingError::LayoutMismatch)),
+                _ => {} // 知らないレコードは読み飛ばす
+            }
+        }
+    }
+}
+
+// =========================================================
+//      SysEx
+// =========================================================
+/// 7byte ごとに、最上位ビットをまとめた1byte を先頭に付けて 7bit にする
+pub fn pack7(src: &[u8], dst: &mut [u8]) -> usize {
+    let mut n = 0;
+    for chunk in src.chunks(7) {
+        let mut msb = 0u8;
+        for (i, byte) in chunk.iter().enumerate() {
+            msb |= (byte >> 7) << i;
+        }
+        dst[n] = msb;
+        n += 1;
+        for byte in chunk.iter() {
+            dst[n] = byte & 0x7f;
+            n += 1;
+        }
+    }
+    n
+}
+
+/// pack7 の逆
+pub fn unpack7(src: &[u8], dst: &mut [u8]) -> usize {
+    let mut n = 0;
+    for chunk in src.chunks(8) {
+        let msb = chunk[0];
+        for (i, byte) in chunk[1..].iter().enumerate() {
+            dst[n] = byte | (((msb >> i) & 1) << 7);
+            n += 1;
+        }
+    }
+    n
+}
+
+/// 7bit に詰め直した後の長さ
+pub const fn packed_len(len: usize) -> usize {
+    len + len.div_ceil(7)
+}
+
+/// 記録ファイルの一部（ヘッダやレコード）を1つの SysEx として送る
+pub async fn send_chunk<M: MidiSink>(
+    midi: &mut M,
+    chunk: &[u8],
+) -> Result<(), SysexTxError<M::Error>> {
+    let mut packed = [0u8; packed_len(FRAME_RECORD_SIZE)];
+    let mut sysex = [0u8; SYSEX_PREFIX.len() + packed_len(FRAME_RECORD_SIZE) + 1];
+    let n = pack7(&chunk[..chunk.len().min(FRAME_RECORD_SIZE)], &mut packed);
+    sysex[..SYSEX_PREFIX.len()].copy_from_slice(&SYSEX_PREFIX);
+    sysex[SYSEX_PREFIX.len()..SYSEX_PREFIX.len() + n].copy_from_slice(&packed[..n]);
+    let len = SYSEX_PREFIX.len() + n + 1;
+    sysex[len - 1] = 0xF7;
+    send_sysex(midi, &sysex[..len]).await
+}
+
+/// SysEx のダンプ（.syx）から記録ファイルのバイト列を取り出して、dst に順に渡す
+/// 他の SysEx やバイトは無視する
+pub fn extract_sysex<F: FnMut(&[u8])>(syx: &[u8], mut dst: F) {
+    let mut rest = syx;
+    while let Some(start) = rest
+        .windows(SYSEX_PREFIX.len())
+        .position(|w| w == SYSEX_PREFIX)
+    {
+        let body = &rest[start + SYSEX_PREFIX.len()..];
+        let Some(end) = body.iter().position(|b| *b == 0xF7) else {
+            return;
+        };
+        let mut buf = [0u8; FRAME_RECORD_SIZE];
+        let packed = &body[..end.min(packed_len(FRAME_RECORD_SIZE))];
+        let n = unpack7(packed, &mut buf);
+        dst(&buf[..n]);
+        rest = &body[end + 1..];
+    }
+}
@@ -0,0 +1,118 @@
+use crate::constants::*;
+
+// =========================================================
+//      ChannelSet Class
+// =========================================================
+/// チャンネル（MUXの先の AT42QT1070 1チップ）の集合
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelSet(u32);
+
+impl ChannelSet {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn insert(&mut self, ch: usize) {
+        if ch < TOTAL_CH {
+            self.0 |= 1 << ch;
+        }
+    }
+
+    pub fn contains(&self, ch: usize) -> bool {
+        ch < TOTAL_CH && (self.0 & (1 << ch)) != 0
+    }
+
+    pub fn union(&self, other: ChannelSet) -> ChannelSet {
+        ChannelSet(self.0 | other.0)
+    }
+}
+
+/// 1回のスキャンで読むチャンネル
+pub struct ScanPlan {
+    pub reads: ChannelSet,        // タッチ値を読むチャンネル
+    pub reference: Option<usize>, // リファレンス値を読むチャンネル（1回に1つだけ）
+}
+
+// =========================================================
+//      ScanScheduler Class
+// =========================================================
+/// タッチのあるチャンネルとその両隣は毎回、それ以外は巡回して数チャンネルずつ読む
+/// リファレンスの読み込みも1回に1チャンネルずつに分散させる
+pub struct ScanScheduler {
+    hot: [u8; TOTAL_CH], // 残り何回、毎回読むか
+    idle_cursor: usize,
+    ref_cursor: usize,
+}
+
+impl ScanScheduler {
+    pub const fn new() -> Self {
+        Self {
+            hot: [0; TOTAL_CH],
+            idle_cursor: 0,
+            ref_cursor: 0,
+        }
+    }
+
+    /// 読み込んだチャンネルにタッチがあれば、そのチャンネルと両隣をしばらく毎回読む
+    pub fn note_activity(&mut self, ch: usize, active: bool) {
+        if active {
+            for offset in [TOTAL_CH - 1, 0, 1] {
+                self.hot[(ch + offset) % TOTAL_CH] = SCAN_HOT_PASSES;
+            }
+        }
+    }
+
+    /// 今回読むチャンネルを決める
+    /// failed: 故障中のチップ（retry_failed が true の回だけ読む）
+    pub fn plan(&mut self, failed: ChannelSet, retry_failed: bool) -> ScanPlan {
+        let mut reads = ChannelSet::new();
+
+        // 1: タッチのあるチャンネルの近く
+        for (ch, hot) in self.hot.iter_mut().enumerate() {
+            if *hot > 0 {
+                *hot -= 1;
+                if !failed.contains(ch) {
+                    reads.insert(ch);
+                }
+            }
+        }
+
+        // 2: それ以外は巡回
+        let mut picked = 0;
+        for _ in 0..TOTAL_CH {
+            if picked >= SCAN_IDLE_PER_PASS {
+                break;
+            }
+            let ch = self.idle_cursor;
+            self.idle_cursor = (ch + 1) % TOTAL_CH;
+            if !reads.contains(ch) && !failed.contains(ch) {
+                reads.insert(ch);
+                picked += 1;
+            }
+        }
+
+        // 3: 故障中のチップは、ときどき復帰を確認する
+        if retry_failed {
+            reads = reads.union(failed);
+        }
+
+        // 4: リファレンスは1チャンネルずつ巡回
+        let mut reference = None;
+        for _ in 0..TOTAL_CH {
+            let ch = self.ref_cursor;
+            self.ref_cursor = (ch + 1) % TOTAL_CH;
+            if !failed.contains(ch) {
+                reference = Some(ch);
+                break;
+            }
+        }
+
+        ScanPlan { reads, reference }
+    }
+}
+
+impl Default for ScanScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
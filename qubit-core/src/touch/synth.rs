@@ -0,0 +1,254 @@
+//! 指のモデルから作る合成タッチフレーム（ホストのテストとシミュレータ用）
+//!
+//! 指はリング上の小数の位置にあるガウス分布の静電容量として扱い、
+//! センサの生値 → リファレンスとの差分 の流れは ReadTouch と同じにする
+//! できたフレームは TouchFrameSource にも、QubitTouch::set_value にもそのまま渡せる
+use heapless::Vec;
+
+use crate::constants::{AT42QT_KEYS_PER_DEVICE, TOTAL_CH, TOTAL_QT_KEYS};
+use crate::touch::frame::TouchFrame;
+use crate::touch::qtouch::QubitTouch;
+
+pub const MAX_FINGERS: usize = 10;
+pub const MAX_KEYFRAMES: usize = 16;
+
+const RING: f32 = TOTAL_QT_KEYS as f32;
+
+/// リング上の位置を 0.0..96.0 にする
+fn wrap(pos: f32) -> f32 {
+    let pos = pos % RING;
+    if pos < 0.0 { pos + RING } else { pos }
+}
+
+// =========================================================
+//      Finger
+// =========================================================
+/// 1本の指: 置いてから離すまでの位置を、キーフレームの間で直線補間する
+/// 位置はキー番号（小数可）。0 未満や 96 以上は、リングを回り込んだ位置になる
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finger {
+    pub down_ms: u32,
+    pub up_ms: u32,
+    pub path: Vec<(u32, f32), MAX_KEYFRAMES>, // (時刻 ms, 位置)、時刻順
+    pub width: f32,                           // ガウス分布の σ（キー数）
+    pub amplitude: f32,                       // 中心での値
+    pub rise_ms: u32,                         // 置いた直後と離す直前に値が変化する時間
+}
+
+impl Finger {
+    pub const DEFAULT_WIDTH: f32 = 0.8;
+    pub const DEFAULT_AMPLITUDE: f32 = 90.0;
+
+    /// pos に置いて、hold_ms 後に離す
+    pub fn tap(pos: f32, at_ms: u32, hold_ms: u32) -> Self {
+        let mut path = Vec::new();
+        let _ = path.push((at_ms, pos));
+        Self {
+            down_ms: at_ms,
+            up_ms: at_ms + hold_ms,
+            path,
+            width: Self::DEFAULT_WIDTH,
+            amplitude: Self::DEFAULT_AMPLITUDE,
+            rise_ms: 0,
+        }
+    }
+
+    /// from に置いて dur_ms かけて to まで滑らせ、そこで離す
+    /// 例えば 94.0 → 98.0 なら、継ぎ目を越えてキー2 まで行く
+    pub fn slide(from: f32, to: f32, at_ms: u32, dur_ms: u32) -> Self {
+        let mut finger = Self::tap(from, at_ms, dur_ms);
+        let _ = finger.path.push((at_ms + dur_ms, to));
+        finger
+    }
+
+    /// 位置を追加する（最後のキーフレームから、そこまで直線で動く）
+    pub fn then(mut self, time_ms: u32, pos: f32) -> Self {
+        let _ = self.path.push((time_ms, pos));
+        self.up_ms = self.up_ms.max(time_ms);
+        self
+    }
+
+    pub fn with_width(mut self, width: f32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    pub fn with_rise(mut self, rise_ms: u32) -> Self {
+        self.rise_ms = rise_ms;
+        self
+    }
+
+    /// 置いている間の位置（0.0..96.0 に回り込ませる）
+    pub fn position_at(&self, time_ms: u32) -> Option<f32> {
+        if time_ms < self.down_ms || time_ms >= self.up_ms {
+            return None;
+        }
+        let (first, last) = (self.path.first()?, self.path.last()?);
+        let pos = if time_ms <= first.0 {
+            first.1
+        } else if time_ms >= last.0 {
+            last.1
+        } else {
+            let i = self.path.iter().position(|k| k.0 > time_ms)?;
+            let ((t0, p0), (t1, p1)) = (self.path[i - 1], self.path[i]);
+            p0 + (p1 - p0) * (time_ms - t0) as f32 / (t1 - t0) as f32
+        };
+        Some(wrap(pos))
+    }
+
+    /// 置いた直後と離す直前は、rise_ms かけて値を変化させる
+    fn envelope(&self, time_ms: u32) -> f32 {
+        if self.rise_ms == 0 {
+            return 1.0;
+        }
+        let from_down = time_ms.saturating_sub(self.down_ms);
+        let to_up = self.up_ms.saturating_sub(time_ms);
+        (from_down.min(to_up) as f32 / self.rise_ms as f32).min(1.0)
+    }
+}
+
+// =========================================================
+//      SensorModel
+// =========================================================
+/// センサとノイズのモデル
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorModel {
+    pub baseline: u16,      // 触れていないときの生値
+    pub noise: u16,         // キーごとのノイズの最大値（±）
+    pub drift_per_s: f32,   // 起動時のリファレンスからの生値のずれ（1秒あたり）
+    pub crosstalk: f32,     // 同じチップの他のキーに漏れる割合
+    pub dropped_chips: u32, // 読み込みに失敗するチップ（1bit/ch）。値と時刻が更新されない
+    pub seed: u32,
+}
+
+impl SensorModel {
+    pub const fn new() -> Self {
+        Self {
+            baseline: 600,
+            noise: 0,
+            drift_per_s: 0.0,
+            crosstalk: 0.0,
+            dropped_chips: 0,
+            seed: 1,
+        }
+    }
+}
+
+impl Default for SensorModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// =========================================================
+//      SynthTouch
+// =========================================================
+/// 指のモデルから ReadTouch と同じ形のフレームを作る
+pub struct SynthTouch {
+    pub model: SensorModel,
+    fingers: Vec<Finger, MAX_FINGERS>,
+    reference: [u16; TOTAL_QT_KEYS],
+    frame: TouchFrame,
+    rng: u32,
+}
+
+impl SynthTouch {
+    pub fn new(model: SensorModel) -> Self {
+        // ReadTouch::store_reference と同じく、各チップの最後のキーは高めに取る
+        let mut reference = [model.baseline; TOTAL_QT_KEYS];
+        for ch in 0..TOTAL_CH {
+            reference[ch * AT42QT_KEYS_PER_DEVICE + 5] += 7;
+        }
+        Self {
+            model,
+            fingers: Vec::new(),
+            reference,
+            frame: TouchFrame::new(),
+            rng: model.seed.max(1),
+        }
+    }
+
+    /// 指がいっぱいなら false
+    pub fn add_finger(&mut self, finger: Finger) -> bool {
+        self.fingers.push(finger).is_ok()
+    }
+
+    /// 離した指を取り除く（指を次々に追加するとき用）
+    pub fn remove_released(&mut self, time_ms: u32) {
+        self.fingers.retain(|f| f.up_ms > time_ms);
+    }
+
+    pub fn fingers(&self) -> &[Finger] {
+        &self.fingers
+    }
+
+    /// xorshift32 で -noise..=noise
+    fn noise(&mut self) -> i32 {
+        let noise = self.model.noise as i32;
+        if noise == 0 {
+            return 0;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng % (2 * noise as u32 + 1)) as i32 - noise
+    }
+
+    /// 指による静電容量の変化（リファレンスとの差分）
+    fn finger_signal(&self, time_ms: u32) -> [f32; TOTAL_QT_KEYS] {
+        let mut signal = [0.0f32; TOTAL_QT_KEYS];
+        for finger in self.fingers.iter() {
+            let Some(pos) = finger.position_at(time_ms) else {
+                continue;
+            };
+            let amplitude = finger.amplitude * finger.envelope(time_ms);
+            let two_sigma2 = 2.0 * finger.width * finger.width;
+            for (key, s) in signal.iter_mut().enumerate() {
+                // リングなので距離は回り込む
+                let mut dist = wrap(key as f32 - pos);
+                dist = dist.min(RING - dist);
+                *s += amplitude * libm::expf(-dist * dist / two_sigma2);
+            }
+        }
+        signal
+    }
+
+    /// time_us の時点のフレーム。読み込みに失敗するチップは前の値のまま
+    pub fn frame_at(&mut self, time_us: u64) -> TouchFrame {
+        let time_ms = (time_us / 1000) as u32;
+        let signal = self.finger_signal(time_ms);
+        let drift = self.model.drift_per_s * time_us as f32 / 1_000_000.0;
+        for ch in 0..TOTAL_CH {
+            if self.model.dropped_chips & (1 << ch) != 0 {
+                continue;
+            }
+            let keys = ch * AT42QT_KEYS_PER_DEVICE..(ch + 1) * AT42QT_KEYS_PER_DEVICE;
+            let chip_max = signal[keys.clone()].iter().fold(0.0f32, |a, b| a.max(*b));
+            for key in keys {
+                // 同じチップ内の一番強いキーの信号が、他のキーに漏れる
+                let crosstalk = (chip_max - signal[key]) * self.model.crosstalk;
+                let raw = self.model.baseline as f32 + drift + signal[key] + crosstalk;
+                let raw = (raw as i32 + self.noise()).clamp(0, u16::MAX as i32) as u16;
+                self.frame.values[key] = raw.saturating_sub(self.reference[key]);
+            }
+            self.frame.timestamps[ch] = time_us;
+        }
+        self.frame
+    }
+
+    /// フレームを作って QubitTouch に渡す
+    pub fn feed<F>(&mut self, time_us: u64, qt: &mut QubitTouch<F>)
+    where
+        F: Fn(u8, u8, u8, f32) + Clone,
+    {
+        let frame = self.frame_at(time_us);
+        for (key, value) in frame.values.iter().enumerate() {
+            qt.set_value(key, *value);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! ホストが RingLED を表示に使う（Loopian やビジュアライザから SysEx で LED のフレームを送る）
+//!
+//! 設定と同じヘッダで、コマンドは 0x40 から。返信はしない（ホストは描画するたびに送る）
+//! 値はすべて 7bit（0-127 を 0-255 にする）
+//!
+//!   コマンド            データ
+//!   0x40 RGBW           先頭の LED | (R G B W) × n
+//!   0x41 パレット        先頭の番号 | (R G B W) × n（16色。0 は消灯、1-8 は led_look::PALETTE で始まる）
+//!   0x42 パレット番号    先頭の LED | 番号 × n
+//!   0x43 ランレングス    先頭の LED | (個数 番号) × n
+//!   0x44 モード          0: ローカル / 1: 置き換え / 2: 重ねる | タイムアウト（100ms 単位、0 は 1秒）
+//!
+//! 1つの SysEx は 256byte までなので、RGBW の1周（96 LED）は2つに分けて送る
+//! 置き換え: ホストのフレームだけを表示する。重ねる: 待機中のアニメーションの代わりにホストのフレームを表示し、
+//! タッチと受信したノートはその上に光らせる。どちらも明るさ（CC 102）は掛ける
+//! タイムアウトまでフレームが届かなければ、ローカルの表示に戻る（次のフレームでまたホストの表示になる）
+//! タイムアウトを延ばすのはフレームのコマンド（0x40, 0x42, 0x43）だけで、モードやパレットを変えても古いフレームは出ない
+use heapless::Vec;
+use portable_atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+
+use crate::constants::{MIDI_SYSEX_BUFFER_SIZE, NUM_LEDS};
+use crate::hal::Rgbw;
+use crate::midi::config::CONFIG_HEADER;
+use crate::ui::led_look::PALETTE;
+
+pub const CMD_RGBW: u8 = 0x40;
+pub const CMD_PALETTE: u8 = 0x41;
+pub const CMD_INDEXED: u8 = 0x42;
+pub const CMD_RUN_LENGTH: u8 = 0x43;
+pub const CMD_MODE: u8 = 0x44;
+pub const PALETTE_SIZE: usize = 16;
+pub const RGBW_PER_SYSEX: usize = NUM_LEDS / 2; // エンコーダが1つの SysEx に入れる LED の数
+pub const HOST_SYSEX_SIZE: usize = MIDI_SYSEX_BUFFER_SIZE + 2;
+const DEFAULT_TIMEOUT: u8 = 10;
+
+/// ホストのフレームの表示のしかた
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HostMode {
+    Local,   // ローカルの表示だけ
+    Replace, // ホストのフレームだけ
+    Blend,   // ホストのフレームの上にタッチと受信したノート
+}
+
+impl HostMode {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Local),
+            1 => Some(Self::Replace),
+            2 => Some(Self::Blend),
+            _ => None,
+        }
+    }
+}
+
+static HOST_MODE: AtomicU8 = AtomicU8::new(HostMode::Local as u8);
+static HOST_TIMEOUT: AtomicU8 = AtomicU8::new(DEFAULT_TIMEOUT); // 100ms 単位
+static HOST_RECEIVED_US: AtomicU64 = AtomicU64::new(0);
+// RGBW を1つの u32 に詰める
+static HOST_FRAME: [AtomicU32; NUM_LEDS] = [const { AtomicU32::new(0) }; NUM_LEDS];
+static HOST_PALETTE: [AtomicU32; PALETTE_SIZE] = {
+    let mut palette = [const { AtomicU32::new(0) }; PALETTE_SIZE];
+    let mut i = 0;
+    while i < PALETTE.len() {
+        let (r, g, b) = PALETTE[i];
+        palette[i + 1] = AtomicU32::new(pack(Rgbw { r, g, b, w: 0 }));
+        i += 1;
+    }
+    palette
+};
+
+const fn pack(c: Rgbw) -> u32 {
+    (c.r as u32) << 24 | (c.g as u32) << 16 | (c.b as u32) << 8 | c.w as u32
+}
+
+fn unpack(v: u32) -> Rgbw {
+    Rgbw {
+        r: (v >> 24) as u8,
+        g: (v >> 16) as u8,
+        b: (v >> 8) as u8,
+        w: v as u8,
+    }
+}
+
+/// 7bit の値を 0-255 にする
+fn to_8bit(value: u8) -> u8 {
+    let value = value & 0x7F;
+    value << 1 | value >> 6
+}
+
+fn rgbw_at(data: &[u8]) -> Rgbw {
+    Rgbw {
+        r: to_8bit(data[0]),
+        g: to_8bit(data[1]),
+        b: to_8bit(data[2]),
+        w: to_8bit(data[3]),
+    }
+}
+
+fn set_led(led: usize, color: u32) {
+    if let Some(v) = HOST_FRAME.get(led) {
+        v.store(color, Ordering::Relaxed);
+    }
+}
+
+fn palette(index: u8) -> u32 {
+    HOST_PALETTE
+        .get(index as usize)
+        .map_or(0, |c| c.load(Ordering::Relaxed))
+}
+
+/// 今表示するホストのモードとフレーム（ローカルの表示なら None）
+pub fn active_frame(now_us: u64) -> Option<(HostMode, [Rgbw; NUM_LEDS])> {
+    let mode = HostMode::from_u8(HOST_MODE.load(Ordering::Relaxed))?;
+    let timeout_us = HOST_TIMEOUT.load(Ordering::Relaxed) as u64 * 100_000;
+    if mode == HostMode::Local
+        || now_us.saturating_sub(HOST_RECEIVED_US.load(Ordering::Relaxed)) > timeout_us
+    {
+        return None;
+    }
+    let mut frame = [Rgbw::default(); NUM_LEDS];
+    for (led, v) in frame.iter_mut().zip(HOST_FRAME.iter()) {
+        *led = unpack(v.load(Ordering::Relaxed));
+    }
+    Some((mode, frame))
+}
+
+/// 受信した SysEx（F0 と F7 を除く）がホストのフレームなら反映して true
+/// 範囲外の LED や番号は無視する
+pub fn receive(sysex: &[u8], now_us: u64) -> bool {
+    let Some([cmd, data @ ..]) = sysex.strip_prefix(&CONFIG_HEADER) else {
+        return false;
+    };
+    let (start, values) = match data.split_first() {
+        Some((start, values)) => (*start as usize, values),
+        None => (0, data),
+    };
+    match *cmd {
+        CMD_RGBW => {
+            for (i, c) in values.chunks_exact(4).enumerate() {
+                set_led(start + i, pack(rgbw_at(c)));
+            }
+        }
+        CMD_PALETTE => {
+            for (i, c) in values.chunks_exact(4).enumerate() {
+                if let Some(entry) = HOST_PALETTE.get(start + i) {
+                    entry.store(pack(rgbw_at(c)), Ordering::Relaxed);
+                }
+            }
+            return true; // フレームではないので、タイムアウトは延ばさない
+        }
+        CMD_INDEXED => {
+            for (i, index) in values.iter().enumerate() {
+                set_led(start + i, palette(*index));
+            }
+        }
+        CMD_RUN_LENGTH => {
+            let mut led = start;
+            for run in values.chunks_exact(2) {
+                let color = palette(run[1]);
+                for _ in 0..run[0] {
+                    set_led(led, color);
+                    led += 1;
+                }
+            }
+        }
+        CMD_MODE => {
+            let (Some(mode), Some(&timeout)) = (HostMode::from_u8(start as u8), values.first())
+            else {
+                return true;
+            };
+            let timeout = if timeout == 0 {
+                DEFAULT_TIMEOUT
+            } else {
+                timeout
+            };
+            HOST_TIMEOUT.store(timeout, Ordering::Relaxed);
+            HOST_MODE.store(mode as u8, Ordering::Relaxed);
+            return true; // タイムアウトした古いフレームを出さない
+        }
+        _ => return false,
+    }
+    HOST_RECEIVED_US.store(now_us, Ordering::Relaxed);
+    true
+}
+
+// =========================================================
+//      Encoder（ホスト側とテスト用）
+// =========================================================
+fn sysex(cmd: u8, start: u8, data: &[u8]) -> Vec<u8, HOST_SYSEX_SIZE> {
+    let mut sysex = Vec::new();
+    let _ = sysex.push(0xF0);
+    let _ = sysex.extend_from_slice(&CONFIG_HEADER);
+    let _ = sysex.extend_from_slice(&[cmd, start & 0x7F]);
+    let _ = sysex.extend_from_slice(data);
+    let _ = sysex.push(0xF7);
+    sysex
+}
+
+fn push_rgbw(data: &mut Vec<u8, MIDI_SYSEX_BUFFER_SIZE>, color: &Rgbw) {
+    let _ = data.extend_from_slice(&[color.r >> 1, color.g >> 1, color.b >> 1, color.w >> 1]);
+}
+
+/// RGBW の SysEx（F0 から F7 まで）。RGBW_PER_SYSEX を越える分は送らない
+pub fn rgbw_sysex(start: u8, colors: &[Rgbw]) -> Vec<u8, HOST_SYSEX_SIZE> {
+    let mut data = Vec::new();
+    for color in colors.iter().take(RGBW_PER_SYSEX) {
+        push_rgbw(&mut data, color);
+    }
+    sysex(CMD_RGBW, start, &data)
+}
+
+/// パレットの SysEx
+pub fn palette_sysex(start: u8, colors: &[Rgbw]) -> Vec<u8, HOST_SYSEX_SIZE> {
+    let mut data = Vec::new();
+    for color in colors.iter().take(PALETTE_SIZE) {
+        push_rgbw(&mut data, color);
+    }
+    sysex(CMD_PALETTE, start, &data)
+}
+
+/// パレット番号の SysEx。連続する同じ番号が多ければランレングスにする
+pub fn indexed_sysex(start: u8, indexes: &[u8]) -> Vec<u8, HOST_SYSEX_SIZE> {
+    let mut runs: Vec<u8, MIDI_SYSEX_BUFFER_SIZE> = Vec::new();
+    for index in indexes.iter().take(NUM_LEDS) {
+        let len = runs.len();
+        if len >= 2 && runs[len - 1] == *index & 0x7F && runs[len - 2] < 0x7F {
+            runs[len - 2] += 1;
+        } else {
+            let _ = runs.extend_from_slice(&[1, index & 0x7F]);
+        }
+    }
+    let indexes = &indexes[..indexes.len().min(NUM_LEDS)];
+    if runs.len() < indexes.len() {
+        return sysex(CMD_RUN_LENGTH, start, &runs);
+    }
+    let data: Vec<u8, MIDI_SYSEX_BUFFER_SIZE> = indexes.iter().map(|i| i & 0x7F).collect();
+    sysex(CMD_INDEXED, start, &data)
+}
+
+/// モードの SysEx（タイムアウトは 100ms 単位）
+pub fn mode_sysex(mode: HostMode, timeout: u8) -> Vec<u8, HOST_SYSEX_SIZE> {
+    sysex(CMD_MODE, mode as u8, &[timeout & 0x7F])
+}
@@ -0,0 +1,317 @@
+//! ホストが OLED の1ページを使う（Loopian のシーン名やパラメータの値を SysEx で送る）
+//!
+//! 設定と同じヘッダで、コマンドは 0x48 から。返信はしない
+//!
+//!   コマンド            データ
+//!   0x48 テキスト        行（0-4） | ASCII 最大 21文字（0x20-0x7E 以外は '?'。短ければ残りは消す）
+//!   0x49 アイコン        番号（0-7） | アイコン（0 は消す、Icon） | x | y
+//!   0x4A ビットマップ    先頭の行（0-63） | 1行 19byte × n（7ドットずつ上位ビットから左、最後の 5ドットは捨てる）
+//!   0x4B クリア          タイムアウト（秒、省略すると前の値。0 はすぐにローカルの画面に戻る）
+//!
+//! 128 × 64 のビットマップは 1つの SysEx に 12行までなので、6つに分けて送る
+//! 描画はビットマップ、アイコン、テキストの順に重ねる
+//! 書き込むとホストのページを表示し、タイムアウト（既定 5秒）まで書き込みがなければローカルの画面に戻る
+//! 書き込みは 20ms に1回まで（16回までは続けて送れる）。越えた分は捨てる
+//!
+//! Core0 の midi_rx_task が書き、Core1 の oled_ui_task が読むので、書き込み中に読んだときは読み直す
+use core::sync::atomic::fence;
+use heapless::{String, Vec};
+use portable_atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+
+use crate::display::{BUFFER_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::midi::config::CONFIG_HEADER;
+
+pub const CMD_TEXT: u8 = 0x48;
+pub const CMD_ICON: u8 = 0x49;
+pub const CMD_BITMAP: u8 = 0x4A;
+pub const CMD_CLEAR: u8 = 0x4B;
+pub const NUM_LINES: usize = 5;
+pub const LINE_LEN: usize = 21; // FONT_6X10 で 128 ドット
+pub const MAX_ICONS: usize = 8;
+pub const BYTES_PER_ROW: usize = DISPLAY_WIDTH.div_ceil(7);
+pub const ROWS_PER_SYSEX: usize = 12;
+pub const HOST_PAGE_SYSEX_SIZE: usize =
+    1 + CONFIG_HEADER.len() + 2 + ROWS_PER_SYSEX * BYTES_PER_ROW + 1;
+const DEFAULT_TIMEOUT: u8 = 5;
+const WRITE_INTERVAL_US: u64 = 20_000;
+const WRITE_BURST: u8 = 16;
+const WORDS_PER_ROW: usize = DISPLAY_WIDTH / 32;
+
+/// 組み込みのアイコン（8 × 8）
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Icon {
+    Play = 1,
+    Stop,
+    Record,
+    Pause,
+    Loop,
+    Note,
+    Up,
+    Down,
+}
+
+impl Icon {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Play),
+            2 => Some(Self::Stop),
+            3 => Some(Self::Record),
+            4 => Some(Self::Pause),
+            5 => Some(Self::Loop),
+            6 => Some(Self::Note),
+            7 => Some(Self::Up),
+            8 => Some(Self::Down),
+            _ => None,
+        }
+    }
+
+    /// 1行 1byte（上位ビットから左）
+    #[rustfmt::skip]
+    pub fn bitmap(self) -> [u8; 8] {
+        match self {
+            Self::Play =>   [0x40, 0x60, 0x70, 0x78, 0x78, 0x70, 0x60, 0x40],
+            Self::Stop =>   [0x00, 0x7E, 0x7E, 0x7E, 0x7E, 0x7E, 0x7E, 0x00],
+            Self::Record => [0x00, 0x3C, 0x7E, 0x7E, 0x7E, 0x7E, 0x3C, 0x00],
+            Self::Pause =>  [0x00, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00],
+            Self::Loop =>   [0x3C, 0x42, 0x81, 0x81, 0x85, 0x46, 0x3C, 0x00],
+            Self::Note =>   [0x0C, 0x0E, 0x0B, 0x08, 0x08, 0x78, 0xF8, 0x70],
+            Self::Up =>     [0x18, 0x3C, 0x7E, 0xFF, 0x18, 0x18, 0x18, 0x00],
+            Self::Down =>   [0x18, 0x18, 0x18, 0xFF, 0x7E, 0x3C, 0x18, 0x00],
+        }
+    }
+}
+
+/// 表示するアイコンと位置
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IconAt {
+    pub icon: Icon,
+    pub x: u8,
+    pub y: u8,
+}
+
+static SEQUENCE: AtomicU32 = AtomicU32::new(0); // 書き込み中は奇数
+static TEXT: [[AtomicU8; LINE_LEN]; NUM_LINES] =
+    [const { [const { AtomicU8::new(0) }; LINE_LEN] }; NUM_LINES];
+// アイコン << 16 | x << 8 | y
+static ICONS: [AtomicU32; MAX_ICONS] = [const { AtomicU32::new(0) }; MAX_ICONS];
+// 1行 4 word、上位ビットから左
+static BITMAP: [AtomicU32; DISPLAY_HEIGHT * WORDS_PER_ROW] =
+    [const { AtomicU32::new(0) }; DISPLAY_HEIGHT * WORDS_PER_ROW];
+static RECEIVED_US: AtomicU64 = AtomicU64::new(0);
+static TIMEOUT: AtomicU8 = AtomicU8::new(DEFAULT_TIMEOUT); // 秒
+static SHOWN: AtomicU8 = AtomicU8::new(0);
+// 書き込みの制限（トークンバケツ）
+static TOKENS: AtomicU8 = AtomicU8::new(WRITE_BURST);
+static REFILLED_US: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// ホストが書いたページ（Core1 で描画するためのコピー）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostPage {
+    pub lines: [String<LINE_LEN>; NUM_LINES],
+    pub icons: [Option<IconAt>; MAX_ICONS],
+    pub bitmap: [u8; BUFFER_SIZE], // 1行 16byte、上位ビットから左
+}
+
+impl HostPage {
+    /// 書き込み中なら読み直す（何度も重なったら最後に読んだものを返す）
+    pub fn load() -> Self {
+        for _ in 0..4 {
+            let before = SEQUENCE.load(Ordering::Acquire);
+            if before.is_multiple_of(2) {
+                let page = Self::read();
+                fence(Ordering::Acquire);
+                if SEQUENCE.load(Ordering::Relaxed) == before {
+                    return page;
+                }
+            }
+        }
+        Self::read()
+    }
+
+    fn read() -> Self {
+        let lines = core::array::from_fn(|line| {
+            let mut text = String::new();
+            for c in TEXT[line].iter().map(|c| c.load(Ordering::Relaxed)) {
+                if c == 0 {
+                    break;
+                }
+                let _ = text.push(c as char);
+            }
+            text
+        });
+        let icons = core::array::from_fn(|i| {
+            let v = ICONS[i].load(Ordering::Relaxed);
+            Icon::from_u8((v >> 16) as u8).map(|icon| IconAt {
+                icon,
+                x: (v >> 8) as u8,
+                y: v as u8,
+            })
+        });
+        let mut bitmap = [0u8; BUFFER_SIZE];
+        for (bytes, word) in bitmap.chunks_exact_mut(4).zip(BITMAP.iter()) {
+            bytes.copy_from_slice(&word.load(Ordering::Relaxed).to_be_bytes());
+        }
+        Self {
+            lines,
+            icons,
+            bitmap,
+        }
+    }
+}
+
+/// ホストのページを表示するか（書き込んでからタイムアウトまで）
+pub fn is_active(now_us: u64) -> bool {
+    let timeout_us = TIMEOUT.load(Ordering::Relaxed) as u64 * 1_000_000;
+    SHOWN.load(Ordering::Relaxed) != 0
+        && now_us.saturating_sub(RECEIVED_US.load(Ordering::Relaxed)) <= timeout_us
+}
+
+/// 最後に書き込んだ時刻（スイッチで閉じたページを次の書き込みでまた表示する）
+pub fn received_us() -> u64 {
+    RECEIVED_US.load(Ordering::Relaxed)
+}
+
+/// 書き込みの制限で捨てた数
+pub fn dropped_writes() -> u32 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// 書き込めるなら 1つ使って true
+fn take_token(now_us: u64) -> bool {
+    let refilled = REFILLED_US.load(Ordering::Relaxed);
+    let add = now_us.saturating_sub(refilled) / WRITE_INTERVAL_US;
+    let mut tokens = TOKENS.load(Ordering::Relaxed);
+    if add > 0 {
+        tokens = (tokens as u64 + add).min(WRITE_BURST as u64) as u8;
+        REFILLED_US.store(refilled + add * WRITE_INTERVAL_US, Ordering::Relaxed);
+    }
+    if tokens == 0 {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    TOKENS.store(tokens - 1, Ordering::Relaxed);
+    true
+}
+
+/// 受信した SysEx（F0 と F7 を除く）がホストのページなら反映して true
+/// 範囲外の行・番号・座標は無視する
+pub fn receive(sysex: &[u8], now_us: u64) -> bool {
+    let Some([cmd, data @ ..]) = sysex.strip_prefix(&CONFIG_HEADER) else {
+        return false;
+    };
+    if !matches!(*cmd, CMD_TEXT | CMD_ICON | CMD_BITMAP | CMD_CLEAR) {
+        return false;
+    }
+    if !take_token(now_us) {
+        return true;
+    }
+    // 書き込みの前後で SEQUENCE を進める（読む側は奇数か変わっていたら読み直す）
+    SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    let mut shown = true;
+    match (*cmd, data) {
+        (CMD_TEXT, [line, text @ ..]) if (*line as usize) < NUM_LINES => {
+            for (i, c) in TEXT[*line as usize].iter().enumerate() {
+                let value = match text.get(i) {
+                    Some(&b) if (0x20..=0x7E).contains(&b) => b,
+                    Some(_) => b'?',
+                    None => 0,
+                };
+                c.store(value, Ordering::Relaxed);
+            }
+        }
+        (CMD_ICON, [slot, icon, x, y]) if (*slot as usize) < MAX_ICONS => {
+            let value = (*icon as u32) << 16 | (*x as u32) << 8 | *y as u32;
+            ICONS[*slot as usize].store(value, Ordering::Relaxed);
+        }
+        (CMD_BITMAP, [start, rows @ ..]) => {
+            for (i, row) in rows.chunks_exact(BYTES_PER_ROW).enumerate() {
+                let y = *start as usize + i;
+                if y >= DISPLAY_HEIGHT {
+                    break;
+                }
+                let mut words = [0u32; WORDS_PER_ROW];
+                for x in 0..DISPLAY_WIDTH {
+                    if row[x / 7] & (0x40 >> (x % 7)) != 0 {
+                        words[x / 32] |= 0x8000_0000 >> (x % 32);
+                    }
+                }
+                for (word, v) in BITMAP[y * WORDS_PER_ROW..].iter().zip(words) {
+                    word.store(v, Ordering::Relaxed);
+                }
+            }
+        }
+        (CMD_CLEAR, timeout) => {
+            TEXT.iter()
+                .flatten()
+                .for_each(|c| c.store(0, Ordering::Relaxed));
+            ICONS.iter().for_each(|v| v.store(0, Ordering::Relaxed));
+            BITMAP.iter().for_each(|v| v.store(0, Ordering::Relaxed));
+            match timeout.first() {
+                Some(0) => shown = false,
+                Some(&seconds) => TIMEOUT.store(seconds, Ordering::Relaxed),
+                None => {}
+            }
+        }
+        _ => {}
+    }
+    fence(Ordering::Release);
+    SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    SHOWN.store(shown as u8, Ordering::Relaxed);
+    RECEIVED_US.store(now_us, Ordering::Relaxed);
+    true
+}
+
+// =========================================================
+//      Encoder（ホスト側とテスト用）
+// =========================================================
+fn sysex(cmd: u8, data: &[u8]) -> Vec<u8, HOST_PAGE_SYSEX_SIZE> {
+    let mut sysex = Vec::new();
+    let _ = sysex.push(0xF0);
+    let _ = sysex.extend_from_slice(&CONFIG_HEADER);
+    let _ = sysex.push(cmd);
+    let _ = sysex.extend_from_slice(data);
+    let _ = sysex.push(0xF7);
+    sysex
+}
+
+/// テキストの SysEx（F0 から F7 まで。21文字を越える分は送らない）
+pub fn text_sysex(line: u8, text: &str) -> Vec<u8, HOST_PAGE_SYSEX_SIZE> {
+    let mut data: Vec<u8, { LINE_LEN + 1 }> = Vec::new();
+    let _ = data.push(line & 0x7F);
+    for b in text.bytes().take(LINE_LEN) {
+        let _ = data.push(b & 0x7F);
+    }
+    sysex(CMD_TEXT, &data)
+}
+
+/// アイコンの SysEx（None は消す）
+pub fn icon_sysex(slot: u8, icon: Option<Icon>, x: u8, y: u8) -> Vec<u8, HOST_PAGE_SYSEX_SIZE> {
+    let icon = icon.map_or(0, |i| i as u8);
+    sysex(CMD_ICON, &[slot & 0x7F, icon, x & 0x7F, y & 0x7F])
+}
+
+/// ビットマップの SysEx（1行 16byte、上位ビットから左）。ROWS_PER_SYSEX を越える分は送らない
+pub fn bitmap_sysex(start: u8, rows: &[[u8; DISPLAY_WIDTH / 8]]) -> Vec<u8, HOST_PAGE_SYSEX_SIZE> {
+    let mut data: Vec<u8, HOST_PAGE_SYSEX_SIZE> = Vec::new();
+    let _ = data.push(start & 0x7F);
+    for row in rows.iter().take(ROWS_PER_SYSEX) {
+        let mut packed = [0u8; BYTES_PER_ROW];
+        for x in 0..DISPLAY_WIDTH {
+            if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                packed[x / 7] |= 0x40 >> (x % 7);
+            }
+        }
+        let _ = data.extend_from_slice(&packed);
+    }
+    sysex(CMD_BITMAP, &data)
+}
+
+/// クリアの SysEx（タイムアウトは秒、None は前の値のまま）
+pub fn clear_sysex(timeout: Option<u8>) -> Vec<u8, HOST_PAGE_SYSEX_SIZE> {
+    match timeout {
+        Some(seconds) => sysex(CMD_CLEAR, &[seconds & 0x7F]),
+        None => sysex(CMD_CLEAR, &[]),
+    }
+}
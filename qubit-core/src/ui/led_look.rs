@@ -0,0 +1,221 @@
+//! RingLED の見た目（明るさ・待機中のアニメーション・色）を MIDI で変える
+//!
+//! 受信した Control Change（全チャンネル。一般の CC とぶつからないように未定義の番号を使う）
+//!   CC 102  全体の明るさ        0-127（127 が最大）
+//!   CC 103  待機中のアニメーション  0-31: Wave, 32-63: Breath, 64-95: Static, 96-127: Off
+//!   CC 104  カラーテーマ（待機中の波の色）  0-31: White, 32-63: Amber, 64-95: Ocean, 96-127: Forest
+//!   CC 105  受信したノートの色   値/16 で PALETTE から選ぶ
+//!   CC 106  タッチの光の色       値/16 で PALETTE から選ぶ
+//! Program Change 0-7 で SCENES（上の組み合わせ）を選ぶ。それ以外は無視する
+use portable_atomic::{AtomicU8, Ordering};
+
+pub const CC_LED_BRIGHTNESS: u8 = 102;
+pub const CC_LED_IDLE: u8 = 103;
+pub const CC_LED_THEME: u8 = 104;
+pub const CC_LED_RX_COLOR: u8 = 105;
+pub const CC_LED_TOUCH_COLOR: u8 = 106;
+
+/// 受信したノートとタッチの光の色 (r, g, b)
+/// Magenta, Cyan, Red, Orange, Yellow, Green, Blue, White
+pub const PALETTE: [(u8, u8, u8); 8] = [
+    (220, 0, 220),
+    (0, 120, 180),
+    (220, 0, 0),
+    (220, 80, 0),
+    (200, 160, 0),
+    (0, 200, 40),
+    (0, 40, 220),
+    (160, 160, 160),
+];
+pub const COLOR_MAGENTA: u8 = 0;
+pub const COLOR_CYAN: u8 = 1;
+pub const COLOR_RED: u8 = 2;
+pub const COLOR_ORANGE: u8 = 3;
+pub const COLOR_YELLOW: u8 = 4;
+pub const COLOR_GREEN: u8 = 5;
+pub const COLOR_BLUE: u8 = 6;
+pub const COLOR_WHITE: u8 = 7;
+
+/// カラーテーマ: 待機中の波の明るさに掛ける (r, g, b, w)
+/// White, Amber, Ocean, Forest
+pub const THEMES: [(f32, f32, f32, f32); 4] = [
+    (0.0, 0.0, 0.0, 1.0),
+    (1.0, 0.35, 0.0, 0.0),
+    (0.0, 0.4, 1.0, 0.0),
+    (0.1, 1.0, 0.2, 0.0),
+];
+pub const THEME_WHITE: u8 = 0;
+pub const THEME_AMBER: u8 = 1;
+pub const THEME_OCEAN: u8 = 2;
+pub const THEME_FOREST: u8 = 3;
+
+/// 待機中（タッチや受信がないところ）のアニメーション
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdleAnimation {
+    Wave,   // 波が回る（MIDI クロックに同期しているときは1小節で1周）
+    Breath, // 全体がゆっくり明るさを変える（同期しているときは拍に合わせる）
+    Static, // 一定の明るさ
+    Off,
+}
+
+impl IdleAnimation {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Wave,
+            1 => Self::Breath,
+            2 => Self::Static,
+            _ => Self::Off,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LedLook {
+    pub brightness: u8, // 0-127
+    pub idle: IdleAnimation,
+    pub theme: u8,       // THEMES の添字
+    pub rx_color: u8,    // PALETTE の添字
+    pub touch_color: u8, // PALETTE の添字
+}
+
+impl LedLook {
+    pub const DEFAULT: Self = Self {
+        brightness: 127,
+        idle: IdleAnimation::Wave,
+        theme: THEME_WHITE,
+        rx_color: COLOR_CYAN,
+        touch_color: COLOR_MAGENTA,
+    };
+
+    pub fn load() -> Self {
+        Self {
+            brightness: LED_BRIGHTNESS.load(Ordering::Relaxed),
+            idle: IdleAnimation::from_u8(LED_IDLE.load(Ordering::Relaxed)),
+            theme: LED_THEME.load(Ordering::Relaxed),
+            rx_color: LED_RX_COLOR.load(Ordering::Relaxed),
+            touch_color: LED_TOUCH_COLOR.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn store(&self) {
+        LED_BRIGHTNESS.store(self.brightness.min(127), Ordering::Relaxed);
+        LED_IDLE.store(self.idle as u8, Ordering::Relaxed);
+        LED_THEME.store(self.theme.min(THEMES.len() as u8 - 1), Ordering::Relaxed);
+        LED_RX_COLOR.store(
+            self.rx_color.min(PALETTE.len() as u8 - 1),
+            Ordering::Relaxed,
+        );
+        LED_TOUCH_COLOR.store(
+            self.touch_color.min(PALETTE.len() as u8 - 1),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn theme(&self) -> (f32, f32, f32, f32) {
+        THEMES[(self.theme as usize).min(THEMES.len() - 1)]
+    }
+
+    pub fn rx_rgb(&self) -> (u8, u8, u8) {
+        PALETTE[(self.rx_color as usize).min(PALETTE.len() - 1)]
+    }
+
+    pub fn touch_rgb(&self) -> (u8, u8, u8) {
+        PALETTE[(self.touch_color as usize).min(PALETTE.len() - 1)]
+    }
+
+    /// 全体の明るさを掛ける（127 ならそのまま）
+    pub fn scale(&self, value: u8) -> u8 {
+        (value as u16 * self.brightness.min(127) as u16 / 127) as u8
+    }
+}
+
+impl Default for LedLook {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static LED_BRIGHTNESS: AtomicU8 = AtomicU8::new(LedLook::DEFAULT.brightness);
+static LED_IDLE: AtomicU8 = AtomicU8::new(LedLook::DEFAULT.idle as u8);
+static LED_THEME: AtomicU8 = AtomicU8::new(LedLook::DEFAULT.theme);
+static LED_RX_COLOR: AtomicU8 = AtomicU8::new(LedLook::DEFAULT.rx_color);
+static LED_TOUCH_COLOR: AtomicU8 = AtomicU8::new(LedLook::DEFAULT.touch_color);
+
+/// Program Change で選ぶシーン
+pub const SCENES: [LedLook; 8] = [
+    // 0: 初期状態
+    LedLook::DEFAULT,
+    // 1: 暗め
+    LedLook {
+        brightness: 40,
+        ..LedLook::DEFAULT
+    },
+    // 2: 暖色でゆっくり
+    LedLook {
+        brightness: 127,
+        idle: IdleAnimation::Breath,
+        theme: THEME_AMBER,
+        rx_color: COLOR_ORANGE,
+        touch_color: COLOR_WHITE,
+    },
+    // 3: 青
+    LedLook {
+        brightness: 127,
+        idle: IdleAnimation::Wave,
+        theme: THEME_OCEAN,
+        rx_color: COLOR_WHITE,
+        touch_color: COLOR_BLUE,
+    },
+    // 4: 緑で動かない
+    LedLook {
+        brightness: 100,
+        idle: IdleAnimation::Static,
+        theme: THEME_FOREST,
+        rx_color: COLOR_GREEN,
+        touch_color: COLOR_MAGENTA,
+    },
+    // 5: 待機中は消してタッチと受信だけ
+    LedLook {
+        idle: IdleAnimation::Off,
+        ..LedLook::DEFAULT
+    },
+    // 6: 赤
+    LedLook {
+        brightness: 127,
+        idle: IdleAnimation::Breath,
+        theme: THEME_AMBER,
+        rx_color: COLOR_RED,
+        touch_color: COLOR_RED,
+    },
+    // 7: 消灯
+    LedLook {
+        brightness: 0,
+        ..LedLook::DEFAULT
+    },
+];
+
+/// 受信した Control Change。RingLED の CC なら反映して true
+pub fn control_change(control: u8, value: u8) -> bool {
+    let mut look = LedLook::load();
+    match control {
+        CC_LED_BRIGHTNESS => look.brightness = value,
+        CC_LED_IDLE => look.idle = IdleAnimation::from_u8(value >> 5),
+        CC_LED_THEME => look.theme = value >> 5,
+        CC_LED_RX_COLOR => look.rx_color = value >> 4,
+        CC_LED_TOUCH_COLOR => look.touch_color = value >> 4,
+        _ => return false,
+    }
+    look.store();
+    true
+}
+
+/// 受信した Program Change。シーンがあれば選んで true
+pub fn program_change(program: u8) -> bool {
+    match SCENES.get(program as usize) {
+        Some(scene) => {
+            scene.store();
+            true
+        }
+        None => false,
+    }
+}
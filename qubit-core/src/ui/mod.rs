@@ -0,0 +1,5 @@
+pub mod host_frame;
+pub mod host_page;
+pub mod led_look;
+pub mod oled_display;
+pub mod ringled;
@@ -1,54 +1,123 @@
 use core::fmt::Write;
 use embedded_graphics::image::{Image, ImageRaw};
 use embedded_graphics::mono_font::MonoTextStyle;
-use embedded_graphics::mono_font::ascii::{FONT_6X10, FONT_10X20};
+use embedded_graphics::mono_font::ascii::{FONT_5X8, FONT_6X10, FONT_10X20};
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::{
     Circle, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, RoundedRectangle, Triangle,
 };
-use embedded_graphics::text::Text;
+use embedded_graphics::text::{Baseline, Text};
 use heapless::String;
 
-use crate::devices::ssd1306::OledBuffer;
+use crate::constants::{I2C_DEVICE_ADDRS, I2C_FAULT_LABELS, TOTAL_CH};
+use crate::display::OledBuffer;
+use crate::midi::clock::{BEATS_PER_BAR, ClockState};
+use crate::midi::loopian::{self, LoopianState, MAX_PARTS};
+use crate::settings::{self, PARAMS};
+use crate::touch::key_mask::KeyMask;
+use crate::ui::host_page::HostPage;
 use crate::{
     AD_VALUE0,
     AD_VALUE1,
     AD_VALUE2,
     AD_VALUE3,
     ELAPSED_TIME,
+    I2C_ERROR_LOG,
+    I2C_RECOVERY_COUNT,
     POINT0,
     POINT1,
     POINT2,
     POINT3,
     PRESSURE,
+    SCAN_FRAME_RATE,
+    SCAN_MAX_AGE,
+    SCAN_RATE,
     TOUCH0,
     TOUCH1,
     TOUCH2,
     TOUCH3,
     //ERROR_CODE,
-    WORK_MODE,
 };
 
+/// 設定画面（両方のスイッチ同時押しで遷移）
+pub const PAGE_SETTINGS: u8 = 4;
+/// MIDI クロックの画面（左スイッチでタップテンポ、長押しでスタート/ストップ）
+pub const PAGE_CLOCK: u8 = 7;
+/// Loopian の画面（SysEx で受け取ったパートとフレーズ名、ループの位置）
+pub const PAGE_LOOPIAN: u8 = 8;
+/// ホストのページ（SysEx で受け取ったテキスト・アイコン・ビットマップ。通常ページには入れない）
+pub const PAGE_HOST: u8 = 9;
+/// スイッチで順に切り替える通常ページ
+const NORMAL_PAGES: [u8; 8] = [0, 1, 2, 3, 5, 6, 7, 8];
+
+/// 次の通常ページ
+pub fn next_page(page: u8) -> u8 {
+    let idx = NORMAL_PAGES.iter().position(|p| *p == page).unwrap_or(0);
+    NORMAL_PAGES[(idx + 1) % NORMAL_PAGES.len()]
+}
+
+/// 前の通常ページ
+pub fn prev_page(page: u8) -> u8 {
+    let idx = NORMAL_PAGES.iter().position(|p| *p == page).unwrap_or(0);
+    NORMAL_PAGES[(idx + NORMAL_PAGES.len() - 1) % NORMAL_PAGES.len()]
+}
+
 pub struct GraphicsDisplay {
     page: u8,
     step: u8,
     anim_x: u8,
+    menu_cursor: usize, // 設定画面で選択中の項目
+    now_us: u64,        // 描画する時刻（クロックの拍の補間用）
+    build_date: &'static str,
+    build_time: &'static str,
 }
 
 impl GraphicsDisplay {
-    pub fn new() -> Self {
+    /// ビルド日時は起動画面に表示する（ファームウェアの build.rs が作る）
+    pub fn new(build_date: &'static str, build_time: &'static str) -> Self {
         Self {
             page: 0,
             step: 0,
             anim_x: 0,
+            menu_cursor: 0,
+            now_us: 0,
+            build_date,
+            build_time,
         }
     }
 
     pub fn change_page(&mut self, page: u8) {
+        if page != self.page {
+            self.menu_cursor = 0; // 設定画面に入るたびに先頭の項目から
+        }
         self.page = page; // 14 demo pages
     }
 
+    pub fn set_time(&mut self, now_us: u64) {
+        self.now_us = now_us;
+    }
+
+    /// 設定画面で次の項目へ移る。最後の項目を過ぎたら false
+    pub fn menu_next(&mut self) -> bool {
+        self.menu_cursor += 1;
+        // 予約の ID は飛ばす
+        while PARAMS
+            .get(self.menu_cursor)
+            .is_some_and(|p| p.is_reserved())
+        {
+            self.menu_cursor += 1;
+        }
+        self.menu_cursor < PARAMS.len()
+    }
+
+    /// 設定画面で選択中の項目の値を変える
+    pub fn menu_change(&mut self) {
+        if let Some(param) = PARAMS.get(self.menu_cursor) {
+            param.cycle();
+        }
+    }
+
     pub fn draw_bringup_screen(&self, buffer: &mut OledBuffer) {
         buffer.clear();
         let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
@@ -60,18 +129,12 @@ impl GraphicsDisplay {
         let style_small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
         let _ = Text::new("Loopian::", Point::new(5, 20), style_small).draw(buffer);
         let _ = Text::new("QUBIT", Point::new(64, 20), style_big).draw(buffer);
-        let _ = Text::new(
-            concat!("build: ", env!("BUILD_DATE")),
-            Point::new(30, 44),
-            style_small,
-        )
-        .draw(buffer);
-        let _ = Text::new(
-            concat!("       ", env!("BUILD_TIME")),
-            Point::new(30, 56),
-            style_small,
-        )
-        .draw(buffer);
+        let mut text: String<32> = String::new();
+        let _ = write!(text, "build: {}", self.build_date);
+        let _ = Text::new(&text, Point::new(30, 44), style_small).draw(buffer);
+        text.clear();
+        let _ = write!(text, "       {}", self.build_time);
+        let _ = Text::new(&text, Point::new(30, 56), style_small).draw(buffer);
     }
 
     /// Executes a single demo step and returns the suggested delay (ms) before the next step.
@@ -81,7 +144,12 @@ impl GraphicsDisplay {
             1 => display1(buffer, counter),
             2 => display2(buffer),
             3 => display3(buffer),
-            4 => display4(buffer, counter),
+            4 => display4(buffer, counter, self.menu_cursor),
+            5 => display5(buffer),
+            6 => display6(buffer),
+            7 => display7(buffer, self.now_us),
+            8 => display8(buffer, self.now_us),
+            9 => display_host(buffer),
             10 => demo_lines(buffer),
             11 => demo_rects(buffer),
             12 => demo_filled_rects(buffer),
@@ -229,9 +297,18 @@ fn display3(buffer: &mut OledBuffer) {
         let _ = write!(text1, "Touch4: ---");
     }
     let _ = Text::new(&text1, Point::new(6, 48), style_small).draw(buffer);
+
+    // マスク中のキー数（故障チップなど）
+    let masked = KeyMask::load().count();
+    if masked > 0 {
+        text1.clear();
+        let _ = write!(text1, "Masked: {} keys", masked);
+        let _ = Text::new(&text1, Point::new(6, 60), style_small).draw(buffer);
+    }
 }
 
-fn display4(buffer: &mut OledBuffer, counter: u32) {
+fn display4(buffer: &mut OledBuffer, counter: u32, cursor: usize) {
+    const ROWS: usize = 4; // 一度に表示する項目数
     buffer.clear();
 
     let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
@@ -239,35 +316,219 @@ fn display4(buffer: &mut OledBuffer, counter: u32) {
         .into_styled(outline)
         .draw(buffer);
 
-    //let style_big = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
     let style_small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
 
+    // 選択中の項目が見えるようにスクロールする（予約の ID は表示しない）
+    let items = || PARAMS.iter().enumerate().filter(|(_, p)| !p.is_reserved());
+    let top = items()
+        .position(|(i, _)| i == cursor)
+        .unwrap_or(0)
+        .saturating_sub(ROWS - 1);
     let mut text: String<32> = String::new();
-    let _ = write!(text, "Piano");
-    let _ = Text::new(&text, Point::new(12, 18), style_small).draw(buffer);
+    for (row, (i, param)) in items().skip(top).take(ROWS).enumerate() {
+        text.clear();
+        let mark = if i == cursor && counter % 10 < 5 {
+            '>'
+        } else {
+            ' '
+        };
+        match param.label() {
+            Some(label) => {
+                let _ = write!(text, "{}{:<11}{}", mark, param.name, label);
+            }
+            None => {
+                let _ = write!(text, "{}{:<11}{}", mark, param.name, param.get());
+            }
+        }
+        let _ = Text::new(&text, Point::new(4, 12 + row as i32 * 11), style_small).draw(buffer);
+    }
 
     text.clear();
-    let _ = write!(text, "Violin");
-    let _ = Text::new(&text, Point::new(12, 36), style_small).draw(buffer);
+    let _ = write!(text, "change      next");
+    let _ = Text::new(&text, Point::new(16, 58), style_small).draw(buffer);
+}
+
+fn display5(buffer: &mut OledBuffer) {
+    buffer.clear();
+
+    let style_tiny = MonoTextStyle::new(&FONT_5X8, BinaryColor::On);
+
+    // I2Cデバイスごとのエラー回数（N:NACK A:Arbitration B:Bus T:Timeout O:Other）
+    let mut text: String<32> = String::new();
+    for (i, addr) in I2C_DEVICE_ADDRS.iter().enumerate() {
+        text.clear();
+        let _ = write!(text, "{:02X} ", addr);
+        for (kind, label) in I2C_FAULT_LABELS.iter().enumerate() {
+            let count = I2C_ERROR_LOG[i][kind].load(core::sync::atomic::Ordering::Relaxed);
+            let _ = write!(text, " {}{}", label, count.min(99));
+        }
+        let _ = Text::new(&text, Point::new(2, 7 + i as i32 * 9), style_tiny).draw(buffer);
+    }
 
     text.clear();
-    let _ = write!(text, "up/down   quit");
-    let _ = Text::new(&text, Point::new(20, 56), style_small).draw(buffer);
+    let recovery = I2C_RECOVERY_COUNT.load(core::sync::atomic::Ordering::Relaxed);
+    let _ = write!(text, "Bus recovery: {}", recovery);
+    let _ = Text::new(&text, Point::new(2, 61), style_tiny).draw(buffer);
+}
 
-    let work_mode = WORK_MODE.load(core::sync::atomic::Ordering::Relaxed);
-    if counter % 10 < 5 {
-        if work_mode == 0 {
-            let _ = Rectangle::new(Point::new(8, 10), Size::new(100, 14))
-                .into_styled(outline)
+fn display6(buffer: &mut OledBuffer) {
+    buffer.clear();
+
+    let style_tiny = MonoTextStyle::new(&FONT_5X8, BinaryColor::On);
+
+    // スキャンのフレームレートと、フレーム内の最も古いデータの経過時間
+    let mut text: String<32> = String::new();
+    let frame_rate = SCAN_FRAME_RATE.load(core::sync::atomic::Ordering::Relaxed);
+    let max_age = SCAN_MAX_AGE.load(core::sync::atomic::Ordering::Relaxed) / 1000;
+    let _ = write!(text, "Scan:{}Hz Age:{}ms", frame_rate, max_age.min(9999));
+    let _ = Text::new(&text, Point::new(2, 7), style_tiny).draw(buffer);
+
+    // チャンネルごとの読み込みレート(Hz)
+    for row in 0..(TOTAL_CH / 4) {
+        text.clear();
+        let _ = write!(text, "ch{:02}:", row * 4);
+        for col in 0..4 {
+            let rate = SCAN_RATE[row * 4 + col].load(core::sync::atomic::Ordering::Relaxed);
+            let _ = write!(text, " {:4}", rate.min(9999));
+        }
+        let _ = Text::new(&text, Point::new(2, 21 + row as i32 * 11), style_tiny).draw(buffer);
+    }
+}
+
+/// MIDI クロック: 内部/外部、再生中か、テンポ、小節と拍
+fn display7(buffer: &mut OledBuffer, now_us: u64) {
+    buffer.clear();
+
+    let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let _ = Rectangle::new(Point::new(0, 0), Size::new(128, 64))
+        .into_styled(outline)
+        .draw(buffer);
+
+    let style_big = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    let style_small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+
+    let state = ClockState::load();
+    let master = settings::is_clock_master();
+    let mut text: String<32> = String::new();
+    let _ = write!(text, "Clock: {}", if master { "Int" } else { "Ext" });
+    let _ = Text::new(&text, Point::new(6, 12), style_small).draw(buffer);
+    let running = if state.running { "Play" } else { "Stop" };
+    let _ = Text::new(running, Point::new(94, 12), style_small).draw(buffer);
+
+    // 内部クロックは設定したテンポ、外部クロックは受信したテンポ
+    text.clear();
+    if master {
+        let _ = write!(
+            text,
+            "{:5}",
+            settings::TEMPO.load(core::sync::atomic::Ordering::Relaxed)
+        );
+    } else if let Some(bpm) = state.tempo_bpm(now_us) {
+        let _ = write!(text, "{:5.1}", bpm);
+    } else {
+        let _ = write!(text, "  ---");
+    }
+    let _ = Text::new(&text, Point::new(6, 34), style_big).draw(buffer);
+    let _ = Text::new("BPM", Point::new(60, 34), style_small).draw(buffer);
+
+    // 小節.拍 と、今の拍の位置
+    if let Some(beat) = state.beat(now_us) {
+        text.clear();
+        let _ = write!(text, "{:3}.{}", beat.bar() + 1, beat.beat_in_bar() + 1);
+        let _ = Text::new(&text, Point::new(6, 46), style_small).draw(buffer);
+        for i in 0..BEATS_PER_BAR {
+            let style = if i == beat.beat_in_bar() {
+                PrimitiveStyle::with_fill(BinaryColor::On)
+            } else {
+                outline
+            };
+            let _ = Rectangle::new(Point::new(70 + i as i32 * 12, 38), Size::new(8, 8))
+                .into_styled(style)
+                .draw(buffer);
+        }
+    }
+
+    let _ = Text::new("tap         next", Point::new(16, 58), style_small).draw(buffer);
+}
+
+/// Loopian: 演奏中のパート（反転）とフレーズ名、小節の中の拍、ループの位置
+fn display8(buffer: &mut OledBuffer, now_us: u64) {
+    buffer.clear();
+
+    let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let _ = Rectangle::new(Point::new(0, 0), Size::new(128, 64))
+        .into_styled(outline)
+        .draw(buffer);
+
+    let style_small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let style_inverted = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+
+    let state = LoopianState::load();
+    let _ = Text::new("Loopian", Point::new(6, 12), style_small).draw(buffer);
+    let mut text: String<32> = String::new();
+    if state.length > 0 {
+        let _ = write!(text, "Beat {}", state.beat as u32 + 1);
+        let _ = Text::new(&text, Point::new(80, 12), style_small).draw(buffer);
+    }
+
+    // パートは 2 × 2 に並べる
+    for part in 0..MAX_PARTS {
+        let x = 4 + (part % 2) as i32 * 62;
+        let y = 26 + (part / 2) as i32 * 12;
+        text.clear();
+        let _ = write!(text, "{} {}", part + 1, loopian::phrase_name(part));
+        let style = if part == state.part as usize {
+            let _ = Rectangle::new(Point::new(x, y - 9), Size::new(60, 11))
+                .into_styled(fill)
                 .draw(buffer);
+            style_inverted
         } else {
-            let _ = Rectangle::new(Point::new(8, 27), Size::new(100, 14))
+            style_small
+        };
+        let _ = Text::new(&text, Point::new(x + 2, y), style).draw(buffer);
+    }
+
+    // ループの位置
+    match state.playhead(&ClockState::load(), now_us) {
+        Some(playhead) => {
+            let _ = Rectangle::new(Point::new(6, 50), Size::new(116, 7))
                 .into_styled(outline)
                 .draw(buffer);
+            let width = (playhead * 114.0) as u32;
+            if width > 0 {
+                let _ = Rectangle::new(Point::new(7, 51), Size::new(width, 5))
+                    .into_styled(fill)
+                    .draw(buffer);
+            }
+        }
+        None => {
+            let _ = Text::new("no loop", Point::new(43, 57), style_small).draw(buffer);
         }
     }
 }
 
+/// ホストのページ: ビットマップ、アイコン、テキストの順に重ねる
+fn display_host(buffer: &mut OledBuffer) {
+    buffer.clear();
+    let page = HostPage::load();
+
+    let raw: ImageRaw<BinaryColor> = ImageRaw::new(&page.bitmap, 128);
+    let _ = Image::new(&raw, Point::zero()).draw(buffer);
+
+    for at in page.icons.iter().flatten() {
+        let bitmap = at.icon.bitmap();
+        let raw: ImageRaw<BinaryColor> = ImageRaw::new(&bitmap, 8);
+        let _ = Image::new(&raw, Point::new(at.x as i32, at.y as i32)).draw(buffer);
+    }
+
+    let style_small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    for (i, line) in page.lines.iter().enumerate() {
+        let position = Point::new(0, i as i32 * 13);
+        let _ = Text::with_baseline(line, position, style_small, Baseline::Top).draw(buffer);
+    }
+}
+
 pub fn draw_bar(buffer: &mut OledBuffer, number: i32, value: u32) {
     const BAR_START_X: i32 = 54;
     let start_y: i32 = 24 + number * 2;
@@ -0,0 +1,208 @@
+use crate::constants::*;
+use crate::hal::Rgbw;
+use crate::midi::clock::BeatPhase;
+use crate::touch::key_mask::KeyMask;
+use crate::ui::host_frame::HostMode;
+use crate::ui::led_look::{IdleAnimation, LedLook};
+use core::f32::consts::PI;
+use libm::sinf;
+
+pub struct RingLed {
+    rxkey_state: [bool; NUM_LEDS], // 受信したNote On/Offの状態を保持
+    touchkey_state: [Option<f32>; MAX_TOUCH_POINTS], // 送信したNote On/Offの状態を保持
+    counter: u32,                  // 色の変化のためのカウンター
+    key_mask: KeyMask,             // マスクされたキー（故障チップなど）
+    beat: Option<BeatPhase>,       // MIDI クロックに同期しているときの拍
+    look: LedLook,                 // 明るさと色（MIDI の CC / Program Change で変わる）
+    playhead: Option<f32>,         // Loopian のループの位置（0.0-1.0）
+    host: Option<(HostMode, [Rgbw; NUM_LEDS])>, // ホストから受け取ったフレーム
+}
+
+impl RingLed {
+    pub fn new() -> Self {
+        Self {
+            rxkey_state: [false; NUM_LEDS],
+            touchkey_state: [None; MAX_TOUCH_POINTS],
+            counter: 0,
+            key_mask: KeyMask::new(),
+            beat: None,
+            look: LedLook::DEFAULT,
+            playhead: None,
+            host: None,
+        }
+    }
+
+    /// マスクされたキーを設定する（該当LEDを赤く表示する）
+    pub fn set_key_mask(&mut self, mask: KeyMask) {
+        self.key_mask = mask;
+    }
+
+    /// MIDI クロックの拍を設定する（None なら自走する）
+    pub fn set_beat(&mut self, beat: Option<BeatPhase>) {
+        self.beat = beat;
+    }
+
+    /// 明るさ・アニメーション・色を設定する
+    pub fn set_look(&mut self, look: LedLook) {
+        self.look = look;
+    }
+
+    /// Loopian のループの位置を設定する（1周がループ1回。None なら表示しない）
+    pub fn set_playhead(&mut self, playhead: Option<f32>) {
+        self.playhead = playhead;
+    }
+
+    /// ホストのフレームを設定する（None ならローカルの表示だけ）
+    pub fn set_host_frame(&mut self, host: Option<(HostMode, [Rgbw; NUM_LEDS])>) {
+        self.host = host;
+    }
+
+    pub fn set_color(&mut self, data: &mut [Rgbw; NUM_LEDS], location: f32, cmd: u8) {
+        let num = (location + 0.5).clamp(0.0, (NUM_LEDS - 1) as f32) as usize; // 安全のために位置をクランプ
+        if cmd == RINGLED_CMD_RX_ON {
+            self.rxkey_state[num] = true;
+        } else if cmd == RINGLED_CMD_RX_OFF {
+            self.rxkey_state[num] = false;
+        } else if cmd & 0xf0 == RINGLED_CMD_TX_ON {
+            let idx = (cmd & 0x0f).clamp(0, MAX_TOUCH_POINTS_U8 - 1) as usize;
+            self.touchkey_state[idx] = Some(location);
+        } else if cmd & 0xf0 == RINGLED_CMD_TX_OFF {
+            let idx = (cmd & 0x0f).clamp(0, MAX_TOUCH_POINTS_U8 - 1) as usize;
+            self.touchkey_state[idx] = None;
+        }
+
+        let num_leds_f = NUM_LEDS as f32;
+        let look = self.look;
+        // 置き換え: ホストのフレームだけを表示する
+        let mut host_frame = [Rgbw::default(); NUM_LEDS];
+        match self.host {
+            Some((HostMode::Replace, frame)) => {
+                for (led, host) in data.iter_mut().zip(frame.iter()) {
+                    *led = Rgbw {
+                        r: look.scale(host.r),
+                        g: look.scale(host.g),
+                        b: look.scale(host.b),
+                        w: look.scale(host.w),
+                    };
+                }
+                self.counter = self.counter.wrapping_add(1);
+                return;
+            }
+            Some((HostMode::Blend, frame)) => host_frame = frame,
+            _ => {}
+        }
+        let blend = matches!(self.host, Some((HostMode::Blend, _)));
+        let time_sec = self.counter as f32 * 0.02; // ringled_task is updated every 20ms
+        let (phase, breath) = match self.beat {
+            // 同期中: 波は1小節で1周期進み、拍の頭で明るくなって減衰する（1拍目は強く）
+            Some(beat) => {
+                let decay = 1.0 - beat.phase;
+                let accent = if beat.beat_in_bar() == 0 { 1.0 } else { 0.6 };
+                (
+                    2.0 * PI * beat.bar_phase(),
+                    0.3 + 0.7 * accent * decay * decay,
+                )
+            }
+            None => (0.5 * PI * time_sec, 1.0), // 0.5pi rad/s
+        };
+        // Breath: 同期中は拍に合わせ、それ以外は4秒で1周期
+        let glow = match self.beat {
+            Some(_) => breath,
+            None => {
+                let s = (sinf(0.5 * PI * time_sec) + 1.0) * 0.5;
+                s * s
+            }
+        };
+        let (theme_r, theme_g, theme_b, theme_w) = look.theme();
+        let (touch_r, touch_g, touch_b) = look.touch_rgb();
+        let (rx_r, rx_g, rx_b) = look.rx_rgb();
+
+        for (i, led) in data.iter_mut().enumerate().take(NUM_LEDS) {
+            // 重ねる: 待機中のアニメーションの代わりにホストのフレーム
+            let idle_animation = if blend { IdleAnimation::Off } else { look.idle };
+            let level = match idle_animation {
+                IdleAnimation::Wave => {
+                    let led_angle = (i as f32 / num_leds_f) * 2.0 * PI;
+                    // 8x finer spatial wave and darker output for NeoPixel brightness perception.
+                    let wave = (sinf(led_angle * 8.0 - phase) + 1.0) * 0.5;
+                    let wave_shaped = wave * wave;
+                    // Keep the wider white range (2..24) without temporal dithering.
+                    2.0 + wave_shaped * 22.0 * breath
+                }
+                IdleAnimation::Breath => 2.0 + 22.0 * glow,
+                IdleAnimation::Static => 8.0,
+                IdleAnimation::Off => 0.0,
+            };
+            let idle = |k: f32| (level * k).clamp(0.0, 255.0) as u8;
+
+            let mut r = 0u8;
+            let mut g = 0u8;
+            let mut b = 0u8;
+
+            // touch position: glow around +/- 3 LEDs (circular wrap)
+            for touch in self.touchkey_state.iter().flatten() {
+                let mut dist = (touch - i as f32).abs();
+                dist = dist.min(num_leds_f - dist);
+                if dist <= 3.0 {
+                    let intensity = 1.0 - dist / 3.0;
+                    let glow = |c: u8| (c as f32 * intensity * intensity).clamp(0.0, 255.0) as u8;
+                    r = r.saturating_add(glow(touch_r));
+                    g = g.saturating_add(glow(touch_g));
+                    b = b.saturating_add(glow(touch_b));
+                }
+            }
+
+            // rx key: only this LED lights
+            if self.rxkey_state[i] {
+                r = r.saturating_add(rx_r);
+                g = g.saturating_add(rx_g);
+                b = b.saturating_add(rx_b);
+            }
+
+            // playhead: white (W) with a short tail behind it (circular wrap)
+            let mut w = 0u8;
+            if let Some(playhead) = self.playhead {
+                let head = playhead * num_leds_f;
+                let mut behind = head - i as f32;
+                if behind < 0.0 {
+                    behind += num_leds_f;
+                }
+                if behind < 4.0 {
+                    let intensity = 1.0 - behind / 4.0;
+                    w = (120.0 * intensity * intensity) as u8;
+                }
+            }
+
+            // masked key: dim red without the idle animation
+            let color = if self.key_mask.is_masked(i) {
+                Rgbw {
+                    r: r.saturating_add(40),
+                    g,
+                    b,
+                    w,
+                }
+            } else {
+                let host = host_frame[i];
+                Rgbw {
+                    r: r.saturating_add(idle(theme_r)).saturating_add(host.r),
+                    g: g.saturating_add(idle(theme_g)).saturating_add(host.g),
+                    b: b.saturating_add(idle(theme_b)).saturating_add(host.b),
+                    w: w.saturating_add(idle(theme_w)).saturating_add(host.w),
+                }
+            };
+            *led = Rgbw {
+                r: look.scale(color.r),
+                g: look.scale(color.g),
+                b: look.scale(color.b),
+                w: look.scale(color.w),
+            };
+        }
+        self.counter = self.counter.wrapping_add(1);
+    }
+}
+
+impl Default for RingLed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
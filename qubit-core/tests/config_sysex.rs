@@ -0,0 +1,436 @@
+//! SysEx による設定の読み書き
+use std::convert::Infallible;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+
+use embassy_futures::block_on;
+
+use qubit_core::hal::{MidiSink, Reboot, SettingsStore};
+use qubit_core::midi::clock::{Transport, take_transport_request};
+use qubit_core::midi::config::*;
+use qubit_core::midi::parser::{MidiMessage, UsbMidiParser};
+use qubit_core::settings::{self, NUM_PARAMS, PARAMS};
+use qubit_core::touch::key_mask::KeyMask;
+
+// 設定値はグローバルなので、テストは1つずつ行う
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Default)]
+struct Packets(Vec<[u8; 4]>);
+
+impl MidiSink for Packets {
+    type Error = Infallible;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        self.0.push(packet);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MemStore {
+    saved: Option<Vec<u8>>,
+    fail: bool,
+    rebooted: bool,
+}
+
+impl SettingsStore for MemStore {
+    type Error = ();
+
+    async fn save(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        if self.fail {
+            return Err(());
+        }
+        self.saved = Some(data.to_vec());
+        Ok(())
+    }
+}
+
+impl Reboot for MemStore {
+    fn reboot_to_bootloader(&mut self) {
+        self.rebooted = true;
+    }
+}
+
+fn reset_settings() {
+    for param in PARAMS.iter() {
+        param.set(param.min);
+    }
+    KeyMask::new().store_manual();
+}
+
+/// 要求を送って、返信の SysEx（F0, F7 を除く）を受け取る
+fn request(body: &[u8], store: &mut MemStore) -> Option<Vec<u8>> {
+    let mut out = Packets::default();
+    let handled = block_on(handle_sysex(body, &mut out, store)).unwrap();
+    let mut parser = UsbMidiParser::<256>::new();
+    let mut replies = Vec::new();
+    for packet in out.0 {
+        if let Ok(Some(event)) = parser.parse(packet)
+            && let MidiMessage::SysEx(data) = event.message
+        {
+            replies.push(data.to_vec());
+        }
+    }
+    assert_eq!(
+        replies.len(),
+        handled as usize,
+        "one reply per handled request"
+    );
+    replies.pop()
+}
+
+/// 設定用のコマンドを送って、ヘッダの後ろの返信を受け取る
+fn command(cmd: u8, data: &[u8], store: &mut MemStore) -> Vec<u8> {
+    let mut body = CONFIG_HEADER.to_vec();
+    body.push(cmd);
+    body.extend_from_slice(data);
+    let reply = request(&body, store).expect("no reply");
+    assert_eq!(reply[..CONFIG_HEADER.len()], CONFIG_HEADER);
+    reply[CONFIG_HEADER.len()..].to_vec()
+}
+
+#[test]
+fn identity_reply() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let reply = request(&[0x7E, 0x7F, 0x06, 0x01], &mut MemStore::default()).unwrap();
+    assert_eq!(
+        reply[..10],
+        [
+            0x7E,
+            device_id(),
+            0x06,
+            0x02,
+            0x7D,
+            0x09,
+            0x24,
+            0x10,
+            0x6D,
+            0x00
+        ]
+    );
+    assert_eq!(reply.len(), 13);
+    // VID/PID が 14bit × 2 で入っている
+    assert_eq!(reply[5] as u16 | (reply[6] as u16) << 7, USB_VID);
+    assert_eq!(reply[7] as u16 | (reply[8] as u16) << 7, USB_PID);
+}
+
+#[test]
+fn other_sysex_is_ignored() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut store = MemStore::default();
+    assert_eq!(request(&[0x7D, b'Q', b'R', 1, 2], &mut store), None);
+    assert_eq!(request(&[0x43, 0x10, 0x4C], &mut store), None);
+    // 他の機器宛ての Identity Request
+    assert_eq!(request(&[0x7E, 0x05, 0x06, 0x01], &mut store), None);
+}
+
+#[test]
+fn identity_request_for_this_device() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let id = device_id();
+    assert_eq!(id, settings::MIDI_CHANNEL.load(Ordering::Relaxed) - 1);
+    let reply = request(&[0x7E, id, 0x06, 0x01], &mut MemStore::default()).unwrap();
+    assert_eq!(reply[..4], [0x7E, id, 0x06, 0x02]);
+}
+
+#[test]
+fn identity_reply_carries_device_id() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    settings::MIDI_CHANNEL.store(5, Ordering::Relaxed);
+    // 全機器宛てでも、この機器宛てでも、返信にはこの機器の id を入れる
+    for id in [0x7F, 4] {
+        let reply = request(&[0x7E, id, 0x06, 0x01], &mut MemStore::default()).unwrap();
+        assert_eq!(reply[..4], [0x7E, 4, 0x06, 0x02]);
+    }
+    reset_settings();
+}
+
+#[test]
+fn header_without_command_is_nak() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let reply = request(&CONFIG_HEADER, &mut MemStore::default()).unwrap();
+    assert_eq!(
+        reply[CONFIG_HEADER.len()..],
+        [NAK, 0x00, ConfigError::NoCommand as u8]
+    );
+}
+
+#[test]
+fn get_and_set_param() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    // Contrast（id 1）は 0x0F-0xFF なので上位ビットも使う
+    assert_eq!(
+        command(CMD_SET_PARAM, &[1, 0x7F, 1], &mut store),
+        [ACK, CMD_SET_PARAM]
+    );
+    assert_eq!(PARAMS[1].get(), 0xFF);
+    assert_eq!(
+        command(CMD_GET_PARAM, &[1], &mut store),
+        [CMD_GET_PARAM | REPLY_FLAG, 1, 0x7F, 1]
+    );
+}
+
+#[test]
+fn errors_are_nak() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    let nak = |cmd: u8, e: ConfigError| vec![NAK, cmd, e as u8];
+    assert_eq!(
+        command(0x55, &[], &mut store),
+        nak(0x55, ConfigError::UnknownCommand)
+    );
+    assert_eq!(
+        command(CMD_GET_PARAM, &[], &mut store),
+        nak(CMD_GET_PARAM, ConfigError::BadLength)
+    );
+    assert_eq!(
+        command(CMD_GET_PARAM, &[NUM_PARAMS as u8], &mut store),
+        nak(CMD_GET_PARAM, ConfigError::UnknownParam)
+    );
+    // Mode は 0-1
+    assert_eq!(
+        command(CMD_SET_PARAM, &[0, 2, 0], &mut store),
+        nak(CMD_SET_PARAM, ConfigError::OutOfRange)
+    );
+    assert_eq!(PARAMS[0].get(), 0);
+    assert_eq!(
+        command(CMD_DUMP, &[0], &mut store),
+        nak(CMD_DUMP, ConfigError::BadLength)
+    );
+    store.fail = true;
+    assert_eq!(
+        command(CMD_SAVE, &[], &mut store),
+        nak(CMD_SAVE, ConfigError::SaveFailed)
+    );
+}
+
+#[test]
+fn dump_then_restore() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    PARAMS[0].set(1);
+    PARAMS[1].set(0x8F);
+    let mut mask = KeyMask::new();
+    mask.set(0, true);
+    mask.set(95, true);
+    mask.store_manual();
+
+    let dump = command(CMD_DUMP, &[], &mut store);
+    assert_eq!(dump[0], CMD_DUMP | REPLY_FLAG);
+    assert_eq!(dump[1] as usize, NUM_PARAMS);
+
+    reset_settings();
+    assert_eq!(
+        command(CMD_RESTORE, &dump[1..], &mut store),
+        [ACK, CMD_RESTORE]
+    );
+    assert_eq!(PARAMS[0].get(), 1);
+    assert_eq!(PARAMS[1].get(), 0x8F);
+    assert_eq!(KeyMask::load_manual(), mask);
+}
+
+#[test]
+fn restore_with_bad_value_changes_nothing() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    let mut dump = command(CMD_DUMP, &[], &mut store);
+    dump[2] = 1; // Mode = 1
+    dump[4] = 0; // Contrast = 0（範囲外）
+    assert_eq!(
+        command(CMD_RESTORE, &dump[1..], &mut store),
+        [NAK, CMD_RESTORE, ConfigError::OutOfRange as u8]
+    );
+    assert_eq!(PARAMS[0].get(), 0);
+    assert_eq!(
+        command(CMD_RESTORE, &dump[1..dump.len() - 1], &mut store),
+        [NAK, CMD_RESTORE, ConfigError::BadLength as u8]
+    );
+}
+
+#[test]
+fn key_mask_get_and_set() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    let mut mask = KeyMask::new();
+    mask.set_chip(3, true);
+    mask.set(40, true);
+    mask.store_manual();
+    let reply = command(CMD_GET_KEY_MASK, &[], &mut store);
+    assert_eq!(reply[0], CMD_GET_KEY_MASK | REPLY_FLAG);
+
+    KeyMask::new().store_manual();
+    assert_eq!(
+        command(CMD_SET_KEY_MASK, &reply[1..], &mut store),
+        [ACK, CMD_SET_KEY_MASK]
+    );
+    assert_eq!(KeyMask::load_manual(), mask);
+}
+
+#[test]
+fn save_and_load() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    PARAMS[6].set(15);
+    let mut mask = KeyMask::new();
+    mask.set(7, true);
+    mask.store_manual();
+    assert_eq!(command(CMD_SAVE, &[], &mut store), [ACK, CMD_SAVE]);
+    let saved = store.saved.unwrap();
+
+    reset_settings();
+    assert!(settings::load_saved(&saved));
+    assert_eq!(PARAMS[6].get(), 15);
+    assert_eq!(KeyMask::load_manual(), mask);
+
+    // 壊れたデータは読み込まない
+    let mut broken = saved.clone();
+    broken[7] ^= 1;
+    reset_settings();
+    assert!(!settings::load_saved(&broken));
+    assert!(!settings::load_saved(&[0xFF; settings::SAVED_SIZE])); // 消去したフラッシュ
+    assert_eq!(PARAMS[6].get(), 0);
+}
+
+/// パラメータが少なかった頃のデータも、あるだけ読み込む
+#[test]
+fn load_older_layout() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut data = settings::SAVED_MAGIC.to_vec();
+    data.extend_from_slice(&[settings::SAVED_VERSION, 2, 1, 0x3F]);
+    data.extend_from_slice(&[0; 12]);
+    data.push(data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+    assert!(settings::load_saved(&data));
+    assert_eq!(PARAMS[0].get(), 1);
+    assert_eq!(PARAMS[1].get(), 0x3F);
+}
+
+/// 使わなくなった ID 16 は予約として残すので、後の ID と保存したデータの並びは変わらない
+#[test]
+fn reserved_id_keeps_later_ids() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_settings();
+    let mut store = MemStore::default();
+    assert_eq!(settings::find_param("Out").unwrap().0, 17);
+    assert!(settings::find_param("16").is_none());
+    assert_eq!(
+        command(CMD_GET_PARAM, &[16], &mut store),
+        [NAK, CMD_GET_PARAM, ConfigError::UnknownParam as u8]
+    );
+    assert_eq!(
+        command(CMD_SET_PARAM, &[16, 0, 0], &mut store),
+        [NAK, CMD_SET_PARAM, ConfigError::UnknownParam as u8]
+    );
+
+    // 以前のダンプ（16 に MIDI2 の値）もリストアできる
+    let mut dump = command(CMD_DUMP, &[], &mut store);
+    dump[2 + 16 * 2] = 1;
+    dump[2 + 17 * 2] = 1; // Out = DIN
+    assert_eq!(
+        command(CMD_RESTORE, &dump[1..], &mut store),
+        [ACK, CMD_RESTORE]
+    );
+    assert_eq!(PARAMS[17].get(), 1);
+    assert_eq!(PARAMS[16].get(), 0);
+
+    // 以前に保存したデータも、Out 以降は同じ位置から読む
+    reset_settings();
+    let mut data = settings::SAVED_MAGIC.to_vec();
+    data.extend_from_slice(&[settings::SAVED_VERSION, 18]);
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&[1, 2]); // MIDI2 = On, Out = Both
+    data.extend_from_slice(&[0; 12]);
+    data.push(data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+    assert!(settings::load_saved(&data));
+    assert_eq!(PARAMS[16].get(), 0);
+    assert_eq!(PARAMS[17].get(), 2);
+    reset_settings();
+}
+
+#[test]
+fn telemetry_on_and_off() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut store = MemStore::default();
+    assert_eq!(
+        command(CMD_TELEMETRY, &[1], &mut store),
+        [ACK, CMD_TELEMETRY]
+    );
+    assert_eq!(settings::TELEMETRY.load(Ordering::Relaxed), 1);
+    assert_eq!(
+        command(CMD_TELEMETRY, &[2], &mut store),
+        [NAK, CMD_TELEMETRY, ConfigError::OutOfRange as u8]
+    );
+    assert_eq!(
+        command(CMD_TELEMETRY, &[0], &mut store),
+        [ACK, CMD_TELEMETRY]
+    );
+    assert_eq!(settings::TELEMETRY.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn telemetry_report_layout() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    qubit_core::TOUCH0.store(4250, Ordering::Relaxed);
+    qubit_core::TOUCH1.store(10000, Ordering::Relaxed);
+    qubit_core::PRESSURE.store(300, Ordering::Relaxed);
+    qubit_core::AD_VALUE3.store(0xFFFF, Ordering::Relaxed); // 14bit で頭打ち
+    let sysex = telemetry_sysex();
+    assert_eq!(sysex.len(), TELEMETRY_SYSEX_SIZE);
+    assert_eq!(sysex[0], 0xF0);
+    assert_eq!(sysex[1..6], CONFIG_HEADER);
+    assert_eq!(sysex[6], TELEMETRY_REPORT);
+    assert_eq!(*sysex.last().unwrap(), 0xF7);
+    let values: Vec<u32> = sysex[7..sysex.len() - 1]
+        .chunks_exact(2)
+        .map(|v| v[0] as u32 | (v[1] as u32) << 7)
+        .collect();
+    assert_eq!(values.len(), TELEMETRY_VALUES);
+    assert_eq!(values[0], 4250);
+    assert_eq!(values[1], 10000);
+    assert_eq!(values[4], 300);
+    assert_eq!(values[8], 0x3FFF);
+}
+
+#[test]
+fn reboot_after_ack() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut store = MemStore::default();
+    assert_eq!(
+        command(CMD_REBOOT_BOOTLOADER, &[0], &mut store),
+        [NAK, CMD_REBOOT_BOOTLOADER, ConfigError::BadLength as u8]
+    );
+    assert!(!store.rebooted);
+    assert_eq!(
+        command(CMD_REBOOT_BOOTLOADER, &[], &mut store),
+        [ACK, CMD_REBOOT_BOOTLOADER]
+    );
+    assert!(store.rebooted);
+}
+
+#[test]
+fn transport_request() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut store = MemStore::default();
+    assert_eq!(
+        command(CMD_TRANSPORT, &[1], &mut store),
+        [ACK, CMD_TRANSPORT]
+    );
+    assert_eq!(take_transport_request(), Some(Transport::Start));
+    assert_eq!(take_transport_request(), None);
+    assert_eq!(
+        command(CMD_TRANSPORT, &[3], &mut store),
+        [NAK, CMD_TRANSPORT, ConfigError::OutOfRange as u8]
+    );
+    assert_eq!(take_transport_request(), None);
+}
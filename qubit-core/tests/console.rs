@@ -0,0 +1,115 @@
+//! デバッグコンソール: 行の入力とコマンド
+use heapless::String;
+use portable_atomic::Ordering;
+use qubit_core::console::{self, LOG_STREAM, LineEditor, Reply};
+use qubit_core::settings::{SCALE, TEMPO};
+use qubit_core::timing::{self, TASK_NAMES, TASK_RINGLED};
+use qubit_core::touch::frame::TouchFrame;
+use qubit_core::{CALIBRATE_REQUEST, ERROR_CODE, I2C_ERROR_LOG, I2C_RECOVERY_COUNT};
+
+/// 入力したバイト列のエコーと、終わった行
+fn type_in(editor: &mut LineEditor, input: &[u8]) -> (String<256>, Vec<std::string::String>) {
+    let mut echo = String::new();
+    let mut lines = Vec::new();
+    for &byte in input {
+        if let Some(line) = editor.feed(byte, &mut echo) {
+            lines.push(line.as_str().to_string());
+        }
+    }
+    (echo, lines)
+}
+
+fn run(line: &str) -> std::string::String {
+    let mut reply = Reply::new();
+    console::execute(line, &TouchFrame::new(), 0, &mut reply);
+    reply.as_str().to_string()
+}
+
+#[test]
+fn line_editor() {
+    let mut editor = LineEditor::new();
+    let (echo, lines) = type_in(&mut editor, b"gt\x7Fet\x1B tempo\r\nhelp\n\r\n");
+    assert_eq!(echo.as_str(), "gt\x08 \x08et tempo\r\nhelp\r\n\r\n");
+    assert_eq!(lines, ["get tempo", "help", ""]);
+
+    // 行頭の BS はエコーしない。長すぎる行は切る
+    let (echo, lines) = type_in(&mut editor, b"\x08x\r");
+    assert_eq!(echo.as_str(), "x\r\n");
+    assert_eq!(lines, ["x"]);
+    let (_, lines) = type_in(&mut editor, &[b'a'; 100]);
+    assert!(lines.is_empty());
+    let (_, lines) = type_in(&mut editor, b"\r");
+    assert_eq!(lines[0].len(), console::LINE_SIZE);
+}
+
+// 設定やエラーの記録はグローバルなので、1つのテストで続けて確かめる
+#[test]
+fn commands() {
+    assert_eq!(run(""), "");
+    assert!(run("help").contains("calibrate"));
+    assert_eq!(run("bogus 1"), "error: \"bogus 1\" (type help)\r\n");
+
+    // 設定: 名前か ID、値は表示名か数値
+    assert_eq!(run("set tempo 128"), "Tempo = 128\r\n");
+    assert_eq!(TEMPO.load(Ordering::Relaxed), 128);
+    assert_eq!(run("get 9"), "Tempo = 128\r\n");
+    assert_eq!(run("set TEMPO 250"), "error: Tempo is 40-240\r\n");
+    assert_eq!(run("set Scale major"), "Scale = 1 (Major)\r\n");
+    assert_eq!(SCALE.load(Ordering::Relaxed), 1);
+    assert_eq!(
+        run("set scale ionian"),
+        "error: invalid value \"ionian\" for Scale\r\n"
+    );
+    assert_eq!(
+        run("get nothing"),
+        "error: unknown parameter \"nothing\"\r\n"
+    );
+    let params = run("params");
+    assert!(params.contains(" 9 Tempo = 128\r\n"));
+    assert!(params.contains("20 Scale = 1 (Major)\r\n"));
+
+    // キャリブレーションは Core1 への要求だけ
+    assert_eq!(CALIBRATE_REQUEST.load(Ordering::Relaxed), 0);
+    run("calibrate");
+    assert_eq!(CALIBRATE_REQUEST.load(Ordering::Relaxed), 1);
+
+    // エラーの記録
+    ERROR_CODE.store(82, Ordering::Relaxed);
+    I2C_ERROR_LOG[4][3].store(7, Ordering::Relaxed);
+    I2C_RECOVERY_COUNT.store(2, Ordering::Relaxed);
+    let errors = run("errors");
+    assert!(errors.starts_with("error code 82\r\ni2c recovery 2\r\n"));
+    assert!(errors.contains("0x1B      0      0      0      7      0\r\n"));
+    assert_eq!(run("errors clear"), "ok\r\n");
+    assert_eq!(ERROR_CODE.load(Ordering::Relaxed), 0);
+    assert_eq!(I2C_ERROR_LOG[4][3].load(Ordering::Relaxed), 0);
+    assert_eq!(I2C_RECOVERY_COUNT.load(Ordering::Relaxed), 0);
+
+    // タスクの処理時間
+    timing::record(TASK_RINGLED, 1_000, 1_300);
+    timing::record(TASK_RINGLED, 2_000, 2_100);
+    let tasks = run("tasks");
+    assert_eq!(tasks.lines().count(), 1 + TASK_NAMES.len());
+    assert!(tasks.contains("ringled         2    100    200    300\r\n"));
+    run("tasks reset");
+    assert!(run("tasks").contains("ringled         0      0      0      0\r\n"));
+
+    assert_eq!(run("log on"), "log on\r\n");
+    assert_eq!(LOG_STREAM.load(Ordering::Relaxed), 1);
+    run("log off");
+    assert_eq!(LOG_STREAM.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn keys_show_values_and_age() {
+    let mut frame = TouchFrame::new();
+    frame.values[6..12].copy_from_slice(&[1, 2, 3, 400, 5, 6]);
+    frame.timestamps[1] = 9_000;
+    frame.timestamps[2] = 12_000;
+    let mut reply = Reply::new();
+    console::execute("keys", &frame, 12_000, &mut reply);
+    let lines: Vec<&str> = reply.lines().collect();
+    assert_eq!(lines.len(), 1 + qubit_core::constants::TOTAL_CH);
+    assert_eq!(lines[2], " 1     1    2    3  400    5    6       3");
+    assert_eq!(lines[3], " 2     0    0    0    0    0    0       0");
+}
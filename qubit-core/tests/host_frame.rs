@@ -0,0 +1,141 @@
+//! ホストが SysEx で送る RingLED のフレーム: 受信、タイムアウト、置き換えと重ねる表示
+use qubit_core::constants::{
+    MIDI_SYSEX_BUFFER_SIZE, NUM_LEDS, RINGLED_CMD_NONE, RINGLED_CMD_TX_ON,
+};
+use qubit_core::hal::Rgbw;
+use qubit_core::ui::host_frame::{self, CMD_INDEXED, CMD_RUN_LENGTH, HostMode};
+use qubit_core::ui::led_look::LedLook;
+use qubit_core::ui::ringled::RingLed;
+
+/// F0 と F7 を除いて受信する
+fn receive(sysex: &[u8], now_us: u64) -> bool {
+    host_frame::receive(&sysex[1..sysex.len() - 1], now_us)
+}
+
+fn rgbw(r: u8, g: u8, b: u8, w: u8) -> Rgbw {
+    Rgbw { r, g, b, w }
+}
+
+// 受信したフレームはグローバルなので、1つのテストで続けて確かめる
+#[test]
+fn receive_frames_and_timeout() {
+    assert_eq!(host_frame::active_frame(0), None);
+    // モードを決めるまではローカルの表示
+    let red = [rgbw(255, 0, 0, 0); NUM_LEDS / 2];
+    assert!(receive(&host_frame::rgbw_sysex(0, &red), 1_000));
+    assert_eq!(host_frame::active_frame(1_000), None);
+
+    assert!(receive(
+        &host_frame::mode_sysex(HostMode::Replace, 5),
+        2_000
+    ));
+    let blue = [rgbw(0, 0, 255, 2); NUM_LEDS / 2];
+    assert!(receive(&host_frame::rgbw_sysex(48, &blue), 3_000));
+    let (mode, frame) = host_frame::active_frame(3_000).unwrap();
+    assert_eq!(mode, HostMode::Replace);
+    assert_eq!(frame[0], rgbw(255, 0, 0, 0));
+    assert_eq!(frame[95], rgbw(0, 0, 255, 2));
+
+    // パレットとパレット番号（ランレングス）
+    assert!(receive(
+        &host_frame::palette_sysex(9, &[rgbw(0, 255, 0, 0)]),
+        4_000
+    ));
+    let mut indexes = [0u8; NUM_LEDS];
+    indexes[10..20].fill(9);
+    indexes[20] = 2; // led_look::PALETTE の Cyan
+    assert!(receive(&host_frame::indexed_sysex(0, &indexes), 5_000));
+    let (_, frame) = host_frame::active_frame(5_000).unwrap();
+    assert_eq!(frame[9], Rgbw::default());
+    assert_eq!(frame[10], rgbw(0, 255, 0, 0));
+    assert_eq!(frame[19], rgbw(0, 255, 0, 0));
+    assert_eq!(frame[20], rgbw(0, 120, 180, 0));
+    // 範囲外の LED は無視する
+    assert!(receive(&host_frame::indexed_sysex(90, &[9; 10]), 6_000));
+    assert_eq!(
+        host_frame::active_frame(6_000).unwrap().1[95],
+        rgbw(0, 255, 0, 0)
+    );
+
+    // タイムアウト（500ms）でローカルの表示に戻り、次のフレームでまたホストの表示になる
+    assert!(host_frame::active_frame(506_000).is_some());
+    assert_eq!(host_frame::active_frame(506_001), None);
+    // モードやパレットだけでは、タイムアウトした古いフレームに戻らない
+    assert!(receive(
+        &host_frame::mode_sysex(HostMode::Blend, 5),
+        600_000
+    ));
+    assert!(receive(
+        &host_frame::palette_sysex(0, &[rgbw(0, 0, 0, 255)]),
+        600_000
+    ));
+    assert_eq!(host_frame::active_frame(600_000), None);
+    assert!(receive(&host_frame::indexed_sysex(0, &[1]), 700_000));
+    assert_eq!(
+        host_frame::active_frame(700_000).map(|(mode, _)| mode),
+        Some(HostMode::Blend)
+    );
+
+    assert!(receive(
+        &host_frame::mode_sysex(HostMode::Local, 0),
+        800_000
+    ));
+    assert_eq!(host_frame::active_frame(800_000), None);
+    // 他の SysEx は扱わない
+    assert!(!host_frame::receive(
+        &[0x7D, 0x09, 0x24, 0x10, 0x6D, 0x01, 0],
+        0
+    ));
+}
+
+#[test]
+fn encoder_fits_in_one_sysex() {
+    let full = host_frame::rgbw_sysex(0, &[rgbw(255, 255, 255, 255); NUM_LEDS]);
+    assert_eq!(full.len(), 1 + 5 + 2 + NUM_LEDS / 2 * 4 + 1);
+    assert!(full.len() - 2 <= MIDI_SYSEX_BUFFER_SIZE);
+    assert!(full[1..full.len() - 1].iter().all(|b| *b < 0x80));
+
+    // 同じ番号が続けばランレングス、そうでなければ1つずつ
+    let runs = host_frame::indexed_sysex(0, &[3; NUM_LEDS]);
+    assert_eq!(runs[6], CMD_RUN_LENGTH);
+    assert_eq!(&runs[7..runs.len() - 1], &[0, 96, 3]);
+    let indexes: Vec<u8> = (0..NUM_LEDS as u8).map(|i| i % 16).collect();
+    let plain = host_frame::indexed_sysex(0, &indexes);
+    assert_eq!(plain[6], CMD_INDEXED);
+    assert_eq!(plain.len(), 1 + 5 + 2 + NUM_LEDS + 1);
+}
+
+#[test]
+fn replace_and_blend() {
+    let mut frame = [Rgbw::default(); NUM_LEDS];
+    frame[40] = rgbw(0, 0, 100, 0);
+    frame[60] = rgbw(10, 20, 30, 40);
+    let render = |host| {
+        let mut ring = RingLed::new();
+        ring.set_host_frame(host);
+        let mut data = [Rgbw::default(); NUM_LEDS];
+        ring.set_color(&mut data, 40.0, RINGLED_CMD_TX_ON);
+        data
+    };
+
+    // 置き換え: タッチも待機中の波も出さない
+    let replaced = render(Some((HostMode::Replace, frame)));
+    assert_eq!(replaced, frame);
+
+    // 重ねる: 待機中の波の代わりにホストのフレーム、タッチはその上に
+    let blended = render(Some((HostMode::Blend, frame)));
+    assert_eq!(blended[60], frame[60]);
+    assert_eq!(blended[40], rgbw(220, 0, 255, 0));
+    assert_eq!(blended[0], Rgbw::default());
+
+    // 明るさは掛ける
+    let mut ring = RingLed::new();
+    ring.set_look(LedLook {
+        brightness: 0,
+        ..LedLook::DEFAULT
+    });
+    ring.set_host_frame(Some((HostMode::Replace, frame)));
+    let mut data = [rgbw(1, 1, 1, 1); NUM_LEDS];
+    ring.set_color(&mut data, RINGLED_CMD_NONE as f32, RINGLED_CMD_NONE);
+    assert!(data.iter().all(|led| *led == Rgbw::default()));
+}
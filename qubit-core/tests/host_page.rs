@@ -0,0 +1,95 @@
+//! ホストが SysEx で書く OLED のページ: 受信、タイムアウト、書き込みの制限
+use qubit_core::display::DISPLAY_WIDTH;
+use qubit_core::ui::host_page::{self, HostPage, Icon, IconAt, LINE_LEN, ROWS_PER_SYSEX};
+
+/// F0 と F7 を除いて受信する
+fn receive(sysex: &[u8], now_us: u64) -> bool {
+    host_page::receive(&sysex[1..sysex.len() - 1], now_us)
+}
+
+// 受信したページはグローバルなので、1つのテストで続けて確かめる
+#[test]
+fn receive_page_and_timeout() {
+    assert!(!host_page::is_active(0));
+
+    assert!(receive(&host_page::text_sysex(1, "Scene\tA"), 1_000));
+    assert!(receive(&host_page::text_sysex(4, &"x".repeat(30)), 1_000));
+    assert!(receive(&host_page::text_sysex(5, "ignored"), 1_000));
+    assert!(receive(
+        &host_page::icon_sysex(3, Some(Icon::Record), 10, 20),
+        1_000
+    ));
+    let mut rows = [[0u8; DISPLAY_WIDTH / 8]; ROWS_PER_SYSEX];
+    rows[0][0] = 0x80; // 左上
+    rows[11][15] = 0x01; // 右下
+    assert!(receive(&host_page::bitmap_sysex(52, &rows), 1_000));
+    assert!(host_page::is_active(1_000));
+
+    let page = HostPage::load();
+    assert_eq!(page.lines[0], "");
+    assert_eq!(page.lines[1], "Scene?A");
+    assert_eq!(page.lines[4].len(), LINE_LEN);
+    assert_eq!(
+        page.icons[3],
+        Some(IconAt {
+            icon: Icon::Record,
+            x: 10,
+            y: 20
+        })
+    );
+    assert_eq!(page.icons[0], None);
+    assert_eq!(page.bitmap[52 * 16], 0x80);
+    assert_eq!(page.bitmap[63 * 16 + 15], 0x01);
+    assert_eq!(page.bitmap.iter().filter(|b| **b != 0).count(), 2);
+
+    // 既定のタイムアウト（5秒）でローカルの画面に戻る
+    assert!(host_page::is_active(5_001_000));
+    assert!(!host_page::is_active(5_001_001));
+
+    // クリアでタイムアウトを変える。0 はすぐにローカルの画面に戻る
+    assert!(receive(&host_page::clear_sysex(Some(2)), 6_000_000));
+    assert_eq!(HostPage::load().lines[1], "");
+    assert!(host_page::is_active(8_000_000));
+    assert!(!host_page::is_active(8_000_001));
+    assert!(receive(&host_page::text_sysex(0, "A"), 9_000_000));
+    assert!(receive(&host_page::clear_sysex(Some(0)), 9_000_000));
+    assert!(!host_page::is_active(9_000_000));
+    assert!(receive(&host_page::text_sysex(0, "B"), 9_100_000));
+    assert!(host_page::is_active(11_100_000));
+
+    // 続けて送れるのは 16回まで、あとは 20ms に1回
+    let dropped = host_page::dropped_writes();
+    for i in 0..20 {
+        assert!(receive(
+            &host_page::text_sysex(0, &i.to_string()),
+            20_000_000
+        ));
+    }
+    assert_eq!(host_page::dropped_writes(), dropped + 4);
+    assert_eq!(HostPage::load().lines[0], "15");
+    assert!(receive(&host_page::text_sysex(0, "late"), 20_010_000));
+    assert_eq!(host_page::dropped_writes(), dropped + 5);
+    assert!(receive(&host_page::text_sysex(0, "next"), 20_020_000));
+    assert_eq!(HostPage::load().lines[0], "next");
+
+    // 他の SysEx は扱わない
+    assert!(!host_page::receive(
+        &[0x7D, 0x09, 0x24, 0x10, 0x6D, 0x01, 0],
+        0
+    ));
+    assert!(!host_page::receive(
+        &[0x7D, 0x09, 0x24, 0x10, 0x6D, 0x40, 0],
+        0
+    ));
+}
+
+#[test]
+fn bitmap_fits_in_one_sysex() {
+    let rows = [[0xFFu8; DISPLAY_WIDTH / 8]; ROWS_PER_SYSEX + 4];
+    let sysex = host_page::bitmap_sysex(0, &rows);
+    assert_eq!(sysex.len(), 1 + 5 + 2 + ROWS_PER_SYSEX * 19 + 1);
+    assert!(sysex.len() - 2 <= qubit_core::constants::MIDI_SYSEX_BUFFER_SIZE);
+    assert!(sysex[1..sysex.len() - 1].iter().all(|b| *b < 0x80));
+    // 最後のバイトは 2ドット（128 = 7 × 18 + 2）
+    assert_eq!(sysex[8 + 18], 0x60);
+}
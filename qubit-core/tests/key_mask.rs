@@ -0,0 +1,109 @@
+//! マスクしたキーの補間: 両隣の有効なキーから直線で埋める（リングなので端は回り込む）
+use qubit_core::constants::{AT42QT_KEYS_PER_DEVICE, TOTAL_CH, TOTAL_QT_KEYS};
+use qubit_core::touch::key_mask::KeyMask;
+
+/// キー番号に比例する値（直線補間で元に戻る）
+fn ramp() -> [u16; TOTAL_QT_KEYS] {
+    core::array::from_fn(|key| 1000 + key as u16 * 10)
+}
+
+fn chips(chips: &[usize]) -> KeyMask {
+    let mut mask = KeyMask::new();
+    for &ch in chips {
+        mask.set_chip(ch, true);
+    }
+    mask
+}
+
+/// マスクしていないキーはそのまま
+fn assert_unmasked_kept(mask: &KeyMask, before: &[u16], after: &[u16]) {
+    for key in 0..TOTAL_QT_KEYS {
+        if !mask.is_masked(key) {
+            assert_eq!(after[key], before[key], "key {}", key);
+        }
+    }
+}
+
+#[test]
+fn masked_chip_in_the_middle() {
+    let mask = chips(&[5]); // キー 30..36
+    let mut values = ramp();
+    values[30..36].fill(0);
+    mask.interpolate(&mut values);
+    // 29 と 36 の間を直線で埋めるので、元の値に戻る
+    assert_eq!(values, ramp());
+}
+
+/// first から len キー続けてマスクした穴が、前後の有効なキーの間の直線になっている
+fn assert_gap_is_linear(src: &[u16; TOTAL_QT_KEYS], values: &[u16], first: usize, len: usize) {
+    let prev = src[(first + TOTAL_QT_KEYS - 1) % TOTAL_QT_KEYS] as u32;
+    let next = src[(first + len) % TOTAL_QT_KEYS] as u32;
+    let span = len as u32 + 1;
+    for d in 1..span {
+        let key = (first + d as usize - 1) % TOTAL_QT_KEYS;
+        let expected = (prev * (span - d) + next * d) / span;
+        assert_eq!(values[key] as u32, expected, "key {}", key);
+    }
+}
+
+#[test]
+fn masked_chip_wraps_around_the_ring() {
+    let src = ramp();
+
+    // キー 0 側: 前の有効なキーは最後のキー
+    let mask = chips(&[0]);
+    let mut values = src;
+    mask.interpolate(&mut values);
+    assert_gap_is_linear(&src, &values, 0, AT42QT_KEYS_PER_DEVICE);
+    assert_unmasked_kept(&mask, &src, &values);
+
+    // 最後のキー側: 次の有効なキーはキー 0
+    let mask = chips(&[TOTAL_CH - 1]);
+    let mut values = src;
+    mask.interpolate(&mut values);
+    let first = TOTAL_QT_KEYS - AT42QT_KEYS_PER_DEVICE;
+    assert_gap_is_linear(&src, &values, first, AT42QT_KEYS_PER_DEVICE);
+    assert_unmasked_kept(&mask, &src, &values);
+}
+
+#[test]
+fn adjacent_masked_chips() {
+    // 3 チップ続けて（キー 18..36）
+    let mask = chips(&[3, 4, 5]);
+    let mut values = ramp();
+    values[18..36].fill(0);
+    mask.interpolate(&mut values);
+    assert_eq!(values, ramp());
+
+    // キー 0 をまたいで 2 チップ続けて
+    let src = ramp();
+    let mask = chips(&[TOTAL_CH - 1, 0]);
+    let mut values = src;
+    mask.interpolate(&mut values);
+    let first = TOTAL_QT_KEYS - AT42QT_KEYS_PER_DEVICE;
+    assert_gap_is_linear(&src, &values, first, 2 * AT42QT_KEYS_PER_DEVICE);
+    assert_unmasked_kept(&mask, &src, &values);
+}
+
+#[test]
+fn every_key_masked() {
+    let mask = chips(&(0..TOTAL_CH).collect::<Vec<_>>());
+    assert_eq!(mask.count() as usize, TOTAL_QT_KEYS);
+    let mut values = ramp();
+    mask.interpolate(&mut values);
+    assert_eq!(values, [0; TOTAL_QT_KEYS]);
+
+    // 1 キーだけ残っていれば、全部そのキーの値になる
+    let mut mask = mask;
+    mask.set(40, false);
+    let mut values = ramp();
+    mask.interpolate(&mut values);
+    assert_eq!(values, [ramp()[40]; TOTAL_QT_KEYS]);
+}
+
+#[test]
+fn no_mask_leaves_values_alone() {
+    let mut values = ramp();
+    KeyMask::new().interpolate(&mut values);
+    assert_eq!(values, ramp());
+}
@@ -0,0 +1,88 @@
+//! RingLED の見た目を MIDI の CC / Program Change で変える
+use qubit_core::constants::{NUM_LEDS, RINGLED_CMD_NONE, RINGLED_CMD_RX_ON, RINGLED_CMD_TX_ON};
+use qubit_core::hal::Rgbw;
+use qubit_core::ui::led_look::{
+    self, COLOR_BLUE, COLOR_CYAN, IdleAnimation, LedLook, SCENES, THEME_OCEAN,
+};
+use qubit_core::ui::ringled::RingLed;
+
+/// タッチ（LED 10）と受信したノート（LED 20）を表示した1フレーム
+fn render(look: LedLook) -> [Rgbw; NUM_LEDS] {
+    let mut ring = RingLed::new();
+    ring.set_look(look);
+    let mut data = [Rgbw::default(); NUM_LEDS];
+    ring.set_color(&mut data, 10.0, RINGLED_CMD_TX_ON);
+    ring.set_color(&mut data, 20.0, RINGLED_CMD_RX_ON);
+    ring.set_color(&mut data, RINGLED_CMD_NONE as f32, RINGLED_CMD_NONE);
+    data
+}
+
+// 見た目はグローバルなので、1つのテストで続けて確かめる
+#[test]
+fn cc_map_and_scenes() {
+    assert_eq!(LedLook::load(), LedLook::DEFAULT);
+    assert!(led_look::control_change(102, 64));
+    assert!(led_look::control_change(103, 40)); // Breath
+    assert!(led_look::control_change(104, 70)); // Ocean
+    assert!(led_look::control_change(105, 127)); // White
+    assert!(led_look::control_change(106, 0)); // Magenta
+    assert!(!led_look::control_change(7, 0)); // 一般の CC は使わない
+    let look = LedLook::load();
+    assert_eq!(look.brightness, 64);
+    assert_eq!(look.idle, IdleAnimation::Breath);
+    assert_eq!(look.theme, THEME_OCEAN);
+    assert_eq!(look.rx_rgb(), (160, 160, 160));
+    assert_eq!(look.touch_rgb(), (220, 0, 220));
+
+    assert!(led_look::program_change(3));
+    assert_eq!(LedLook::load(), SCENES[3]);
+    assert!(!led_look::program_change(SCENES.len() as u8));
+    assert_eq!(LedLook::load(), SCENES[3]);
+    assert!(led_look::program_change(0));
+    assert_eq!(LedLook::load(), LedLook::DEFAULT);
+}
+
+#[test]
+fn default_look_is_magenta_touch_and_cyan_rx() {
+    let data = render(LedLook::DEFAULT);
+    assert_eq!((data[10].r, data[10].g, data[10].b), (220, 0, 220));
+    assert_eq!((data[20].r, data[20].g, data[20].b), (0, 120, 180));
+    assert!(data.iter().all(|led| led.w >= 2)); // White のテーマは W だけで波を描く
+}
+
+#[test]
+fn colors_brightness_and_idle() {
+    let look = LedLook {
+        touch_color: COLOR_BLUE,
+        rx_color: COLOR_CYAN,
+        idle: IdleAnimation::Off,
+        ..LedLook::DEFAULT
+    };
+    let data = render(look);
+    assert_eq!((data[10].r, data[10].g, data[10].b), (0, 40, 220));
+    assert!(data.iter().all(|led| led.w == 0));
+    assert_eq!(data[0], Rgbw::default());
+
+    // 明るさは全てに掛かる
+    let half = render(LedLook {
+        brightness: 64,
+        ..look
+    });
+    assert_eq!(half[10].b, (220u16 * 64 / 127) as u8);
+    let dark = render(LedLook {
+        brightness: 0,
+        ..LedLook::DEFAULT
+    });
+    assert!(dark.iter().all(|led| *led == Rgbw::default()));
+
+    // テーマの色で待機中の波を描く（W は使わない）
+    let ocean = render(LedLook {
+        theme: THEME_OCEAN,
+        idle: IdleAnimation::Static,
+        ..LedLook::DEFAULT
+    });
+    assert_eq!(
+        (ocean[0].r, ocean[0].g, ocean[0].b, ocean[0].w),
+        (0, 3, 8, 0)
+    );
+}
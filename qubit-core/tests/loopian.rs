@@ -0,0 +1,120 @@
+//! Loopian の状態の表示: SysEx の受信、プレイヘッドの位置、RingLED の描画
+use qubit_core::constants::{NUM_LEDS, RINGLED_CMD_NONE};
+use qubit_core::hal::Rgbw;
+use qubit_core::midi::clock::ClockState;
+use qubit_core::midi::loopian::{self, LoopianState};
+use qubit_core::ui::led_look::{IdleAnimation, LedLook};
+use qubit_core::ui::ringled::RingLed;
+
+/// F0 と F7 を除いて受信する
+fn receive(sysex: &[u8], now_us: u64) -> bool {
+    loopian::receive(&sysex[1..sysex.len() - 1], now_us)
+}
+
+// 状態はグローバルなので、受信は1つのテストで続けて確かめる
+#[test]
+fn receive_messages() {
+    assert_eq!(LoopianState::load(), LoopianState::default());
+    assert!(receive(&loopian::part_sysex(2), 0));
+    assert!(receive(&loopian::loop_sysex(384, 200, 3), 5_000));
+    assert_eq!(
+        LoopianState::load(),
+        LoopianState {
+            length: 384,
+            position: 200,
+            received_us: 5_000,
+            beat: 3,
+            part: 2,
+        }
+    );
+
+    assert!(receive(&loopian::phrase_sysex(1, "Chorus B long"), 0));
+    assert_eq!(loopian::phrase_name(1), "Chorus B");
+    assert!(receive(&loopian::phrase_sysex(1, "A\tB"), 0));
+    assert_eq!(loopian::phrase_name(1), "A?B"); // 短くなれば残りは消す
+    assert_eq!(loopian::phrase_name(0), "");
+
+    // 長さやパートが違うものは無視する（Loopian のコマンドなので true）
+    let mut bad = loopian::loop_sysex(96, 0, 0).to_vec();
+    bad.remove(8);
+    assert!(receive(&bad, 0));
+    assert!(receive(&loopian::phrase_sysex(4, "X"), 0));
+    assert_eq!(LoopianState::load().length, 384);
+    assert!(receive(&loopian::part_sysex(9), 0));
+    assert_eq!(LoopianState::load().part, 3);
+
+    // 設定のコマンドや他の SysEx は扱わない
+    assert!(!loopian::receive(
+        &[0x7D, 0x09, 0x24, 0x10, 0x6D, 0x01, 0],
+        0
+    ));
+    assert!(!loopian::receive(&[0x7E, 0x7F, 0x06, 0x01], 0));
+}
+
+#[test]
+fn playhead_follows_the_clock() {
+    let state = LoopianState {
+        length: 96, // 1小節
+        position: 24,
+        received_us: 1_000_000,
+        beat: 1,
+        part: 0,
+    };
+    // 120BPM: Clock の間隔は 20833us
+    let clock = ClockState {
+        running: true,
+        ticked: true,
+        position: 24,
+        last_us: 1_000_000,
+        interval_us: 20_833,
+    };
+    assert_eq!(state.playhead(&clock, 1_000_000), Some(0.25));
+    let later = state.playhead(&clock, 1_000_000 + 20_833 * 12).unwrap();
+    assert!((later - 0.375).abs() < 0.001, "{}", later);
+    // ループの頭に戻る
+    let clock_later = ClockState {
+        last_us: 1_000_000 + 20_833 * 72,
+        ..clock
+    };
+    let wrapped = state
+        .playhead(&clock_later, 1_000_000 + 20_833 * 74)
+        .unwrap();
+    assert!((wrapped - 2.0 / 96.0).abs() < 0.001, "{}", wrapped);
+
+    // 止まっているか同期していなければ、受信した位置のまま
+    let stopped = ClockState {
+        running: false,
+        ..clock
+    };
+    assert_eq!(state.playhead(&stopped, 3_000_000), Some(0.25));
+    assert_eq!(
+        state.playhead(&ClockState::default(), 3_000_000),
+        Some(0.25)
+    );
+    let no_loop = LoopianState { length: 0, ..state };
+    assert_eq!(no_loop.playhead(&clock, 1_000_000), None);
+}
+
+#[test]
+fn ring_draws_playhead() {
+    let render = |playhead| {
+        let mut ring = RingLed::new();
+        ring.set_look(LedLook {
+            idle: IdleAnimation::Off,
+            ..LedLook::DEFAULT
+        });
+        ring.set_playhead(playhead);
+        let mut data = [Rgbw::default(); NUM_LEDS];
+        ring.set_color(&mut data, RINGLED_CMD_NONE as f32, RINGLED_CMD_NONE);
+        data
+    };
+    assert!(render(None).iter().all(|led| *led == Rgbw::default()));
+    let data = render(Some(0.5));
+    assert_eq!(data[48].w, 120);
+    assert!(data[47].w > data[46].w && data[46].w > 0);
+    assert_eq!(data[49].w, 0); // 尾は後ろだけ
+    // LED 0 の後ろは最後の LED
+    let data = render(Some(0.0));
+    assert_eq!(data[0].w, 120);
+    assert!(data[NUM_LEDS - 1].w > 0);
+}
@@ -0,0 +1,201 @@
+//! MIDI クロックへの同期
+use qubit_core::constants::NUM_LEDS;
+use qubit_core::hal::Rgbw;
+use qubit_core::midi::clock::{
+    BeatPhase, CLOCKS_PER_BEAT, ClockMaster, ClockState, MidiClock, TapTempo,
+};
+use qubit_core::midi::parser::MidiMessage;
+use qubit_core::ui::ringled::RingLed;
+
+const TICK_120BPM: u64 = 20_833; // 60s / 120 / 24
+
+/// 120BPM の Clock を count 回、jitter(us) で前後させて送る
+fn send_clocks(
+    clock: &mut MidiClock,
+    start_us: u64,
+    count: u64,
+    jitter: impl Fn(u64) -> i64,
+) -> u64 {
+    let mut t = start_us;
+    for i in 0..count {
+        t = start_us + i * TICK_120BPM;
+        clock.receive(&MidiMessage::Clock, (t as i64 + jitter(i)) as u64);
+    }
+    t
+}
+
+fn assert_bpm(state: &ClockState, now_us: u64, bpm: f32) {
+    let tempo = state.tempo_bpm(now_us).expect("no tempo");
+    assert!((tempo - bpm).abs() < 0.5, "tempo {} != {}", tempo, bpm);
+}
+
+#[test]
+fn tempo_with_jitter() {
+    let mut clock = MidiClock::new();
+    // ±2ms のジッタ
+    let t = send_clocks(&mut clock, 1_000_000, 100, |i| {
+        if i % 2 == 0 { 2000 } else { -2000 }
+    });
+    assert_bpm(&clock.state(), t, 120.0);
+}
+
+/// USB で2つずつまとめて届いても、テンポは変わらない
+#[test]
+fn tempo_with_batched_packets() {
+    let mut clock = MidiClock::new();
+    let t = send_clocks(&mut clock, 1_000_000, 97, |i| {
+        if i % 2 == 1 { -(TICK_120BPM as i64) } else { 0 }
+    });
+    assert_bpm(&clock.state(), t, 120.0);
+}
+
+#[test]
+fn tempo_change_and_timeout() {
+    let mut clock = MidiClock::new();
+    let t = send_clocks(&mut clock, 1_000_000, 48, |_| 0);
+    // 1拍分で新しいテンポ（60BPM）になる
+    let mut t2 = t;
+    for _ in 0..CLOCKS_PER_BEAT {
+        t2 += TICK_120BPM * 2;
+        clock.receive(&MidiMessage::Clock, t2);
+    }
+    assert_bpm(&clock.state(), t2, 60.0);
+    // Clock が止まったら同期を外す
+    assert_eq!(clock.state().tempo_bpm(t2 + 600_000), None);
+}
+
+#[test]
+fn start_and_beat_phase() {
+    let mut clock = MidiClock::new();
+    send_clocks(&mut clock, 1_000_000, 30, |_| 0);
+    let t0 = 2_000_000;
+    assert_eq!(clock.state().beat(t0), None); // 再生していない
+
+    clock.receive(&MidiMessage::Start, t0);
+    // Start の後、最初の Clock までは曲の頭で止まっている
+    assert_eq!(clock.state().beat(t0 + 1000).unwrap().phase, 0.0);
+    // 1拍 + 6 Clock（16分音符1つ）進める
+    let mut t = t0;
+    for _ in 0..CLOCKS_PER_BEAT + 7 {
+        t += TICK_120BPM;
+        clock.receive(&MidiMessage::Clock, t);
+    }
+    let beat = clock.state().beat(t).unwrap();
+    assert_eq!(beat.beat, 1);
+    assert!((beat.phase - 0.25).abs() < 1e-4);
+    assert!((beat.bar_phase() - 1.25 / 4.0).abs() < 1e-4);
+    // Clock の間は補間する（次の Clock は越えない）
+    let half = clock.state().beat(t + TICK_120BPM / 2).unwrap();
+    assert!((half.phase - (6.5 / 24.0)).abs() < 1e-3);
+    let late = clock.state().beat(t + TICK_120BPM * 3).unwrap();
+    assert!(late.phase < 7.0 / 24.0);
+    assert_eq!(clock.state().song_position(), 5);
+}
+
+#[test]
+fn stop_continue_and_song_position() {
+    let mut clock = MidiClock::new();
+    let mut t = 1_000_000;
+    clock.receive(&MidiMessage::Start, t);
+    for _ in 0..10 {
+        t += TICK_120BPM;
+        clock.receive(&MidiMessage::Clock, t);
+    }
+    clock.receive(&MidiMessage::Stop, t);
+    assert_eq!(clock.state().beat(t), None);
+    // 止まっている間の Clock では進まない
+    for _ in 0..10 {
+        t += TICK_120BPM;
+        clock.receive(&MidiMessage::Clock, t);
+    }
+    clock.receive(&MidiMessage::Continue, t);
+    t += TICK_120BPM;
+    clock.receive(&MidiMessage::Clock, t);
+    assert_eq!(clock.state().position, 10);
+
+    // Song Position（16分音符）で移動してから続ける
+    clock.receive(&MidiMessage::Stop, t);
+    clock.receive(&MidiMessage::SongPosition(16), t);
+    assert_eq!(clock.state().song_position(), 16);
+    clock.receive(&MidiMessage::Continue, t);
+    t += TICK_120BPM;
+    clock.receive(&MidiMessage::Clock, t);
+    let beat = clock.state().beat(t).unwrap();
+    assert_eq!((beat.bar(), beat.beat_in_bar(), beat.phase), (1, 0, 0.0));
+    assert!(!clock.receive(&MidiMessage::ActiveSensing, t));
+}
+
+#[test]
+fn state_is_shared() {
+    let mut clock = MidiClock::new();
+    clock.receive(&MidiMessage::Start, 1_000_000);
+    send_clocks(&mut clock, 1_000_000, 5, |_| 0);
+    clock.state().store();
+    assert_eq!(ClockState::load(), clock.state());
+}
+
+fn total_white(ring: &mut RingLed, beat: Option<BeatPhase>) -> u32 {
+    let mut data = [Rgbw::default(); NUM_LEDS];
+    ring.set_beat(beat);
+    ring.set_color(&mut data, 0.0, 0);
+    data.iter().map(|led| led.w as u32).sum()
+}
+
+/// 同期中の RingLED は拍の頭で明るく、拍の終わりに向かって暗くなる
+#[test]
+fn ring_pulses_on_beat() {
+    let mut ring = RingLed::new();
+    let at = |beat, phase| Some(BeatPhase { beat, phase });
+    let downbeat = total_white(&mut ring, at(4, 0.0));
+    let beat2 = total_white(&mut ring, at(5, 0.0));
+    let tail = total_white(&mut ring, at(5, 0.9));
+    assert!(downbeat > beat2, "{} {}", downbeat, beat2);
+    assert!(beat2 > tail, "{} {}", beat2, tail);
+}
+
+/// 内部クロック: 何拍進んでも、Clock の時刻はテンポから計算した時刻のまま
+#[test]
+fn master_ticks_do_not_drift() {
+    let mut master = ClockMaster::new(1_000_000, 120);
+    for _ in 0..24 * 120 * 10 {
+        master.advance();
+    }
+    // 10分後（120BPM × 10分）
+    assert_eq!(master.next_tick_us(), 1_000_000 + 600_000_000);
+
+    // テンポの変更は次の Clock から
+    let mut master = ClockMaster::new(0, 120);
+    master.advance();
+    let next = master.next_tick_us();
+    master.set_tempo(60);
+    assert_eq!(master.next_tick_us(), next);
+    master.advance();
+    assert_eq!(master.next_tick_us(), next + 41_666);
+    for _ in 0..23 {
+        master.advance();
+    }
+    assert_eq!(master.next_tick_us(), next + 1_000_000);
+}
+
+#[test]
+fn tap_tempo() {
+    let mut tap = TapTempo::new();
+    assert_eq!(tap.tap(1_000_000), None);
+    let bpm = tap.tap(1_500_000).unwrap();
+    assert!((bpm - 120.0).abs() < 0.01);
+    // ずれたタップも平均する
+    let bpm = tap.tap(2_010_000).unwrap();
+    assert!((bpm - 118.8).abs() < 0.1, "{}", bpm);
+    // 間が空いたら数え直す
+    assert_eq!(tap.tap(5_000_000), None);
+    let bpm = tap.tap(5_600_000).unwrap();
+    assert!((bpm - 100.0).abs() < 0.01);
+    // 古いタップは平均から外れていく
+    let mut t = 5_600_000;
+    for _ in 0..20 {
+        t += 400_000;
+        tap.tap(t);
+    }
+    let bpm = tap.tap(t + 400_000).unwrap();
+    assert!((bpm - 150.0).abs() < 0.5, "{}", bpm);
+}
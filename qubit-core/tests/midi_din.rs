@@ -0,0 +1,209 @@
+//! DIN MIDI: ランニングステータスの送信、バイト列の解析、USB と DIN のルーティング
+use std::convert::Infallible;
+use std::sync::atomic::Ordering;
+
+use embassy_futures::block_on;
+use qubit_core::hal::{MidiSink, MidiSource, SerialSink, SerialSource};
+use qubit_core::midi::din::{DinEncoder, DinMidiIn, DinMidiOut, DinParser};
+use qubit_core::midi::parser::packetize;
+use qubit_core::midi::router::{self, OUT_BOTH, OUT_DIN, OUT_USB, Route};
+use qubit_core::settings;
+
+fn packets(bytes: &[u8]) -> Vec<[u8; 4]> {
+    let mut out = Vec::new();
+    packetize(0, bytes, |p| out.push(p));
+    out
+}
+
+fn encode(encoder: &mut DinEncoder, bytes: &[u8]) -> Vec<u8> {
+    packets(bytes)
+        .into_iter()
+        .flat_map(|p| encoder.encode(p))
+        .collect()
+}
+
+fn parse(parser: &mut DinParser, bytes: &[u8]) -> Vec<[u8; 4]> {
+    bytes.iter().filter_map(|b| parser.parse(*b)).collect()
+}
+
+#[test]
+fn encoder_uses_running_status() {
+    let mut enc = DinEncoder::new();
+    let bytes = encode(
+        &mut enc,
+        &[0x9C, 60, 100, 0x9C, 62, 100, 0x8C, 60, 64, 0x8C, 62, 64],
+    );
+    assert_eq!(bytes, vec![0x9C, 60, 100, 62, 100, 0x8C, 60, 64, 62, 64]);
+
+    // リアルタイムはそのまま。ランニングステータスは続く
+    let bytes = encode(&mut enc, &[0xF8, 0x8C, 61, 64, 0xC0, 5, 0xC0, 6]);
+    assert_eq!(bytes, vec![0xF8, 61, 64, 0xC0, 5, 6]);
+
+    // システムコモンと SysEx の後はステータスから
+    let bytes = encode(
+        &mut enc,
+        &[0xF3, 1, 0xC0, 7, 0xF0, 1, 2, 3, 4, 0xF7, 0xC0, 8],
+    );
+    assert_eq!(
+        bytes,
+        vec![0xF3, 1, 0xC0, 7, 0xF0, 1, 2, 3, 4, 0xF7, 0xC0, 8]
+    );
+    enc.reset();
+    assert_eq!(encode(&mut enc, &[0xC0, 9]), vec![0xC0, 9]);
+    assert!(enc.encode([0x00, 0, 0, 0]).is_empty());
+}
+
+#[test]
+fn encoder_masks_data_bytes() {
+    let mut enc = DinEncoder::new();
+    // 0xFF のベロシティを送ると、受け手は System Reset と読んでしまう
+    assert_eq!(
+        enc.encode([0x09, 0x9C, 60, 0xFF]).as_slice(),
+        [0x9C, 60, 0x7F]
+    );
+    assert_eq!(enc.encode([0x09, 0x9C, 0xBC, 100]).as_slice(), [0x3C, 100]);
+    assert_eq!(enc.encode([0x02, 0xF3, 0x81, 0]).as_slice(), [0xF3, 0x01]);
+    // SysEx の F0 / F7 はそのまま
+    assert_eq!(enc.encode([0x04, 0xF0, 1, 2]).as_slice(), [0xF0, 1, 2]);
+    assert_eq!(enc.encode([0x06, 3, 0xF7, 0]).as_slice(), [3, 0xF7]);
+}
+
+#[test]
+fn parser_restores_running_status() {
+    let mut parser = DinParser::new();
+    let got = parse(&mut parser, &[0x9C, 60, 100, 62, 100, 0xD0, 10, 11]);
+    assert_eq!(
+        got,
+        packets(&[0x9C, 60, 100, 0x9C, 62, 100, 0xD0, 10, 0xD0, 11])
+    );
+
+    // リアルタイムはメッセージの途中でもすぐに。ステータスのないデータは捨てる
+    let mut parser = DinParser::new();
+    let got = parse(&mut parser, &[1, 2, 0xB0, 7, 0xF8, 100, 0xFE]);
+    assert_eq!(
+        got,
+        vec![[0x0F, 0xF8, 0, 0], [0x0B, 0xB0, 7, 100], [0x0F, 0xFE, 0, 0]]
+    );
+
+    // システムコモンの後は、ステータスがなければ捨てる
+    let got = parse(&mut parser, &[0xF2, 1, 2, 3, 0xF6, 0xF1, 5, 6]);
+    assert_eq!(got, packets(&[0xF2, 1, 2, 0xF6, 0xF1, 5]));
+}
+
+#[test]
+fn parser_sysex() {
+    let mut parser = DinParser::new();
+    for len in 0..8u8 {
+        let mut sysex = vec![0xF0];
+        sysex.extend(1..=len);
+        sysex.push(0xF7);
+        assert_eq!(parse(&mut parser, &sysex), packets(&sysex), "{}", len);
+    }
+    // リアルタイムは SysEx の途中でも届く
+    let got = parse(&mut parser, &[0xF0, 1, 0xF8, 2, 0xF7]);
+    assert_eq!(
+        got,
+        vec![[0x0F, 0xF8, 0, 0], [0x04, 0xF0, 1, 2], [0x05, 0xF7, 0, 0]]
+    );
+    // 他のステータスで終わった SysEx の残りは捨てる
+    let got = parse(&mut parser, &[0xF0, 1, 0x90, 60, 1, 0xF7]);
+    assert_eq!(got, packets(&[0x90, 60, 1]));
+}
+
+#[test]
+fn round_trip() {
+    let bytes = [
+        0x9C, 60, 100, 0xF8, 0x9C, 61, 100, 0xE0, 0, 64, 0xF0, 0x7D, 1, 2, 3, 0xF7, 0xE0, 1, 64,
+        0xFA, 0xC3, 1,
+    ];
+    let mut enc = DinEncoder::new();
+    let mut parser = DinParser::new();
+    let din = encode(&mut enc, &bytes);
+    assert!(din.len() < bytes.len());
+    assert_eq!(parse(&mut parser, &din), packets(&bytes));
+}
+
+// =========================================================
+//      UART
+// =========================================================
+#[derive(Default)]
+struct Uart {
+    written: Vec<Vec<u8>>,
+    incoming: Vec<u8>,
+}
+
+impl SerialSink for Uart {
+    type Error = Infallible;
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.written.push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+impl SerialSource for Uart {
+    type Error = Infallible;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = buf.len().min(self.incoming.len());
+        buf[..n].copy_from_slice(&self.incoming[..n]);
+        self.incoming.drain(..n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn uart_glue() {
+    let mut out = DinMidiOut::new(Uart::default());
+    let notes: Vec<[u8; 4]> = (0..30).flat_map(|n| packets(&[0x9C, n, 100])).collect();
+    block_on(out.send_all(&notes)).unwrap();
+    block_on(out.send([0x08, 0x8C, 0, 64])).unwrap();
+
+    let mut midi_in = DinMidiIn::new(Uart {
+        written: Vec::new(),
+        incoming: out_written(out),
+    });
+    let mut got = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let n = block_on(midi_in.receive(&mut buf)).unwrap();
+        if n == 0 {
+            break;
+        }
+        got.extend(buf[..n].chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]));
+    }
+    assert_eq!(got[..30], notes[..]);
+    assert_eq!(got[30], [0x08, 0x8C, 0, 64]);
+}
+
+/// 書いたバイト列をつなげる（send_all は1回の書き込みを 48byte までに分ける）
+fn out_written(out: DinMidiOut<Uart>) -> Vec<u8> {
+    let uart = out.into_inner();
+    assert!(uart.written.iter().all(|w| w.len() <= 48));
+    assert_eq!(uart.written.len(), 3);
+    uart.written.concat()
+}
+
+#[test]
+fn routing() {
+    let set = |out, usb, din| {
+        settings::MIDI_OUT.store(out, Ordering::Relaxed);
+        settings::USB_THRU.store(usb, Ordering::Relaxed);
+        settings::DIN_THRU.store(din, Ordering::Relaxed);
+        Route::load()
+    };
+    let route = set(OUT_USB, 0, 0);
+    assert!(route.touch_usb && !route.touch_din && !route.usb_to_din && !route.din_to_usb);
+    let route = set(OUT_DIN, 1, 0);
+    assert!(!route.touch_usb && route.touch_din && route.usb_to_din && !route.din_to_usb);
+    let route = set(OUT_BOTH, 0, 1);
+    assert!(route.touch_usb && route.touch_din && route.din_to_usb);
+
+    // USB から DIN には SysEx を流さない
+    assert!(router::usb_thru([0x09, 0x90, 60, 100]));
+    assert!(router::usb_thru([0x0F, 0xF8, 0, 0]));
+    assert!(router::usb_thru([0x05, 0xF6, 0, 0]));
+    assert!(!router::usb_thru([0x04, 0xF0, 0x7D, 1]));
+    assert!(!router::usb_thru([0x05, 0xF7, 0, 0]));
+    assert!(!router::usb_thru([0x00, 0, 0, 0]));
+}
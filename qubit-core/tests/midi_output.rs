@@ -0,0 +1,88 @@
+//! タッチで送るノートの変換（チャンネル・トランスポーズ・音域）
+use portable_atomic::Ordering;
+use qubit_core::midi::output::{NoteOutput, Scale, SoundingNotes};
+use qubit_core::settings::{self, PARAMS};
+
+fn output(shift: i16, low: u8, high: u8, fold: bool) -> NoteOutput {
+    NoteOutput {
+        channel: 12,
+        shift,
+        low,
+        high,
+        fold,
+        scale: Scale::Chromatic,
+    }
+}
+
+/// 設定を変えていなければ、今までと同じ（チャンネル13、ノートはそのまま）
+/// 設定値はグローバルなので、設定を読むテストはこれだけにする
+#[test]
+fn settings() {
+    let default = NoteOutput::load();
+    assert_eq!(default, output(0, 0, 127, false));
+
+    let set = |name: &str, value: u8| {
+        let param = PARAMS.iter().find(|p| p.name == name).unwrap();
+        assert!(param.set(value), "{} {}", name, value);
+    };
+    set("Channel", 1);
+    set("Transpose", settings::TRANSPOSE_ZERO - 2);
+    set("Octave", settings::OCTAVE_ZERO + 1);
+    set("NoteLow", 36);
+    set("NoteHigh", 91);
+    set("Range", 1);
+    let mut expected = output(10, 36, 91, true);
+    expected.channel = 0;
+    assert_eq!(NoteOutput::load(), expected);
+    assert!(!PARAMS.iter().find(|p| p.name == "Channel").unwrap().set(17));
+    // 範囲外の値が入っていても、チャンネルは 1-16 にする
+    settings::MIDI_CHANNEL.store(0, Ordering::Relaxed);
+    assert_eq!(NoteOutput::load().channel, 0);
+}
+
+#[test]
+fn clamp_to_midi_range() {
+    // 0-127 を越えても折り返さない
+    assert_eq!(output(36, 0, 127, false).note(112), 127);
+    assert_eq!(output(-36, 0, 127, false).note(17), 0);
+    assert_eq!(output(12, 0, 127, false).note(60), 72);
+    // 設定した音域の端に寄せる
+    assert_eq!(output(0, 48, 72, false).note(40), 48);
+    assert_eq!(output(0, 48, 72, false).note(80), 72);
+    // low > high なら low だけ
+    assert_eq!(output(0, 60, 50, false).note(40), 60);
+}
+
+#[test]
+fn fold_into_range() {
+    let fold = output(0, 48, 71, true);
+    assert_eq!(fold.note(40), 52); // E2 → E3
+    assert_eq!(fold.note(47), 59);
+    assert_eq!(fold.note(48), 48);
+    assert_eq!(fold.note(83), 71);
+    assert_eq!(fold.note(84), 60); // C6 → C4
+    // 0-127 の外も同じ音名にする
+    assert_eq!(output(36, 0, 127, true).note(100), 124);
+    assert_eq!(output(36, 0, 127, true).note(104), 116);
+    // 1オクターブに満たない音域では寄せる
+    assert_eq!(output(0, 60, 66, true).note(40), 60);
+}
+
+/// 押している間に設定が変わっても、Note Off は Note On と同じチャンネル・ノート
+#[test]
+fn note_off_matches_note_on() {
+    let mut sounding = SoundingNotes::new();
+    let before = output(0, 0, 127, false);
+    let mut after = output(12, 0, 127, false);
+    after.channel = 0;
+
+    assert_eq!(sounding.note_on(0, 60, &before), (0x9C, 60));
+    assert_eq!(sounding.note_on(1, 60, &after), (0x90, 72));
+    // 移動: 新しいノートの On の後に、元のノートの Off
+    assert_eq!(sounding.note_on(0, 61, &after), (0x90, 73));
+    assert_eq!(sounding.note_off(0, 60, &after), (0x8C, 60));
+    assert_eq!(sounding.note_off(1, 60, &before), (0x80, 72));
+    assert_eq!(sounding.note_off(0, 61, &before), (0x80, 73));
+    // On を送っていない Off は今の設定で送る
+    assert_eq!(sounding.note_off(2, 60, &before), (0x8C, 60));
+}
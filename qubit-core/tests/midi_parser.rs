@@ -0,0 +1,305 @@
+//! USB-MIDI パケットの解析
+use std::convert::Infallible;
+
+use embassy_futures::block_on;
+use qubit_core::constants::MIDI_SYSEX_TX_MAX;
+use qubit_core::hal::MidiSink;
+use qubit_core::midi::parser::{
+    MidiMessage, MidiParseError, SysexTxError, UsbMidiParser, packetize, send_sysex,
+};
+
+/// パケットを順に解析して、揃ったメッセージとエラーを並べる
+/// SysEx 以外のメッセージはパーサーから借用しないので、Debug 表記で比べる
+#[derive(Debug, PartialEq)]
+enum Parsed {
+    Msg(u8, String),
+    SysEx(u8, Vec<u8>),
+    Err(MidiParseError),
+}
+
+fn msg(cable: u8, message: MidiMessage) -> Parsed {
+    Parsed::Msg(cable, format!("{:?}", message))
+}
+
+fn parse_all<const N: usize>(parser: &mut UsbMidiParser<N>, packets: &[[u8; 4]]) -> Vec<Parsed> {
+    let mut out = Vec::new();
+    for packet in packets {
+        match parser.parse(*packet) {
+            Ok(Some(event)) => out.push(match event.message {
+                MidiMessage::SysEx(data) => Parsed::SysEx(event.cable, data.to_vec()),
+                other => msg(event.cable, other),
+            }),
+            Ok(None) => {}
+            Err(e) => out.push(Parsed::Err(e)),
+        }
+    }
+    out
+}
+
+fn packets(cable: u8, bytes: &[u8]) -> Vec<[u8; 4]> {
+    let mut out = Vec::new();
+    packetize(cable, bytes, |p| out.push(p));
+    out
+}
+
+#[test]
+fn channel_messages() {
+    let mut parser = UsbMidiParser::<64>::new();
+    let bytes = [
+        0x80, 60, 64, // Note Off
+        0x91, 61, 100, // Note On
+        0x92, 62, 0, // Note On velocity 0
+        0xA3, 63, 10, // Poly Pressure
+        0xB4, 7, 127, // CC
+        0xC5, 12, // Program Change
+        0xD6, 99, // Channel Pressure
+        0xE7, 0x00, 0x40, // Pitch Bend 中央
+        0xEF, 0x7F, 0x7F, // Pitch Bend 最大
+        0xEF, 0x00, 0x00, // Pitch Bend 最小
+    ];
+    let parsed = parse_all(&mut parser, &packets(0, &bytes));
+    use MidiMessage::*;
+    let expected = [
+        NoteOff {
+            channel: 0,
+            note: 60,
+            velocity: 64,
+        },
+        NoteOn {
+            channel: 1,
+            note: 61,
+            velocity: 100,
+        },
+        NoteOn {
+            channel: 2,
+            note: 62,
+            velocity: 0,
+        },
+        PolyPressure {
+            channel: 3,
+            note: 63,
+            pressure: 10,
+        },
+        ControlChange {
+            channel: 4,
+            control: 7,
+            value: 127,
+        },
+        ProgramChange {
+            channel: 5,
+            program: 12,
+        },
+        ChannelPressure {
+            channel: 6,
+            pressure: 99,
+        },
+        PitchBend {
+            channel: 7,
+            value: 0,
+        },
+        PitchBend {
+            channel: 15,
+            value: 8191,
+        },
+        PitchBend {
+            channel: 15,
+            value: -8192,
+        },
+    ];
+    assert_eq!(
+        parsed,
+        expected.iter().map(|m| msg(0, *m)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn system_common_and_realtime() {
+    let mut parser = UsbMidiParser::<64>::new();
+    let bytes = [
+        0xF1, 0x23, // MTC
+        0xF2, 0x10, 0x02, // Song Position = 0x10 + 2 << 7
+        0xF3, 5,    // Song Select
+        0xF6, // Tune Request
+        0xF8, 0xFA, 0xFB, 0xFC, 0xFE, 0xFF,
+    ];
+    let parsed = parse_all(&mut parser, &packets(2, &bytes));
+    use MidiMessage::*;
+    let expected = [
+        TimeCode(0x23),
+        SongPosition(0x110),
+        SongSelect(5),
+        TuneRequest,
+        Clock,
+        Start,
+        Continue,
+        Stop,
+        ActiveSensing,
+        Reset,
+    ];
+    assert_eq!(
+        parsed,
+        expected.iter().map(|m| msg(2, *m)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn cable_number_is_kept() {
+    let mut parser = UsbMidiParser::<64>::new();
+    let event = parser.parse([0x39, 0x90, 60, 100]).unwrap().unwrap();
+    assert_eq!(event.cable, 3);
+}
+
+/// 最後のパケットの長さ（CIN 0x5/0x6/0x7）がどれでも組み立てられる
+#[test]
+fn sysex_of_every_length() {
+    let mut parser = UsbMidiParser::<64>::new();
+    for len in 0..20u8 {
+        let mut bytes = vec![0xF0];
+        bytes.extend(0..len);
+        bytes.push(0xF7);
+        let parsed = parse_all(&mut parser, &packets(1, &bytes));
+        assert_eq!(
+            parsed,
+            [Parsed::SysEx(1, (0..len).collect())],
+            "len {}",
+            len
+        );
+    }
+}
+
+#[test]
+fn realtime_inside_sysex() {
+    let mut parser = UsbMidiParser::<64>::new();
+    let mut input = packets(0, &[0xF0, 1, 2, 3, 4, 5]);
+    input.push([0x0F, 0xF8, 0, 0]);
+    input.push([0x06, 6, 0xF7, 0]);
+    let parsed = parse_all(&mut parser, &input);
+    assert_eq!(
+        parsed,
+        [
+            msg(0, MidiMessage::Clock),
+            Parsed::SysEx(0, vec![1, 2, 3, 4, 5, 6]),
+        ]
+    );
+}
+
+#[test]
+fn sysex_overflow_is_dropped_and_parser_recovers() {
+    let mut parser = UsbMidiParser::<8>::new();
+    let mut bytes = vec![0xF0];
+    bytes.extend(0..9);
+    bytes.push(0xF7);
+    bytes.extend([0xF0, 1, 2, 3, 4, 5, 6, 7, 8, 0xF7]); // ちょうど入る
+    let parsed = parse_all(&mut parser, &packets(0, &bytes));
+    assert_eq!(
+        parsed,
+        [
+            Parsed::Err(MidiParseError::SysExOverflow),
+            Parsed::SysEx(0, vec![1, 2, 3, 4, 5, 6, 7, 8]),
+        ]
+    );
+}
+
+#[test]
+fn broken_sysex() {
+    let mut parser = UsbMidiParser::<64>::new();
+    // F0 なしの続き
+    assert_eq!(
+        parser.parse([0x04, 1, 2, 3]),
+        Err(MidiParseError::SysExNotStarted)
+    );
+    assert_eq!(
+        parser.parse([0x05, 0xF7, 0, 0]),
+        Err(MidiParseError::SysExNotStarted)
+    );
+    // 組み立て中に別の SysEx が始まったら、新しい方を組み立てる
+    let mut input = packets(0, &[0xF0, 1, 2]);
+    input.extend(packets(0, &[0xF0, 9, 8, 7, 0xF7]));
+    assert_eq!(
+        parse_all(&mut parser, &input),
+        [
+            Parsed::Err(MidiParseError::SysExInterrupted),
+            Parsed::SysEx(0, vec![9, 8, 7]),
+        ]
+    );
+    // チャンネルメッセージで SysEx は終わる
+    let mut input = packets(0, &[0xF0, 1, 2]);
+    input.extend(packets(0, &[0x90, 60, 1]));
+    input.push([0x05, 0xF7, 0, 0]);
+    assert_eq!(
+        parse_all(&mut parser, &input),
+        [
+            msg(
+                0,
+                MidiMessage::NoteOn {
+                    channel: 0,
+                    note: 60,
+                    velocity: 1
+                }
+            ),
+            Parsed::Err(MidiParseError::SysExNotStarted),
+        ]
+    );
+}
+
+#[test]
+fn malformed_packets() {
+    let mut parser = UsbMidiParser::<64>::new();
+    // CIN とステータスが合わない
+    assert_eq!(
+        parser.parse([0x09, 0x80, 60, 0]),
+        Err(MidiParseError::Malformed)
+    );
+    // データの最上位ビット
+    assert_eq!(
+        parser.parse([0x09, 0x90, 0x80, 0]),
+        Err(MidiParseError::Malformed)
+    );
+    // 予約の CIN と空のパケットは無視する
+    assert_eq!(parser.parse([0x00, 0, 0, 0]), Ok(None));
+    assert_eq!(parser.parse([0x01, 0x12, 0x34, 0x56]), Ok(None));
+    // その後も普通に解析できる
+    assert!(parser.parse([0x0B, 0xB0, 1, 2]).unwrap().is_some());
+}
+
+/// 送ったパケットをそのまま溜める
+#[derive(Default)]
+struct Capture(Vec<[u8; 4]>);
+
+impl MidiSink for Capture {
+    type Error = Infallible;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        self.0.push(packet);
+        Ok(())
+    }
+}
+
+fn sysex_of_len(len: usize) -> Vec<u8> {
+    let mut sysex = vec![0x10; len];
+    sysex[0] = 0xF0;
+    sysex[len - 1] = 0xF7;
+    sysex
+}
+
+#[test]
+fn oversized_sysex_is_rejected() {
+    // 上限ちょうどは F7 まで送る
+    let sysex = sysex_of_len(MIDI_SYSEX_TX_MAX);
+    let mut out = Capture::default();
+    assert_eq!(block_on(send_sysex(&mut out, &sysex)), Ok(()));
+    assert_eq!(out.0, packets(0, &sysex));
+    let mut parser = UsbMidiParser::<{ MIDI_SYSEX_TX_MAX }>::new();
+    assert_eq!(
+        parse_all(&mut parser, &out.0),
+        [Parsed::SysEx(0, sysex[1..sysex.len() - 1].to_vec())]
+    );
+
+    // 1 バイトでも超えたら、途中で切らずに何も送らない
+    let mut out = Capture::default();
+    assert_eq!(
+        block_on(send_sysex(&mut out, &sysex_of_len(MIDI_SYSEX_TX_MAX + 1))),
+        Err(SysexTxError::TooLong)
+    );
+    assert!(out.0.is_empty());
+}
@@ -0,0 +1,124 @@
+//! MIDI の送信キュー: 優先度、置き換え、満杯のときに捨てるもの、まとめて取り出す
+use qubit_core::constants::MIDI_TX_BATCH;
+use qubit_core::midi::tx_queue::{Priority, TxBatch, TxMessage, TxQueue};
+
+fn on(note: u8) -> TxMessage {
+    TxMessage::midi1([0x09, 0x9C, note, 100])
+}
+
+fn off(note: u8) -> TxMessage {
+    TxMessage::midi1([0x08, 0x8C, note, 64])
+}
+
+fn cc(index: u8, value: u8) -> TxMessage {
+    TxMessage::midi1([0x0B, 0xBC, index, value])
+}
+
+fn bend(value: u16) -> TxMessage {
+    TxMessage::midi1([0x0E, 0xEC, (value & 0x7f) as u8, (value >> 7) as u8])
+}
+
+fn drain<const N: usize>(queue: &mut TxQueue<N>) -> Vec<TxMessage> {
+    std::iter::from_fn(|| queue.pop()).collect()
+}
+
+#[test]
+fn priorities() {
+    assert_eq!(on(60).priority(), Priority::NoteOn);
+    assert_eq!(off(60).priority(), Priority::NoteOff);
+    assert_eq!(
+        TxMessage::midi1([0x09, 0x9C, 60, 0]).priority(),
+        Priority::NoteOff
+    );
+    assert_eq!(cc(1, 0).priority(), Priority::Control);
+
+    let mut queue = TxQueue::<8>::new();
+    queue.push(cc(1, 10));
+    queue.push(on(60));
+    queue.push(off(50));
+    queue.push(on(62));
+    assert_eq!(drain(&mut queue), vec![off(50), on(60), on(62), cc(1, 10)]);
+}
+
+/// 同じノートの Note On が残っていれば、Note Off は追い越さない
+#[test]
+fn note_off_never_overtakes_its_note_on() {
+    let mut queue = TxQueue::<8>::new();
+    queue.push(on(60));
+    queue.push(on(61));
+    queue.push(off(60));
+    queue.push(off(70));
+    assert_eq!(drain(&mut queue), vec![off(70), on(60), on(61), off(60)]);
+}
+
+#[test]
+fn controllers_are_coalesced() {
+    let mut queue = TxQueue::<8>::new();
+    queue.push(cc(1, 10));
+    queue.push(bend(100));
+    queue.push(cc(7, 1));
+    queue.push(cc(1, 20));
+    queue.push(bend(200));
+    assert_eq!(queue.len(), 3);
+    assert_eq!(drain(&mut queue), vec![cc(1, 20), bend(200), cc(7, 1)]);
+
+    // Poly Pressure はノートごと
+    let pressure = |note, value| TxMessage::midi1([0x0A, 0xAC, note, value]);
+    queue.push(pressure(60, 1));
+    queue.push(pressure(61, 2));
+    queue.push(pressure(60, 3));
+    assert_eq!(drain(&mut queue), vec![pressure(60, 3), pressure(61, 2)]);
+}
+
+#[test]
+fn drop_policy_when_full() {
+    let mut queue = TxQueue::<4>::new();
+    queue.push(on(60));
+    queue.push(cc(1, 0));
+    queue.push(cc(2, 0));
+    queue.push(on(61));
+    // 連続的な値は新しいものを捨てる（置き換えはできる）
+    assert!(!queue.push(cc(3, 0)));
+    assert!(queue.push(cc(1, 5)));
+    // Note On は最も新しい連続的な値を捨てて入る
+    assert!(!queue.push(on(62)));
+    assert_eq!(queue.len(), 4);
+    // Note Off は連続的な値、なければ最も新しい Note On を捨てて入る
+    assert!(!queue.push(off(40)));
+    assert!(!queue.push(off(41)));
+    assert_eq!(drain(&mut queue), vec![off(40), off(41), on(60), on(61)]);
+
+    // Note Off だけで満杯なら、新しいものは入らない
+    for note in 0..4 {
+        queue.push(off(note));
+    }
+    assert!(!queue.push(off(10)));
+    assert!(!queue.push(on(10)));
+    assert_eq!(queue.len(), 4);
+    drain(&mut queue);
+
+    // Note On の後に送る Note Off も捨てない（最も新しい Note On を捨てる。Note Off は残るので鳴りっぱなしにならない）
+    queue.push(on(60));
+    queue.push(on(61));
+    queue.push(on(62));
+    queue.push(off(62));
+    assert!(!queue.push(off(70)));
+    assert_eq!(drain(&mut queue), vec![off(70), on(60), on(61), off(62)]);
+}
+
+/// 1回の USB 転送（64byte = 16 パケット）に入るだけ取り出す
+#[test]
+fn batches() {
+    let mut queue = TxQueue::<32>::new();
+    for note in 0..20 {
+        queue.push(on(note));
+    }
+    let mut batch = TxBatch::new();
+    queue.pop_batch(&mut batch);
+    assert_eq!(batch.len(), MIDI_TX_BATCH);
+    assert_eq!(batch[0], [0x09, 0x9C, 0, 100]);
+    queue.pop_batch(&mut batch);
+    assert_eq!(batch.len(), 4);
+    queue.pop_batch(&mut batch);
+    assert!(batch.is_empty());
+}
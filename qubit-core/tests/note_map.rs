@@ -0,0 +1,100 @@
+//! ノート・パッド・LED の対応: 受信したノートは、そのノートを鳴らすパッドに表示する
+use qubit_core::midi::note_map::{NUM_PADS, NoteMap, RxLeds};
+use qubit_core::midi::output::{NoteOutput, Scale};
+
+fn map(shift: i16, low: u8, high: u8, scale: Scale, fold: bool) -> NoteMap {
+    NoteMap {
+        output: NoteOutput {
+            channel: 12,
+            shift,
+            low,
+            high,
+            fold: false,
+            scale,
+        },
+        fold,
+    }
+}
+
+/// 表示するパッドは、必ずそのノートを鳴らす
+fn assert_shows_what_it_plays(map: &NoteMap) {
+    for note in 0..128 {
+        if let Some(pad) = map.note_pad(note) {
+            assert_eq!(map.pad_note(pad), note, "{:?} {}", map, note);
+        }
+    }
+}
+
+#[test]
+fn default_matches_touch_notes() {
+    let map = map(0, 0, 127, Scale::Chromatic, false);
+    // パッド 0 は A0 の 4つ下（今までのタッチのノート）
+    assert_eq!(map.pad_note(0), 17);
+    assert_eq!(map.note_led(60), Some(43.0));
+    for pad in 0..NUM_PADS {
+        assert_eq!(map.note_pad(map.pad_note(pad)), Some(pad));
+    }
+    assert_eq!(map.note_pad(16), None);
+    assert_eq!(map.note_pad(17 + NUM_PADS), None);
+    assert_shows_what_it_plays(&map);
+}
+
+#[test]
+fn transpose_moves_the_leds() {
+    let map = map(-14, 0, 127, Scale::Chromatic, false);
+    assert_eq!(map.pad_note(43), 46);
+    assert_eq!(map.note_pad(46), Some(43));
+    assert_eq!(map.note_pad(60), Some(57));
+    assert_shows_what_it_plays(&map);
+}
+
+#[test]
+fn scale_and_range() {
+    // スケールにないパッドは下の音を鳴らす。受信した音は、元のノートが同じパッドに出す
+    let major = map(2, 0, 127, Scale::Major, false);
+    assert_eq!(major.pad_note(60 - 17 + 1), 62); // C# → C → D（D メジャー）
+    assert_eq!(major.note_pad(62), Some(60 - 17));
+    assert_eq!(major.note_pad(63), None); // D# は鳴らせない
+    assert_shows_what_it_plays(&major);
+    let penta = map(0, 0, 127, Scale::Pentatonic, false);
+    assert_eq!(penta.pad_note(65 - 17), 64); // F → E
+    assert_eq!(penta.note_pad(64), Some(64 - 17));
+    assert_shows_what_it_plays(&penta);
+
+    // 音域の端に寄せたノートは、寄せる前が同じパッドに出す。音域の外は出さない
+    let zone = map(0, 48, 72, Scale::Chromatic, false);
+    assert_eq!(zone.note_pad(48), Some(48 - 17));
+    assert_eq!(zone.note_pad(72), Some(72 - 17));
+    assert_eq!(zone.note_pad(47), None);
+    assert_eq!(zone.note_pad(73), None);
+    assert_shows_what_it_plays(&zone);
+}
+
+#[test]
+fn fold_shows_pitch_class() {
+    let zone = map(0, 48, 71, Scale::Chromatic, true);
+    assert_eq!(zone.note_pad(40), Some(52 - 17)); // E2 → E3
+    assert_eq!(zone.note_pad(96), Some(60 - 17)); // C7 → C4（一番近い C）
+    assert_eq!(zone.note_pad(60), Some(60 - 17));
+    // スケールにない音名は、折り返しても出さない
+    let major = map(0, 48, 71, Scale::Major, true);
+    assert_eq!(major.note_pad(37), None);
+    assert_eq!(major.note_pad(38), Some(50 - 17));
+}
+
+#[test]
+fn note_off_clears_the_same_led() {
+    let mut rx = RxLeds::new();
+    assert_eq!(
+        rx.note_on(60, &map(0, 0, 127, Scale::Chromatic, false)),
+        Some(43.0)
+    );
+    // 点けている間に設定が変わっても、同じ LED を消す
+    assert_eq!(rx.note_off(60), Some(43.0));
+    assert_eq!(rx.note_off(60), None);
+    assert_eq!(
+        rx.note_on(5, &map(0, 0, 127, Scale::Chromatic, false)),
+        None
+    );
+    assert_eq!(rx.note_off(5), None);
+}
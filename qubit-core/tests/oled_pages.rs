@@ -0,0 +1,226 @@
+//! OLED の各ページの描画を、保存してある画像（tests/golden/*.pbm）と比べる
+//!
+//! 表示する値は固定にしてから描画する
+//! 意図してレイアウトを変えたときは、画像を更新して差分を確認してからコミットする
+//!   UPDATE_FIXTURES=1 cargo test --test oled_pages
+//! 一致しないときは、実際の描画を target の一時ディレクトリに書き出す
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use qubit_core::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH, OledBuffer};
+use qubit_core::midi::clock::ClockState;
+use qubit_core::midi::loopian::{self, LoopianState};
+use qubit_core::touch::key_mask::KeyMask;
+use qubit_core::ui::host_page::{self, Icon};
+use qubit_core::ui::oled_display::GraphicsDisplay;
+use qubit_core::*;
+
+const PAGES: &[u8] = &[
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+];
+
+// 表示する値はグローバルなので、描画は1つずつ行う
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+// ホストのページの書き込みは制限があるので、固定するたびに時刻を進める
+static HOST_PAGE_US: AtomicU64 = AtomicU64::new(0);
+
+/// 表示する値を固定する
+fn set_fixed_state() {
+    for (v, value) in [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3]
+        .iter()
+        .zip([1000, 2000, 3000, 4095])
+    {
+        v.store(value, Ordering::Relaxed);
+    }
+    PRESSURE.store(123, Ordering::Relaxed);
+    ELAPSED_TIME.store(4567, Ordering::Relaxed);
+    for (i, p) in [&POINT0, &POINT1, &POINT2, &POINT3].iter().enumerate() {
+        p.store(100 + i as u16, Ordering::Relaxed);
+    }
+    for (i, t) in [&TOUCH0, &TOUCH1, &TOUCH2].iter().enumerate() {
+        t.store(i as i32 * 250, Ordering::Relaxed);
+    }
+    TOUCH3.store(10000, Ordering::Relaxed); // 範囲外は "---"
+    let mut mask = KeyMask::new();
+    mask.set_chip(3, true);
+    mask.store_auto();
+    KeyMask::new().store_manual();
+    for (dev, log) in I2C_ERROR_LOG.iter().enumerate() {
+        for (kind, count) in log.iter().enumerate() {
+            count.store((dev * 7 + kind * 13) as u32 % 120, Ordering::Relaxed);
+        }
+    }
+    I2C_RECOVERY_COUNT.store(2, Ordering::Relaxed);
+    for (ch, rate) in SCAN_RATE.iter().enumerate() {
+        rate.store(90 + ch as u16, Ordering::Relaxed);
+    }
+    SCAN_FRAME_RATE.store(95, Ordering::Relaxed);
+    SCAN_MAX_AGE.store(21_500, Ordering::Relaxed);
+    // 内部クロックで再生中、2小節目の2拍目
+    settings::CLOCK_SOURCE.store(1, Ordering::Relaxed);
+    settings::TEMPO.store(128, Ordering::Relaxed);
+    ClockState {
+        running: true,
+        position: 30 + 96,
+        ..Default::default()
+    }
+    .store();
+    // Loopian: 2小節のループの半分、パート2を演奏中
+    for (part, name) in ["Intro", "Verse", "Chorus", ""].iter().enumerate() {
+        let sysex = loopian::phrase_sysex(part as u8, name);
+        loopian::receive(&sysex[1..sysex.len() - 1], 0);
+    }
+    LoopianState {
+        length: 192,
+        position: 96,
+        received_us: 0,
+        beat: 0,
+        part: 1,
+    }
+    .store();
+    // ホストのページ: テキスト2行、アイコン2つ、下の 8行にビットマップ
+    let now = HOST_PAGE_US.fetch_add(1_000_000, Ordering::Relaxed);
+    let mut rows = [[0u8; DISPLAY_WIDTH / 8]; 8];
+    for (y, row) in rows.iter_mut().enumerate() {
+        row[..8].fill(if y % 2 == 0 { 0xFF } else { 0x81 });
+    }
+    for sysex in [
+        host_page::clear_sysex(None),
+        host_page::text_sysex(0, "Scene: Verse B"),
+        host_page::text_sysex(2, "Cutoff   64"),
+        host_page::icon_sysex(0, Some(Icon::Play), 118, 0),
+        host_page::icon_sysex(1, Some(Icon::Loop), 118, 26),
+        host_page::bitmap_sysex(56, &rows),
+    ] {
+        host_page::receive(&sysex[1..sysex.len() - 1], now);
+    }
+}
+
+fn render(page: u8, counter: u32) -> OledBuffer {
+    let mut gui = GraphicsDisplay::new("2026/01/01", "12:34:56");
+    gui.change_page(page);
+    let mut buffer = OledBuffer::new();
+    gui.tick(&mut buffer, counter);
+    buffer
+}
+
+fn pixel(buffer: &OledBuffer, x: usize, y: usize) -> bool {
+    buffer.data[x + (y / 8) * DISPLAY_WIDTH] & (1 << (y % 8)) != 0
+}
+
+/// 1bit の PBM (P4)。点灯しているピクセルを黒にする
+fn to_pbm(buffer: &OledBuffer) -> Vec<u8> {
+    let mut pbm = format!("P4\n{} {}\n", DISPLAY_WIDTH, DISPLAY_HEIGHT).into_bytes();
+    for y in 0..DISPLAY_HEIGHT {
+        for x0 in (0..DISPLAY_WIDTH).step_by(8) {
+            let mut byte = 0u8;
+            for bit in 0..8 {
+                if pixel(buffer, x0 + bit, y) {
+                    byte |= 0x80 >> bit;
+                }
+            }
+            pbm.push(byte);
+        }
+    }
+    pbm
+}
+
+/// 違うピクセルを、描画の範囲と一緒に表示する
+fn describe_diff(expected: &[u8], actual: &[u8]) -> String {
+    let header = format!("P4\n{} {}\n", DISPLAY_WIDTH, DISPLAY_HEIGHT).len();
+    if expected.len() != actual.len() || expected.len() < header {
+        return "size differs".to_string();
+    }
+    let (mut count, mut min, mut max) = (0, (usize::MAX, usize::MAX), (0, 0));
+    for (i, (e, a)) in expected[header..].iter().zip(&actual[header..]).enumerate() {
+        for bit in 0..8 {
+            if (e ^ a) & (0x80 >> bit) != 0 {
+                let (x, y) = ((i % (DISPLAY_WIDTH / 8)) * 8 + bit, i / (DISPLAY_WIDTH / 8));
+                count += 1;
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+    }
+    format!(
+        "{} pixels differ in x {}..={}, y {}..={}",
+        count, min.0, max.0, min.1, max.1
+    )
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+#[test]
+fn pages_match_golden_images() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    set_fixed_state();
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    let actual_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden-actual");
+    let mut failures = Vec::new();
+
+    for page in PAGES {
+        let name = format!("page_{:02}.pbm", page);
+        let path = golden_dir().join(&name);
+        let actual = to_pbm(&render(*page, 42));
+        if update {
+            std::fs::create_dir_all(golden_dir()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read(&path).unwrap_or_default();
+        if expected != actual {
+            std::fs::create_dir_all(&actual_dir).unwrap();
+            let actual_path = actual_dir.join(&name);
+            std::fs::write(&actual_path, &actual).unwrap();
+            failures.push(format!(
+                "{}: {} (actual: {})",
+                name,
+                describe_diff(&expected, &actual),
+                actual_path.display()
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "OLED pages differ from the golden images:\n{}\nRun with UPDATE_FIXTURES=1 if the change is intended.",
+        failures.join("\n")
+    );
+}
+
+/// 設定画面の選択マークは点滅するので、消えているときも確認する
+#[test]
+fn settings_cursor_blinks() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    set_fixed_state();
+    assert_ne!(render(4, 0).data, render(4, 5).data);
+    assert_eq!(render(4, 0).data, render(4, 1).data);
+}
+
+/// ページ1の文字が、ADC のバーの領域に重ならないこと
+#[test]
+fn page1_text_stays_out_of_bar_area() {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    set_fixed_state();
+    for v in [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3] {
+        v.store(0, Ordering::Relaxed);
+    }
+    // draw_bar: x 54..118, y 24..32
+    for counter in [0, 9, 99_999, u32::MAX] {
+        let buffer = render(1, counter);
+        for y in 24..32 {
+            for x in 54..118 {
+                assert!(
+                    !pixel(&buffer, x, y),
+                    "counter {}: pixel ({}, {}) overlaps the bar area",
+                    counter,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+    set_fixed_state();
+}
@@ -0,0 +1,156 @@
+//! 記録フォーマット（.qtr）の書き込みと読み込み
+use std::convert::Infallible;
+
+use qubit_core::constants::{TOTAL_CH, TOTAL_QT_KEYS};
+use qubit_core::hal::MidiSink;
+use qubit_core::touch::frame::TouchFrame;
+use qubit_core::touch::recording::{
+    self, FrameRecord, RecordingError, RecordingReader, encode_header,
+};
+
+fn sample_record(time_us: u64) -> FrameRecord {
+    let mut frame = TouchFrame::new();
+    for (i, v) in frame.values.iter_mut().enumerate() {
+        *v = (i as u16 * 37) ^ 0x8001; // 上位ビットも使う
+    }
+    for (ch, t) in frame.timestamps.iter_mut().enumerate() {
+        *t = time_us - ch as u64 * 1300; // 100us 単位で割り切れる
+    }
+    FrameRecord {
+        time_us,
+        frame,
+        adc: [1700, 1800, 4095, 0],
+    }
+}
+
+fn file_of(records: &[FrameRecord]) -> Vec<u8> {
+    let mut data = encode_header().to_vec();
+    for rec in records {
+        data.extend_from_slice(&rec.encode());
+    }
+    data
+}
+
+#[test]
+fn roundtrip() {
+    let records = [sample_record(1_000_000), sample_record(1_010_000)];
+    let data = file_of(&records);
+    let read: Vec<_> = RecordingReader::new(&data)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(read, records);
+}
+
+#[test]
+fn old_timestamps_saturate() {
+    let mut rec = sample_record(10_000_000);
+    rec.frame.timestamps[0] = 0; // 10s 前は u16 に入らない
+    let data = file_of(&[rec]);
+    let read = RecordingReader::new(&data)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.frame.timestamps[0], 10_000_000 - u16::MAX as u64 * 100);
+    assert_eq!(read.frame.timestamps[1..], rec.frame.timestamps[1..]);
+    assert_eq!(read.frame.values, rec.frame.values);
+}
+
+#[test]
+fn header_is_checked() {
+    let mut data = file_of(&[]);
+    assert!(RecordingReader::new(&data).is_ok());
+    assert_eq!(
+        RecordingReader::new(&data[..4]).err(),
+        Some(RecordingError::Truncated)
+    );
+    data[4] = 2;
+    assert_eq!(
+        RecordingReader::new(&data).err(),
+        Some(RecordingError::UnsupportedVersion(2))
+    );
+    data[4] = recording::VERSION;
+    data[5] = (TOTAL_QT_KEYS + 1) as u8;
+    assert_eq!(
+        RecordingReader::new(&data).err(),
+        Some(RecordingError::LayoutMismatch)
+    );
+    data[0] = b'X';
+    assert_eq!(
+        RecordingReader::new(&data).err(),
+        Some(RecordingError::BadMagic)
+    );
+}
+
+#[test]
+fn unknown_records_are_skipped() {
+    let rec = sample_record(500_000);
+    let mut data = encode_header().to_vec();
+    data.extend_from_slice(&[0x7f, 3, 0, 1, 2, 3]); // 将来のレコード
+    data.extend_from_slice(&rec.encode());
+    let read: Vec<_> = RecordingReader::new(&data).unwrap().collect();
+    assert_eq!(read, [Ok(rec)]);
+}
+
+#[test]
+fn truncated_record_is_an_error() {
+    let data = file_of(&[sample_record(500_000)]);
+    let mut reader = RecordingReader::new(&data[..data.len() - 1]).unwrap();
+    assert_eq!(reader.next(), Some(Err(RecordingError::Truncated)));
+    assert_eq!(reader.next(), None);
+}
+
+#[test]
+fn pack7_roundtrip() {
+    let src: Vec<u8> = (0..=255).collect();
+    let mut packed = vec![0u8; recording::packed_len(src.len())];
+    let n = recording::pack7(&src, &mut packed);
+    assert_eq!(n, packed.len());
+    assert!(packed.iter().all(|b| *b < 0x80));
+    let mut out = vec![0u8; src.len()];
+    assert_eq!(recording::unpack7(&packed, &mut out), src.len());
+    assert_eq!(out, src);
+}
+
+/// USB-MIDI パケットを SysEx のバイト列に戻す
+struct SysexCapture(Vec<u8>);
+
+impl MidiSink for SysexCapture {
+    type Error = Infallible;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        let len = match packet[0] {
+            0x4 | 0x7 => 3,
+            0x6 => 2,
+            0x5 => 1,
+            cin => panic!("unexpected CIN {:x}", cin),
+        };
+        self.0.extend_from_slice(&packet[1..=len]);
+        Ok(())
+    }
+}
+
+#[test]
+fn sysex_stream_rebuilds_the_file() {
+    let records = [sample_record(2_000_000), sample_record(2_010_000)];
+    let mut capture = SysexCapture(Vec::new());
+    embassy_futures::block_on(async {
+        recording::send_chunk(&mut capture, &encode_header())
+            .await
+            .unwrap();
+        for rec in records.iter() {
+            recording::send_chunk(&mut capture, &rec.encode())
+                .await
+                .unwrap();
+        }
+    });
+    // 他のメッセージが混ざっていても取り出せる
+    let mut syx = vec![0x90, 0x3c, 0x40, 0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7];
+    syx.extend_from_slice(&capture.0);
+
+    let mut data = Vec::new();
+    recording::extract_sysex(&syx, |chunk| data.extend_from_slice(chunk));
+    assert_eq!(data, file_of(&records));
+    assert_eq!(TOTAL_CH, records[0].frame.timestamps.len());
+}
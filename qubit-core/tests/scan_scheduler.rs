@@ -0,0 +1,124 @@
+//! スキャンの割り振り: タッチのあるチャンネルと両隣は毎回、それ以外は巡回して読む
+use qubit_core::constants::{SCAN_HOT_PASSES, SCAN_IDLE_PER_PASS, TOTAL_CH};
+use qubit_core::touch::scan_scheduler::{ChannelSet, ScanScheduler};
+
+fn channels(set: ChannelSet) -> Vec<usize> {
+    (0..TOTAL_CH).filter(|&ch| set.contains(ch)).collect()
+}
+
+fn set(chs: &[usize]) -> ChannelSet {
+    let mut set = ChannelSet::new();
+    for &ch in chs {
+        set.insert(ch);
+    }
+    set
+}
+
+#[test]
+fn channel_set() {
+    let mut a = ChannelSet::new();
+    a.insert(0);
+    a.insert(TOTAL_CH - 1);
+    a.insert(TOTAL_CH); // 範囲外は無視
+    assert_eq!(channels(a), [0, TOTAL_CH - 1]);
+    assert!(!a.contains(TOTAL_CH));
+    assert_eq!(channels(a.union(set(&[3]))), [0, 3, TOTAL_CH - 1]);
+}
+
+#[test]
+fn idle_channels_round_robin() {
+    let mut scheduler = ScanScheduler::new();
+    let mut counts = [0; TOTAL_CH];
+    for pass in 0..TOTAL_CH / SCAN_IDLE_PER_PASS {
+        let plan = scheduler.plan(ChannelSet::new(), false);
+        let first = pass * SCAN_IDLE_PER_PASS;
+        assert_eq!(
+            channels(plan.reads),
+            (first..first + SCAN_IDLE_PER_PASS).collect::<Vec<_>>()
+        );
+        for ch in channels(plan.reads) {
+            counts[ch] += 1;
+        }
+    }
+    // 一巡で全チャンネルを1回ずつ読み、また先頭から
+    assert_eq!(counts, [1; TOTAL_CH]);
+    let plan = scheduler.plan(ChannelSet::new(), false);
+    assert_eq!(
+        channels(plan.reads),
+        (0..SCAN_IDLE_PER_PASS).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn hot_channels_and_neighbours() {
+    let mut scheduler = ScanScheduler::new();
+    scheduler.note_activity(5, true);
+    scheduler.note_activity(9, false); // タッチがなければ何もしない
+
+    // 1回目: 4..=6 と、巡回の 0..4
+    let plan = scheduler.plan(ChannelSet::new(), false);
+    assert_eq!(channels(plan.reads), [0, 1, 2, 3, 4, 5, 6]);
+    // 2回目: 巡回は読み込み済みの 4..=6 を飛ばして 7..11
+    let plan = scheduler.plan(ChannelSet::new(), false);
+    assert_eq!(channels(plan.reads), [4, 5, 6, 7, 8, 9, 10]);
+    // SCAN_HOT_PASSES 回は毎回読む
+    for _ in 2..SCAN_HOT_PASSES {
+        let plan = scheduler.plan(ChannelSet::new(), false);
+        assert!([4, 5, 6].iter().all(|&ch| plan.reads.contains(ch)));
+    }
+    // その後は巡回だけ
+    let plan = scheduler.plan(ChannelSet::new(), false);
+    assert_eq!(channels(plan.reads).len(), SCAN_IDLE_PER_PASS);
+
+    // 端のチャンネルの両隣はリングで回り込む
+    let mut scheduler = ScanScheduler::new();
+    scheduler.note_activity(0, true);
+    for _ in 0..TOTAL_CH / SCAN_IDLE_PER_PASS {
+        let plan = scheduler.plan(ChannelSet::new(), false);
+        assert!(
+            [TOTAL_CH - 1, 0, 1]
+                .iter()
+                .all(|&ch| plan.reads.contains(ch))
+        );
+    }
+}
+
+#[test]
+fn failed_chips_are_read_only_on_retry() {
+    let failed = set(&[2, 7]);
+    let mut scheduler = ScanScheduler::new();
+    scheduler.note_activity(2, true); // 故障中のチップは、タッチがあっても読まない
+    for _ in 0..2 * TOTAL_CH {
+        let plan = scheduler.plan(failed, false);
+        assert!(!plan.reads.contains(2) && !plan.reads.contains(7));
+    }
+    // 巡回は故障中のチップを飛ばして、1回に SCAN_IDLE_PER_PASS チャンネル読む
+    let mut scheduler = ScanScheduler::new();
+    let plan = scheduler.plan(failed, false);
+    assert_eq!(channels(plan.reads), [0, 1, 3, 4]);
+    let plan = scheduler.plan(failed, false);
+    assert_eq!(channels(plan.reads), [5, 6, 8, 9]);
+
+    // retry_failed の回だけ加える
+    let plan = scheduler.plan(failed, true);
+    assert_eq!(channels(plan.reads), [2, 7, 10, 11, 12, 13]);
+}
+
+#[test]
+fn reference_cursor_skips_failed_chips() {
+    let failed = set(&[1, 2, TOTAL_CH - 1]);
+    let mut scheduler = ScanScheduler::new();
+    let refs: Vec<_> = (0..TOTAL_CH)
+        .map(|_| scheduler.plan(failed, true).reference)
+        .collect();
+    let mut expected: Vec<_> = (0..TOTAL_CH)
+        .filter(|&ch| !failed.contains(ch))
+        .map(Some)
+        .collect();
+    expected.extend([Some(0), Some(3), Some(4)]);
+    assert_eq!(refs, expected[..TOTAL_CH]);
+
+    // 全部故障中ならリファレンスは読まない
+    let all = set(&(0..TOTAL_CH).collect::<Vec<_>>());
+    assert_eq!(scheduler.plan(all, false).reference, None);
+}
@@ -0,0 +1,167 @@
+//! 合成タッチフレームを QubitTouch に渡して、タッチの解析を確かめる
+use std::cell::RefCell;
+
+use qubit_core::constants::{
+    AT42QT_KEYS_PER_DEVICE, KEYBD_LO, RINGLED_CMD_TX_MOVED, RINGLED_CMD_TX_OFF, RINGLED_CMD_TX_ON,
+    TOTAL_QT_KEYS,
+};
+use qubit_core::touch::qtouch::{QubitTouch, TOUCH_THRESHOLD};
+use qubit_core::touch::synth::{Finger, SensorModel, SynthTouch};
+
+const FRAME_MS: u64 = 10; // qubit_touch_task と同じ周期
+
+/// (status の上位4bit, note)
+type Event = (u8, u8);
+
+/// end_ms まで 10ms ごとにフレームを渡し、MIDI イベントを集める
+fn run(synth: &mut SynthTouch, end_ms: u64) -> Vec<Event> {
+    let events = RefCell::new(Vec::new());
+    let mut qt = QubitTouch::new(|status, note, _velocity, _location| {
+        events.borrow_mut().push((status & 0xf0, note));
+    });
+    for t in (FRAME_MS..=end_ms).step_by(FRAME_MS as usize) {
+        synth.feed(t * 1000, &mut qt);
+        qt.seek_and_update_touch_point();
+    }
+    events.into_inner()
+}
+
+fn note_of(key: u8) -> u8 {
+    key + KEYBD_LO - 4
+}
+
+fn count(events: &[Event], status: u8) -> usize {
+    events.iter().filter(|e| e.0 == status).count()
+}
+
+#[test]
+fn tap_is_one_note() {
+    let mut synth = SynthTouch::new(SensorModel::new());
+    synth.add_finger(Finger::tap(30.0, 100, 300));
+    let events = run(&mut synth, 800);
+    assert_eq!(
+        events,
+        [
+            (RINGLED_CMD_TX_ON, note_of(30)),
+            (RINGLED_CMD_TX_OFF, note_of(30))
+        ]
+    );
+}
+
+#[test]
+fn slide_moves_note_by_note() {
+    let mut synth = SynthTouch::new(SensorModel::new());
+    synth.add_finger(Finger::slide(20.0, 26.0, 100, 600).then(900, 26.0));
+    let events = run(&mut synth, 1200);
+    let ons: Vec<u8> = events
+        .iter()
+        .filter(|e| e.0 == RINGLED_CMD_TX_ON)
+        .map(|e| e.1)
+        .collect();
+    assert_eq!(ons, (20..=26).map(note_of).collect::<Vec<_>>());
+    assert_eq!(count(&events, RINGLED_CMD_TX_MOVED), 6);
+    assert_eq!(events.last(), Some(&(RINGLED_CMD_TX_OFF, note_of(26))));
+}
+
+#[test]
+fn two_fingers_crossing_stay_balanced() {
+    let mut synth = SynthTouch::new(SensorModel::new());
+    synth.add_finger(Finger::slide(40.0, 60.0, 100, 800));
+    synth.add_finger(Finger::slide(60.0, 40.0, 100, 800));
+    let events = run(&mut synth, 1300);
+    // 重なった瞬間に1本に見えても、Note On と Off の数は釣り合う
+    let ons = count(&events, RINGLED_CMD_TX_ON);
+    let offs = count(&events, RINGLED_CMD_TX_OFF) + count(&events, RINGLED_CMD_TX_MOVED);
+    assert!(ons >= 2);
+    assert_eq!(ons, offs);
+}
+
+#[test]
+fn profile_wraps_past_the_seam() {
+    let mut synth = SynthTouch::new(SensorModel::new());
+    synth.add_finger(Finger::slide(94.0, 98.0, 0, 400));
+    // 95.5: キー95 とキー0 の両方に信号が出る
+    let frame = synth.frame_at(150_000);
+    assert!(frame.values[95] > TOUCH_THRESHOLD);
+    assert!(frame.values[0] > TOUCH_THRESHOLD);
+    // 継ぎ目を越えた後
+    let frame = synth.frame_at(399_000);
+    assert!(frame.values[2] > frame.values[95]);
+    let events = run(&mut synth, 800);
+    assert_eq!(
+        count(&events, RINGLED_CMD_TX_ON),
+        count(&events, RINGLED_CMD_TX_OFF) + count(&events, RINGLED_CMD_TX_MOVED)
+    );
+}
+
+/// QubitTouch は4フレームの合計で判定するので、1フレームあたりの閾値は TOUCH_THRESHOLD / 4
+#[test]
+fn small_noise_and_drift_make_no_notes() {
+    let model = SensorModel {
+        noise: 3,
+        drift_per_s: 1.0,
+        ..SensorModel::new()
+    };
+    let mut synth = SynthTouch::new(model);
+    let events = run(&mut synth, 3000);
+    assert!(events.is_empty(), "{:?}", events);
+}
+
+#[test]
+fn noisy_tap_is_still_one_note() {
+    let model = SensorModel {
+        noise: 6,
+        crosstalk: 0.1,
+        seed: 1234,
+        ..SensorModel::new()
+    };
+    let mut synth = SynthTouch::new(model);
+    synth.add_finger(Finger::tap(50.3, 100, 400).with_rise(20));
+    let events = run(&mut synth, 900);
+    assert_eq!(count(&events, RINGLED_CMD_TX_ON), 1);
+    assert_eq!(count(&events, RINGLED_CMD_TX_OFF), 1);
+}
+
+#[test]
+fn same_seed_gives_same_frames() {
+    let model = SensorModel {
+        noise: 10,
+        seed: 42,
+        ..SensorModel::new()
+    };
+    let (mut a, mut b) = (SynthTouch::new(model), SynthTouch::new(model));
+    for synth in [&mut a, &mut b] {
+        synth.add_finger(Finger::tap(10.0, 0, 100));
+    }
+    for t in 0..20 {
+        assert_eq!(a.frame_at(t * 10_000), b.frame_at(t * 10_000));
+    }
+}
+
+#[test]
+fn dropped_chip_keeps_stale_values() {
+    let mut synth = SynthTouch::new(SensorModel::new());
+    synth.add_finger(Finger::tap(3.0, 0, 100)); // チップ0
+    let before = synth.frame_at(50_000);
+    assert!(before.values[3] > TOUCH_THRESHOLD);
+
+    synth.model.dropped_chips = 1 << 0;
+    let after = synth.frame_at(200_000); // 指は離れているが、読めないので前の値のまま
+    assert_eq!(
+        after.values[..AT42QT_KEYS_PER_DEVICE],
+        before.values[..AT42QT_KEYS_PER_DEVICE]
+    );
+    assert_eq!(after.timestamps[0], 50_000);
+    assert_eq!(after.timestamps[1], 200_000);
+}
+
+#[test]
+fn last_key_of_each_chip_has_higher_reference() {
+    // ReadTouch と同じく、各チップの最後のキーは 7 だけ小さく出る
+    let mut synth = SynthTouch::new(SensorModel::new());
+    synth.add_finger(Finger::tap(5.0, 0, 100).with_width(20.0));
+    let frame = synth.frame_at(10_000);
+    assert!(frame.values[5] + 7 >= frame.values[4]);
+    assert!(frame.values[5] < frame.values[4]);
+    assert_eq!(frame.values.len(), TOTAL_QT_KEYS);
+}
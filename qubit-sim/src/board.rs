@@ -0,0 +1,358 @@
+//! ホスト向けの hal トレイト実装（ファームウェアの board.rs に対応）
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::poll_fn;
+use std::io;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::task::Poll;
+
+use qubit_core::constants::{
+    MIDI_TX_QUEUE_SIZE, NUM_LEDS, REPORT_QUEUE_SIZE, RINGLED_MESSAGE_SIZE, TOTAL_CH,
+};
+use qubit_core::display::OledBuffer;
+use qubit_core::hal::{
+    Clock, FrameBufferSink, LedEventSink, LedEventSource, MidiQueueSink, MidiQueueSource, MidiSink,
+    MidiSource, PixelSink, Reboot, Report, ReportSink, ReportSource, Rgbw, SettingsStore,
+    SwitchInput, Switches, TouchFrameSource,
+};
+use qubit_core::midi::parser::packetize;
+use qubit_core::midi::tx_queue::{TxBatch, TxMessage, TxQueue};
+use qubit_core::touch::frame::TouchFrame;
+use qubit_core::touch::recording::{self, FrameRecord};
+use qubit_core::touch::synth::SynthTouch;
+use qubit_core::{AD_VALUE0, AD_VALUE1, AD_VALUE2, AD_VALUE3};
+
+use crate::output::{FrameOutput, MidiEvent};
+use crate::script::{Command, Script};
+use crate::sim::SimClock;
+
+// =========================================================
+//      Touch / Switch
+// =========================================================
+/// タッチフレーム: スクリプトの指の位置から作るか、記録を1フレームずつ再生する
+pub struct SimTouch<'a> {
+    clock: &'a SimClock,
+    script: &'a Script,
+    synth: SynthTouch,
+    next_finger: usize,
+    replay: Option<&'a [FrameRecord]>,
+    next: usize,
+    pub recording: Option<Vec<u8>>, // 読んだフレームの記録（.qtr）
+}
+
+impl<'a> SimTouch<'a> {
+    pub fn new(
+        clock: &'a SimClock,
+        script: &'a Script,
+        replay: Option<&'a [FrameRecord]>,
+        record: bool,
+    ) -> Self {
+        Self {
+            clock,
+            script,
+            synth: SynthTouch::new(script.sensor_at(0)),
+            next_finger: 0,
+            replay,
+            next: 0,
+            recording: record.then(|| recording::encode_header().to_vec()),
+        }
+    }
+
+    fn script_frame(&mut self, now: u64) -> TouchFrame {
+        let time_ms = (now / 1000) as u32;
+        self.synth.model = self.script.sensor_at(time_ms as u64);
+        self.synth.remove_released(time_ms);
+        let fingers = self.script.fingers();
+        while let Some(finger) = fingers.get(self.next_finger) {
+            if finger.down_ms > time_ms {
+                break;
+            }
+            // 同時に置ける指の数を超えたら無視する
+            let _ = self.synth.add_finger(finger.clone());
+            self.next_finger += 1;
+        }
+        self.synth.frame_at(now)
+    }
+
+    fn replay_frame(&mut self, records: &[FrameRecord], now: u64) -> TouchFrame {
+        let Some(rec) = records.get(self.next) else {
+            // 記録の終わり: 指を離した状態
+            let mut frame = TouchFrame::new();
+            frame.timestamps = [now; TOTAL_CH];
+            return frame;
+        };
+        self.next += 1;
+        for (v, sample) in [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3]
+            .iter()
+            .zip(rec.adc)
+        {
+            v.store(sample as u32, Ordering::Relaxed);
+        }
+        // チャンネルごとの経過時間は記録の時のまま、今の時刻に合わせる
+        let mut frame = rec.frame;
+        for t in frame.timestamps.iter_mut() {
+            *t = now.saturating_sub(rec.time_us.saturating_sub(*t));
+        }
+        frame
+    }
+}
+
+impl TouchFrameSource for SimTouch<'_> {
+    async fn read_frame(&mut self) -> TouchFrame {
+        let now = self.clock.now_us();
+        let frame = match self.replay {
+            Some(records) => self.replay_frame(records, now),
+            None => self.script_frame(now),
+        };
+        if let Some(data) = &mut self.recording {
+            let adc = [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3]
+                .map(|v| v.load(Ordering::Relaxed).min(u16::MAX as u32) as u16);
+            let rec = FrameRecord {
+                time_us: now,
+                frame,
+                adc,
+            };
+            data.extend_from_slice(&rec.encode());
+        }
+        frame
+    }
+}
+
+pub struct ScriptSwitches<'a> {
+    pub clock: &'a SimClock,
+    pub script: &'a Script,
+}
+
+impl SwitchInput for ScriptSwitches<'_> {
+    fn read(&mut self) -> Switches {
+        let (right, left) = self.script.switches_at(self.clock.now_ms());
+        Switches { right, left }
+    }
+}
+
+// =========================================================
+//      RingLED
+// =========================================================
+/// 最新の LED の状態（OLED と一緒に描画する）
+pub type SharedRing = Rc<RefCell<[Rgbw; NUM_LEDS]>>;
+
+pub struct SimPixels {
+    pub ring: SharedRing,
+}
+
+impl PixelSink for SimPixels {
+    type Error = Infallible;
+
+    async fn write(&mut self, pixels: &[Rgbw; NUM_LEDS]) -> Result<(), Self::Error> {
+        *self.ring.borrow_mut() = *pixels;
+        Ok(())
+    }
+}
+
+/// RINGLED_MESSAGE の代わり（ファームウェアと同じ容量）
+#[derive(Clone, Default)]
+pub struct SimLedQueue {
+    queue: Rc<RefCell<VecDeque<(u8, f32)>>>,
+}
+
+impl LedEventSink for SimLedQueue {
+    fn post(&mut self, cmd: u8, location: f32) -> bool {
+        let mut queue = self.queue.borrow_mut();
+        if queue.len() >= RINGLED_MESSAGE_SIZE {
+            return false;
+        }
+        queue.push_back((cmd, location));
+        true
+    }
+}
+
+impl LedEventSource for SimLedQueue {
+    fn poll(&mut self) -> Option<(u8, f32)> {
+        self.queue.borrow_mut().pop_front()
+    }
+}
+
+// =========================================================
+//      OLED
+// =========================================================
+/// 描画済みのフレームを、その時の RingLED と一緒に出力する
+pub struct SimDisplay<'a> {
+    pub clock: &'a SimClock,
+    pub ring: SharedRing,
+    pub output: FrameOutput,
+    pub error: Option<io::Error>, // 最初の出力エラー
+    spare: Option<OledBuffer>,
+}
+
+impl<'a> SimDisplay<'a> {
+    pub fn new(clock: &'a SimClock, ring: SharedRing, output: FrameOutput) -> Self {
+        Self {
+            clock,
+            ring,
+            output,
+            error: None,
+            spare: None,
+        }
+    }
+}
+
+impl FrameBufferSink for SimDisplay<'_> {
+    async fn acquire(&mut self) -> OledBuffer {
+        self.spare.take().unwrap_or_default()
+    }
+
+    async fn present(&mut self, buffer: OledBuffer) {
+        let ring = *self.ring.borrow();
+        if let Err(e) = self.output.frame(self.clock.now_us(), &buffer, &ring) {
+            self.error.get_or_insert(e);
+        }
+        self.spare = Some(buffer);
+    }
+}
+
+// =========================================================
+//      MIDI
+// =========================================================
+/// 送信された MIDI を時刻付きで記録する（タッチのタスクと、設定の返信で共有する）
+#[derive(Clone)]
+pub struct SimMidiOut<'a> {
+    pub clock: &'a SimClock,
+    pub log: Rc<RefCell<Vec<MidiEvent>>>,
+}
+
+impl MidiSink for SimMidiOut<'_> {
+    type Error = Infallible;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        self.log.borrow_mut().push((self.clock.now_us(), packet));
+        Ok(())
+    }
+}
+
+/// MIDI_TX_QUEUE の代わり（ファームウェアと同じ容量）
+/// 入れたら同じ時刻に midi_tx_task を起こすので、送信の時刻はタッチのタスクと同じになる
+#[derive(Clone)]
+pub struct SimMidiQueue<'a> {
+    pub clock: &'a SimClock,
+    pub queue: Rc<RefCell<TxQueue<MIDI_TX_QUEUE_SIZE>>>,
+}
+
+impl MidiQueueSink for SimMidiQueue<'_> {
+    fn post(&mut self, message: TxMessage) -> bool {
+        self.clock.wake_now();
+        self.queue.borrow_mut().push(message)
+    }
+}
+
+impl MidiQueueSource for SimMidiQueue<'_> {
+    async fn take_batch(&mut self, batch: &mut TxBatch) {
+        poll_fn(|_| {
+            self.queue.borrow_mut().pop_batch(batch);
+            if batch.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+}
+
+/// REPORT_QUEUE の代わり（ファームウェアと同じ容量）
+#[derive(Clone)]
+pub struct SimReportQueue<'a> {
+    pub clock: &'a SimClock,
+    pub queue: Rc<RefCell<VecDeque<Report>>>,
+}
+
+impl ReportSink for SimReportQueue<'_> {
+    fn post(&mut self, report: Report) -> bool {
+        let mut queue = self.queue.borrow_mut();
+        if queue.len() >= REPORT_QUEUE_SIZE {
+            return false;
+        }
+        self.clock.wake_now();
+        queue.push_back(report);
+        true
+    }
+}
+
+impl ReportSource for SimReportQueue<'_> {
+    async fn receive(&mut self) -> Report {
+        poll_fn(|_| match self.queue.borrow_mut().pop_front() {
+            Some(report) => Poll::Ready(report),
+            None => Poll::Pending,
+        })
+        .await
+    }
+}
+
+// =========================================================
+//      Settings
+// =========================================================
+/// フラッシュの代わり: 保存したデータを持っておく
+#[derive(Default)]
+pub struct SimSettingsStore {
+    pub saved: Option<Vec<u8>>,
+    pub rebooted: bool, // ブートローダーへの再起動を要求された
+}
+
+impl SettingsStore for SimSettingsStore {
+    type Error = Infallible;
+
+    async fn save(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.saved = Some(data.to_vec());
+        Ok(())
+    }
+}
+
+impl Reboot for SimSettingsStore {
+    fn reboot_to_bootloader(&mut self) {
+        self.rebooted = true;
+    }
+}
+
+/// スクリプトの midi コマンドを、その時刻に受信する
+pub struct ScriptMidiIn<'a> {
+    clock: &'a SimClock,
+    pending: VecDeque<(u64, [u8; 4])>, // (時刻 ms, USB-MIDI パケット)
+}
+
+impl<'a> ScriptMidiIn<'a> {
+    pub fn new(clock: &'a SimClock, script: &Script) -> Self {
+        let mut pending = VecDeque::new();
+        for e in script.events() {
+            if let Command::Midi(bytes) = &e.cmd {
+                packetize(0, bytes, |packet| pending.push_back((e.time_ms, packet)));
+            }
+        }
+        Self { clock, pending }
+    }
+}
+
+impl MidiSource for ScriptMidiIn<'_> {
+    type Error = Infallible;
+
+    async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let Some(&(time_ms, _)) = self.pending.front() else {
+            // もう受信するものはない
+            return std::future::pending().await;
+        };
+        self.clock.delay_until(time_ms * 1000).await;
+        // 同じ時刻のパケットは、バッファに入るだけまとめて受信する
+        let mut n = 0;
+        while n + 4 <= buf.len() {
+            match self.pending.front() {
+                Some(&(t, packet)) if t == time_ms => {
+                    buf[n..n + 4].copy_from_slice(&packet);
+                    n += 4;
+                    self.pending.pop_front();
+                }
+                _ => break,
+            }
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,186 @@
+//  Created by Hasebe Masahiko on 2026/02/11.
+//  Copyright (c) 2026 Hasebe Masahiko.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+//! Loopian::QUBIT シミュレータ
+//! ファームウェアと同じ qubit_core のタスクを、仮想時間の上で動かす
+pub mod board;
+pub mod output;
+pub mod script;
+pub mod sim;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+use embassy_futures::join::{join, join3, join5};
+
+use qubit_core::constants::{MAX_ADC_CHANNELS, NUM_LEDS};
+use qubit_core::hal::{Clock, Rgbw};
+use qubit_core::midi::tx_queue::TxQueue;
+use qubit_core::settings::PARAMS;
+use qubit_core::touch::pressure::update_pressure;
+use qubit_core::touch::recording::{self, FrameRecord, RecordingReader};
+use qubit_core::ui::oled_display::GraphicsDisplay;
+use qubit_core::{AD_VALUE0, AD_VALUE1, AD_VALUE2, AD_VALUE3, tasks};
+
+use board::{
+    ScriptMidiIn, ScriptSwitches, SimDisplay, SimLedQueue, SimMidiOut, SimMidiQueue,
+    SimReportQueue, SimSettingsStore, SimTouch,
+};
+use board::{SharedRing, SimPixels};
+use output::{FrameOutput, MidiEvent};
+use script::{Command, Script};
+use sim::{SimClock, run_until};
+
+/// タッチの記録の読み込み: .qtr ファイルか、デバイスから受信した SysEx のダンプ（.syx）
+pub fn load_recording(bytes: &[u8]) -> Result<Vec<FrameRecord>, String> {
+    let mut data = Vec::new();
+    let bytes = if bytes.first() == Some(&0xF0) {
+        recording::extract_sysex(bytes, |chunk| data.extend_from_slice(chunk));
+        &data[..]
+    } else {
+        bytes
+    };
+    RecordingReader::new(bytes)
+        .and_then(|reader| reader.collect())
+        .map_err(|e| format!("invalid recording: {:?}", e))
+}
+
+/// シミュレーションの結果
+pub struct SimResult {
+    pub midi: Vec<MidiEvent>,            // 送信された MIDI
+    pub din: Vec<MidiEvent>,             // DIN に送信された MIDI（パケットの形で）
+    pub recording: Option<Vec<u8>>,      // 記録したタッチのデータ（.qtr）
+    pub saved_settings: Option<Vec<u8>>, // SysEx でフラッシュに保存した設定
+}
+
+/// ADC Task と設定変更の代わり: スクリプトの adc / set を反映する
+/// 再生中の ADC の値は、記録から SimTouch が書き込む
+async fn script_task(clock: &SimClock, script: &Script, replaying: bool) {
+    let mut sums = [0u64; MAX_ADC_CHANNELS];
+    let mut adc_counter = 0u32;
+    let mut next_event = 0;
+    let ad_values = [&AD_VALUE0, &AD_VALUE1, &AD_VALUE2, &AD_VALUE3];
+    loop {
+        // ファームウェアは 5ms ごとに 2ch ずつ読むので、4ch 揃うのは 10ms ごと
+        clock.delay_ms(10).await;
+        let now_ms = clock.now_ms();
+
+        let samples = if replaying {
+            ad_values.map(|v| v.load(Ordering::Relaxed))
+        } else {
+            script.adc_at(now_ms)
+        };
+        for (v, sample) in ad_values.iter().zip(samples) {
+            v.store(sample, Ordering::Relaxed);
+        }
+        update_pressure(&samples, &mut sums, adc_counter);
+        adc_counter = adc_counter.wrapping_add(1);
+
+        let events = script.events();
+        while next_event < events.len() && events[next_event].time_ms <= now_ms {
+            // 名前と範囲は Script::parse で確かめてある
+            if let Command::Set { id, value } = events[next_event].cmd {
+                PARAMS[id as usize].set(value);
+            }
+            next_event += 1;
+        }
+    }
+}
+
+/// duration_ms の間、タスクを動かす
+/// replay があればタッチは記録から、なければスクリプトから作る
+/// record なら、タッチのタスクが読んだフレームを記録する
+pub fn simulate(
+    script: &Script,
+    replay: Option<&[FrameRecord]>,
+    duration_ms: u64,
+    output: FrameOutput,
+    record: bool,
+) -> io::Result<SimResult> {
+    let clock = SimClock::new();
+    let ring: SharedRing = Rc::new(RefCell::new([Rgbw::default(); NUM_LEDS]));
+    let leds = SimLedQueue::default();
+
+    let mut touch = SimTouch::new(&clock, script, replay, record);
+    let mut switches = ScriptSwitches {
+        clock: &clock,
+        script,
+    };
+    let midi_out = SimMidiOut {
+        clock: &clock,
+        log: Rc::new(RefCell::new(Vec::new())),
+    };
+    let mut replies = midi_out.clone();
+    let mut clock_out = midi_out.clone();
+    let mut tx_out = midi_out.clone();
+    let mut report_out = midi_out.clone();
+    let mut din_out = SimMidiOut {
+        clock: &clock,
+        log: Rc::new(RefCell::new(Vec::new())),
+    };
+    let mut thru_out = din_out.clone();
+    let mut clock_din = din_out.clone();
+    let mut tx_queue = SimMidiQueue {
+        clock: &clock,
+        queue: Rc::new(RefCell::new(TxQueue::new())),
+    };
+    let mut touch_queue = tx_queue.clone();
+    let mut reports = SimReportQueue {
+        clock: &clock,
+        queue: Rc::new(RefCell::new(VecDeque::new())),
+    };
+    let mut touch_reports = reports.clone();
+    let mut store = SimSettingsStore::default();
+    let mut midi_in = ScriptMidiIn::new(&clock, script);
+    let mut pixels = SimPixels { ring: ring.clone() };
+    let mut display = SimDisplay::new(&clock, ring, output);
+    let mut gui = GraphicsDisplay::new("simulator", env!("CARGO_PKG_VERSION"));
+    let (mut touch_leds, mut rx_leds, mut ring_events) = (leds.clone(), leds.clone(), leds);
+
+    run_until(
+        &clock,
+        duration_ms * 1000,
+        join(
+            join5(
+                tasks::qubit_touch_task(
+                    &mut touch,
+                    &mut touch_reports,
+                    &mut touch_queue,
+                    &mut touch_leds,
+                    &clock,
+                ),
+                tasks::midi_rx_task(
+                    &mut midi_in,
+                    &mut rx_leds,
+                    &mut replies,
+                    &mut thru_out,
+                    &mut store,
+                    &clock,
+                ),
+                tasks::ringled_task(&mut pixels, &mut ring_events, &clock),
+                tasks::oled_ui_task(&mut gui, &mut display, &mut switches, &clock),
+                script_task(&clock, script, replay.is_some()),
+            ),
+            join3(
+                tasks::midi_clock_task(&mut clock_out, &mut clock_din, &clock),
+                tasks::midi_tx_task(&mut tx_queue, &mut tx_out, &mut din_out, &clock),
+                tasks::report_task(&mut reports, &mut report_out),
+            ),
+        ),
+    );
+
+    if let Some(e) = display.error {
+        return Err(e);
+    }
+    Ok(SimResult {
+        midi: midi_out.log.take(),
+        din: din_out.log.take(),
+        recording: touch.recording,
+        saved_settings: store.saved,
+    })
+}
@@ -0,0 +1,116 @@
+//  Created by Hasebe Masahiko on 2026/02/11.
+//  Copyright (c) 2026 Hasebe Masahiko.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+
+use clap::Parser;
+
+use qubit_core::{ERROR_CODE, PRESSURE};
+use qubit_sim::output::{self, FrameOutput};
+use qubit_sim::script::{DEMO_SCRIPT, Script};
+
+#[derive(Parser)]
+#[command(about = "Loopian::QUBIT desktop simulator")]
+struct Args {
+    /// スクリプトファイル（省略時はデモ。--replay のときは空）
+    script: Option<PathBuf>,
+    /// シミュレーションする時間(ms)（省略時はスクリプトか記録の最後 + 1000ms）
+    #[arg(long)]
+    duration: Option<u64>,
+    /// タッチを記録（.qtr）か SysEx のダンプ（.syx）から再生する
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// タッチのタスクが読んだフレームを .qtr に記録する
+    #[arg(long)]
+    record: Option<PathBuf>,
+    /// MIDI のテキストログの出力先（省略時は標準出力）
+    #[arg(long)]
+    midi_log: Option<PathBuf>,
+    /// Standard MIDI File の出力先
+    #[arg(long)]
+    smf: Option<PathBuf>,
+    /// OLED と RingLED を端末に描画する
+    #[arg(long)]
+    terminal: bool,
+    /// OLED と RingLED を PNG で書き出すディレクトリ
+    #[arg(long)]
+    png_dir: Option<PathBuf>,
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let text = match (&args.script, &args.replay) {
+        (Some(path), _) => String::from_utf8_lossy(&read(path)?).into_owned(),
+        (None, Some(_)) => String::new(),
+        (None, None) => DEMO_SCRIPT.to_string(),
+    };
+    let script = Script::parse(&text)?;
+    let replay = match &args.replay {
+        Some(path) => Some(
+            qubit_sim::load_recording(&read(path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    // タッチのタスクは 10ms ごとに1フレーム読む
+    let replay_ms = replay.as_ref().map_or(0, |r| r.len() as u64 * 10);
+    let duration_ms = args
+        .duration
+        .unwrap_or(script.end_ms().max(replay_ms) + 1000);
+    if let Some(dir) = &args.png_dir {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+
+    let output = FrameOutput::new(args.terminal, args.png_dir.clone());
+    let result = qubit_sim::simulate(
+        &script,
+        replay.as_deref(),
+        duration_ms,
+        output,
+        args.record.is_some(),
+    )
+    .map_err(|e| format!("frame output: {}", e))?;
+
+    match &args.midi_log {
+        Some(path) => {
+            let file =
+                std::fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            output::write_midi_log(&mut BufWriter::new(file), &result.midi)
+        }
+        None => output::write_midi_log(&mut io::stdout().lock(), &result.midi),
+    }
+    .map_err(|e| format!("midi log: {}", e))?;
+    if let Some(path) = &args.smf {
+        output::write_smf(path, &result.midi).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if let (Some(path), Some(data)) = (&args.record, &result.recording) {
+        std::fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    eprintln!(
+        "simulated {} ms, {} MIDI events, pressure {}, error code {}",
+        duration_ms,
+        result.midi.len(),
+        PRESSURE.load(Ordering::Relaxed),
+        ERROR_CODE.load(Ordering::Relaxed)
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("qubit-sim: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
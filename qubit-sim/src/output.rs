@@ -0,0 +1,212 @@
+//! シミュレーション結果の出力: MIDI のテキストログ / Standard MIDI File、OLED と RingLED の描画
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use qubit_core::constants::NUM_LEDS;
+use qubit_core::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH, OledBuffer};
+use qubit_core::hal::Rgbw;
+
+/// 送信された MIDI（時刻 us, USB-MIDI パケット）
+pub type MidiEvent = (u64, [u8; 4]);
+
+// =========================================================
+//      MIDI
+// =========================================================
+fn describe(packet: &[u8; 4]) -> String {
+    let status = packet[1];
+    let ch = status & 0x0f;
+    match status & 0xf0 {
+        0x90 if packet[3] > 0 => format!("NoteOn  ch={} note={} vel={}", ch, packet[2], packet[3]),
+        0x90 | 0x80 => format!("NoteOff ch={} note={} vel={}", ch, packet[2], packet[3]),
+        0xb0 => format!("CC      ch={} cc={} val={}", ch, packet[2], packet[3]),
+        _ => String::new(),
+    }
+}
+
+/// 1行1イベントのテキストログ
+pub fn write_midi_log(out: &mut impl Write, events: &[MidiEvent]) -> io::Result<()> {
+    for (time_us, packet) in events {
+        writeln!(
+            out,
+            "{:>9.1} ms  {:02X} {:02X} {:02X} {:02X}  {}",
+            *time_us as f64 / 1000.0,
+            packet[0],
+            packet[1],
+            packet[2],
+            packet[3],
+            describe(packet)
+        )?;
+    }
+    Ok(())
+}
+
+fn push_vlq(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 4];
+    let mut n = 0;
+    loop {
+        bytes[n] = (value & 0x7f) as u8;
+        n += 1;
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    for i in (0..n).rev() {
+        buf.push(bytes[i] | if i > 0 { 0x80 } else { 0 });
+    }
+}
+
+/// Standard MIDI File (format 0) として書き出す
+/// 分解能 500tick/四分音符、テンポ 120 なので 1tick = 1ms
+pub fn write_smf(path: &Path, events: &[MidiEvent]) -> io::Result<()> {
+    const TICKS_PER_QUARTER: u16 = 500;
+    let mut track = Vec::new();
+    // Tempo: 500000us/四分音符
+    track.extend_from_slice(&[0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20]);
+    let mut last_ms = 0u64;
+    for (time_us, packet) in events {
+        let len = match packet[1] & 0xf0 {
+            0x80 | 0x90 | 0xa0 | 0xb0 | 0xe0 => 3,
+            0xc0 | 0xd0 => 2,
+            _ => continue, // チャンネルメッセージ以外は書かない
+        };
+        let ms = time_us / 1000;
+        push_vlq(&mut track, (ms - last_ms) as u32);
+        last_ms = ms;
+        track.extend_from_slice(&packet[1..=len]);
+    }
+    track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]); // End of track
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"MThd")?;
+    out.write_all(&6u32.to_be_bytes())?;
+    out.write_all(&0u16.to_be_bytes())?; // format 0
+    out.write_all(&1u16.to_be_bytes())?; // 1 track
+    out.write_all(&TICKS_PER_QUARTER.to_be_bytes())?;
+    out.write_all(b"MTrk")?;
+    out.write_all(&(track.len() as u32).to_be_bytes())?;
+    out.write_all(&track)?;
+    out.flush()
+}
+
+// =========================================================
+//      OLED / RingLED
+// =========================================================
+fn pixel(buffer: &OledBuffer, x: usize, y: usize) -> bool {
+    buffer.data[x + (y / 8) * DISPLAY_WIDTH] & (1 << (y % 8)) != 0
+}
+
+/// LED の値は暗いので、見やすいように明るくする
+fn led_rgb(led: &Rgbw) -> [u8; 3] {
+    const GAIN: u16 = 3;
+    let c = |v: u8| ((v as u16 + led.w as u16) * GAIN).min(255) as u8;
+    [c(led.r), c(led.g), c(led.b)]
+}
+
+/// 描画先
+pub struct FrameOutput {
+    pub terminal: bool,
+    pub png_dir: Option<PathBuf>,
+    count: usize,
+}
+
+impl FrameOutput {
+    pub fn new(terminal: bool, png_dir: Option<PathBuf>) -> Self {
+        Self {
+            terminal,
+            png_dir,
+            count: 0,
+        }
+    }
+
+    pub fn frame(
+        &mut self,
+        time_us: u64,
+        oled: &OledBuffer,
+        ring: &[Rgbw; NUM_LEDS],
+    ) -> io::Result<()> {
+        if self.terminal {
+            let mut out = io::stdout().lock();
+            write_terminal(&mut out, time_us, oled, ring)?;
+        }
+        if let Some(dir) = &self.png_dir {
+            let path = dir.join(format!("frame_{:05}.png", self.count));
+            write_png(&path, oled, ring)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// 上下2ピクセルを1文字にして表示する。RingLED は 24bit カラーの1行
+fn write_terminal(
+    out: &mut impl Write,
+    time_us: u64,
+    oled: &OledBuffer,
+    ring: &[Rgbw; NUM_LEDS],
+) -> io::Result<()> {
+    writeln!(out, "--- {:.1} ms", time_us as f64 / 1000.0)?;
+    for y in (0..DISPLAY_HEIGHT).step_by(2) {
+        let line: String = (0..DISPLAY_WIDTH)
+            .map(|x| match (pixel(oled, x, y), pixel(oled, x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            })
+            .collect();
+        writeln!(out, "|{}|", line)?;
+    }
+    for led in ring.iter() {
+        let [r, g, b] = led_rgb(led);
+        write!(out, "\x1b[38;2;{};{};{}m●", r, g, b)?;
+    }
+    writeln!(out, "\x1b[0m")
+}
+
+/// 左に OLED（3倍）、右に RingLED を円形に並べた画像
+fn write_png(path: &Path, oled: &OledBuffer, ring: &[Rgbw; NUM_LEDS]) -> io::Result<()> {
+    const SCALE: usize = 3;
+    const OLED_W: usize = DISPLAY_WIDTH * SCALE;
+    const HEIGHT: usize = DISPLAY_HEIGHT * SCALE;
+    const WIDTH: usize = OLED_W + HEIGHT;
+    let mut image = vec![0u8; WIDTH * HEIGHT * 3];
+    let mut put = |x: usize, y: usize, rgb: [u8; 3]| {
+        if x < WIDTH && y < HEIGHT {
+            let i = (y * WIDTH + x) * 3;
+            image[i..i + 3].copy_from_slice(&rgb);
+        }
+    };
+
+    for y in 0..HEIGHT {
+        for x in 0..OLED_W {
+            if pixel(oled, x / SCALE, y / SCALE) {
+                put(x, y, [0xd0, 0xe8, 0xff]);
+            }
+        }
+    }
+
+    // LED 0 を真上にして時計回り
+    let center = (OLED_W + HEIGHT / 2) as f32;
+    let middle = (HEIGHT / 2) as f32;
+    let radius = HEIGHT as f32 * 0.42;
+    for (i, led) in ring.iter().enumerate() {
+        let angle = i as f32 / NUM_LEDS as f32 * std::f32::consts::TAU;
+        let cx = center + radius * angle.sin();
+        let cy = middle - radius * angle.cos();
+        let rgb = led_rgb(led);
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                put((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, rgb);
+            }
+        }
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&image).map_err(io::Error::other)
+}
@@ -0,0 +1,354 @@
+//! シミュレータのスクリプト
+//!
+//! 1行に1イベント: `時刻(ms) コマンド 引数...`（# 以降はコメント）
+//!
+//!   touch   <指> <位置> <強さ>   指を置く（位置はキー番号 0-95、小数可）
+//!   slide   <指> <位置> <時間ms> 置いている指を滑らせる
+//!   release <指>                 指を離す
+//!   switch  right|left|both [ms] スイッチを押す（省略時は 150ms で離す）
+//!   midi    <byte> ...           MIDI を受信する（16進。SysEx やリアルタイムメッセージも可）
+//!   adc     <A0> <A1> <B0> <B1>  圧力センサの ADC 値
+//!   set     <名前> <値>          設定値を変更する（OLED の設定画面の項目名か ID。範囲外はエラー）
+//!   loopian loop <長さ> <位置> <拍> | part <パート> | phrase <パート> <名前>
+//!                                Loopian の状態の SysEx を受信する（qubit_core::midi::loopian）
+//!   oled    text <行> <文字列...> | icon <番号> <アイコン> <x> <y> | clear [秒]
+//!                                ホストの OLED のページの SysEx を受信する（qubit_core::ui::host_page）
+//!   sensor  noise <±値> | drift <値/秒> | crosstalk <割合> | drop <ch> | restore <ch>
+//!                                センサのモデルを変える（qubit_core::touch::synth）
+//!
+//! 位置は 0 未満や 96 以上も書ける（slide で継ぎ目を越える）
+use qubit_core::constants::{MAX_ADC_CHANNELS, TOTAL_CH};
+use qubit_core::midi::loopian;
+use qubit_core::settings;
+use qubit_core::touch::synth::{Finger, MAX_FINGERS, SensorModel};
+use qubit_core::ui::host_page::{self, Icon};
+
+const SWITCH_HOLD_MS: u64 = 150;
+const ADC_IDLE: u32 = 2000; // 何も押していないときの ADC 値
+
+/// スクリプトを指定しないときのデモ
+pub const DEMO_SCRIPT: &str = "\
+# 1本指で押して滑らせる
+200   touch   0 30.0 90
+700   slide   0 42.0 600
+1600  release 0
+# 2本指の和音
+2000  touch   0 20.0 80
+2000  touch   1 60.0 80
+2600  release 0
+2600  release 1
+# MIDI 受信（RingLED に表示される）
+3000  midi    90 3C 64
+3500  midi    80 3C 40
+# 圧力
+3800  adc     1700 1700 1800 1800
+4300  adc     2000 2000 2000 2000
+# ページ送り
+4500  switch  right
+5000  switch  right
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Touch {
+        finger: usize,
+        pos: f32,
+        strength: f32,
+    },
+    Slide {
+        finger: usize,
+        to: f32,
+        dur_ms: u64,
+    },
+    Release {
+        finger: usize,
+    },
+    Switch {
+        right: bool,
+        left: bool,
+        hold_ms: u64,
+    },
+    Midi(Vec<u8>),
+    Adc([u32; MAX_ADC_CHANNELS]),
+    Set {
+        id: u8, // settings::PARAMS の添字
+        value: u8,
+    },
+    Sensor(SensorChange),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SensorChange {
+    Noise(u16),
+    Drift(f32),
+    Crosstalk(f32),
+    Drop(usize),
+    Restore(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub time_ms: u64,
+    pub cmd: Command,
+}
+
+pub struct Script {
+    events: Vec<Event>,   // 時刻順
+    fingers: Vec<Finger>, // touch / slide / release から作った指の動き（置いた順）
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let event = parse_line(line).map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+            events.push(event);
+        }
+        events.sort_by_key(|e| e.time_ms);
+        let fingers = fingers_of(&events)?;
+        Ok(Self { events, fingers })
+    }
+
+    /// 最後のイベントの時刻
+    pub fn end_ms(&self) -> u64 {
+        self.events.last().map(|e| e.time_ms).unwrap_or(0)
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn fingers(&self) -> &[Finger] {
+        &self.fingers
+    }
+
+    /// time_ms までの sensor コマンドを反映したセンサのモデル
+    pub fn sensor_at(&self, time_ms: u64) -> SensorModel {
+        let mut model = SensorModel::new();
+        for event in self.events.iter().take_while(|e| e.time_ms <= time_ms) {
+            if let Command::Sensor(change) = &event.cmd {
+                match *change {
+                    SensorChange::Noise(noise) => model.noise = noise,
+                    SensorChange::Drift(drift) => model.drift_per_s = drift,
+                    SensorChange::Crosstalk(ratio) => model.crosstalk = ratio,
+                    SensorChange::Drop(ch) => model.dropped_chips |= 1 << ch,
+                    SensorChange::Restore(ch) => model.dropped_chips &= !(1 << ch),
+                }
+            }
+        }
+        model
+    }
+
+    /// (右, 左) のスイッチが押されているか
+    pub fn switches_at(&self, time_ms: u64) -> (bool, bool) {
+        let mut state = (false, false);
+        for event in self.events.iter().take_while(|e| e.time_ms <= time_ms) {
+            if let Command::Switch {
+                right,
+                left,
+                hold_ms,
+            } = event.cmd
+                && time_ms < event.time_ms + hold_ms
+            {
+                state = (state.0 || right, state.1 || left);
+            }
+        }
+        state
+    }
+
+    pub fn adc_at(&self, time_ms: u64) -> [u32; MAX_ADC_CHANNELS] {
+        self.events
+            .iter()
+            .take_while(|e| e.time_ms <= time_ms)
+            .filter_map(|e| match e.cmd {
+                Command::Adc(samples) => Some(samples),
+                _ => None,
+            })
+            .last()
+            .unwrap_or([ADC_IDLE; MAX_ADC_CHANNELS])
+    }
+}
+
+/// 指ごとに、置いてから離すまでの動きをまとめる
+fn fingers_of(events: &[Event]) -> Result<Vec<Finger>, String> {
+    let mut fingers: Vec<Finger> = Vec::new();
+    let mut active: [Option<usize>; MAX_FINGERS] = [None; MAX_FINGERS]; // 指番号 → fingers の添字
+    for event in events {
+        let time = event.time_ms as u32;
+        match event.cmd {
+            Command::Touch {
+                finger,
+                pos,
+                strength,
+            } => {
+                if let Some(i) = active[finger] {
+                    fingers[i].up_ms = time; // 置き直し
+                }
+                active[finger] = Some(fingers.len());
+                fingers.push(Finger::tap(pos, time, u32::MAX - time).with_amplitude(strength));
+            }
+            Command::Slide { finger, to, dur_ms } => {
+                let Some(i) = active[finger] else {
+                    return Err(format!(
+                        "{} ms: slide of finger {} that is not touching",
+                        time, finger
+                    ));
+                };
+                let f = &mut fingers[i];
+                let from = f.position_at(time).unwrap_or(to);
+                f.path.retain(|k| k.0 < time);
+                if f.path.push((time, from)).is_err()
+                    || f.path.push((time + dur_ms.max(1) as u32, to)).is_err()
+                {
+                    return Err(format!("{} ms: too many slides of finger {}", time, finger));
+                }
+            }
+            Command::Release { finger } => {
+                if let Some(i) = active[finger].take() {
+                    fingers[i].up_ms = time;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(fingers)
+}
+
+fn parse_line(line: &str) -> Result<Event, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let arg = |i: usize| -> Result<&str, String> {
+        words
+            .get(i)
+            .copied()
+            .ok_or_else(|| format!("missing argument for '{}'", words[1]))
+    };
+    let num = |i: usize| -> Result<f32, String> {
+        let s = arg(i)?;
+        s.parse().map_err(|_| format!("invalid number '{}'", s))
+    };
+    let int = |i: usize| -> Result<u64, String> {
+        let s = arg(i)?;
+        s.parse().map_err(|_| format!("invalid integer '{}'", s))
+    };
+    let hex = |i: usize| -> Result<u8, String> {
+        let s = arg(i)?;
+        u8::from_str_radix(s, 16).map_err(|_| format!("invalid hex byte '{}'", s))
+    };
+    let finger = |i: usize| -> Result<usize, String> {
+        let f = int(i)? as usize;
+        if f < MAX_FINGERS {
+            Ok(f)
+        } else {
+            Err(format!("finger must be 0-{}", MAX_FINGERS - 1))
+        }
+    };
+    let chip = |i: usize| -> Result<usize, String> {
+        let ch = int(i)? as usize;
+        if ch < TOTAL_CH {
+            Ok(ch)
+        } else {
+            Err(format!("chip must be 0-{}", TOTAL_CH - 1))
+        }
+    };
+
+    let time_ms = words[0]
+        .parse()
+        .map_err(|_| format!("invalid time '{}'", words[0]))?;
+    let cmd = match arg(1)? {
+        "touch" => Command::Touch {
+            finger: finger(2)?,
+            pos: num(3)?,
+            strength: num(4)?,
+        },
+        "slide" => Command::Slide {
+            finger: finger(2)?,
+            to: num(3)?,
+            dur_ms: int(4)?,
+        },
+        "release" => Command::Release { finger: finger(2)? },
+        "switch" => {
+            let (right, left) = match arg(2)? {
+                "right" => (true, false),
+                "left" => (false, true),
+                "both" => (true, true),
+                other => return Err(format!("unknown switch '{}'", other)),
+            };
+            let hold_ms = if words.len() > 3 {
+                int(3)?
+            } else {
+                SWITCH_HOLD_MS
+            };
+            Command::Switch {
+                right,
+                left,
+                hold_ms,
+            }
+        }
+        "midi" => Command::Midi((2..words.len().max(3)).map(hex).collect::<Result<_, _>>()?),
+        "adc" => Command::Adc([
+            int(2)? as u32,
+            int(3)? as u32,
+            int(4)? as u32,
+            int(5)? as u32,
+        ]),
+        "set" => {
+            let name = arg(2)?;
+            let (id, param) =
+                settings::find_param(name).ok_or_else(|| format!("unknown setting '{}'", name))?;
+            let value = int(3)?;
+            if value < param.min as u64 || value > param.max as u64 {
+                return Err(format!(
+                    "{} must be {}-{}",
+                    param.name, param.min, param.max
+                ));
+            }
+            Command::Set {
+                id,
+                value: value as u8,
+            }
+        }
+        "sensor" => Command::Sensor(match arg(2)? {
+            "noise" => SensorChange::Noise(int(3)? as u16),
+            "drift" => SensorChange::Drift(num(3)?),
+            "crosstalk" => SensorChange::Crosstalk(num(3)?),
+            "drop" => SensorChange::Drop(chip(3)?),
+            "restore" => SensorChange::Restore(chip(3)?),
+            other => return Err(format!("unknown sensor setting '{}'", other)),
+        }),
+        "loopian" => Command::Midi(
+            match arg(2)? {
+                "loop" => loopian::loop_sysex(int(3)? as u32, int(4)? as u32, int(5)? as u8),
+                "part" => loopian::part_sysex(int(3)? as u8),
+                "phrase" => {
+                    loopian::phrase_sysex(int(3)? as u8, words.get(4).copied().unwrap_or(""))
+                }
+                other => return Err(format!("unknown loopian message '{}'", other)),
+            }
+            .to_vec(),
+        ),
+        "oled" => Command::Midi(
+            match arg(2)? {
+                "text" => {
+                    host_page::text_sysex(int(3)? as u8, &words[4.min(words.len())..].join(" "))
+                }
+                "icon" => host_page::icon_sysex(
+                    int(3)? as u8,
+                    Icon::from_u8(int(4)? as u8),
+                    int(5)? as u8,
+                    int(6)? as u8,
+                ),
+                "clear" => host_page::clear_sysex(
+                    words.get(3).map(|_| int(3)).transpose()?.map(|s| s as u8),
+                ),
+                other => return Err(format!("unknown oled message '{}'", other)),
+            }
+            .to_vec(),
+        ),
+        other => return Err(format!("unknown command '{}'", other)),
+    };
+    Ok(Event { time_ms, cmd })
+}
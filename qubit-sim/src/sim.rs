@@ -0,0 +1,76 @@
+use std::cell::Cell;
+use std::future::{Future, poll_fn};
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use qubit_core::hal::Clock;
+
+// =========================================================
+//      SimClock Class
+// =========================================================
+/// 仮想時間のクロック
+/// 全てのタスクが待ちに入ったら、次に起きるタスクの時刻まで一気に時間を進める
+pub struct SimClock {
+    now: Cell<u64>,       // us
+    next_wake: Cell<u64>, // 待っているタスクの中で最も早い起床時刻
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        Self {
+            now: Cell::new(0),
+            next_wake: Cell::new(u64::MAX),
+        }
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        self.now.get() / 1000
+    }
+
+    /// 時間を進めずに、同じ時刻でもう一度タスクを動かす（キューに何か入れたとき）
+    pub fn wake_now(&self) {
+        self.next_wake.set(self.now.get());
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SimClock {
+    fn now_us(&self) -> u64 {
+        self.now.get()
+    }
+
+    async fn delay_until(&self, deadline_us: u64) {
+        poll_fn(|_| {
+            if self.now.get() >= deadline_us {
+                Poll::Ready(())
+            } else {
+                self.next_wake.set(self.next_wake.get().min(deadline_us));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// end_us まで仮想時間を進めながら future を動かす
+/// タスクは時間待ちと、wake_now で起こされる待ち以外では止まらない前提（止まったらそこで終わる）
+pub fn run_until<F: Future>(clock: &SimClock, end_us: u64, future: F) {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        clock.next_wake.set(u64::MAX);
+        if future.as_mut().poll(&mut cx).is_ready() {
+            return;
+        }
+        let next = clock.next_wake.get();
+        if next == u64::MAX || next > end_us {
+            return;
+        }
+        clock.now.set(next);
+    }
+}
@@ -0,0 +1,54 @@
+//! 内部クロック: Clock が Int のとき、テンポどおりに Clock（F8）を送る
+use qubit_sim::output::FrameOutput;
+use qubit_sim::script::Script;
+use qubit_sim::simulate;
+
+// 設定値はグローバルなので、1つのテストで続けて確かめる
+#[test]
+fn sends_clock_at_tempo() {
+    // 120BPM で 1秒（2拍）、SysEx（0x0A）でスタートしてからストップ
+    let script = Script::parse(
+        "0    set  Clock 1
+         0    set  Tempo 120
+         100  midi F0 7D 09 24 10 6D 0A 01 F7
+         1100 midi F0 7D 09 24 10 6D 0A 00 F7
+         1500 set  Clock 0",
+    )
+    .unwrap();
+    let result = simulate(&script, None, 2000, FrameOutput::new(false, None), false).unwrap();
+    let realtime: Vec<(u64, u8)> = result
+        .midi
+        .iter()
+        .filter(|(_, p)| p[0] == 0x0F)
+        .map(|(t, p)| (*t, p[1]))
+        .collect();
+
+    let start = realtime.iter().position(|(_, s)| *s == 0xFA).unwrap();
+    let stop = realtime.iter().position(|(_, s)| *s == 0xFC).unwrap();
+    let clocks = realtime[start..stop].iter().filter(|(_, s)| *s == 0xF8);
+    assert!((47..=49).contains(&clocks.count()));
+    // Int の間だけ送る（Ext にした後は、待っていた1つで止まる）
+    let (last_us, _) = realtime.last().unwrap();
+    assert!(*last_us < 1_500_000 + 20_834);
+    // Out が USB なので DIN には送らない
+    assert!(result.din.is_empty());
+
+    // Out が DIN: コンピュータなしで、DIN のシンセを内部クロックで動かす
+    let script = Script::parse(
+        "0    set  Out 1
+         0    set  Clock 1
+         0    set  Tempo 120
+         100  midi F0 7D 09 24 10 6D 0A 01 F7
+         600  midi F0 7D 09 24 10 6D 0A 00 F7
+         700  set  Clock 0
+         750  set  Out 0",
+    )
+    .unwrap();
+    let result = simulate(&script, None, 800, FrameOutput::new(false, None), false).unwrap();
+    assert!(!result.midi.iter().any(|(_, p)| p[0] == 0x0F));
+    let statuses: Vec<u8> = result.din.iter().map(|(_, p)| p[1]).collect();
+    let start = statuses.iter().position(|s| *s == 0xFA).unwrap();
+    let stop = statuses.iter().position(|s| *s == 0xFC).unwrap();
+    let clocks = statuses[start..stop].iter().filter(|s| **s == 0xF8);
+    assert!((23..=25).contains(&clocks.count()));
+}
@@ -0,0 +1,57 @@
+//! DIN MIDI のルーティング: タッチの送信先（Out）と USB → DIN のスルー（UsbThru）
+use qubit_sim::output::{FrameOutput, MidiEvent};
+use qubit_sim::script::Script;
+use qubit_sim::simulate;
+
+fn statuses(events: &[MidiEvent]) -> Vec<u8> {
+    events.iter().map(|(_, p)| p[1]).collect()
+}
+
+// 設定値はグローバルなので、1つのテストで続けて確かめる
+#[test]
+fn routes_touch_and_thru() {
+    let script = Script::parse(
+        "0    set   Out 2
+         100  touch 0 40.0 90
+         300  release 0
+         400  set   Out 1
+         500  touch 0 40.0 90
+         700  release 0
+         800  set   Out 0
+         800  set   UsbThru 1
+         900  midi  90 3C 64
+         900  midi  F0 7D 09 24 10 6D 01 F7
+         1000 set   UsbThru 0",
+    )
+    .unwrap();
+    let result = simulate(&script, None, 1100, FrameOutput::new(false, None), false).unwrap();
+
+    // Both: USB と DIN に同じもの。DIN だけのときは USB に送らない
+    let usb_notes: Vec<&MidiEvent> = result
+        .midi
+        .iter()
+        .filter(|(_, p)| matches!(p[0], 0x08 | 0x09))
+        .collect();
+    assert_eq!(usb_notes.len(), 2);
+    assert!(usb_notes.iter().all(|(t, _)| *t < 400_000));
+    let din_notes: Vec<MidiEvent> = result
+        .din
+        .iter()
+        .filter(|(t, _)| *t < 800_000)
+        .copied()
+        .collect();
+    assert_eq!(statuses(&din_notes), vec![0x9C, 0x8C, 0x9C, 0x8C]);
+    assert_eq!(
+        din_notes[..2],
+        usb_notes.iter().map(|e| **e).collect::<Vec<_>>()[..]
+    );
+
+    // UsbThru: 受信した Note On は DIN に流し、設定の SysEx は流さない
+    let thru: Vec<MidiEvent> = result
+        .din
+        .iter()
+        .filter(|(t, _)| *t >= 800_000)
+        .copied()
+        .collect();
+    assert_eq!(thru, vec![(900_000, [0x09, 0x90, 0x3C, 0x64])]);
+}
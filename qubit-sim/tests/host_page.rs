@@ -0,0 +1,30 @@
+//! ホストの代わりに tests/data/host_page.sim で OLED のページの SysEx を送る
+use qubit_core::ui::host_page::{self, HostPage, Icon};
+use qubit_sim::output::FrameOutput;
+use qubit_sim::script::Script;
+use qubit_sim::simulate;
+
+#[test]
+fn host_page_fixture() {
+    let script = Script::parse(include_str!("data/host_page.sim")).unwrap();
+    let result = simulate(&script, None, 8000, FrameOutput::new(false, None), false).unwrap();
+
+    // 返信はしない（設定のコマンドとして NAK も返さない）
+    assert!(
+        result
+            .midi
+            .iter()
+            .all(|(_, p)| !(0x04..=0x07).contains(&p[0])),
+        "{:?}",
+        result.midi
+    );
+
+    let page = HostPage::load();
+    assert_eq!(page.lines[0], "Scene: Verse B");
+    assert_eq!(page.lines[2], "Cutoff 88");
+    assert_eq!(page.lines[3], "Reso 40");
+    assert_eq!(page.icons[1].map(|at| at.icon), Some(Icon::Loop));
+    assert_eq!(host_page::received_us(), 4_000_000);
+    assert!(!host_page::is_active(8_000_000));
+    assert_eq!(host_page::dropped_writes(), 0);
+}
@@ -0,0 +1,30 @@
+//! Loopian の代わりに tests/data/loopian.sim で状態の SysEx を送る
+use qubit_core::midi::loopian::{self, LoopianState};
+use qubit_sim::output::FrameOutput;
+use qubit_sim::script::Script;
+use qubit_sim::simulate;
+
+#[test]
+fn loopian_fixture() {
+    let script = Script::parse(include_str!("data/loopian.sim")).unwrap();
+    let result = simulate(&script, None, 6700, FrameOutput::new(false, None), false).unwrap();
+
+    // 返信はしない（設定のコマンドとして NAK も返さない）
+    let replies: Vec<_> = result
+        .midi
+        .iter()
+        .filter(|(_, p)| (0x04..=0x07).contains(&p[0]))
+        .collect();
+    assert!(replies.iter().all(|(t, _)| *t < 2_100_000), "{:?}", replies); // スタートの ACK だけ
+
+    let state = LoopianState::load();
+    assert_eq!(
+        (state.length, state.position, state.beat, state.part),
+        (192, 24, 1, 1)
+    );
+    assert_eq!(state.received_us, 6_500_000);
+    let names: Vec<String> = (0..4)
+        .map(|p| loopian::phrase_name(p).to_string())
+        .collect();
+    assert_eq!(names, ["Intro", "Bridge", "Chorus", "Outro"]);
+}
@@ -0,0 +1,120 @@
+//! タッチの記録の再生: 同じフレームからは同じ MIDI が出ること
+//!
+//! tests/data/*.qtr を再生して、同じ名前の .txt（MIDI のテキストログ）と比べる
+//! 実機で記録したデータもここに置けば回帰テストになる
+//! 期待値の更新: UPDATE_FIXTURES=1 cargo test
+use std::path::Path;
+use std::sync::Mutex;
+
+use qubit_core::touch::recording::FrameRecord;
+use qubit_sim::output::{FrameOutput, MidiEvent, write_midi_log};
+use qubit_sim::script::{DEMO_SCRIPT, Script};
+use qubit_sim::{SimResult, load_recording, simulate};
+
+// 設定値はグローバルなので、シミュレーションは1つずつ動かす
+static SIM_LOCK: Mutex<()> = Mutex::new(());
+
+fn run(
+    script: &Script,
+    replay: Option<&[FrameRecord]>,
+    duration_ms: u64,
+    record: bool,
+) -> SimResult {
+    let _lock = SIM_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    simulate(
+        script,
+        replay,
+        duration_ms,
+        FrameOutput::new(false, None),
+        record,
+    )
+    .unwrap()
+}
+
+/// チャンネルメッセージだけ（記録中の SysEx を除く）
+fn channel_messages(midi: &[MidiEvent]) -> Vec<MidiEvent> {
+    midi.iter().filter(|(_, p)| p[0] >= 0x8).copied().collect()
+}
+
+fn log_text(midi: &[MidiEvent]) -> String {
+    let mut out = Vec::new();
+    write_midi_log(&mut out, midi).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn record_then_replay_is_identical() {
+    let script = Script::parse(DEMO_SCRIPT).unwrap();
+    let duration_ms = script.end_ms() + 1000;
+    let recorded = run(&script, None, duration_ms, true);
+    let records = load_recording(&recorded.recording.unwrap()).unwrap();
+    assert!(!records.is_empty());
+
+    // タッチ以外（受信 MIDI・スイッチ）は空のスクリプトで再生する
+    let empty = Script::parse("").unwrap();
+    let replayed = run(&empty, Some(&records), duration_ms, false);
+    assert!(!recorded.midi.is_empty());
+    assert_eq!(replayed.midi, recorded.midi);
+}
+
+#[test]
+fn sysex_capture_can_be_replayed() {
+    let script = Script::parse(
+        "0    set   Record 1
+         100  touch 0 50.0 90
+         400  release 0
+         500  set   Record 0",
+    )
+    .unwrap();
+    let captured = run(&script, None, 700, false);
+
+    // USB-MIDI のパケットから SysEx のバイト列（.syx）を作る
+    let mut syx = Vec::new();
+    for (_, packet) in captured.midi.iter() {
+        let len = match packet[0] {
+            0x4 | 0x7 => 3,
+            0x6 => 2,
+            0x5 => 1,
+            _ => 0,
+        };
+        syx.extend_from_slice(&packet[1..=len]);
+    }
+    let records = load_recording(&syx).unwrap();
+    assert!(records.len() >= 40);
+
+    let empty = Script::parse("").unwrap();
+    let replayed = run(&empty, Some(&records), 700, false);
+    let notes = channel_messages(&captured.midi);
+    assert_eq!(notes.len(), 2); // Note On / Off
+    // 記録は Record 1 になった後の最初のフレームから始まるので、時刻はずれる
+    let strip = |m: &[MidiEvent]| m.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+    assert_eq!(strip(&replayed.midi), strip(&notes));
+}
+
+#[test]
+fn fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    let empty = Script::parse("").unwrap();
+    let mut count = 0;
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "qtr") {
+            continue;
+        }
+        let records = load_recording(&std::fs::read(&path).unwrap()).unwrap();
+        let duration_ms = records.len() as u64 * 10 + 1000;
+        let result = run(&empty, Some(&records), duration_ms, false);
+        let actual = log_text(&result.midi);
+
+        let expected_path = path.with_extension("txt");
+        if update {
+            std::fs::write(&expected_path, &actual).unwrap();
+        } else {
+            let expected = std::fs::read_to_string(&expected_path).unwrap();
+            assert_eq!(actual, expected, "{}", path.display());
+        }
+        count += 1;
+    }
+    assert!(count > 0);
+}
@@ -0,0 +1,27 @@
+//! スクリプトの解析
+use qubit_core::settings::find_param;
+use qubit_sim::script::{Command, Script};
+
+#[test]
+fn set_is_checked_when_parsed() {
+    let script = Script::parse("0 set Tempo 120\n10 set tempo 90").unwrap();
+    let (id, _) = find_param("Tempo").unwrap();
+    let values: Vec<_> = script.events().iter().map(|e| e.cmd.clone()).collect();
+    assert_eq!(
+        values,
+        [
+            Command::Set { id, value: 120 },
+            Command::Set { id, value: 90 }
+        ]
+    );
+
+    // 知らない名前と範囲外の値は、行番号つきのエラー
+    let err = Script::parse("0 set Tempo 120\n10 set Nothing 1")
+        .err()
+        .unwrap();
+    assert_eq!(err, "line 2: unknown setting 'Nothing'");
+    let err = Script::parse("0 set Tempo 1000").err().unwrap();
+    assert!(err.starts_with("line 1: Tempo must be "), "{}", err);
+    // 予約の ID も知らない名前として扱う
+    assert!(Script::parse("0 set 16 1").is_err());
+}
@@ -0,0 +1,480 @@
+//! RP2350 (XIAO) 向けの hal トレイト実装
+//! qubit_core のタスクには、ここの型を渡して動かす
+use core::fmt::Write as _;
+
+use embassy_rp::flash::{Blocking, ERASE_SIZE, Error as FlashError, Flash};
+use embassy_rp::gpio::Input;
+use embassy_rp::peripherals::{FLASH, PIO0, USB};
+use embassy_rp::pio_programs::ws2812::RgbwPioWs2812;
+use embassy_rp::uart::{BufferedUartRx, BufferedUartTx, Error as UartError};
+use embassy_rp::usb::Driver;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant, TimeoutError, Timer, with_timeout};
+use embassy_usb::class::cdc_acm;
+use embassy_usb::class::midi::{Receiver, Sender};
+use embassy_usb::driver::EndpointError;
+use embedded_io_async::{Read, Write};
+use heapless::String;
+use portable_atomic::Ordering;
+use smart_leds::{RGBW, White};
+
+use qubit_core::console;
+use qubit_core::constants::{LOG_LINE_SIZE, MIDI_TX_BATCH, NUM_LEDS};
+use qubit_core::display::OledBuffer;
+use qubit_core::hal::{
+    Clock, FrameBufferSink, LedEventSink, LedEventSource, MidiQueueSink, MidiQueueSource, MidiSink,
+    MidiSource, PixelSink, Reboot, Report, ReportSink, ReportSource, Rgbw, SerialSink,
+    SerialSource, SettingsStore, SwitchInput, Switches, TouchFrameSource,
+};
+use qubit_core::midi::din::DinMidiOut;
+use qubit_core::midi::tx_queue::{TxBatch, TxMessage};
+use qubit_core::settings;
+use qubit_core::touch::frame::TouchFrame;
+
+use crate::{
+    BUFFER_FROM_DISPLAY, BUFFER_TO_DISPLAY, LOG_PIPE, MIDI_TX_QUEUE, MIDI_TX_SIGNAL, REPORT_QUEUE,
+    RINGLED_MESSAGE, TOUCH_RAW_DATA,
+};
+
+// =========================================================
+//      Clock
+// =========================================================
+/// embassy-time のタイマー
+pub struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now_us(&self) -> u64 {
+        Instant::now().as_micros()
+    }
+
+    async fn delay_until(&self, deadline_us: u64) {
+        Timer::at(Instant::from_micros(deadline_us)).await;
+    }
+}
+
+// =========================================================
+//      Touch
+// =========================================================
+/// Core1 の core1_i2c_task が TOUCH_RAW_DATA に書き込んだ最新のフレーム
+pub struct RawTouchSource;
+
+impl TouchFrameSource for RawTouchSource {
+    async fn read_frame(&mut self) -> TouchFrame {
+        // ロック保持時間を最小化するため、コピーしてすぐに解放する
+        *TOUCH_RAW_DATA.lock().await
+    }
+}
+
+// =========================================================
+//      RingLED
+// =========================================================
+/// PIO で駆動する RGBW NeoPixel
+pub struct Ws2812Ring {
+    ws2812: RgbwPioWs2812<'static, PIO0, 0, NUM_LEDS>,
+    data: [RGBW<u8>; NUM_LEDS],
+}
+
+impl Ws2812Ring {
+    pub fn new(ws2812: RgbwPioWs2812<'static, PIO0, 0, NUM_LEDS>) -> Self {
+        Self {
+            ws2812,
+            data: [RGBW::default(); NUM_LEDS],
+        }
+    }
+}
+
+impl PixelSink for Ws2812Ring {
+    type Error = TimeoutError;
+
+    async fn write(&mut self, pixels: &[Rgbw; NUM_LEDS]) -> Result<(), Self::Error> {
+        for (out, px) in self.data.iter_mut().zip(pixels.iter()) {
+            *out = RGBW {
+                r: px.r,
+                g: px.g,
+                b: px.b,
+                a: White(px.w),
+            };
+        }
+        // バグ対策: NeoPixel書き込みが固着してもタスク全体が停止しないようタイムアウト保護
+        with_timeout(Duration::from_millis(8), self.ws2812.write(&self.data)).await
+    }
+}
+
+/// RINGLED_MESSAGE チャンネル（送信側・受信側の両方）
+pub struct RingLedQueue;
+
+impl LedEventSink for RingLedQueue {
+    fn post(&mut self, cmd: u8, location: f32) -> bool {
+        RINGLED_MESSAGE.try_send((cmd, location)).is_ok()
+    }
+}
+
+impl LedEventSource for RingLedQueue {
+    fn poll(&mut self) -> Option<(u8, f32)> {
+        RINGLED_MESSAGE.try_receive().ok()
+    }
+}
+
+// =========================================================
+//      OLED
+// =========================================================
+/// BUFFER_FROM_DISPLAY / BUFFER_TO_DISPLAY でI2C Taskとバッファをやり取りする
+pub struct OledChannels;
+
+impl FrameBufferSink for OledChannels {
+    async fn acquire(&mut self) -> OledBuffer {
+        BUFFER_FROM_DISPLAY.receive().await
+    }
+
+    async fn present(&mut self, buffer: OledBuffer) {
+        BUFFER_TO_DISPLAY.send(buffer).await;
+    }
+}
+
+// =========================================================
+//      MIDI
+// =========================================================
+/// USB-MIDI の送信エラー
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiTxError {
+    Timeout,  // USB未接続などで送れない
+    Endpoint, // エンドポイントのエラー
+}
+
+/// USB-MIDI の送信側（QubitTouch Task と MIDI RX Task の設定の返信で共有する）
+pub type SharedMidiSender = Mutex<CriticalSectionRawMutex, Sender<'static, Driver<'static, USB>>>;
+
+/// USB-MIDI 送信
+pub struct UsbMidiOut {
+    sender: &'static SharedMidiSender,
+}
+
+impl UsbMidiOut {
+    pub fn new(sender: &'static SharedMidiSender) -> Self {
+        Self { sender }
+    }
+}
+
+/// 1回の USB 転送（最大 64byte = 4byte のパケット16個）
+async fn write_packet(
+    sender: &mut Sender<'static, Driver<'static, USB>>,
+    data: &[u8],
+) -> Result<(), MidiTxError> {
+    match with_timeout(Duration::from_millis(5), sender.write_packet(data)).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(_)) => Err(MidiTxError::Endpoint),
+        Err(_) => Err(MidiTxError::Timeout),
+    }
+}
+
+impl MidiSink for UsbMidiOut {
+    type Error = MidiTxError;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        write_packet(&mut *self.sender.lock().await, &packet).await
+    }
+
+    /// ロックしたまま送るので、SysEx の途中に他のタスクのメッセージが入らない
+    /// MIDI_TX_BATCH 個ずつ、1回の USB 転送にまとめる
+    async fn send_all(&mut self, packets: &[[u8; 4]]) -> Result<(), Self::Error> {
+        let mut sender = self.sender.lock().await;
+        for chunk in packets.chunks(MIDI_TX_BATCH) {
+            write_packet(&mut sender, chunk.as_flattened()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// MIDI_TX_QUEUE（入れたら MIDI_TX_SIGNAL で midi_tx_task を起こす）
+pub struct MidiTxQueue;
+
+impl MidiQueueSink for MidiTxQueue {
+    fn post(&mut self, message: TxMessage) -> bool {
+        let queued = MIDI_TX_QUEUE.lock(|queue| queue.borrow_mut().push(message));
+        MIDI_TX_SIGNAL.signal(());
+        queued
+    }
+}
+
+impl MidiQueueSource for MidiTxQueue {
+    async fn take_batch(&mut self, batch: &mut TxBatch) {
+        loop {
+            MIDI_TX_QUEUE.lock(|queue| queue.borrow_mut().pop_batch(batch));
+            if !batch.is_empty() {
+                return;
+            }
+            MIDI_TX_SIGNAL.wait().await;
+        }
+    }
+}
+
+/// REPORT_QUEUE（タッチの記録とテレメトリ）
+pub struct ReportQueue;
+
+impl ReportSink for ReportQueue {
+    fn post(&mut self, report: Report) -> bool {
+        REPORT_QUEUE.try_send(report).is_ok()
+    }
+}
+
+impl ReportSource for ReportQueue {
+    async fn receive(&mut self) -> Report {
+        REPORT_QUEUE.receive().await
+    }
+}
+
+/// USB-MIDI 受信
+pub struct UsbMidiIn {
+    receiver: Receiver<'static, Driver<'static, USB>>,
+}
+
+impl UsbMidiIn {
+    pub fn new(receiver: Receiver<'static, Driver<'static, USB>>) -> Self {
+        Self { receiver }
+    }
+}
+
+impl MidiSource for UsbMidiIn {
+    type Error = EndpointError;
+
+    async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.receiver.read_packet(buf).await
+    }
+}
+
+// =========================================================
+//      DIN MIDI (UART0: TX GP0, RX GP1)
+// =========================================================
+/// UART の送信側（ランニングステータスは qubit_core::midi::din::DinMidiOut が作る）
+pub struct DinUartTx(pub BufferedUartTx);
+
+impl SerialSink for DinUartTx {
+    type Error = UartError;
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(bytes).await
+    }
+}
+
+/// UART の受信側
+pub struct DinUartRx(pub BufferedUartRx);
+
+impl SerialSource for DinUartRx {
+    type Error = UartError;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+}
+
+/// DIN MIDI の送信側（MIDI TX Task と MIDI RX Task のスルーで共有し、ランニングステータスも共有する）
+pub type SharedDinSender = Mutex<CriticalSectionRawMutex, DinMidiOut<DinUartTx>>;
+
+/// DIN MIDI 送信
+pub struct DinOut {
+    sender: &'static SharedDinSender,
+}
+
+impl DinOut {
+    pub fn new(sender: &'static SharedDinSender) -> Self {
+        Self { sender }
+    }
+}
+
+impl MidiSink for DinOut {
+    type Error = UartError;
+
+    async fn send(&mut self, packet: [u8; 4]) -> Result<(), Self::Error> {
+        self.sender.lock().await.send(packet).await
+    }
+
+    async fn send_all(&mut self, packets: &[[u8; 4]]) -> Result<(), Self::Error> {
+        self.sender.lock().await.send_all(packets).await
+    }
+}
+
+// =========================================================
+//      Console (USB CDC-ACM)
+// =========================================================
+/// コンソールの送信側（Console Task の返事と Log Task のレコードで共有する）
+pub type SharedConsoleSender =
+    Mutex<CriticalSectionRawMutex, cdc_acm::Sender<'static, Driver<'static, USB>>>;
+
+/// コンソールの受信。接続するまで待つ
+pub struct ConsoleIn(pub cdc_acm::Receiver<'static, Driver<'static, USB>>);
+
+impl SerialSource for ConsoleIn {
+    type Error = EndpointError;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.wait_connection().await;
+        self.0.read_packet(buf).await
+    }
+}
+
+/// コンソールの送信エラー
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConsoleTxError {
+    Timeout,  // ターミナルを開いていないなどで送れない
+    Endpoint, // エンドポイントのエラー
+}
+
+/// コンソールの送信。ターミナルを開いていなければタイムアウトして捨てる
+pub struct ConsoleOut {
+    sender: &'static SharedConsoleSender,
+}
+
+impl ConsoleOut {
+    pub fn new(sender: &'static SharedConsoleSender) -> Self {
+        Self { sender }
+    }
+}
+
+impl SerialSink for ConsoleOut {
+    type Error = ConsoleTxError;
+
+    /// ロックしたまま送るので、返事の途中に log のレコードが入らない
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut sender = self.sender.lock().await;
+        let size = sender.max_packet_size() as usize;
+        for chunk in bytes.chunks(size) {
+            match with_timeout(Duration::from_millis(20), sender.write_packet(chunk)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => return Err(ConsoleTxError::Endpoint),
+                Err(_) => return Err(ConsoleTxError::Timeout),
+            }
+        }
+        // ちょうどパケットの大きさで終わったら、空のパケットで転送の終わりを知らせる
+        if bytes.len() % size == 0 {
+            let _ = with_timeout(Duration::from_millis(20), sender.write_packet(&[])).await;
+        }
+        Ok(())
+    }
+}
+
+/// log のレコードを LOG_PIPE に書く（Log Task がコンソールに送る）
+/// 割り込みや Core1 からも呼ばれるので待たない。コンソールで log off なら何もしない
+pub struct ConsoleLogger;
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        console::LOG_STREAM.load(Ordering::Relaxed) != 0
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut line: String<LOG_LINE_SIZE> = String::new();
+        let ms = Instant::now().as_millis();
+        if write!(
+            line,
+            "[{:8}] {:5} {}\r\n",
+            ms,
+            record.level(),
+            record.args()
+        )
+        .is_err()
+        {
+            // 長すぎる行は切り詰める
+            while line.len() > LOG_LINE_SIZE - 2 {
+                line.pop();
+            }
+            let _ = line.push_str("\r\n");
+        }
+        // 入りきらなければ、行の途中で切れないように丸ごと捨てる
+        if LOG_PIPE.free_capacity() >= line.len() {
+            let _ = LOG_PIPE.try_write(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+/// log のレコードをコンソールに流すようにする（起動時に1回）
+pub fn init_logger() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+}
+
+// =========================================================
+//      Settings
+// =========================================================
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+/// 設定はフラッシュの最後のセクタに保存する（memory.x で FLASH から外してある）
+pub const SETTINGS_OFFSET: u32 = (FLASH_SIZE - ERASE_SIZE) as u32;
+
+/// 設定の保存先のフラッシュ（SysEx からのブートローダーへの再起動もここで受ける）
+pub struct FlashSettings {
+    flash: Flash<'static, FLASH, Blocking, FLASH_SIZE>,
+}
+
+impl FlashSettings {
+    pub fn new(flash: Flash<'static, FLASH, Blocking, FLASH_SIZE>) -> Self {
+        Self { flash }
+    }
+
+    /// 保存した設定を読み込んで反映する（起動時）。保存していなければ false
+    pub fn load(&mut self) -> bool {
+        // パラメータ数が違う古いデータも読めるように、多めに読む
+        let mut data = [0u8; 256];
+        self.flash.blocking_read(SETTINGS_OFFSET, &mut data).is_ok() && settings::load_saved(&data)
+    }
+}
+
+impl SettingsStore for FlashSettings {
+    type Error = FlashError;
+
+    /// 消去と書き込みの間（数十ms）は Core0 のタスクも止まる（Core1 は embassy-rp が止める）
+    async fn save(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.flash
+            .blocking_erase(SETTINGS_OFFSET, SETTINGS_OFFSET + ERASE_SIZE as u32)?;
+        self.flash.blocking_write(SETTINGS_OFFSET, data)
+    }
+}
+
+const REBOOT_TYPE_BOOTSEL: u32 = 0x0002;
+const REBOOT_NO_RETURN_ON_SUCCESS: u32 = 0x0100;
+
+impl Reboot for FlashSettings {
+    /// BOOTSEL を押して起動したのと同じ USB マスストレージ（UF2）モードで再起動する
+    fn reboot_to_bootloader(&mut self) {
+        // ACK が USB から出ていくまで 10ms 待ってから再起動する
+        unsafe {
+            embassy_rp::rom_data::reboot(
+                REBOOT_TYPE_BOOTSEL | REBOOT_NO_RETURN_ON_SUCCESS,
+                10,
+                0,
+                0,
+            );
+        }
+    }
+}
+
+// =========================================================
+//      Switch
+// =========================================================
+/// 2つのスイッチ（プルアップ、押すと Low）
+pub struct SwitchPair {
+    right: Input<'static>,
+    left: Input<'static>,
+}
+
+impl SwitchPair {
+    pub fn new(right: Input<'static>, left: Input<'static>) -> Self {
+        Self { right, left }
+    }
+}
+
+impl SwitchInput for SwitchPair {
+    fn read(&mut self) -> Switches {
+        Switches {
+            right: self.right.is_low(),
+            left: self.left.is_low(),
+        }
+    }
+}
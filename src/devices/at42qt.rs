@@ -2,10 +2,11 @@
 pub struct At42Qt1070 {}
 
 impl At42Qt1070 {
-    const ADDR: u8 = 0x1B;
+    pub const ADDR: u8 = 0x1B;
     const _STATUS: u8 = 2;
     const LP_MODE: u8 = 54;
     const MAX_DUR: u8 = 55;
+    const CALIBRATE: u8 = 56;
 
     pub const fn new() -> Self {
         Self {}
@@ -22,6 +23,14 @@ impl At42Qt1070 {
         i2c.write(Self::ADDR, &i2cdata).await
     }
 
+    /// キャリブレーションを始める（0 以外を書くと、全キーのリファレンスを取り直す）
+    pub async fn calibrate<I2C>(&mut self, i2c: &mut I2C) -> Result<(), I2C::Error>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        i2c.write(Self::ADDR, &[Self::CALIBRATE, 1]).await
+    }
+
     /// 1キーの状態を読み取る
     #[allow(dead_code)]
     pub async fn read_1key<I2C>(
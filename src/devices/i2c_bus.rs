@@ -0,0 +1,246 @@
+use embassy_rp::Peri;
+use embassy_rp::gpio::Pin;
+use embassy_rp::gpio::{Flex, Pull};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+use portable_atomic::Ordering;
+
+use qubit_core::constants::*;
+use qubit_core::{I2C_ERROR_LOG, I2C_RECOVERY_COUNT};
+
+/// エラー記録の対象デバイス（I2C_DEVICE_ADDRS と同じ並び）ごとに、エラーが続いたら休止するか
+/// AT42QT1070 はMUXの先の16個が同じアドレスなので休止しない（チップ単位の対処は ReadTouch で行う）
+const I2C_BACKOFF: [bool; I2C_DEVICE_COUNT] = [true, true, true, true, false, true];
+
+// =========================================================
+//      I2C Fault / Error
+// =========================================================
+/// エラーの種類（I2C_ERROR_LOG の添字）
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum I2cFault {
+    Nack = 0,
+    ArbitrationLoss = 1,
+    Bus = 2,
+    Timeout = 3,
+    Other = 4,
+}
+
+impl I2cFault {
+    fn from_kind(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NoAcknowledge(_) => Self::Nack,
+            ErrorKind::ArbitrationLoss => Self::ArbitrationLoss,
+            ErrorKind::Bus => Self::Bus,
+            _ => Self::Other,
+        }
+    }
+
+    /// バス全体の異常を示すエラーか（NACKはデバイス単体の問題）
+    fn is_bus_level(&self) -> bool {
+        matches!(self, Self::ArbitrationLoss | Self::Bus | Self::Timeout)
+    }
+}
+
+/// MonitoredI2c のエラー
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BusError<E> {
+    Device(E), // I2Cペリフェラルからのエラー
+    Timeout,   // 応答なし（バス固着など）
+    Backoff,   // エラーが続いたデバイスを一時的に休止中
+}
+
+impl<E: embedded_hal::i2c::Error> embedded_hal::i2c::Error for BusError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            BusError::Device(e) => e.kind(),
+            BusError::Timeout => ErrorKind::Bus,
+            BusError::Backoff => ErrorKind::Other,
+        }
+    }
+}
+
+// =========================================================
+//      I2cMonitor Class
+// =========================================================
+#[derive(Copy, Clone, Debug, Default)]
+struct DeviceState {
+    consecutive: u8,                // 連続エラー回数
+    backoff_until: Option<Instant>, // この時刻まではバスに出さない
+}
+
+/// デバイスごとのエラー記録と、バスリカバリの要否を判断する
+/// I2Cペリフェラルを作り直してもこちらは保持し続ける
+pub struct I2cMonitor {
+    devices: [DeviceState; I2C_DEVICE_COUNT],
+    fault_streak: u16, // バスレベルのエラーの連続回数
+    fail_streak: u16,  // デバイスを問わない連続失敗回数
+}
+
+impl I2cMonitor {
+    pub const fn new() -> Self {
+        Self {
+            devices: [DeviceState {
+                consecutive: 0,
+                backoff_until: None,
+            }; I2C_DEVICE_COUNT],
+            fault_streak: 0,
+            fail_streak: 0,
+        }
+    }
+
+    fn slot(addr: u8) -> Option<usize> {
+        I2C_DEVICE_ADDRS.iter().position(|a| *a == addr)
+    }
+
+    fn in_backoff(&self, slot: Option<usize>) -> bool {
+        slot.and_then(|s| self.devices[s].backoff_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn record<E: embedded_hal::i2c::Error>(
+        &mut self,
+        slot: Option<usize>,
+        result: &Result<(), BusError<E>>,
+    ) {
+        let fault = match result {
+            Ok(()) => {
+                self.fault_streak = 0;
+                self.fail_streak = 0;
+                if let Some(s) = slot {
+                    self.devices[s] = DeviceState::default();
+                }
+                return;
+            }
+            Err(BusError::Device(e)) => I2cFault::from_kind(e.kind()),
+            Err(BusError::Timeout) => I2cFault::Timeout,
+            Err(BusError::Backoff) => return,
+        };
+
+        self.fail_streak = self.fail_streak.saturating_add(1);
+        if fault.is_bus_level() {
+            self.fault_streak = self.fault_streak.saturating_add(1);
+        }
+        if let Some(s) = slot {
+            I2C_ERROR_LOG[s][fault as usize].fetch_add(1, Ordering::Relaxed);
+            let dev = &mut self.devices[s];
+            dev.consecutive = dev.consecutive.saturating_add(1);
+            if I2C_BACKOFF[s] && dev.consecutive >= I2C_BACKOFF_START {
+                // 連続エラー回数に応じて休止時間を倍々に延ばす
+                let shift = (dev.consecutive - I2C_BACKOFF_START).min(I2C_BACKOFF_MAX_SHIFT);
+                dev.backoff_until =
+                    Some(Instant::now() + Duration::from_millis(I2C_BACKOFF_BASE_MS << shift));
+            }
+        }
+    }
+
+    /// バスリカバリが必要か
+    pub fn needs_recovery(&self) -> bool {
+        self.fault_streak >= I2C_BUS_FAULT_LIMIT || self.fail_streak >= I2C_FAIL_STREAK_LIMIT
+    }
+
+    /// バスリカバリ後に呼ぶ。休止中のデバイスも含めて状態をリセットする
+    pub fn reset_after_recovery(&mut self) {
+        self.devices = [DeviceState::default(); I2C_DEVICE_COUNT];
+        self.fault_streak = 0;
+        self.fail_streak = 0;
+        I2C_RECOVERY_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// =========================================================
+//      MonitoredI2c Class
+// =========================================================
+/// I2Cバスのラッパー: タイムアウト、リトライ、休止、エラー記録を行う
+pub struct MonitoredI2c<'m, I2C> {
+    bus: I2C,
+    monitor: &'m mut I2cMonitor,
+}
+
+impl<'m, I2C> MonitoredI2c<'m, I2C> {
+    pub fn new(bus: I2C, monitor: &'m mut I2cMonitor) -> Self {
+        Self { bus, monitor }
+    }
+
+    pub fn needs_recovery(&self) -> bool {
+        self.monitor.needs_recovery()
+    }
+}
+
+impl<I2C: ErrorType> ErrorType for MonitoredI2c<'_, I2C> {
+    type Error = BusError<I2C::Error>;
+}
+
+impl<I2C: embedded_hal_async::i2c::I2c> embedded_hal_async::i2c::I2c for MonitoredI2c<'_, I2C> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let slot = I2cMonitor::slot(address);
+        if self.monitor.in_backoff(slot) {
+            return Err(BusError::Backoff);
+        }
+        let mut result = Err(BusError::Timeout);
+        for _ in 0..=I2C_RETRIES {
+            result = match with_timeout(
+                Duration::from_millis(I2C_TIMEOUT_MS),
+                embedded_hal_async::i2c::I2c::transaction(&mut self.bus, address, operations),
+            )
+            .await
+            {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(e)) => Err(BusError::Device(e)),
+                Err(_) => Err(BusError::Timeout),
+            };
+            // タイムアウトはバス固着の可能性が高いので、リトライせずリカバリに任せる
+            if !matches!(result, Err(BusError::Device(_))) {
+                break;
+            }
+        }
+        self.monitor.record(slot, &result);
+        result
+    }
+}
+
+// =========================================================
+//      Bus Recovery
+// =========================================================
+/// I2Cペリフェラルを解放した状態で呼ぶ
+/// SDAを掴んだままのスレーブを解放するため、SCLを最大9回クロックしてからSTOPを出す
+/// 終了時にSDAがHighに戻っていれば true
+pub async fn recover_bus(scl: Peri<'_, impl Pin>, sda: Peri<'_, impl Pin>) -> bool {
+    const HALF_CLOCK_US: u64 = 5; // 100kHz相当
+
+    // オープンドレイン動作: 入力(プルアップ)で解放、出力Lowで駆動
+    let mut scl = Flex::new(scl);
+    let mut sda = Flex::new(sda);
+    scl.set_pull(Pull::Up);
+    sda.set_pull(Pull::Up);
+    scl.set_low();
+    sda.set_low();
+    scl.set_as_input();
+    sda.set_as_input();
+    Timer::after_micros(HALF_CLOCK_US).await;
+
+    for _ in 0..9 {
+        if sda.is_high() {
+            break;
+        }
+        scl.set_as_output();
+        Timer::after_micros(HALF_CLOCK_US).await;
+        scl.set_as_input();
+        Timer::after_micros(HALF_CLOCK_US).await;
+    }
+
+    // STOP condition: SCL Low の間に SDA を Low にし、SCL High の後に SDA を解放
+    scl.set_as_output();
+    Timer::after_micros(HALF_CLOCK_US).await;
+    sda.set_as_output();
+    Timer::after_micros(HALF_CLOCK_US).await;
+    scl.set_as_input();
+    Timer::after_micros(HALF_CLOCK_US).await;
+    sda.set_as_input();
+    Timer::after_micros(HALF_CLOCK_US).await;
+
+    sda.is_high() && scl.is_high()
+}
@@ -1,4 +1,5 @@
 pub mod at42qt;
+pub mod i2c_bus;
 pub mod pca9544;
 pub mod ssd1306;
 pub mod ws2812;
@@ -1,9 +1,9 @@
-use crate::constants;
+use qubit_core::constants;
 
 pub struct Pca9544 {}
 
 impl Pca9544 {
-    const ADDR: u8 = 0x70;
+    pub const ADDR: u8 = 0x70;
     pub const fn new() -> Self {
         Self {}
     }
@@ -1,90 +1,188 @@
-use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embassy_time::{Duration, Instant};
+use qubit_core::display::{DISPLAY_PAGES, DISPLAY_WIDTH, OledBuffer, OledConfig, Panel};
 
-const DISPLAY_WIDTH: usize = 128;
-const DISPLAY_HEIGHT: usize = 64;
-const BUFFER_SIZE: usize = (DISPLAY_WIDTH * DISPLAY_HEIGHT) / 8;
+const CHUNK_SIZE: usize = 31; // 1回のI2C書き込みで送るデータ数
+const RUN_GAP_MERGE: usize = 8; // この間隔以下の変化はまとめて送る（アドレス設定の方が高くつく）
+const MAX_RUN: usize = CHUNK_SIZE * 2; // 1回に送る連続データの最大長（時間予算を守るため）
 
-/// フレームバッファ（描画用、1024バイト）
-#[derive(Debug)]
-pub struct OledBuffer {
-    pub data: [u8; BUFFER_SIZE],
-}
-
-impl OledBuffer {
-    /// 新規バッファを作成（ゼロクリア）
-    pub fn new() -> Self {
-        Self {
-            data: [0; BUFFER_SIZE],
-        }
-    }
-
-    /// バッファをクリア
-    pub fn clear(&mut self) {
-        self.data.fill(0);
+/// 表示の先頭カラムのRAM上の位置
+fn column_offset(panel: Panel) -> usize {
+    match panel {
+        Panel::Ssd1306 => 0,
+        Panel::Sh1106 => 2,
     }
 }
 
-impl Default for OledBuffer {
-    fn default() -> Self {
-        Self::new()
+/// 実際に設定するコントラストとプリチャージ期間
+fn contrast_and_precharge(config: &OledConfig) -> (u8, u8) {
+    if config.dim {
+        (config.contrast >> 4, 0x22)
+    } else {
+        (config.contrast, 0xF1)
     }
 }
 
-/// SSD1306 OLEDディスプレイドライバ（I2C非保持版）
+/// SSD1306/SH1106 OLEDディスプレイドライバ（I2C非保持版）
+/// 前回送った内容を保持し、変化した部分だけを少しずつ転送する
 pub struct Oled {
     addr: u8,
+    config: OledConfig,
+    target: OledBuffer, // 表示したい内容（UIタスクから受け取った最新フレーム）
+    shadow: OledBuffer, // パネルに送信済みの内容
+    invalid_from: [usize; DISPLAY_PAGES], // ページごとに、このカラム以降はパネルの内容が不定
 }
 
 impl Oled {
+    pub const DEFAULT_ADDR: u8 = 0x3C;
+
     /// デフォルトアドレス(0x3C)で新規作成
     pub fn new() -> Self {
-        Self::new_at(0x3C)
+        Self::new_at(Self::DEFAULT_ADDR)
     }
 
     /// 指定アドレスで新規作成
     pub fn new_at(addr: u8) -> Self {
-        Self { addr }
+        Self {
+            addr,
+            config: OledConfig::new(),
+            target: OledBuffer::new(),
+            shadow: OledBuffer::new(),
+            invalid_from: [0; DISPLAY_PAGES],
+        }
+    }
+
+    pub fn config(&self) -> OledConfig {
+        self.config
     }
 
     /// ディスプレイを初期化（I2Cを借用）
-    pub fn init<I2C>(&mut self, i2c: &mut I2C) -> Result<(), display_interface::DisplayError>
+    pub async fn init<I2C>(
+        &mut self,
+        i2c: &mut I2C,
+        config: OledConfig,
+    ) -> Result<(), display_interface::DisplayError>
     where
-        I2C: embedded_hal::i2c::I2c,
+        I2C: embedded_hal_async::i2c::I2c,
     {
-        // SSD1306初期化シーケンス
-        self.send_commands(
-            i2c,
-            &[
-                0xAE, // Display off
-                0xD5, 0x80, // Set display clock divide ratio/oscillator frequency
-                0xA8, 0x3F, // Set multiplex ratio (1 to 64)
-                0xD3, 0x00, // Set display offset
-                0x40, // Set start line address
-                0x8D, 0x14, // Enable charge pump
-                0x20, 0x00, // Set memory addressing mode (horizontal)
-                0xA1, // Set segment re-map
-                0xC8, // Set COM output scan direction
-                0xDA, 0x12, // Set COM pins hardware configuration
-                0x81, 0xFF, // Set contrast control
-                0xD9, 0xF1, // Set pre-charge period
-                0xDB, 0x40, // Set VCOMH deselect level
-                0xA4, // Entire display on (resume to RAM content display)
-                0xA6, // Set normal display (not inverted)
-                0xAF, // Display on
-            ],
-        )?;
+        let (contrast, precharge) = contrast_and_precharge(&config);
+        let (seg_remap, com_scan) = if config.flip {
+            (0xA0, 0xC0)
+        } else {
+            (0xA1, 0xC8)
+        };
+        let invert = if config.invert { 0xA7 } else { 0xA6 };
+        let display = if config.sleep { 0xAE } else { 0xAF };
+        match config.panel {
+            Panel::Ssd1306 => {
+                // SSD1306初期化シーケンス
+                self.send_commands(
+                    i2c,
+                    &[
+                        0xAE, // Display off
+                        0xD5, 0x80, // Set display clock divide ratio/oscillator frequency
+                        0xA8, 0x3F, // Set multiplex ratio (1 to 64)
+                        0xD3, 0x00, // Set display offset
+                        0x40, // Set start line address
+                        0x8D, 0x14, // Enable charge pump
+                        0x20, 0x00,      // Set memory addressing mode (horizontal)
+                        seg_remap, // Set segment re-map
+                        com_scan,  // Set COM output scan direction
+                        0xDA, 0x12, // Set COM pins hardware configuration
+                        0x81, contrast, // Set contrast control
+                        0xD9, precharge, // Set pre-charge period
+                        0xDB, 0x40,    // Set VCOMH deselect level
+                        0xA4,    // Entire display on (resume to RAM content display)
+                        invert,  // Set normal/inverted display
+                        display, // Display on/off
+                    ],
+                )
+                .await?;
+            }
+            Panel::Sh1106 => {
+                // SH1106初期化シーケンス（水平アドレッシングモードはなく、ページ単位で書く）
+                self.send_commands(
+                    i2c,
+                    &[
+                        0xAE, // Display off
+                        0xD5, 0x80, // Set display clock divide ratio/oscillator frequency
+                        0xA8, 0x3F, // Set multiplex ratio (1 to 64)
+                        0xD3, 0x00, // Set display offset
+                        0x40, // Set start line address
+                        0xAD, 0x8B,      // DC-DC converter on
+                        seg_remap, // Set segment re-map
+                        com_scan,  // Set COM output scan direction
+                        0xDA, 0x12, // Set COM pins hardware configuration
+                        0x81, contrast, // Set contrast control
+                        0xD9, precharge, // Set pre-charge period
+                        0xDB, 0x35,    // Set VCOM deselect level
+                        0xA4,    // Entire display on (resume to RAM content display)
+                        invert,  // Set normal/inverted display
+                        display, // Display on/off
+                    ],
+                )
+                .await?;
+            }
+        }
+        self.config = config;
+
+        // パネルのRAMは不定なので、全体を送り直す
+        self.invalidate();
+        Ok(())
+    }
 
+    /// 表示設定を変更する（変化した項目のコマンドだけを送る）
+    pub async fn apply_config<I2C>(
+        &mut self,
+        i2c: &mut I2C,
+        config: OledConfig,
+    ) -> Result<(), display_interface::DisplayError>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        let old = self.config;
+        if old.panel != config.panel {
+            return self.init(i2c, config).await;
+        }
+        if contrast_and_precharge(&old) != contrast_and_precharge(&config) {
+            let (contrast, precharge) = contrast_and_precharge(&config);
+            self.send_commands(i2c, &[0x81, contrast, 0xD9, precharge])
+                .await?;
+        }
+        if old.invert != config.invert {
+            self.send_commands(i2c, &[if config.invert { 0xA7 } else { 0xA6 }])
+                .await?;
+        }
+        if old.flip != config.flip {
+            // セグメントリマップは以降の書き込みにしか効かないので、全体を送り直す
+            let cmds = if config.flip {
+                [0xA0, 0xC0]
+            } else {
+                [0xA1, 0xC8]
+            };
+            self.send_commands(i2c, &cmds).await?;
+            self.invalidate();
+        }
+        if old.sleep != config.sleep {
+            self.send_commands(i2c, &[if config.sleep { 0xAE } else { 0xAF }])
+                .await?;
+        }
+        self.config = config;
         Ok(())
     }
 
+    /// パネルの内容を不定とみなし、次の flush_step から全体を送り直す
+    fn invalidate(&mut self) {
+        self.invalid_from = [0; DISPLAY_PAGES];
+    }
+
     /// 複数のコマンドを送信
-    fn send_commands<I2C>(
+    async fn send_commands<I2C>(
         &self,
         i2c: &mut I2C,
         commands: &[u8],
     ) -> Result<(), display_interface::DisplayError>
     where
-        I2C: embedded_hal::i2c::I2c,
+        I2C: embedded_hal_async::i2c::I2c,
     {
         let mut buf = [0u8; 32];
         buf[0] = 0x00; // Command mode
@@ -92,79 +190,111 @@ impl Oled {
         for chunk in commands.chunks(31) {
             buf[1..=chunk.len()].copy_from_slice(chunk);
             i2c.write(self.addr, &buf[..=chunk.len()])
+                .await
                 .map_err(|_| display_interface::DisplayError::BusWriteError)?;
         }
         Ok(())
     }
 
-    /// バッファをディスプレイに転送
-    pub fn flush_buffer<I2C>(
+    /// データを送信（チャンク単位で）
+    async fn send_data<I2C>(
         &self,
-        buffer: &OledBuffer,
         i2c: &mut I2C,
+        bytes: &[u8],
     ) -> Result<(), display_interface::DisplayError>
     where
-        I2C: embedded_hal::i2c::I2c,
+        I2C: embedded_hal_async::i2c::I2c,
     {
-        // ページアドレス範囲を設定（Page0〜Page7）
-        self.send_commands(i2c, &[0x22, 0x00, 0x07])?;
-
-        // カラムアドレス範囲を設定（0〜127）
-        self.send_commands(i2c, &[0x21, 0x00, 0x7F])?;
-
-        // データを送信（チャンク単位で）
-        const CHUNK_SIZE: usize = 31;
-        for chunk in buffer.data.chunks(CHUNK_SIZE) {
+        for chunk in bytes.chunks(CHUNK_SIZE) {
             let mut data = [0u8; CHUNK_SIZE + 1];
             data[0] = 0x40; // Data mode
             data[1..=chunk.len()].copy_from_slice(chunk);
             i2c.write(self.addr, &data[..=chunk.len()])
+                .await
                 .map_err(|_| display_interface::DisplayError::BusWriteError)?;
         }
-
         Ok(())
     }
-}
 
-impl Default for Oled {
-    fn default() -> Self {
-        Self::new()
+    /// 表示したいフレームを設定する（転送は flush_step で行うので、バッファはすぐ返却できる）
+    pub fn set_frame(&mut self, buffer: &OledBuffer) {
+        self.target.data.copy_from_slice(&buffer.data);
     }
-}
 
-/// embedded-graphics の DrawTarget トレイト実装（OledBuffer用）
-impl DrawTarget for OledBuffer {
-    type Color = BinaryColor;
-    type Error = core::convert::Infallible;
+    /// 送信済みの内容と異なる次の範囲 (page, 開始カラム, 終了カラム) を探す
+    fn next_dirty_run(&self) -> Option<(usize, usize, usize)> {
+        for page in 0..DISPLAY_PAGES {
+            // 内容が不定の部分は、そのまま送る
+            let invalid = self.invalid_from[page];
+            if invalid < DISPLAY_WIDTH {
+                let last = (invalid + MAX_RUN - 1).min(DISPLAY_WIDTH - 1);
+                return Some((page, invalid, last));
+            }
+
+            let row = page * DISPLAY_WIDTH;
+            let differs = |col: usize| self.target.data[row + col] != self.shadow.data[row + col];
+            let Some(first) = (0..DISPLAY_WIDTH).find(|col| differs(*col)) else {
+                continue;
+            };
+            // 近くの変化はまとめる
+            let mut last = first;
+            let mut col = first + 1;
+            while col < DISPLAY_WIDTH && col - last <= RUN_GAP_MERGE && col - first < MAX_RUN {
+                if differs(col) {
+                    last = col;
+                }
+                col += 1;
+            }
+            return Some((page, first, last));
+        }
+        None
+    }
 
-    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    /// 送信済みの内容と異なる部分を、時間予算の範囲で転送する
+    /// 全て送り終えたら true を返す（予算を超えても最低1範囲は送る）
+    pub async fn flush_step<I2C>(
+        &mut self,
+        i2c: &mut I2C,
+        budget: Duration,
+    ) -> Result<bool, display_interface::DisplayError>
     where
-        I: IntoIterator<Item = Pixel<Self::Color>>,
+        I2C: embedded_hal_async::i2c::I2c,
     {
-        for Pixel(coord, color) in pixels.into_iter() {
-            let x = coord.x;
-            let y = coord.y;
-
-            if x >= 0 && x < DISPLAY_WIDTH as i32 && y >= 0 && y < DISPLAY_HEIGHT as i32 {
-                let x = x as usize;
-                let y = y as usize;
-                let byte_idx = x + (y / 8) * DISPLAY_WIDTH;
-                let bit_idx = y % 8;
-
-                if byte_idx < BUFFER_SIZE {
-                    match color {
-                        BinaryColor::On => self.data[byte_idx] |= 1 << bit_idx,
-                        BinaryColor::Off => self.data[byte_idx] &= !(1 << bit_idx),
-                    }
+        let start = Instant::now();
+        while let Some((page, first, last)) = self.next_dirty_run() {
+            // ページとカラムの範囲を設定して、変化した部分だけ送る
+            match self.config.panel {
+                Panel::Ssd1306 => {
+                    self.send_commands(
+                        i2c,
+                        &[0x22, page as u8, page as u8, 0x21, first as u8, last as u8],
+                    )
+                    .await?;
+                }
+                Panel::Sh1106 => {
+                    let col = (first + column_offset(self.config.panel)) as u8;
+                    self.send_commands(i2c, &[0xB0 | page as u8, col & 0x0F, 0x10 | (col >> 4)])
+                        .await?;
                 }
             }
+            let range = (page * DISPLAY_WIDTH + first)..=(page * DISPLAY_WIDTH + last);
+            self.send_data(i2c, &self.target.data[range.clone()])
+                .await?;
+            self.shadow.data[range.clone()].copy_from_slice(&self.target.data[range]);
+            if self.invalid_from[page] == first {
+                self.invalid_from[page] = last + 1;
+            }
+
+            if start.elapsed() >= budget {
+                return Ok(self.next_dirty_run().is_none());
+            }
         }
-        Ok(())
+        Ok(true)
     }
 }
 
-impl OriginDimensions for OledBuffer {
-    fn size(&self) -> Size {
-        Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32)
+impl Default for Oled {
+    fn default() -> Self {
+        Self::new()
     }
 }
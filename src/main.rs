@@ -6,22 +6,26 @@
 #![no_std]
 #![no_main]
 
-mod constants;
+mod board;
 mod devices;
 mod touch;
-mod ui;
+
+use core::cell::RefCell;
 
 use cortex_m::asm;
-use portable_atomic::{AtomicI32, AtomicU8, AtomicU16, AtomicU32, AtomicU64, Ordering};
+use portable_atomic::Ordering;
 use static_cell::StaticCell;
 
 use embassy_executor::Executor;
 use embassy_rp::Peri;
 use embassy_rp::multicore::{Stack, spawn_core1};
+use embassy_sync::blocking_mutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Instant, Ticker, Timer, with_timeout};
+use embassy_sync::pipe::Pipe;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
 
 use rp235x_hal::{self as hal};
 
@@ -29,15 +33,26 @@ use embassy_rp::adc::InterruptHandler as AdcInterruptHandler;
 use embassy_rp::bind_interrupts;
 use embassy_rp::dma::InterruptHandler as DmaInterruptHandler;
 use embassy_rp::gpio::{Input, Level, Output, Pull};
-use embassy_rp::i2c::{self, Config as I2cConfig, I2c, InterruptHandler as I2cInterruptHandler};
-use embassy_rp::peripherals::{DMA_CH0, DMA_CH1, I2C1, PIO0, USB};
+use embassy_rp::i2c::{Config as I2cConfig, I2c, InterruptHandler as I2cInterruptHandler};
+use embassy_rp::peripherals::{DMA_CH0, DMA_CH1, I2C1, PIN_6, PIN_7, PIO0, UART0, USB};
 use embassy_rp::pio::{InterruptHandler as PioInterruptHandler, Pio};
 use embassy_rp::pio_programs::ws2812::PioWs2812Program;
+use embassy_rp::uart::{BufferedInterruptHandler, BufferedUart, Config as UartConfig};
 use embassy_rp::usb::{Driver, InterruptHandler as UsbInterruptHandler};
-use embassy_usb::class::midi::{MidiClass, Receiver, Sender};
+use embassy_usb::class::cdc_acm::{self, CdcAcmClass, State as CdcState};
+use embassy_usb::class::midi::{MidiClass, Receiver};
 use embassy_usb::{Builder, Config};
 
-use crate::constants::*;
+use qubit_core::constants::{self, *};
+use qubit_core::display::OledBuffer;
+use qubit_core::hal::Report;
+use qubit_core::midi::din::{DIN_BAUDRATE, DinMidiIn, DinMidiOut};
+use qubit_core::midi::tx_queue::TxQueue;
+use qubit_core::settings;
+use qubit_core::timing::{self, TASK_I2C};
+use qubit_core::{
+    AD_VALUE0, AD_VALUE1, AD_VALUE2, AD_VALUE3, CALIBRATE_REQUEST, ELAPSED_TIME, ERROR_CODE,
+};
 
 bind_interrupts!(struct Irqs {
     ADC_IRQ_FIFO => AdcInterruptHandler;
@@ -45,6 +60,7 @@ bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => UsbInterruptHandler<USB>;
     PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
     DMA_IRQ_0 => DmaInterruptHandler<DMA_CH0>, DmaInterruptHandler<DMA_CH1>;
+    UART0_IRQ => BufferedInterruptHandler<UART0>;
 });
 
 macro_rules! make_static {
@@ -58,7 +74,6 @@ macro_rules! make_static {
 }
 
 // パニックハンドラ: エラーカウントを最大値にして永久ループ
-pub static ERROR_CODE: AtomicU8 = AtomicU8::new(0);
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     ERROR_CODE.store(255, Ordering::Relaxed);
@@ -77,24 +92,37 @@ fn panic(_info: &core::panic::PanicInfo) -> ! {
 // 33: MIDI RX Taskの起動に失敗
 // 34: RingLED Taskの起動に失敗
 // 35: ADC Taskの起動に失敗
+// 36: MIDI Clock Taskの起動に失敗
+// 37: MIDI TX Taskの起動に失敗
+// 38: DIN RX Taskの起動に失敗
+// 39: Console Task（USB シリアルのデバッグコンソール）の起動に失敗
 // 41: タッチイベントのバッファオーバーフロー
 // 42: MIDIイベントの送信失敗（USB未接続など）
-// 43: MIDIイベントのバッファオーバーフロー
+// 43: MIDI送信キュー満杯（メッセージを捨てた）
 // 44: RingLEDキュー満杯
 // 45: RingLEDへの書き込みのタイムアウト
+// 46: タッチの記録（SysEx）の送信失敗、または REPORT_QUEUE 満杯
+// 47: テレメトリ（SysEx）の送信失敗、または REPORT_QUEUE 満杯
+// 48: MIDI クロック（内部クロック）の送信失敗
+// 49: DIN MIDI の送信失敗
 // 51-54: MIDI RX Error
+// 55: 受信した MIDI パケットの異常（SysEx の溢れ、CIN とステータスの不一致など）
+// 56: 設定の SysEx への返信の送信失敗
+// 57: DIN MIDI の受信エラー（フレーミングエラーなど）
 // 61: ADC値取得エラー
 // 71: OLED初期化エラー
 // 72: 描画バッファ受信エラー
 // 73: 描画バッファ返却エラー
-
-// タッチイベントのデータ構造
-#[derive(Copy, Clone, Default)]
-struct TouchEvent(u8, u8, u8, f32); // (status, note, velocity, location)
+// 81: タッチセンサの読み込み失敗が続いたため、該当チップの6キーをマスク
+// 82: I2Cバスの異常を検出し、バスリカバリを実行
+// 83: バスリカバリ後もSDA/SCLがLowのまま
+// 91: Log Task（log のレコードをコンソールに送る）の起動に失敗
+// 92: Report Task（タッチの記録とテレメトリの SysEx を送る）の起動に失敗
 
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 //      Global static variables
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+// 表示用変数やキーマスクなど、タスク間で共有する値は qubit_core で定義している
 /// Tell the Boot ROM about our application
 #[unsafe(link_section = ".start_block")]
 #[used]
@@ -106,8 +134,6 @@ static EXECUTOR0: StaticCell<embassy_executor::Executor> = StaticCell::new();
 static EXECUTOR1: StaticCell<embassy_executor::Executor> = StaticCell::new();
 
 // OLEDバッファ転送用チャンネル（ダブルバッファリング）
-use devices::ssd1306::OledBuffer;
-
 static BUFFER_TO_DISPLAY: Channel<
     embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex,
     OledBuffer,
@@ -119,33 +145,9 @@ static BUFFER_FROM_DISPLAY: Channel<
     2,
 > = Channel::new();
 
-// 表示用変数
-pub static POINT0: AtomicU16 = AtomicU16::new(0);
-pub static POINT1: AtomicU16 = AtomicU16::new(0);
-pub static POINT2: AtomicU16 = AtomicU16::new(0);
-pub static POINT3: AtomicU16 = AtomicU16::new(0);
-pub static TOUCH0: AtomicI32 = AtomicI32::new(10000);
-pub static TOUCH1: AtomicI32 = AtomicI32::new(10000);
-pub static TOUCH2: AtomicI32 = AtomicI32::new(10000);
-pub static TOUCH3: AtomicI32 = AtomicI32::new(10000);
-pub static ELAPSED_TIME: AtomicU64 = AtomicU64::new(0); // タッチスキャンの経過時間（us）
-pub static AD_VALUE0: AtomicU32 = AtomicU32::new(0); // ADCの値(A0)
-pub static AD_VALUE1: AtomicU32 = AtomicU32::new(0); // ADCの値(A1)
-pub static AD_VALUE2: AtomicU32 = AtomicU32::new(0); // ADCの値(B0)
-pub static AD_VALUE3: AtomicU32 = AtomicU32::new(0); // ADCの値(B1)
-pub static PRESSURE: AtomicU32 = AtomicU32::new(0); // 圧力計算結果
-pub static WORK_MODE: AtomicU8 = AtomicU8::new(0); // 動作モード（Piano/Violin）
-
-// タッチセンサの生データ格納用（16bit/key）
-pub static TOUCH_RAW_DATA: Mutex<
-    CriticalSectionRawMutex,
-    [u16; (constants::PCA9544_NUM_CHANNELS * constants::PCA9544_NUM_DEVICES) as usize
-        * constants::AT42QT_KEYS_PER_DEVICE],
-> = Mutex::new(
-    [0u16;
-        (constants::PCA9544_NUM_CHANNELS * constants::PCA9544_NUM_DEVICES) as usize
-            * constants::AT42QT_KEYS_PER_DEVICE],
-);
+// タッチセンサの生データ格納用（16bit/key + チャンネルごとの読み込み時刻）
+pub static TOUCH_RAW_DATA: Mutex<CriticalSectionRawMutex, qubit_core::touch::frame::TouchFrame> =
+    Mutex::new(qubit_core::touch::frame::TouchFrame::new());
 
 // RINGLED用メッセージチャンネル
 static RINGLED_MESSAGE: Channel<
@@ -154,12 +156,27 @@ static RINGLED_MESSAGE: Channel<
     { constants::RINGLED_MESSAGE_SIZE },
 > = Channel::new();
 
+// MIDI送信キュー（優先度つき。qubit_touch_task が入れ、midi_tx_task が送る）
+static MIDI_TX_QUEUE: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    RefCell<TxQueue<{ constants::MIDI_TX_QUEUE_SIZE }>>,
+> = blocking_mutex::Mutex::new(RefCell::new(TxQueue::new()));
+static MIDI_TX_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+// タッチの記録とテレメトリ（qubit_touch_task が入れ、report_task が SysEx にして送る）
+static REPORT_QUEUE: Channel<CriticalSectionRawMutex, Report, { constants::REPORT_QUEUE_SIZE }> =
+    Channel::new();
+
+// log のレコード（board::ConsoleLogger が書き、log_task がコンソールに送る）
+static LOG_PIPE: Pipe<CriticalSectionRawMutex, { constants::LOG_PIPE_SIZE }> = Pipe::new();
+
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 //      Main entry point
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 #[cortex_m_rt::entry]
 fn main() -> ! {
     let p = embassy_rp::init(Default::default());
+    board::init_logger();
 
     // LEDピン
     // XIAO RP系の内蔵LEDは Active Low 想定: High=消灯, Low=点灯
@@ -186,6 +203,11 @@ fn main() -> ! {
     config.serial_number = Some("000000");
     config.max_power = 100;
     config.max_packet_size_0 = 64;
+    // MIDI とコンソール（CDC-ACM）の複合デバイス
+    config.device_class = 0xEF;
+    config.device_sub_class = 0x02;
+    config.device_protocol = 0x01;
+    config.composite_with_iads = true;
 
     // Buffers
     let config_descriptor = make_static!([u8; 256], [0; 256]);
@@ -205,9 +227,12 @@ fn main() -> ! {
     // Midi Class
     let class = MidiClass::new(&mut builder, 1, 1, 64);
 
-    let mut i2c_config = I2cConfig::default();
-    i2c_config.frequency = 400_000;
-    let i2c = I2c::new_async(p.I2C1, p.PIN_7, p.PIN_6, Irqs, i2c_config);
+    // デバッグコンソール（USB シリアル）
+    let cdc_state = make_static!(CdcState<'static>, CdcState::new());
+    let console = CdcAcmClass::new(&mut builder, cdc_state, 64);
+
+    // I2C1 (SCL: GP7, SDA: GP6) はバスリカバリで作り直すため、ペリフェラルのまま Core1 に渡す
+    let (i2c_peri, i2c_scl, i2c_sda) = (p.I2C1, p.PIN_7, p.PIN_6);
 
     // PIO / Neopixel
     let Pio {
@@ -218,6 +243,18 @@ fn main() -> ! {
         PioWs2812Program::new(&mut common)
     );
 
+    // 手動マスクの設定
+    let mut manual_mask = qubit_core::touch::key_mask::KeyMask::new();
+    for key in constants::MANUAL_MASKED_KEYS {
+        manual_mask.set(*key, true);
+    }
+    manual_mask.store_manual();
+
+    // フラッシュに保存した設定（SysEx で保存したもの）があれば、手動マスクも含めて上書きする
+    let mut flash_settings =
+        board::FlashSettings::new(embassy_rp::flash::Flash::new_blocking(p.FLASH));
+    flash_settings.load();
+
     // 初期バッファを準備してチャンネルに投入（Core1起動前に実行）
     // 2つのバッファを確実に投入
     if BUFFER_FROM_DISPLAY.try_send(OledBuffer::new()).is_err() {
@@ -238,7 +275,7 @@ fn main() -> ! {
                     Ok(token) => spawner.spawn(token),
                     Err(_) => ERROR_CODE.store(21, Ordering::Relaxed),
                 }
-                match core1_i2c_task(i2c) {
+                match core1_i2c_task(i2c_peri, i2c_scl, i2c_sda) {
                     Ok(token) => spawner.spawn(token),
                     Err(_) => ERROR_CODE.store(22, Ordering::Relaxed),
                 }
@@ -252,11 +289,32 @@ fn main() -> ! {
 
     let usb = builder.build();
     let (sender, receiver) = class.split();
+    let sender = make_static!(board::SharedMidiSender, Mutex::new(sender));
+    let (console_tx, console_rx) = console.split();
+    let console_tx = make_static!(board::SharedConsoleSender, Mutex::new(console_tx));
+
+    // DIN MIDI (UART0: TX GP0, RX GP1, 31250bps)
+    let mut uart_config = UartConfig::default();
+    uart_config.baudrate = DIN_BAUDRATE;
+    let uart = BufferedUart::new(
+        p.UART0,
+        p.PIN_0,
+        p.PIN_1,
+        Irqs,
+        make_static!([u8; 256], [0; 256]),
+        make_static!([u8; 64], [0; 64]),
+        uart_config,
+    );
+    let (din_tx, din_rx) = uart.split();
+    let din = make_static!(
+        board::SharedDinSender,
+        Mutex::new(DinMidiOut::new(board::DinUartTx(din_tx)))
+    );
 
     // Core0もExecutorを回す（必須）
     let executor0 = EXECUTOR0.init(Executor::new());
     executor0.run(|spawner| {
-        match qubit_touch_task(sender) {
+        match qubit_touch_task() {
             Ok(token) => spawner.spawn(token),
             Err(_) => ERROR_CODE.store(31, Ordering::Relaxed),
         }
@@ -264,7 +322,7 @@ fn main() -> ! {
             Ok(token) => spawner.spawn(token),
             Err(_) => ERROR_CODE.store(32, Ordering::Relaxed),
         }
-        match midi_rx_task(receiver) {
+        match midi_rx_task(receiver, sender, din, flash_settings) {
             Ok(token) => spawner.spawn(token),
             Err(_) => ERROR_CODE.store(33, Ordering::Relaxed),
         }
@@ -276,6 +334,30 @@ fn main() -> ! {
             Ok(token) => spawner.spawn(token),
             Err(_) => ERROR_CODE.store(35, Ordering::Relaxed),
         }
+        match midi_clock_task(sender, din) {
+            Ok(token) => spawner.spawn(token),
+            Err(_) => ERROR_CODE.store(36, Ordering::Relaxed),
+        }
+        match midi_tx_task(sender, din) {
+            Ok(token) => spawner.spawn(token),
+            Err(_) => ERROR_CODE.store(37, Ordering::Relaxed),
+        }
+        match din_rx_task(board::DinUartRx(din_rx), sender) {
+            Ok(token) => spawner.spawn(token),
+            Err(_) => ERROR_CODE.store(38, Ordering::Relaxed),
+        }
+        match console_task(console_rx, console_tx) {
+            Ok(token) => spawner.spawn(token),
+            Err(_) => ERROR_CODE.store(39, Ordering::Relaxed),
+        }
+        match log_task(console_tx) {
+            Ok(token) => spawner.spawn(token),
+            Err(_) => ERROR_CODE.store(91, Ordering::Relaxed),
+        }
+        match report_task(sender) {
+            Ok(token) => spawner.spawn(token),
+            Err(_) => ERROR_CODE.store(92, Ordering::Relaxed),
+        }
     });
 }
 
@@ -292,131 +374,65 @@ async fn ringled_task(
 ) {
     // Neopixel on D0 (GP26)
     use embassy_rp::pio_programs::ws2812::RgbwPioWs2812;
-    use embassy_time::Ticker;
-    use smart_leds::RGBW;
-    use ui::ringled::RingLed;
 
     // RgbwPioWs2812 is needed for RGBW
-    let mut ws2812 = RgbwPioWs2812::new(&mut common, sm, dma, Irqs, pin, program);
-    let mut ring_led = RingLed::new();
-    let mut ticker = Ticker::every(embassy_time::Duration::from_millis(20));
-
-    let mut data = [RGBW::default(); constants::NUM_LEDS];
-    loop {
-        // バグ対策: 1周期でキューを可能な限りドレインして、送信側の詰まりを防ぐ
-        let mut drained = false;
-        while let Ok((cmd, location)) = RINGLED_MESSAGE.try_receive() {
-            drained = true;
-            ring_led.set_color(&mut data, location, cmd);
-        }
-        if !drained {
-            ring_led.set_color(
-                &mut data,
-                constants::RINGLED_CMD_NONE as f32,
-                constants::RINGLED_CMD_NONE,
-            );
-        }
-        // バグ対策: NeoPixel書き込みが固着してもタスク全体が停止しないようタイムアウト保護
-        let write_result = with_timeout(Duration::from_millis(8), ws2812.write(&data)).await;
-        if write_result.is_err() {
-            ERROR_CODE.store(45, Ordering::Relaxed);
-        }
-        ticker.next().await;
-    }
+    let ws2812 = RgbwPioWs2812::new(&mut common, sm, dma, Irqs, pin, program);
+    let mut pixels = board::Ws2812Ring::new(ws2812);
+    qubit_core::tasks::ringled_task(&mut pixels, &mut board::RingLedQueue, &board::EmbassyClock)
+        .await;
 }
 
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 //      QubitTouch Task: タッチセンサのスキャンとMIDIイベントの送信
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 #[embassy_executor::task]
-async fn qubit_touch_task(mut sender: Sender<'static, Driver<'static, USB>>) {
-    use core::cell::RefCell;
-    use touch::qtouch::QubitTouch;
-    let send_buffer = RefCell::new([TouchEvent::default(); 8]);
-    let send_index = RefCell::new(0);
-    let mut qt = QubitTouch::new(|status, note, velocity, location| {
-        // MIDIコールバック: タッチイベントをMIDIパケットに変換して送信
-        let packet = TouchEvent(status, note, velocity, location);
-        let mut buf = send_buffer.borrow_mut();
-        let mut idx = send_index.borrow_mut();
-        if *idx < buf.len() {
-            buf[*idx] = packet;
-            *idx += 1;
-        } else {
-            // バッファオーバーフローの場合はエラーカウントをインクリメント
-            ERROR_CODE.store(41, Ordering::Relaxed);
-        }
-    });
+async fn qubit_touch_task() {
+    qubit_core::tasks::qubit_touch_task(
+        &mut board::RawTouchSource,
+        &mut board::ReportQueue,
+        &mut board::MidiTxQueue,
+        &mut board::RingLedQueue,
+        &board::EmbassyClock,
+    )
+    .await;
+}
 
-    let mut loop_times = 0u64;
-    let mut total_time = 0u64;
-    let mut _ticker = Ticker::every(embassy_time::Duration::from_millis(10));
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      MIDI TX Task: 送信キューのMIDIイベントをまとめてUSB/DINに送る
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn midi_tx_task(
+    sender: &'static board::SharedMidiSender,
+    din: &'static board::SharedDinSender,
+) {
+    let mut midi = board::UsbMidiOut::new(sender);
+    let mut din = board::DinOut::new(din);
+    qubit_core::tasks::midi_tx_task(
+        &mut board::MidiTxQueue,
+        &mut midi,
+        &mut din,
+        &board::EmbassyClock,
+    )
+    .await;
+}
 
-    loop {
-        // タッチスキャンは10msごとに実行
-        Timer::after(embassy_time::Duration::from_millis(10)).await;
-        // ticker.next().await; // タッチスキャンはtickerに合わせて実行
-        let start = Instant::now();
-
-        // タッチセンサの生データを取得してQubitTouchにセット
-        // ロック保持時間を最小化し、以降の await をロック外で実行する
-        let mut touch_values = [0u16; constants::TOTAL_QT_KEYS];
-        {
-            let data = TOUCH_RAW_DATA.lock().await;
-            touch_values.copy_from_slice(&*data);
-        }
-        for (ch, tv) in touch_values.iter().enumerate() {
-            qt.set_value(ch, *tv);
-        }
-        qt.seek_and_update_touch_point();
-        let idx = *send_index.borrow();
-        const MAX_EVENT: usize = 8;
-        if idx == 0 {
-            // no event
-        } else if idx < MAX_EVENT {
-            // await前にバッファをコピーして借用を解放
-            let mut packets = [TouchEvent::default(); MAX_EVENT];
-            {
-                let buf = send_buffer.borrow();
-                packets[0..idx].copy_from_slice(&buf[0..idx]);
-            }
-            for packet in packets.iter().take(idx) {
-                let status = packet.0 & 0xf0; // コマンド部分
-                let status = if status == constants::RINGLED_CMD_TX_MOVED {
-                    0x8c // 移動イベントはNote Offとして扱う
-                } else {
-                    status | 0x0c
-                };
-                let result = with_timeout(
-                    Duration::from_millis(5),
-                    sender.write_packet(&[status >> 4, status, packet.1, packet.2]),
-                )
-                .await;
-                if result.is_err() {
-                    // タイムアウトまたは送信エラー（USB未接続時など）
-                    ERROR_CODE.store(42, Ordering::Relaxed);
-                }
-                // バグ対策: RingLEDキュー満杯でCore0全体が停止しないよう非ブロッキング送信にする
-                if RINGLED_MESSAGE.try_send((packet.0, packet.3)).is_err() {
-                    ERROR_CODE.store(44, Ordering::Relaxed);
-                }
-            }
-            *send_index.borrow_mut() = 0;
-        } else {
-            // バッファオーバーフロー
-            ERROR_CODE.store(43, Ordering::Relaxed);
-            *send_index.borrow_mut() = 0;
-        }
-        qt.lighten_leds(|_location, _intensity| {
-            // LEDの明るさをタッチの強さに応じて変化させる
-            //WHITE_LEVEL.store(intensity as u8, Ordering::Relaxed);
-        });
-
-        // 時間計測
-        loop_times = loop_times.wrapping_add(1);
-        total_time = total_time.wrapping_add(start.elapsed().as_micros());
-        //ELAPSED_TIME.store(total_time / loop_times, Ordering::Relaxed);
-    }
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Report Task: タッチの記録とテレメトリの SysEx をUSBに送る
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn report_task(sender: &'static board::SharedMidiSender) {
+    let mut midi = board::UsbMidiOut::new(sender);
+    qubit_core::tasks::report_task(&mut board::ReportQueue, &mut midi).await;
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      DIN RX Task: DIN MIDIで受信したMIDIイベントをUSBに流す
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn din_rx_task(rx: board::DinUartRx, sender: &'static board::SharedMidiSender) {
+    let mut din = DinMidiIn::new(rx);
+    let mut midi = board::UsbMidiOut::new(sender);
+    qubit_core::tasks::din_rx_task(&mut din, &mut midi, &board::EmbassyClock).await;
 }
 
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
@@ -431,56 +447,71 @@ async fn usb_task(mut usb: embassy_usb::UsbDevice<'static, Driver<'static, USB>>
 //      MIDI RX Task: USB経由で受信したMIDIイベントの処理
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 #[embassy_executor::task]
-async fn midi_rx_task(mut receiver: Receiver<'static, Driver<'static, USB>>) {
-    let mut buf = [0; 64];
+async fn midi_rx_task(
+    receiver: Receiver<'static, Driver<'static, USB>>,
+    sender: &'static board::SharedMidiSender,
+    din: &'static board::SharedDinSender,
+    mut flash_settings: board::FlashSettings,
+) {
+    let mut midi = board::UsbMidiIn::new(receiver);
+    let mut replies = board::UsbMidiOut::new(sender);
+    let mut thru = board::DinOut::new(din);
+    qubit_core::tasks::midi_rx_task(
+        &mut midi,
+        &mut board::RingLedQueue,
+        &mut replies,
+        &mut thru,
+        &mut flash_settings,
+        &board::EmbassyClock,
+    )
+    .await;
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      MIDI Clock Task: 内部クロック（Clock が Int のとき）の送信
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn midi_clock_task(
+    sender: &'static board::SharedMidiSender,
+    din: &'static board::SharedDinSender,
+) {
+    let mut midi = board::UsbMidiOut::new(sender);
+    let mut din = board::DinOut::new(din);
+    qubit_core::tasks::midi_clock_task(&mut midi, &mut din, &board::EmbassyClock).await;
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Console Task: USB シリアルのデバッグコンソール
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn console_task(
+    receiver: cdc_acm::Receiver<'static, Driver<'static, USB>>,
+    sender: &'static board::SharedConsoleSender,
+) {
+    let mut input = board::ConsoleIn(receiver);
+    let mut output = board::ConsoleOut::new(sender);
+    qubit_core::tasks::console_task(
+        &mut input,
+        &mut output,
+        &mut board::RawTouchSource,
+        &board::EmbassyClock,
+    )
+    .await;
+}
+
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Log Task: log のレコードをコンソールに送る
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn log_task(sender: &'static board::SharedConsoleSender) {
+    use qubit_core::hal::SerialSink;
 
+    let mut console = board::ConsoleOut::new(sender);
+    let mut buf = [0u8; 64];
     loop {
-        match receiver.read_packet(&mut buf).await {
-            Ok(n) => {
-                for packet in buf[0..n].chunks(4) {
-                    if packet.len() == 4 {
-                        let status = packet[1];
-                        let note = packet[2];
-                        let velocity = packet[3];
-
-                        // Note On (Channel 0-15)
-                        if (status & 0xF0) == 0x90 {
-                            if velocity > 0 {
-                                // バグ対策: RingLEDキュー満杯でもmidi_rx_taskを止めない
-                                if RINGLED_MESSAGE
-                                    .try_send((constants::RINGLED_CMD_RX_ON, note as f32))
-                                    .is_err()
-                                {
-                                    ERROR_CODE.store(51, Ordering::Relaxed);
-                                }
-                            } else {
-                                // バグ対策: RingLEDキュー満杯でもmidi_rx_taskを止めない
-                                if RINGLED_MESSAGE
-                                    .try_send((constants::RINGLED_CMD_RX_OFF, note as f32))
-                                    .is_err()
-                                {
-                                    ERROR_CODE.store(52, Ordering::Relaxed);
-                                }
-                            }
-                        }
-                        // Note Off
-                        else if (status & 0xF0) == 0x80 {
-                            // バグ対策: RingLEDキュー満杯でもmidi_rx_taskを止めない
-                            if RINGLED_MESSAGE
-                                .try_send((constants::RINGLED_CMD_RX_OFF, note as f32))
-                                .is_err()
-                            {
-                                ERROR_CODE.store(53, Ordering::Relaxed);
-                            }
-                        }
-                    }
-                }
-            }
-            Err(_e) => {
-                // エラーカウント
-                ERROR_CODE.store(54, Ordering::Relaxed);
-            }
-        }
+        let n = LOG_PIPE.read(&mut buf).await;
+        // ターミナルを開いていなければ捨てる
+        let _ = console.write(&buf[..n]).await;
     }
 }
 
@@ -581,7 +612,7 @@ async fn adc_task(
 
         // 圧力を計算
         if a0b0_available {
-            touch::pressure::update_pressure(&samples, &mut sums, adc_counter);
+            qubit_core::touch::pressure::update_pressure(&samples, &mut sums, adc_counter);
             adc_counter = adc_counter.wrapping_add(1);
         }
     }
@@ -591,128 +622,126 @@ async fn adc_task(
 //      Core1 I2C Task: タッチセンサとOLED Device の処理
 //+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
 #[embassy_executor::task]
-async fn core1_i2c_task(mut i2c: I2c<'static, I2C1, i2c::Async>) {
+async fn core1_i2c_task(
+    mut i2c_peri: Peri<'static, I2C1>,
+    mut scl: Peri<'static, PIN_7>,
+    mut sda: Peri<'static, PIN_6>,
+) {
+    use crate::devices::i2c_bus::{I2cMonitor, MonitoredI2c, recover_bus};
+    use crate::devices::ssd1306::Oled;
+
     // AT42QT1070 と PCA9544 の生成
     let pca = devices::pca9544::Pca9544::new();
     let mut at42 = devices::at42qt::At42Qt1070::new();
 
     // OLED初期化（I2Cを保持しない）
-    use crate::devices::ssd1306::Oled;
     let mut oled = Oled::new();
 
-    // --- init phase ---
-    let mut read_touch = touch::read_touch::ReadTouch::new(); // タッチイベントの状態を保持する構造体を生成
-    read_touch
-        .init_touch_sensors(&pca, &mut at42, &mut i2c)
-        .await;
-
-    // OLED初期化
-    if oled.init(&mut i2c).is_err() {
-        ERROR_CODE.store(71, Ordering::Relaxed);
-    }
+    // タッチイベントの状態を保持する構造体を生成（バスリカバリをまたいで保持する）
+    let mut read_touch = touch::read_touch::ReadTouch::new();
+    let mut monitor = I2cMonitor::new();
 
     let start = Instant::now();
     let mut loop_times = 0u64;
 
-    // Task Loop
     loop {
-        // OLED更新:UIタスクから描画済みバッファを受信（非ブロッキング）
-        if let Ok(buffer) = BUFFER_TO_DISPLAY.try_receive() {
-            if oled.flush_buffer(&buffer, &mut i2c).is_err() {
-                ERROR_CODE.store(72, Ordering::Relaxed);
-            }
+        {
+            // I2Cペリフェラルはこのブロックの中だけで使い、バスリカバリ時に解放する
+            let mut i2c_config = I2cConfig::default();
+            i2c_config.frequency = constants::I2C_FREQUENCY;
+            let bus = I2c::new_async(
+                i2c_peri.reborrow(),
+                scl.reborrow(),
+                sda.reborrow(),
+                Irqs,
+                i2c_config,
+            );
+            let mut i2c = MonitoredI2c::new(bus, &mut monitor);
 
-            // バッファを返却
-            if BUFFER_FROM_DISPLAY.try_send(buffer).is_err() {
-                ERROR_CODE.store(73, Ordering::Relaxed);
-            }
-        }
+            // --- init phase ---
+            read_touch
+                .init_touch_sensors(&pca, &mut at42, &mut i2c)
+                .await;
 
-        // タッチセンサのスキャンとイベント処理
-        read_touch
-            .touch_sensor_scan(&pca, &mut at42, &mut i2c)
-            .await;
+            // OLED初期化
+            if oled.init(&mut i2c, settings::oled_config()).await.is_err() {
+                ERROR_CODE.store(71, Ordering::Relaxed);
+            }
 
-        // 他のタスクに処理を譲る
-        embassy_futures::yield_now().await;
+            // Task Loop: バスの異常を検出するまで回る
+            while !i2c.needs_recovery() {
+                let loop_start = Instant::now().as_micros();
 
-        // 時間計測
-        loop_times = loop_times.wrapping_add(1);
-        let elapsed_time = start.elapsed().as_micros();
-        ELAPSED_TIME.store(elapsed_time / loop_times, Ordering::Relaxed);
-    }
-}
+                // コンソールからのキャリブレーションの要求
+                if CALIBRATE_REQUEST.swap(0, Ordering::Relaxed) != 0 {
+                    read_touch.calibrate(&pca, &mut at42, &mut i2c).await;
+                }
 
-//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
-//      Core1 OLED UI Task: OLEDディスプレイの更新
-//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
-#[embassy_executor::task]
-async fn core1_oled_ui_task(switch1: Input<'static>, switch2: Input<'static>) {
-    use ui::oled_display::GraphicsDisplay;
+                // OLED更新:UIタスクから描画済みバッファを受信（非ブロッキング）
+                if let Ok(buffer) = BUFFER_TO_DISPLAY.try_receive() {
+                    oled.set_frame(&buffer);
 
-    let mut gui = GraphicsDisplay::new();
-    let mut counter = 0u32;
-    let mut ui_page = 0u8;
+                    // バッファを返却（転送はOled内のコピーから行うので、すぐ返せる）
+                    if BUFFER_FROM_DISPLAY.try_send(buffer).is_err() {
+                        ERROR_CODE.store(73, Ordering::Relaxed);
+                    }
+                }
+                // 表示設定（コントラスト、反転、スリープなど）の変更を反映する
+                let config = settings::oled_config();
+                if config != oled.config() && oled.apply_config(&mut i2c, config).await.is_err() {
+                    ERROR_CODE.store(71, Ordering::Relaxed);
+                }
+                // 変化した部分だけを、時間予算の範囲で少しずつ転送する（タッチスキャンを待たせない）
+                let budget = Duration::from_micros(constants::OLED_FLUSH_BUDGET_US);
+                if !config.sleep && oled.flush_step(&mut i2c, budget).await.is_err() {
+                    ERROR_CODE.store(72, Ordering::Relaxed);
+                }
 
-    let mut switch1_prev = false;
-    let mut switch2_prev = false;
+                // タッチセンサのスキャンとイベント処理
+                read_touch
+                    .touch_sensor_scan(&pca, &mut at42, &mut i2c)
+                    .await;
+                timing::record(TASK_I2C, loop_start, Instant::now().as_micros());
 
-    // 初期画面表示
-    let mut buffer = BUFFER_FROM_DISPLAY.receive().await;
-    gui.draw_bringup_screen(&mut buffer);
-    BUFFER_TO_DISPLAY.send(buffer).await;
+                // 他のタスクに処理を譲る
+                embassy_futures::yield_now().await;
 
-    loop {
-        // 次のステップまで待機(10fps想定)
-        Timer::after_millis(100).await;
-
-        // 空バッファを受信
-        buffer = BUFFER_FROM_DISPLAY.receive().await;
-
-        // スイッチの状態を取得
-        let switch_r_state = switch1.is_low();
-        let switch_l_state = switch2.is_low();
-        if switch_r_state != switch1_prev && switch_r_state {
-            if switch_l_state {
-                // 両方のスイッチが同時に押された場合は、設定画面に直接遷移
-                ui_page = 4;
-            } else if ui_page == 3 || ui_page == 4 {
-                ui_page = 0;
-            } else {
-                ui_page += 1;
+                // 時間計測
+                loop_times = loop_times.wrapping_add(1);
+                let elapsed_time = start.elapsed().as_micros();
+                ELAPSED_TIME.store(elapsed_time / loop_times, Ordering::Relaxed);
             }
-            gui.change_page(ui_page); // ページ切替をGUIに通知
         }
-        if switch_l_state != switch2_prev && switch_l_state {
-            if switch_r_state {
-                // 両方のスイッチが同時に押された場合は、設定画面に直接遷移
-                ui_page = 4;
-            } else if ui_page == 4 {
-                WORK_MODE.store(
-                    (WORK_MODE.load(Ordering::Relaxed) + 1) % 2,
-                    Ordering::Relaxed,
-                ); // 動作モードを切り替え
-                // 設定変更時にエラーコードをリセットする
-                ERROR_CODE.store(0, Ordering::Relaxed);
-            } else if ui_page == 0 {
-                ui_page = 3;
-            } else {
-                ui_page -= 1;
-            }
-            gui.change_page(ui_page); // ページ切替をGUIに通知
-        }
-        switch1_prev = switch_r_state;
-        switch2_prev = switch_l_state;
 
-        // 描画
-        gui.tick(&mut buffer, counter);
-        counter = counter.wrapping_add(1);
-
-        // 描画済みバッファを送信
-        BUFFER_TO_DISPLAY.send(buffer).await;
+        // バスリカバリ: I2Cペリフェラルを解放し、SCLをクロックしてSTOPを出してから作り直す
+        ERROR_CODE.store(82, Ordering::Relaxed);
+        log::warn!("i2c: bus recovery");
+        if !recover_bus(scl.reborrow(), sda.reborrow()).await {
+            ERROR_CODE.store(83, Ordering::Relaxed);
+            Timer::after_millis(100).await;
+        }
+        monitor.reset_after_recovery();
     }
 }
 
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+//      Core1 OLED UI Task: OLEDディスプレイの更新
+//+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+#[embassy_executor::task]
+async fn core1_oled_ui_task(switch1: Input<'static>, switch2: Input<'static>) {
+    use qubit_core::ui::oled_display::GraphicsDisplay;
+
+    let mut gui = GraphicsDisplay::new(env!("BUILD_DATE"), env!("BUILD_TIME"));
+    let mut switches = board::SwitchPair::new(switch1, switch2);
+    qubit_core::tasks::oled_ui_task(
+        &mut gui,
+        &mut board::OledChannels,
+        &mut switches,
+        &board::EmbassyClock,
+    )
+    .await;
+}
+
 /// Program metadata for `picotool info`
 #[unsafe(link_section = ".bi_entries")]
 #[used]
@@ -1,3 +1 @@
-pub mod pressure;
-pub mod qtouch;
 pub mod read_touch;
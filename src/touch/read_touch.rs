@@ -1,16 +1,28 @@
-use embassy_rp::i2c::{self, I2c};
-use embassy_rp::peripherals::I2C1;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_async::i2c::I2c;
 use portable_atomic::Ordering;
 
 use crate::TOUCH_RAW_DATA;
-use crate::constants;
 use crate::devices::{at42qt, pca9544};
-use crate::{POINT0, POINT1, POINT2, POINT3};
+use qubit_core::constants;
+use qubit_core::touch::frame::TouchFrame;
+use qubit_core::touch::key_mask::KeyMask;
+use qubit_core::touch::scan_scheduler::{ChannelSet, ScanScheduler};
+use qubit_core::{ERROR_CODE, SCAN_FRAME_RATE, SCAN_RATE};
+use qubit_core::{POINT0, POINT1, POINT2, POINT3};
 
 pub struct ReadTouch {
     raw_value: [u16; constants::TOTAL_QT_KEYS],
     refference: [u16; constants::TOTAL_QT_KEYS],
-    refference_counter: usize,
+    frame: TouchFrame,
+    scheduler: ScanScheduler,
+    fail_count: [u8; constants::TOTAL_CH], // チップごとの連続読み込み失敗回数
+    ok_count: [u8; constants::TOTAL_CH],   // マスク中のチップの連続読み込み成功回数
+    failed_chips: KeyMask,                 // 読み込み失敗でマスクしたキー
+    scan_counter: u32,                     // マスク中のチップを間引いて読むためのカウンタ
+    read_counts: [u16; constants::TOTAL_CH], // スキャンレート計測用
+    frame_count: u16,
+    rate_start: Instant,
 }
 
 impl ReadTouch {
@@ -20,110 +32,255 @@ impl ReadTouch {
         Self {
             raw_value: [0u16; constants::TOTAL_QT_KEYS],
             refference: [0u16; constants::TOTAL_QT_KEYS],
-            refference_counter: 0,
+            frame: TouchFrame::new(),
+            scheduler: ScanScheduler::new(),
+            fail_count: [0u8; constants::TOTAL_CH],
+            ok_count: [0u8; constants::TOTAL_CH],
+            failed_chips: KeyMask::new(),
+            scan_counter: 0,
+            read_counts: [0u16; constants::TOTAL_CH],
+            frame_count: 0,
+            rate_start: Instant::now(),
         }
     }
 
-    pub async fn init_touch_sensors(
+    /// チップの読み込み結果を記録し、連続失敗ならマスク、連続成功ならマスク解除する
+    /// マスク状態が変化したら true を返す
+    fn record_read_result(&mut self, ch: usize, ok: bool) -> bool {
+        let masked = self
+            .failed_chips
+            .is_masked(ch * constants::AT42QT_KEYS_PER_DEVICE);
+        if ok {
+            self.fail_count[ch] = 0;
+            if masked {
+                self.ok_count[ch] = self.ok_count[ch].saturating_add(1);
+                if self.ok_count[ch] >= constants::CHIP_RECOVER_LIMIT {
+                    self.ok_count[ch] = 0;
+                    self.failed_chips.set_chip(ch, false);
+                    log::info!("touch: chip {} recovered", ch);
+                    return true;
+                }
+            }
+        } else {
+            self.ok_count[ch] = 0;
+            self.fail_count[ch] = self.fail_count[ch].saturating_add(1);
+            if !masked && self.fail_count[ch] >= constants::CHIP_FAIL_LIMIT {
+                self.failed_chips.set_chip(ch, true);
+                ERROR_CODE.store(81, Ordering::Relaxed);
+                log::warn!("touch: chip {} masked after read failures", ch);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// マスク中のチップか
+    fn is_failed_chip(&self, ch: usize) -> bool {
+        self.failed_chips
+            .is_masked(ch * constants::AT42QT_KEYS_PER_DEVICE)
+    }
+
+    /// チャンネル番号から (PCA9544の番号, PCA9544内のチャンネル) を求める
+    fn mux_route(ch: usize) -> (u8, u8) {
+        let ch = ch as u8;
+        let dev = ch / constants::PCA9544_NUM_CHANNELS;
+        let ch_in_dev = Self::CH_CONVERTION[(ch % constants::PCA9544_NUM_CHANNELS) as usize];
+        (dev, ch_in_dev)
+    }
+
+    /// 読み込んだタッチ値をフレームに反映し、タッチがあればスケジューラに知らせる
+    fn store_values(&mut self, ch: usize, raw_data: &[u16; constants::AT42QT_KEYS_PER_DEVICE]) {
+        let start_ch = ch * constants::AT42QT_KEYS_PER_DEVICE;
+        let mut active = false;
+        for (sid, rawd) in (start_ch..).zip(raw_data.iter()) {
+            let mut raw = *rawd;
+            let old = self.raw_value[sid];
+            if old != 0 && raw > old + 200 {
+                raw -= 256; // hiからloを読む間に数値が変化した場合の対策
+            }
+            self.raw_value[sid] = raw;
+            let value = raw.saturating_sub(self.refference[sid]);
+            self.frame.values[sid] = value;
+            if value > 10 {
+                POINT0.store(sid as u16, Ordering::Relaxed);
+                POINT1.store(self.refference[sid], Ordering::Relaxed);
+                POINT2.store(old, Ordering::Relaxed);
+                POINT3.store(raw, Ordering::Relaxed);
+            }
+            active |= value > constants::SCAN_ACTIVE_THRESHOLD;
+        }
+        self.frame.timestamps[ch] = Instant::now().as_micros();
+        self.read_counts[ch] = self.read_counts[ch].saturating_add(1);
+        self.scheduler.note_activity(ch, active);
+    }
+
+    /// リファレンス値を保存する
+    fn store_reference(&mut self, ch: usize, raw_data: &[u16; constants::AT42QT_KEYS_PER_DEVICE]) {
+        let sid = ch * constants::AT42QT_KEYS_PER_DEVICE;
+        self.refference[sid..(sid + constants::AT42QT_KEYS_PER_DEVICE)]
+            .copy_from_slice(&raw_data[..constants::AT42QT_KEYS_PER_DEVICE]);
+        self.refference[sid + 5] += 7; // 5キーのうち最後のキーはリファレンス値を高めに取る（タッチセンサーの特性による）
+    }
+
+    /// 1秒ごとにチャンネルごとの読み込み回数を公開する
+    fn update_scan_rate(&mut self) {
+        self.frame_count = self.frame_count.saturating_add(1);
+        let elapsed = self.rate_start.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let ms = elapsed.as_millis().max(1) as u32;
+        for (ch, count) in self.read_counts.iter_mut().enumerate() {
+            SCAN_RATE[ch].store((*count as u32 * 1000 / ms) as u16, Ordering::Relaxed);
+            *count = 0;
+        }
+        SCAN_FRAME_RATE.store(
+            (self.frame_count as u32 * 1000 / ms) as u16,
+            Ordering::Relaxed,
+        );
+        self.frame_count = 0;
+        self.rate_start = Instant::now();
+    }
+
+    pub async fn init_touch_sensors<I2C: I2c>(
         &mut self,
         pca: &pca9544::Pca9544,
         at42: &mut at42qt::At42Qt1070,
-        i2c: &mut I2c<'static, I2C1, i2c::Async>,
+        i2c: &mut I2C,
     ) {
-        for ch in 0..constants::PCA9544_NUM_CHANNELS * constants::PCA9544_NUM_DEVICES {
-            let dev = ch / constants::PCA9544_NUM_CHANNELS;
-            let ch_in_dev = Self::CH_CONVERTION[(ch % constants::PCA9544_NUM_CHANNELS) as usize];
+        // 途中の状態で止まっているかもしれないので、まず全MUXを切断する
+        for dev in 0..constants::PCA9544_NUM_DEVICES {
+            pca.disconnect(i2c, dev).await.ok();
+        }
+        for ch in 0..constants::TOTAL_CH {
+            let (dev, ch_in_dev) = Self::mux_route(ch);
             pca.select(i2c, dev, ch_in_dev).await.ok();
             at42.init(i2c).await.ok();
+            // スキャン中はリファレンスを1チャンネルずつしか読まないので、最初に全チャンネル読んでおく
+            let mut raw_data = [0u16; constants::AT42QT_KEYS_PER_DEVICE];
+            if at42.read_6key(i2c, &mut raw_data, true).await.is_ok() {
+                self.store_reference(ch, &raw_data);
+            }
             // PCA9544のチャネルが最後のときに切断する
-            if ch % constants::PCA9544_NUM_CHANNELS == constants::PCA9544_NUM_CHANNELS - 1 {
+            if ch % constants::PCA9544_NUM_CHANNELS as usize
+                == constants::PCA9544_NUM_CHANNELS as usize - 1
+            {
                 pca.disconnect(i2c, dev).await.ok();
             }
         }
     }
 
-    pub async fn touch_sensor_scan(
+    /// キャリブレーション（コンソールから）: 全チップに指示し、終わるのを待ってリファレンスを取り直す
+    pub async fn calibrate<I2C: I2c>(
         &mut self,
         pca: &pca9544::Pca9544,
         at42: &mut at42qt::At42Qt1070,
-        i2c: &mut I2c<'static, I2C1, i2c::Async>,
+        i2c: &mut I2C,
     ) {
-        let mut data = [0u16; constants::TOTAL_QT_KEYS];
-        for ch in 0..(constants::TOTAL_CH as u8) {
-            let dev = ch / constants::PCA9544_NUM_CHANNELS;
-            let ch_in_dev = Self::CH_CONVERTION[(ch % constants::PCA9544_NUM_CHANNELS) as usize];
-            pca.select(i2c, dev, ch_in_dev).await.ok();
+        let last_in_dev = constants::PCA9544_NUM_CHANNELS as usize - 1;
+        for ch in 0..constants::TOTAL_CH {
+            let (dev, ch_in_dev) = Self::mux_route(ch);
+            if pca.select(i2c, dev, ch_in_dev).await.is_ok() {
+                at42.calibrate(i2c).await.ok();
+            }
+            if ch % constants::PCA9544_NUM_CHANNELS as usize == last_in_dev {
+                pca.disconnect(i2c, dev).await.ok();
+            }
+        }
+        Timer::after_millis(constants::CALIBRATION_WAIT_MS).await;
 
+        let mut calibrated = 0;
+        for ch in 0..constants::TOTAL_CH {
+            let (dev, ch_in_dev) = Self::mux_route(ch);
+            pca.select(i2c, dev, ch_in_dev).await.ok();
             let mut raw_data = [0u16; constants::AT42QT_KEYS_PER_DEVICE];
-            if let Ok(()) = at42.read_6key(i2c, &mut raw_data, false).await {
-                //let mut sid = (ch as usize) * constants::AT42QT_KEYS_PER_DEVICE;
-                let start_ch = (ch as usize) * constants::AT42QT_KEYS_PER_DEVICE;
-                for (sid, rawd) in
-                    (start_ch..).zip(raw_data.iter().take(constants::AT42QT_KEYS_PER_DEVICE))
-                {
-                    let mut raw = *rawd;
-                    let old = self.raw_value[sid];
-                    if old != 0 && raw > old + 200 {
-                        raw -= 256; // hiからloを読む間に数値が変化した場合の対策
-                    }
-                    self.raw_value[sid] = raw;
-                    data[sid] = raw.saturating_sub(self.refference[sid]);
-                    if data[sid] > 10 {
-                        POINT0.store(sid as u16, Ordering::Relaxed);
-                        POINT1.store(self.refference[sid], Ordering::Relaxed);
-                        POINT2.store(old, Ordering::Relaxed);
-                        POINT3.store(raw, Ordering::Relaxed);
-                    }
-                }
-                /*for rawd in raw_data.iter().take(constants::AT42QT_KEYS_PER_DEVICE) {
-                    let mut raw = *rawd;
-                    let old = self.raw_value[sid];
-                    if old != 0 && raw > old + 200 {
-                        raw -= 256; // hiからloを読む間に数値が変化した場合の対策
-                    }
-                    self.raw_value[sid] = raw;
-                    data[sid] = raw.saturating_sub(self.refference[sid]);
-                    if data[sid] > 10 {
-                        POINT0.store(sid as u16, Ordering::Relaxed);
-                        POINT1.store(self.refference[sid], Ordering::Relaxed);
-                        POINT2.store(old, Ordering::Relaxed);
-                        POINT3.store(raw, Ordering::Relaxed);
-                    }
-                    sid += 1;
-                }*/
+            if at42.read_6key(i2c, &mut raw_data, true).await.is_ok() {
+                self.store_reference(ch, &raw_data);
+                calibrated += 1;
             }
-            // PCA9544のチャネルが最後のときに切断する
-            if ch % constants::PCA9544_NUM_CHANNELS == constants::PCA9544_NUM_CHANNELS - 1 {
+            if ch % constants::PCA9544_NUM_CHANNELS as usize == last_in_dev {
                 pca.disconnect(i2c, dev).await.ok();
             }
         }
-        {
-            // タッチセンサーの生データを Mutex で保護されたグローバル変数に保存
-            let mut raw_data = TOUCH_RAW_DATA.lock().await;
-            raw_data.copy_from_slice(&data);
+        log::info!(
+            "touch: calibrated {}/{} chips",
+            calibrated,
+            constants::TOTAL_CH
+        );
+    }
+
+    pub async fn touch_sensor_scan<I2C: I2c>(
+        &mut self,
+        pca: &pca9544::Pca9544,
+        at42: &mut at42qt::At42Qt1070,
+        i2c: &mut I2C,
+    ) {
+        // 今回読むチャンネルを決める
+        // マスク中のチップは数回に一度だけ読み、復帰を待つ（バスの時間を無駄にしない）
+        let mut failed = ChannelSet::new();
+        for ch in 0..constants::TOTAL_CH {
+            if self.is_failed_chip(ch) {
+                failed.insert(ch);
+            }
         }
+        let retry_failed = self
+            .scan_counter
+            .is_multiple_of(constants::FAILED_CHIP_POLL_INTERVAL);
+        let plan = self.scheduler.plan(failed, retry_failed);
 
-        if self.refference_counter == 0 {
-            for ch in 0..constants::PCA9544_NUM_CHANNELS * constants::PCA9544_NUM_DEVICES {
-                let dev = ch / constants::PCA9544_NUM_CHANNELS;
-                let ch_in_dev =
-                    Self::CH_CONVERTION[(ch % constants::PCA9544_NUM_CHANNELS) as usize];
-                pca.select(i2c, dev, ch_in_dev).await.ok();
+        let mut mask_changed = false;
+        let mut connected: Option<u8> = None; // 接続中のPCA9544
+        for ch in 0..constants::TOTAL_CH {
+            let read = plan.reads.contains(ch);
+            let reference = plan.reference == Some(ch);
+            if !read && !reference {
+                continue;
+            }
 
+            // 同じアドレスのAT42QT1070が並ぶので、別のPCA9544に移る前に切断する
+            let (dev, ch_in_dev) = Self::mux_route(ch);
+            if let Some(prev) = connected
+                && prev != dev
+            {
+                pca.disconnect(i2c, prev).await.ok();
+            }
+            connected = Some(dev);
+            // MUXの切り替えに失敗したら、別のチップを読んでしまうので読まない
+            let selected = pca.select(i2c, dev, ch_in_dev).await.is_ok();
+
+            if read {
                 let mut raw_data = [0u16; constants::AT42QT_KEYS_PER_DEVICE];
-                if let Ok(()) = at42.read_6key(i2c, &mut raw_data, true).await {
-                    let sid = (ch as usize) * constants::AT42QT_KEYS_PER_DEVICE;
-                    self.refference[sid..(sid + constants::AT42QT_KEYS_PER_DEVICE)]
-                        .copy_from_slice(&raw_data[..constants::AT42QT_KEYS_PER_DEVICE]);
-                    self.refference[sid + 5] += 7; // 5キーのうち最後のキーはリファレンス値を高めに取る（タッチセンサーの特性による）
+                let read_ok = selected && at42.read_6key(i2c, &mut raw_data, false).await.is_ok();
+                mask_changed |= self.record_read_result(ch, read_ok);
+                if read_ok {
+                    self.store_values(ch, &raw_data);
+                } else {
+                    let sid = ch * constants::AT42QT_KEYS_PER_DEVICE;
+                    self.frame.values[sid..(sid + constants::AT42QT_KEYS_PER_DEVICE)].fill(0);
                 }
-                // PCA9544のチャネルが最後のときに切断する
-                if ch % constants::PCA9544_NUM_CHANNELS == constants::PCA9544_NUM_CHANNELS - 1 {
-                    pca.disconnect(i2c, dev).await.ok();
+            }
+            if reference && selected {
+                let mut raw_data = [0u16; constants::AT42QT_KEYS_PER_DEVICE];
+                if at42.read_6key(i2c, &mut raw_data, true).await.is_ok() {
+                    self.store_reference(ch, &raw_data);
                 }
             }
         }
-        self.refference_counter = (self.refference_counter + 1) % 12;
+        if let Some(dev) = connected {
+            pca.disconnect(i2c, dev).await.ok();
+        }
+
+        {
+            // タッチセンサーの生データを Mutex で保護されたグローバル変数に保存
+            let mut frame = TOUCH_RAW_DATA.lock().await;
+            *frame = self.frame;
+        }
+        self.scan_counter = self.scan_counter.wrapping_add(1);
+        if mask_changed {
+            // 読み込み失敗によるマスクを Core0 に公開する
+            self.failed_chips.store_auto();
+        }
+        self.update_scan_rate();
 
         //POINT0.store(self.raw_value[64], Ordering::Relaxed);
         //POINT1.store(self.raw_value[65], Ordering::Relaxed);
@@ -1,2 +0,0 @@
-pub mod oled_display;
-pub mod ringled;